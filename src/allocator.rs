@@ -1,20 +1,248 @@
-use crate::memory_space::MemorySpace;
+use std::fmt;
+
+use crate::free_chunk;
+use crate::memory_space::{MemorySpace, SegmentKind};
 //use crate::oop::*;
 use crate::oop_projections::oop_common::*;
+use crate::oop_projections::oop_headers::OopHeaders;
+
+// The free words the small objects of the moving space are bumped into: the rest of the chunk
+// the strategy chose last. The chunk has no valid header while the buffer is in use,
+// it is written when the buffer is retired, before any walk of the heap.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AllocationBuffer {
+    pub next: usize,
+    pub limit: usize,
+}
+
+// How the allocation picks a free chunk among the ones big enough.
+// Each memory space has its own, first-fit unless told otherwise.
+pub trait AllocationStrategy: fmt::Debug {
+    fn name(&self) -> &'static str;
+
+    // Answers the index of the chosen free chunk of the segments of that kind,
+    // None when no chunk has that many words
+    fn find_free_chunk(
+        &mut self,
+        space: &mut MemorySpace,
+        kind: SegmentKind,
+        number_of_usize: usize,
+    ) -> Option<usize>;
+}
+
+pub const ALLOCATION_STRATEGIES: [&str; 4] = ["first-fit", "next-fit", "best-fit", "worst-fit"];
+
+pub fn allocation_strategy_named(name: &str) -> Option<Box<dyn AllocationStrategy>> {
+    match name {
+        "first-fit" => Some(Box::new(FirstFit)),
+        "next-fit" => Some(Box::<NextFit>::default()),
+        "best-fit" => Some(Box::new(BestFit)),
+        "worst-fit" => Some(Box::new(WorstFit)),
+        _ => None,
+    }
+}
 
-pub fn where_to_allocate(number_of_usize: usize, space: &mut MemorySpace) -> usize {
-    let mut iter = space.iter();
-    while let Some(oop) = iter.next(space) {
-        if oop.is_free_oop() && oop.oop_size() >= number_of_usize {
-            // We found a free index with enough space !
-            return oop.get_index();
+// Walks the free chunks with at least that many words from that index in address order,
+// until visit answers false
+fn visit_fitting_chunks(
+    space: &mut MemorySpace,
+    kind: SegmentKind,
+    from: usize,
+    number_of_usize: usize,
+    mut visit: impl FnMut(usize, usize) -> bool,
+) {
+    // The pages of the large objects are theirs alone
+    for (index, size) in space.free_chunks().from_index(kind, from) {
+        if size >= number_of_usize && !visit(index, size) {
+            return;
         }
     }
-    //We didn't find a proper place in memory to put that many usize
+}
+
+// The first chunk big enough from the start of the space
+#[derive(Debug, Default)]
+pub struct FirstFit;
+
+impl AllocationStrategy for FirstFit {
+    fn name(&self) -> &'static str {
+        "first-fit"
+    }
 
-    // Should probably say we need a GC.
-    // Throw an error in the meantime.
-    panic!("Couldn't allocate {} slots", number_of_usize);
+    fn find_free_chunk(
+        &mut self,
+        space: &mut MemorySpace,
+        kind: SegmentKind,
+        number_of_usize: usize,
+    ) -> Option<usize> {
+        let mut found = None;
+        visit_fitting_chunks(space, kind, 0, number_of_usize, |index, _| {
+            found = Some(index);
+            false
+        });
+        found
+    }
+}
+
+// The first chunk big enough from where the previous allocation ended, wrapping around
+#[derive(Debug, Default)]
+pub struct NextFit {
+    rover: usize,
+}
+
+impl AllocationStrategy for NextFit {
+    fn name(&self) -> &'static str {
+        "next-fit"
+    }
+
+    fn find_free_chunk(
+        &mut self,
+        space: &mut MemorySpace,
+        kind: SegmentKind,
+        number_of_usize: usize,
+    ) -> Option<usize> {
+        let mut found = None;
+        for from in [self.rover, 0] {
+            visit_fitting_chunks(space, kind, from, number_of_usize, |index, _| {
+                found = Some(index);
+                false
+            });
+            if found.is_some() {
+                break;
+            }
+        }
+        if let Some(index) = found {
+            self.rover = index + number_of_usize;
+        }
+        found
+    }
+}
+
+// The smallest chunk big enough, the first one of its size
+#[derive(Debug, Default)]
+pub struct BestFit;
+
+impl AllocationStrategy for BestFit {
+    fn name(&self) -> &'static str {
+        "best-fit"
+    }
+
+    fn find_free_chunk(
+        &mut self,
+        space: &mut MemorySpace,
+        kind: SegmentKind,
+        number_of_usize: usize,
+    ) -> Option<usize> {
+        space.free_chunks().smallest_fitting(kind, number_of_usize)
+    }
+}
+
+// The largest chunk, the remainder stays as big as possible
+#[derive(Debug, Default)]
+pub struct WorstFit;
+
+impl AllocationStrategy for WorstFit {
+    fn name(&self) -> &'static str {
+        "worst-fit"
+    }
+
+    fn find_free_chunk(
+        &mut self,
+        space: &mut MemorySpace,
+        kind: SegmentKind,
+        number_of_usize: usize,
+    ) -> Option<usize> {
+        space
+            .free_chunks()
+            .largest(kind)
+            .filter(|(_, size)| *size >= number_of_usize)
+            .map(|(index, _)| index)
+    }
+}
+
+// The free chunks of the segments of a kind
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FreeSpaceCensus {
+    pub free_chunks: usize,
+    pub free_words: usize,
+    pub largest_free_chunk: usize,
+}
+
+impl FreeSpaceCensus {
+    pub fn of(space: &mut MemorySpace, kind: SegmentKind) -> Self {
+        let mut census = Self::default();
+        visit_fitting_chunks(space, kind, 0, 0, |_, size| {
+            census.free_chunks += 1;
+            census.free_words += size;
+            census.largest_free_chunk = census.largest_free_chunk.max(size);
+            true
+        });
+        census
+    }
+
+    // The share of the free words out of the largest chunk: 0 when they are all in one chunk,
+    // close to 1 when they are scattered in small ones
+    pub fn fragmentation(&self) -> f64 {
+        if self.free_words == 0 {
+            return 0.0;
+        }
+        1.0 - self.largest_free_chunk as f64 / self.free_words as f64
+    }
+}
+
+// The fast path bumps the buffer. The slow path lets the strategy pick a chunk,
+// its words past the object become the buffer.
+// The objects bumped into the buffer bypass the strategy: without the buffer it places each one.
+// None when the space cannot grow.
+pub fn allocate(number_of_usize: usize, space: &mut MemorySpace) -> Option<usize> {
+    if !space.uses_allocation_buffer() {
+        let index = where_to_allocate(number_of_usize, space)?;
+        split_free_chunk(index, number_of_usize, space);
+        return Some(index);
+    }
+    if let Some(index) = space.bump_allocate(number_of_usize) {
+        return Some(index);
+    }
+    let index = where_to_allocate(number_of_usize, space)?;
+    let chunk_size = OopHeaders::new(index, space).oop_size();
+    space.take_free_chunk(index);
+    space.set_allocation_buffer(AllocationBuffer {
+        next: index + number_of_usize,
+        limit: index + chunk_size,
+    });
+    Some(index)
+}
+
+// The words of the free chunk past that many become a free chunk of their own, or a filler
+pub fn split_free_chunk(index: usize, number_of_usize: usize, space: &mut MemorySpace) {
+    let chunk_size = OopHeaders::new(index, space).oop_size();
+    space.take_free_chunk(index);
+    free_chunk::write_free_words(index + number_of_usize, chunk_size - number_of_usize, space);
+}
+
+pub fn where_to_allocate(number_of_usize: usize, space: &mut MemorySpace) -> Option<usize> {
+    where_to_allocate_in(SegmentKind::Objects, number_of_usize, space)
+}
+
+// Only the free chunks of the segments of that kind are candidates
+pub fn where_to_allocate_in(
+    kind: SegmentKind,
+    number_of_usize: usize,
+    space: &mut MemorySpace,
+) -> Option<usize> {
+    // The strategy leaves the space while it walks it
+    let mut strategy = space.replace_allocation_strategy(Box::new(FirstFit));
+    let found = strategy.find_free_chunk(space, kind, number_of_usize);
+    space.replace_allocation_strategy(strategy);
+    if found.is_some() {
+        return found;
+    }
+    //We didn't find a proper place in memory to put that many usize,
+    // the space grows rather than collecting the garbage.
+    match kind {
+        SegmentKind::Objects => space.grow(number_of_usize),
+        SegmentKind::LargeObject => space.grow_for_large_object(number_of_usize),
+        SegmentKind::Pinned => space.grow_for_pinned(number_of_usize),
+    }
 }
 
 #[cfg(test)]
@@ -34,10 +262,13 @@ mod tests {
     #[test]
     fn test_allocate_object_that_fit_in_hole() {
         let mut space = MemorySpace::for_bit_size(240);
-        let builder = OopBuilder::new();
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(1);
         builder.build(&mut space);
         builder.build(&mut space);
         space.first_oop().become_free_oop();
+        // The hole is a candidate once the allocation buffer is retired
+        space.retire_allocation_buffer();
         let new_object = builder.build(&mut space);
 
         assert_eq!(new_object, space.get_start_index());
@@ -151,4 +382,151 @@ mod tests {
 
         assert!(iter.next(&mut space).unwrap().is_free_oop());
     }
+
+    // Objects of 6, 2, 4, 2, 3 and 2 words, the ones of 6, 4 and 3 words freed,
+    // then the free tail of 221 words
+    fn space_with_holes() -> (MemorySpace, Vec<usize>) {
+        let mut space = MemorySpace::for_bit_size(240);
+        let mut builder = OopBuilder::new();
+        let objects: Vec<usize> = [5, 1, 3, 1, 2, 1]
+            .into_iter()
+            .map(|number_of_slots| {
+                builder.set_number_of_slots(number_of_slots);
+                builder.build(&mut space)
+            })
+            .collect();
+        for index in [0, 2, 4] {
+            space.get_oop_at(objects[index]).become_free_oop();
+        }
+        space.retire_allocation_buffer();
+        (space, objects)
+    }
+
+    #[parameterized(name_and_offset = {
+        ("first-fit", 0),
+        ("next-fit", 0),
+        ("best-fit", 14),
+        ("worst-fit", 19),
+    })]
+    fn test_strategies_choose_their_chunk(name_and_offset: (&str, usize)) {
+        let (name, offset) = name_and_offset;
+        let (mut space, _) = space_with_holes();
+        space.replace_allocation_strategy(allocation_strategy_named(name).unwrap());
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(2);
+
+        assert_eq!(space.allocation_strategy_name(), name);
+        assert_eq!(builder.build(&mut space), space.get_start_index() + offset);
+    }
+
+    #[test]
+    fn test_next_fit_resumes_after_the_last_allocation_and_wraps_around() {
+        let mut space = MemorySpace::for_bit_size(240);
+        space.replace_allocation_strategy(Box::<NextFit>::default());
+        let start = space.get_start_index();
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(1);
+        let first = builder.build(&mut space);
+        builder.build(&mut space);
+        space.get_oop_at(first).become_free_oop();
+        // The hole is a candidate once the allocation buffer is retired
+        space.retire_allocation_buffer();
+
+        assert_eq!(builder.build(&mut space), start + 4);
+        builder.set_number_of_slots(233);
+        builder.build(&mut space);
+        builder.set_number_of_slots(1);
+        assert_eq!(builder.build(&mut space), first);
+        assert_eq!(space.segments().len(), 1);
+    }
+
+    #[test]
+    fn test_unknown_strategy() {
+        assert!(allocation_strategy_named("random-fit").is_none());
+        for name in ALLOCATION_STRATEGIES {
+            assert_eq!(allocation_strategy_named(name).unwrap().name(), name);
+        }
+    }
+
+    #[test]
+    fn test_free_space_census_reports_the_fragmentation() {
+        let (mut space, _) = space_with_holes();
+        let census = FreeSpaceCensus::of(&mut space, SegmentKind::Objects);
+
+        assert_eq!(
+            census,
+            FreeSpaceCensus {
+                free_chunks: 4,
+                free_words: 234,
+                largest_free_chunk: 221,
+            }
+        );
+        assert!((census.fragmentation() - 13.0 / 234.0).abs() < 1e-9);
+        assert_eq!(FreeSpaceCensus::default().fragmentation(), 0.0);
+        assert_eq!(
+            FreeSpaceCensus::of(&mut space, SegmentKind::Pinned),
+            FreeSpaceCensus::default()
+        );
+    }
+
+    #[test]
+    fn test_small_objects_are_bumped_into_the_buffer() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let start = space.get_start_index();
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(1);
+        builder.build(&mut space);
+
+        assert_eq!(builder.build(&mut space), start + 2);
+        assert_eq!(
+            space.allocation_buffer(),
+            AllocationBuffer {
+                next: start + 4,
+                limit: start + 240,
+            }
+        );
+        // The free chunk header is only written for the walk
+        assert_eq!(space[start + 4], 0);
+        let mut iter = space.iter();
+        iter.next(&mut space);
+        iter.next(&mut space);
+        assert_eq!(iter.next(&mut space).unwrap().oop_size(), 236);
+        assert_eq!(space.allocation_buffer(), AllocationBuffer::default());
+    }
+
+    #[test]
+    fn test_without_the_buffer_the_strategy_places_each_object() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let start = space.get_start_index();
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(1);
+        builder.build(&mut space);
+        space.set_uses_allocation_buffer(false);
+
+        assert_eq!(space.allocation_buffer(), AllocationBuffer::default());
+        assert_eq!(builder.build(&mut space), start + 2);
+        assert_eq!(space.allocation_buffer(), AllocationBuffer::default());
+        let free_chunk = space.get_oop_at(start + 4);
+        assert!(free_chunk.get_header().is_free_space());
+        assert_eq!(free_chunk.oop_size(), 236);
+    }
+
+    #[test]
+    fn test_too_small_buffer_falls_back_on_the_strategy() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let start = space.get_start_index();
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(1);
+        builder.build(&mut space);
+        builder.set_number_of_slots(236);
+        builder.build(&mut space);
+        builder.set_number_of_slots(1);
+        let in_new_segment = builder.build(&mut space);
+
+        assert_eq!(space.segments().len(), 2);
+        assert_eq!(in_new_segment, space.segments()[1].start());
+        let left_over = space.get_oop_at(start + 239);
+        assert!(left_over.get_header().is_filler());
+        assert_eq!(left_over.oop_size(), 1);
+    }
 }
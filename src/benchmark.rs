@@ -0,0 +1,501 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::allocator::{allocation_strategy_named, FreeSpaceCensus};
+use crate::garbage_collector::{garbage_collector_named, GarbageCollector};
+use crate::header_format_values::HeaderFormatValues;
+use crate::memory_space::{MemorySpace, SegmentKind};
+use crate::oop_builder::OopBuilder;
+use crate::oop_projections::oop_carcass::OopCarcass;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::slot_content::SlotContent;
+use crate::special_class_index::SpecialClassIndexes;
+
+// Reproducible workloads for the allocator and the collectors, on a bare memory space.
+// The workload allocates arrays and keeps the objects it holds on to in the roots, the collector
+// runs before the words allocated since the last collection pass the free words it left.
+// The same seed gives the same sequence of allocations and stores.
+pub const WORKLOADS: [&str; 5] = [
+    "binary-trees",
+    "linked-lists",
+    "random-graph",
+    "churn",
+    "large-arrays",
+];
+
+// All the objects are arrays, the collectors follow their slots
+const BENCHMARK_CLASS_INDEX: usize = SpecialClassIndexes::Array as usize;
+
+// The collector runs at least every that fraction of the heap, when the live objects fill it
+const MINIMUM_BUDGET_DIVISOR: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkConfiguration {
+    pub workload: String,
+    pub collector: String,
+    pub allocation_strategy: String,
+    // The words of the first segment, the space grows past them when the live objects need it
+    pub heap_words: usize,
+    // Multiplies the work of the workload
+    pub scale: usize,
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BenchmarkError {
+    UnknownWorkload(String),
+    UnknownCollector(String),
+    UnknownAllocationStrategy(String),
+}
+
+impl fmt::Display for BenchmarkError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BenchmarkError::UnknownWorkload(name) => write!(formatter, "unknown workload {}", name),
+            BenchmarkError::UnknownCollector(name) => {
+                write!(formatter, "unknown collector {}", name)
+            }
+            BenchmarkError::UnknownAllocationStrategy(name) => {
+                write!(formatter, "unknown allocation strategy {}", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BenchmarkError {}
+
+#[derive(Debug, Clone)]
+pub struct BenchmarkResult {
+    pub configuration: BenchmarkConfiguration,
+    pub allocations: usize,
+    pub allocated_words: usize,
+    pub total_time: Duration,
+    // One per collection, in order
+    pub pauses: Vec<Duration>,
+    pub final_heap_words: usize,
+}
+
+impl BenchmarkResult {
+    pub fn gc_time(&self) -> Duration {
+        self.pauses.iter().sum()
+    }
+
+    pub fn mutator_time(&self) -> Duration {
+        self.total_time.saturating_sub(self.gc_time())
+    }
+
+    // Outside of the collections
+    pub fn allocations_per_second(&self) -> f64 {
+        self.allocations as f64 / self.mutator_time().as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn max_pause(&self) -> Duration {
+        self.pauses.iter().max().copied().unwrap_or_default()
+    }
+
+    pub fn mean_pause(&self) -> Duration {
+        if self.pauses.is_empty() {
+            return Duration::ZERO;
+        }
+        self.gc_time() / self.pauses.len() as u32
+    }
+
+    pub fn median_pause(&self) -> Duration {
+        let mut pauses = self.pauses.clone();
+        pauses.sort();
+        pauses.get(pauses.len() / 2).copied().unwrap_or_default()
+    }
+
+    // One line of JSON, the times in milliseconds
+    pub fn as_json(&self) -> String {
+        let configuration = &self.configuration;
+        format!(
+            "{{\"workload\":\"{}\",\"collector\":\"{}\",\"allocation_strategy\":\"{}\",\
+             \"heap_words\":{},\"scale\":{},\"seed\":{},\"allocations\":{},\
+             \"allocated_words\":{},\"allocations_per_second\":{:.0},\"total_ms\":{:.3},\
+             \"mutator_ms\":{:.3},\"collections\":{},\"gc_total_ms\":{:.3},\
+             \"pause_max_ms\":{:.3},\"pause_mean_ms\":{:.3},\"pause_median_ms\":{:.3},\
+             \"final_heap_words\":{}}}",
+            configuration.workload,
+            configuration.collector,
+            configuration.allocation_strategy,
+            configuration.heap_words,
+            configuration.scale,
+            configuration.seed,
+            self.allocations,
+            self.allocated_words,
+            self.allocations_per_second(),
+            milliseconds(self.total_time),
+            milliseconds(self.mutator_time()),
+            self.pauses.len(),
+            milliseconds(self.gc_time()),
+            milliseconds(self.max_pause()),
+            milliseconds(self.mean_pause()),
+            milliseconds(self.median_pause()),
+            self.final_heap_words
+        )
+    }
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+// xorshift64*, good enough to pick sizes and slots
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn between(&mut self, low: usize, high: usize) -> usize {
+        low + self.below(high - low)
+    }
+}
+
+// Allocates for the workload and collects when the budget is spent.
+// The workload reads its objects back from the roots after each allocation, a moving collector
+// would have updated them.
+struct Mutator {
+    space: MemorySpace,
+    collector: Box<dyn GarbageCollector>,
+    roots: Vec<usize>,
+    random: Random,
+    heap_words: usize,
+    // The words to allocate before the next collection
+    budget: usize,
+    allocated_since_collection: usize,
+    allocations: usize,
+    allocated_words: usize,
+    pauses: Vec<Duration>,
+}
+
+impl Mutator {
+    fn allocate(&mut self, number_of_slots: usize) -> usize {
+        let mut carcass = OopCarcass::default();
+        carcass.set_number_of_slots(number_of_slots);
+        if self.allocated_since_collection + carcass.oop_size() > self.budget {
+            self.collect();
+        }
+        let mut builder = OopBuilder::new();
+        builder.set_class_index(BENCHMARK_CLASS_INDEX);
+        builder.set_format(HeaderFormatValues::IndexableWithoutSlotsFormat as usize);
+        builder.set_number_of_slots(number_of_slots);
+        builder.set_slots_value(SlotContent::small_integer(0));
+        let index = builder.build(&mut self.space);
+        self.allocations += 1;
+        self.allocated_words += carcass.oop_size();
+        self.allocated_since_collection += carcass.oop_size();
+        index
+    }
+
+    // Sizing the next budget is part of the pause, as a sweep would count the free words
+    fn collect(&mut self) {
+        let start = Instant::now();
+        self.collector.collect(&mut self.roots, &mut self.space);
+        let free_words = FreeSpaceCensus::of(&mut self.space, SegmentKind::Objects).free_words;
+        self.pauses.push(start.elapsed());
+        self.budget = free_words.max(self.heap_words / MINIMUM_BUDGET_DIVISOR);
+        self.allocated_since_collection = 0;
+    }
+
+    fn store(&mut self, object: usize, slot: usize, value: usize) {
+        self.space.get_oop_at(object).slot_at_index_put(slot, value);
+    }
+
+    fn fetch(&mut self, object: usize, slot: usize) -> usize {
+        self.space.get_oop_at(object).slot_at_index(slot)
+    }
+
+    fn push(&mut self, object: usize) {
+        self.roots.push(object);
+    }
+
+    fn pop(&mut self) -> usize {
+        self.roots.pop().unwrap()
+    }
+}
+
+// Perfect trees of two slot nodes, many short lived ones next to a long lived one
+fn binary_trees(mutator: &mut Mutator, scale: usize) {
+    const MINIMUM_DEPTH: usize = 4;
+    const MAXIMUM_DEPTH: usize = 10;
+    let long_lived = tree(mutator, MAXIMUM_DEPTH);
+    mutator.push(long_lived);
+    for _ in 0..scale {
+        for depth in (MINIMUM_DEPTH..=MAXIMUM_DEPTH).step_by(2) {
+            for _ in 0..1 << (MAXIMUM_DEPTH - depth + MINIMUM_DEPTH) {
+                tree(mutator, depth);
+            }
+        }
+    }
+    mutator.pop();
+}
+
+// The subtrees are roots while their parent is allocated
+fn tree(mutator: &mut Mutator, depth: usize) -> usize {
+    if depth == 0 {
+        return mutator.allocate(2);
+    }
+    let left = tree(mutator, depth - 1);
+    mutator.push(left);
+    let right = tree(mutator, depth - 1);
+    mutator.push(right);
+    let node = mutator.allocate(2);
+    let right = mutator.pop();
+    let left = mutator.pop();
+    mutator.store(node, 1, left);
+    mutator.store(node, 2, right);
+    node
+}
+
+// Lists built by consing onto their head, the last two stay alive
+fn linked_lists(mutator: &mut Mutator, scale: usize) {
+    const LIST_LENGTH: usize = 1000;
+    const LIVE_LISTS: usize = 2;
+    let first_root = mutator.roots.len();
+    for _ in 0..scale * 16 {
+        mutator.push(SlotContent::small_integer(0));
+        for value in 0..LIST_LENGTH {
+            let node = mutator.allocate(2);
+            let head = mutator.pop();
+            mutator.store(node, 1, SlotContent::small_integer(value as isize));
+            mutator.store(node, 2, head);
+            mutator.push(node);
+        }
+        if mutator.roots.len() - first_root > LIVE_LISTS {
+            mutator.roots.remove(first_root);
+        }
+    }
+    mutator.roots.truncate(first_root);
+}
+
+// Nodes of a few slots hanging from a table, random edges between them,
+// nodes replaced at random become garbage unless another one still references them
+fn random_graph(mutator: &mut Mutator, scale: usize) {
+    const NODES: usize = 500;
+    const MAXIMUM_NODE_SLOTS: usize = 8;
+    let table = mutator.allocate(NODES);
+    mutator.push(table);
+    for slot in 1..=NODES {
+        let number_of_slots = mutator.random.between(1, MAXIMUM_NODE_SLOTS + 1);
+        let node = mutator.allocate(number_of_slots);
+        let table = *mutator.roots.last().unwrap();
+        mutator.store(table, slot, node);
+    }
+    for _ in 0..scale * 20000 {
+        let from_slot = mutator.random.between(1, NODES + 1);
+        if mutator.random.below(10) < 6 {
+            let to_slot = mutator.random.between(1, NODES + 1);
+            let table = *mutator.roots.last().unwrap();
+            let from = mutator.fetch(table, from_slot);
+            let to = mutator.fetch(table, to_slot);
+            let number_of_slots = mutator.space.get_oop_at(from).number_of_slots();
+            let slot = mutator.random.between(1, number_of_slots + 1);
+            mutator.store(from, slot, to);
+        } else {
+            let number_of_slots = mutator.random.between(1, MAXIMUM_NODE_SLOTS + 1);
+            let node = mutator.allocate(number_of_slots);
+            let table = *mutator.roots.last().unwrap();
+            mutator.store(table, from_slot, node);
+        }
+    }
+    mutator.pop();
+}
+
+// Objects of mixed sizes replacing each other in a ring, a few of them large
+fn churn(mutator: &mut Mutator, scale: usize) {
+    const RING_SIZE: usize = 256;
+    let ring = mutator.allocate(RING_SIZE);
+    mutator.push(ring);
+    for _ in 0..scale * 10000 {
+        let number_of_slots = match mutator.random.below(100) {
+            0..=69 => mutator.random.below(8),
+            70..=89 => mutator.random.between(8, 64),
+            90..=98 => mutator.random.between(64, 254),
+            _ => mutator.random.between(300, 2000),
+        };
+        let object = mutator.allocate(number_of_slots);
+        let slot = mutator.random.between(1, RING_SIZE + 1);
+        let ring = *mutator.roots.last().unwrap();
+        mutator.store(ring, slot, object);
+    }
+    mutator.pop();
+}
+
+// Arrays in the large object space, the last few stay alive
+fn large_arrays(mutator: &mut Mutator, scale: usize) {
+    const LIVE_ARRAYS: usize = 4;
+    let table = mutator.allocate(LIVE_ARRAYS);
+    mutator.push(table);
+    for _ in 0..scale * 50 {
+        let number_of_slots = mutator.random.between(300, 4000);
+        let array = mutator.allocate(number_of_slots);
+        let slot = mutator.random.between(1, LIVE_ARRAYS + 1);
+        let table = *mutator.roots.last().unwrap();
+        mutator.store(table, slot, array);
+    }
+    mutator.pop();
+}
+
+fn run_workload(
+    configuration: &BenchmarkConfiguration,
+) -> Result<(BenchmarkResult, Mutator), BenchmarkError> {
+    let workload: fn(&mut Mutator, usize) = match configuration.workload.as_str() {
+        "binary-trees" => binary_trees,
+        "linked-lists" => linked_lists,
+        "random-graph" => random_graph,
+        "churn" => churn,
+        "large-arrays" => large_arrays,
+        _ => {
+            return Err(BenchmarkError::UnknownWorkload(
+                configuration.workload.clone(),
+            ))
+        }
+    };
+    let collector = garbage_collector_named(&configuration.collector)
+        .ok_or_else(|| BenchmarkError::UnknownCollector(configuration.collector.clone()))?;
+    let allocation_strategy = allocation_strategy_named(&configuration.allocation_strategy)
+        .ok_or_else(|| {
+            BenchmarkError::UnknownAllocationStrategy(configuration.allocation_strategy.clone())
+        })?;
+
+    let mut space = MemorySpace::for_bit_size(configuration.heap_words);
+    space.replace_allocation_strategy(allocation_strategy);
+    let mut mutator = Mutator {
+        space,
+        collector,
+        roots: Vec::new(),
+        random: Random::new(configuration.seed),
+        heap_words: configuration.heap_words,
+        budget: configuration.heap_words,
+        allocated_since_collection: 0,
+        allocations: 0,
+        allocated_words: 0,
+        pauses: Vec::new(),
+    };
+
+    let start = Instant::now();
+    workload(&mut mutator, configuration.scale);
+    let total_time = start.elapsed();
+
+    let result = BenchmarkResult {
+        configuration: configuration.clone(),
+        allocations: mutator.allocations,
+        allocated_words: mutator.allocated_words,
+        total_time,
+        pauses: mutator.pauses.clone(),
+        final_heap_words: mutator.space.number_of_words(),
+    };
+    Ok((result, mutator))
+}
+
+pub fn run_benchmark(
+    configuration: &BenchmarkConfiguration,
+) -> Result<BenchmarkResult, BenchmarkError> {
+    run_workload(configuration).map(|(result, _)| result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::benchmark::{
+        run_benchmark, run_workload, BenchmarkConfiguration, BenchmarkError, BENCHMARK_CLASS_INDEX,
+        WORKLOADS,
+    };
+    use crate::heap_verifier::verify_space;
+
+    fn configuration(workload: &str, heap_words: usize) -> BenchmarkConfiguration {
+        BenchmarkConfiguration {
+            workload: workload.to_string(),
+            collector: String::from("simple"),
+            allocation_strategy: String::from("first-fit"),
+            heap_words,
+            scale: 1,
+            seed: 42,
+        }
+    }
+
+    #[parameterized(workload = {
+        "binary-trees", "linked-lists", "random-graph", "churn", "large-arrays"
+    })]
+    fn test_workloads_leave_a_consistent_heap(workload: &str) {
+        let (result, mut mutator) = run_workload(&configuration(workload, 1024)).unwrap();
+
+        assert!(result.allocations > 0);
+        assert!(!result.pauses.is_empty());
+        assert!(result.gc_time() <= result.total_time);
+        assert!(mutator.roots.is_empty());
+        let roots = mutator.roots.clone();
+        assert_eq!(
+            verify_space(
+                &mut mutator.space,
+                &|class_index| class_index == BENCHMARK_CLASS_INDEX,
+                &roots
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_the_same_seed_gives_the_same_work() {
+        let first = run_benchmark(&configuration("churn", 4096)).unwrap();
+        let second = run_benchmark(&configuration("churn", 4096)).unwrap();
+        let mut other_seed = configuration("churn", 4096);
+        other_seed.seed = 7;
+        let third = run_benchmark(&other_seed).unwrap();
+
+        assert_eq!(first.allocated_words, second.allocated_words);
+        assert_eq!(first.pauses.len(), second.pauses.len());
+        assert_ne!(first.allocated_words, third.allocated_words);
+    }
+
+    #[test]
+    fn test_a_bigger_heap_collects_less_often() {
+        let small = run_benchmark(&configuration("linked-lists", 4096)).unwrap();
+        let big = run_benchmark(&configuration("linked-lists", 65536)).unwrap();
+
+        assert_eq!(small.allocations, big.allocations);
+        assert!(big.pauses.len() < small.pauses.len());
+    }
+
+    #[test]
+    fn test_results_are_json_lines() {
+        let result = run_benchmark(&configuration(WORKLOADS[0], 4096)).unwrap();
+        let json = result.as_json();
+
+        assert!(json.starts_with("{\"workload\":\"binary-trees\",\"collector\":\"simple\","));
+        assert!(json.contains(&format!("\"collections\":{},", result.pauses.len())));
+        assert!(json.ends_with(&format!(
+            "\"final_heap_words\":{}}}",
+            result.final_heap_words
+        )));
+        assert!(!json.contains('\n'));
+    }
+
+    #[test]
+    fn test_unknown_names_are_reported() {
+        let mut unknown_collector = configuration("churn", 4096);
+        unknown_collector.collector = String::from("copying");
+
+        assert_eq!(
+            run_benchmark(&configuration("fibonacci", 4096)).unwrap_err(),
+            BenchmarkError::UnknownWorkload(String::from("fibonacci"))
+        );
+        assert_eq!(
+            run_benchmark(&unknown_collector).unwrap_err().to_string(),
+            "unknown collector copying"
+        );
+    }
+}
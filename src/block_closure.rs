@@ -0,0 +1,9 @@
+// BlockClosure slots, 1 based like the oop slots.
+// The values copied from the outer context follow the fixed slots.
+pub mod block_closure_constants {
+    pub const OUTER_CONTEXT_INDEX: usize = 1;
+    pub const COMPILED_BLOCK_INDEX: usize = 2;
+    pub const NUMBER_OF_ARGUMENTS_INDEX: usize = 3;
+    pub const RECEIVER_INDEX: usize = 4;
+    pub const NUMBER_OF_FIXED_SLOTS: usize = 4;
+}
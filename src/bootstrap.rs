@@ -0,0 +1,706 @@
+use crate::bytecodes::SPECIAL_SELECTORS;
+use crate::class_table::{class_constants, ClassFormat, ClassTable};
+use crate::compiler::file_in;
+use crate::header_format_values::HeaderFormatValues;
+use crate::interpreter::Interpreter;
+use crate::memory_space::MemorySpace;
+use crate::oop_builder::OopBuilder;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::slot_content::SlotContent;
+use crate::special_class_index::SpecialClassIndexes;
+use crate::special_object_index::SpecialObjectIndexes;
+use crate::symbol_table;
+
+struct KernelClass {
+    name: &'static str,
+    superclass_name: Option<&'static str>,
+    // None to get the first free index of the class table
+    class_index: Option<usize>,
+    special_object_index: Option<usize>,
+    instance_specification: usize,
+    instance_variables: &'static [&'static str],
+}
+
+const CLASS_INSTANCE_VARIABLES: &[&str] = &[
+    "superclass",
+    "methodDict",
+    "format",
+    "instanceVariables",
+    "name",
+];
+
+const METACLASS_INSTANCE_VARIABLES: &[&str] = &[
+    "superclass",
+    "methodDict",
+    "format",
+    "instanceVariables",
+    "thisClass",
+];
+
+const KERNEL_CLASSES: &[KernelClass] = &[
+    KernelClass {
+        name: "Object",
+        superclass_name: None,
+        class_index: None,
+        special_object_index: None,
+        instance_specification: HeaderFormatValues::ZeroSizedFormat as usize,
+        instance_variables: &[],
+    },
+    KernelClass {
+        name: "UndefinedObject",
+        superclass_name: Some("Object"),
+        class_index: Some(SpecialClassIndexes::UndefinedObject as usize),
+        special_object_index: None,
+        instance_specification: HeaderFormatValues::ZeroSizedFormat as usize,
+        instance_variables: &[],
+    },
+    KernelClass {
+        name: "True",
+        superclass_name: Some("Object"),
+        class_index: Some(SpecialClassIndexes::True as usize),
+        special_object_index: None,
+        instance_specification: HeaderFormatValues::ZeroSizedFormat as usize,
+        instance_variables: &[],
+    },
+    KernelClass {
+        name: "False",
+        superclass_name: Some("Object"),
+        class_index: Some(SpecialClassIndexes::False as usize),
+        special_object_index: None,
+        instance_specification: HeaderFormatValues::ZeroSizedFormat as usize,
+        instance_variables: &[],
+    },
+    KernelClass {
+        name: "Class",
+        superclass_name: Some("Object"),
+        class_index: None,
+        special_object_index: None,
+        instance_specification: HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+        instance_variables: CLASS_INSTANCE_VARIABLES,
+    },
+    KernelClass {
+        name: "Metaclass",
+        superclass_name: Some("Object"),
+        class_index: None,
+        special_object_index: None,
+        instance_specification: HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+        instance_variables: METACLASS_INSTANCE_VARIABLES,
+    },
+    KernelClass {
+        name: "SmallInteger",
+        superclass_name: Some("Object"),
+        class_index: Some(SpecialClassIndexes::SmallInteger as usize),
+        special_object_index: Some(SpecialObjectIndexes::ClassSmallInteger as usize),
+        instance_specification: HeaderFormatValues::ImmediateFormat as usize,
+        instance_variables: &[],
+    },
+    KernelClass {
+        name: "Character",
+        superclass_name: Some("Object"),
+        class_index: Some(SpecialClassIndexes::Character as usize),
+        special_object_index: Some(SpecialObjectIndexes::ClassCharacter as usize),
+        instance_specification: HeaderFormatValues::ImmediateFormat as usize,
+        instance_variables: &[],
+    },
+    KernelClass {
+        name: "Array",
+        superclass_name: Some("Object"),
+        class_index: Some(SpecialClassIndexes::Array as usize),
+        special_object_index: Some(SpecialObjectIndexes::ClassArray as usize),
+        instance_specification: HeaderFormatValues::IndexableWithoutSlotsFormat as usize,
+        instance_variables: &[],
+    },
+    KernelClass {
+        name: "ByteString",
+        superclass_name: Some("Object"),
+        class_index: Some(SpecialClassIndexes::ByteString as usize),
+        special_object_index: Some(SpecialObjectIndexes::ClassByteString as usize),
+        instance_specification: HeaderFormatValues::I8BitIndexable as usize,
+        instance_variables: &[],
+    },
+    KernelClass {
+        name: "Symbol",
+        superclass_name: Some("ByteString"),
+        class_index: Some(SpecialClassIndexes::Symbol as usize),
+        special_object_index: Some(SpecialObjectIndexes::ClassSymbol as usize),
+        instance_specification: HeaderFormatValues::I8BitIndexable as usize,
+        instance_variables: &[],
+    },
+    KernelClass {
+        name: "WeakSet",
+        superclass_name: Some("Object"),
+        class_index: None,
+        special_object_index: Some(SpecialObjectIndexes::ClassWeakSet as usize),
+        instance_specification: HeaderFormatValues::WeakIndexableWithSlotsFormat as usize,
+        instance_variables: &["tally"],
+    },
+    KernelClass {
+        name: "CompiledMethod",
+        superclass_name: Some("Object"),
+        class_index: Some(SpecialClassIndexes::CompiledMethod as usize),
+        special_object_index: Some(SpecialObjectIndexes::ClassCompiledMethod as usize),
+        instance_specification: HeaderFormatValues::CompiledMethodFormat as usize,
+        instance_variables: &[],
+    },
+    KernelClass {
+        name: "MethodDictionary",
+        superclass_name: Some("Object"),
+        class_index: Some(SpecialClassIndexes::MethodDictionary as usize),
+        special_object_index: Some(SpecialObjectIndexes::ClassMethodDictionary as usize),
+        instance_specification: HeaderFormatValues::IndexableWithSlotsFormat as usize,
+        instance_variables: &["tally", "array"],
+    },
+    KernelClass {
+        name: "Context",
+        superclass_name: Some("Object"),
+        class_index: Some(SpecialClassIndexes::Context as usize),
+        special_object_index: Some(SpecialObjectIndexes::ClassContext as usize),
+        instance_specification: HeaderFormatValues::IndexableWithSlotsFormat as usize,
+        instance_variables: &[
+            "sender",
+            "pc",
+            "stackp",
+            "method",
+            "closureOrNil",
+            "receiver",
+        ],
+    },
+    KernelClass {
+        name: "BlockClosure",
+        superclass_name: Some("Object"),
+        class_index: None,
+        special_object_index: Some(SpecialObjectIndexes::ClassBlockClosure as usize),
+        instance_specification: HeaderFormatValues::IndexableWithSlotsFormat as usize,
+        instance_variables: &["outerContext", "compiledBlock", "numArgs", "receiver"],
+    },
+    KernelClass {
+        name: "CompiledBlock",
+        superclass_name: Some("Object"),
+        class_index: None,
+        special_object_index: Some(SpecialObjectIndexes::ClassCompiledBlock as usize),
+        instance_specification: HeaderFormatValues::CompiledMethodFormat as usize,
+        instance_variables: &[],
+    },
+    KernelClass {
+        name: "Message",
+        superclass_name: Some("Object"),
+        class_index: None,
+        special_object_index: Some(SpecialObjectIndexes::ClassMessage as usize),
+        instance_specification: HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+        instance_variables: &["selector", "arguments"],
+    },
+    KernelClass {
+        name: "Exception",
+        superclass_name: Some("Object"),
+        class_index: None,
+        special_object_index: None,
+        instance_specification: HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+        instance_variables: &[
+            "messageText",
+            "signalContext",
+            "handlerContext",
+            "outerContext",
+        ],
+    },
+    KernelClass {
+        name: "Error",
+        superclass_name: Some("Exception"),
+        class_index: None,
+        special_object_index: None,
+        instance_specification: HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+        instance_variables: &[],
+    },
+    KernelClass {
+        name: "Notification",
+        superclass_name: Some("Exception"),
+        class_index: None,
+        special_object_index: None,
+        instance_specification: HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+        instance_variables: &[],
+    },
+    KernelClass {
+        name: "ZeroDivide",
+        superclass_name: Some("Error"),
+        class_index: None,
+        special_object_index: None,
+        instance_specification: HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+        instance_variables: &[],
+    },
+    KernelClass {
+        name: "MessageNotUnderstood",
+        superclass_name: Some("Error"),
+        class_index: None,
+        special_object_index: None,
+        instance_specification: HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+        instance_variables: &["message", "receiver"],
+    },
+    KernelClass {
+        name: "ModificationForbidden",
+        superclass_name: Some("Error"),
+        class_index: None,
+        special_object_index: None,
+        instance_specification: HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+        instance_variables: &["object", "fieldIndex", "newValue", "retrySelector"],
+    },
+    KernelClass {
+        name: "BlockCannotReturn",
+        superclass_name: Some("Error"),
+        class_index: None,
+        special_object_index: None,
+        instance_specification: HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+        instance_variables: &["result"],
+    },
+];
+
+fn kernel_class_position(name: &str) -> usize {
+    KERNEL_CLASSES
+        .iter()
+        .position(|kernel_class| kernel_class.name == name)
+        .unwrap()
+}
+
+// The instances get the slots of the superclasses, then the new instance variables
+fn number_of_fixed_slots_of(kernel_class: &KernelClass) -> usize {
+    let inherited_slots = match kernel_class.superclass_name {
+        Some(superclass_name) => {
+            number_of_fixed_slots_of(&KERNEL_CLASSES[kernel_class_position(superclass_name)])
+        }
+        None => 0,
+    };
+    inherited_slots + kernel_class.instance_variables.len()
+}
+
+fn set_class_index_of(oop: usize, class_index: usize, space: &mut MemorySpace) {
+    let mut an_oop = space.get_oop_at(oop);
+    an_oop.get_header_mut().set_class_index_bits(class_index);
+    an_oop.apply_header();
+}
+
+fn new_array_of(interpreter: &mut Interpreter, elements: &[usize]) -> usize {
+    let array_class = interpreter.special_object(SpecialObjectIndexes::ClassArray);
+    let array = interpreter
+        .instantiate_class(array_class, elements.len())
+        .unwrap();
+    let mut array_oop = interpreter.space.get_oop_at(array);
+    for (index, element) in elements.iter().enumerate() {
+        array_oop.slot_at_index_put(index + 1, *element);
+    }
+    array
+}
+
+// The objects the interpreter cannot live without.
+// The special objects array is itself an Array, its class is set once Array exists.
+fn new_special_objects(space: &mut MemorySpace) -> usize {
+    let mut builder = OopBuilder::new();
+    builder.set_class_index(SpecialClassIndexes::UndefinedObject as usize);
+    let nil = builder.build(space);
+    builder.set_class_index(SpecialClassIndexes::False as usize);
+    let false_object = builder.build(space);
+    builder.set_class_index(SpecialClassIndexes::True as usize);
+    let true_object = builder.build(space);
+
+    builder.reset();
+    builder.set_class_index(SpecialClassIndexes::Array as usize);
+    builder.set_format(HeaderFormatValues::IndexableWithoutSlotsFormat as usize);
+    builder.set_number_of_slots(SpecialObjectIndexes::NUMBER_OF_SPECIAL_OBJECTS);
+    builder.set_slots_value(nil);
+    let special_objects = builder.build(space);
+
+    let mut special_objects_oop = space.get_oop_at(special_objects);
+    special_objects_oop.slot_at_index_put(SpecialObjectIndexes::Nil as usize, nil);
+    special_objects_oop.slot_at_index_put(SpecialObjectIndexes::False as usize, false_object);
+    special_objects_oop.slot_at_index_put(SpecialObjectIndexes::True as usize, true_object);
+    special_objects
+}
+
+fn new_class_object(space: &mut MemorySpace, nil: usize) -> usize {
+    let mut builder = OopBuilder::new();
+    builder.set_format(HeaderFormatValues::NonIndexableWithSlotsFormat as usize);
+    builder.set_number_of_slots(class_constants::NUMBER_OF_FIXED_SLOTS);
+    builder.set_slots_value(nil);
+    builder.build(space)
+}
+
+// Answers the class and its metaclass, registered in the class table.
+// The metaclass is left without a class, Metaclass may not exist yet.
+fn new_class_and_metaclass(
+    interpreter: &mut Interpreter,
+    class_index: Option<usize>,
+) -> (usize, usize) {
+    let nil = interpreter.nil_object();
+    let class = new_class_object(&mut interpreter.space, nil);
+    let metaclass = new_class_object(&mut interpreter.space, nil);
+    match class_index {
+        Some(class_index) => {
+            interpreter
+                .class_table
+                .register_at(class_index, class, &mut interpreter.space)
+        }
+        None => {
+            interpreter
+                .class_table
+                .register(class, &mut interpreter.space);
+        }
+    }
+    let metaclass_index = interpreter
+        .class_table
+        .register(metaclass, &mut interpreter.space);
+    set_class_index_of(class, metaclass_index, &mut interpreter.space);
+    (class, metaclass)
+}
+
+fn initialize_class(
+    interpreter: &mut Interpreter,
+    (class, metaclass): (usize, usize),
+    (superclass, meta_superclass): (usize, usize),
+    format: ClassFormat,
+) {
+    let meta_format = ClassFormat::new(
+        HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+        class_constants::NUMBER_OF_FIXED_SLOTS,
+    );
+    let mut class_oop = interpreter.space.get_oop_at(class);
+    class_oop.slot_at_index_put(class_constants::SUPERCLASS_INDEX, superclass);
+    class_oop.slot_at_index_put(class_constants::FORMAT_INDEX, format.as_slot_value());
+    let mut metaclass_oop = interpreter.space.get_oop_at(metaclass);
+    metaclass_oop.slot_at_index_put(class_constants::SUPERCLASS_INDEX, meta_superclass);
+    metaclass_oop.slot_at_index_put(class_constants::FORMAT_INDEX, meta_format.as_slot_value());
+    metaclass_oop.slot_at_index_put(class_constants::THIS_CLASS_INDEX, class);
+}
+
+// Classes only list the instance variables they add to their superclass.
+// Metaclasses add none, the class instance variables all come from Class.
+fn name_class(
+    interpreter: &mut Interpreter,
+    (class, metaclass): (usize, usize),
+    name: &str,
+    instance_variables: &[&str],
+) {
+    let name = symbol_table::intern(interpreter, name);
+    let instance_variables: Vec<usize> = instance_variables
+        .iter()
+        .map(|instance_variable| symbol_table::intern(interpreter, instance_variable))
+        .collect();
+    let instance_variables = new_array_of(interpreter, &instance_variables);
+    let meta_instance_variables = new_array_of(interpreter, &[]);
+
+    let mut class_oop = interpreter.space.get_oop_at(class);
+    class_oop.slot_at_index_put(class_constants::NAME_INDEX, name);
+    class_oop.slot_at_index_put(
+        class_constants::INSTANCE_VARIABLES_INDEX,
+        instance_variables,
+    );
+    interpreter.space.get_oop_at(metaclass).slot_at_index_put(
+        class_constants::INSTANCE_VARIABLES_INDEX,
+        meta_instance_variables,
+    );
+}
+
+fn new_special_selectors(interpreter: &mut Interpreter) -> usize {
+    let mut elements: Vec<usize> = Vec::new();
+    for (selector, number_of_arguments) in SPECIAL_SELECTORS {
+        elements.push(symbol_table::intern(interpreter, selector));
+        elements.push(SlotContent::small_integer(number_of_arguments as isize));
+    }
+    new_array_of(interpreter, &elements)
+}
+
+// Creates a class, and its metaclass, in a bootstrapped space.
+// The instances get the slots of the superclass, then the new instance variables.
+pub fn define_class(
+    interpreter: &mut Interpreter,
+    name: &str,
+    superclass: usize,
+    instance_specification: usize,
+    instance_variables: &[&str],
+) -> usize {
+    let metaclass_class = interpreter
+        .class_named("Metaclass")
+        .expect("Metaclass should be bootstrapped");
+    let metaclass_class_index = interpreter
+        .space
+        .get_oop_at(metaclass_class)
+        .get_header()
+        .hash_bits();
+    let meta_superclass = interpreter.class_of(superclass);
+    let inherited_slots = interpreter
+        .class_format_of(superclass)
+        .number_of_fixed_slots();
+
+    let (class, metaclass) = new_class_and_metaclass(interpreter, None);
+    set_class_index_of(metaclass, metaclass_class_index, &mut interpreter.space);
+    initialize_class(
+        interpreter,
+        (class, metaclass),
+        (superclass, meta_superclass),
+        ClassFormat::new(
+            instance_specification,
+            inherited_slots + instance_variables.len(),
+        ),
+    );
+    name_class(interpreter, (class, metaclass), name, instance_variables);
+    class
+}
+
+// Builds a fresh memory space with nil, true, false, the kernel classes and the symbol table,
+// then files in the kernel methods.
+pub fn bootstrap(memory_space_size: usize) -> Interpreter {
+    bootstrap_in(MemorySpace::for_bit_size(memory_space_size))
+}
+
+// Same, in an empty memory space of any backend
+pub fn bootstrap_in(mut space: MemorySpace) -> Interpreter {
+    let special_objects = new_special_objects(&mut space);
+    let mut interpreter = Interpreter::new(space, special_objects, ClassTable::new());
+    let nil = interpreter.nil_object();
+
+    // Every class comes with its metaclass, which is the only one to get a free index
+    let classes_and_metaclasses: Vec<(usize, usize)> = KERNEL_CLASSES
+        .iter()
+        .map(|kernel_class| new_class_and_metaclass(&mut interpreter, kernel_class.class_index))
+        .collect();
+
+    let (metaclass_class, _) = classes_and_metaclasses[kernel_class_position("Metaclass")];
+    let metaclass_class_index = interpreter
+        .space
+        .get_oop_at(metaclass_class)
+        .get_header()
+        .hash_bits();
+    let (class_class, _) = classes_and_metaclasses[kernel_class_position("Class")];
+    let special_objects_oop = interpreter.special_objects();
+
+    for (position, kernel_class) in KERNEL_CLASSES.iter().enumerate() {
+        let (class, metaclass) = classes_and_metaclasses[position];
+        set_class_index_of(metaclass, metaclass_class_index, &mut interpreter.space);
+
+        // Object class inherits from Class, which closes the loop
+        let superclasses = match kernel_class.superclass_name {
+            Some(superclass_name) => {
+                classes_and_metaclasses[kernel_class_position(superclass_name)]
+            }
+            None => (nil, class_class),
+        };
+        let format = ClassFormat::new(
+            kernel_class.instance_specification,
+            number_of_fixed_slots_of(kernel_class),
+        );
+        initialize_class(&mut interpreter, (class, metaclass), superclasses, format);
+
+        if let Some(special_object_index) = kernel_class.special_object_index {
+            interpreter
+                .space
+                .get_oop_at(special_objects_oop)
+                .slot_at_index_put(special_object_index, class);
+        }
+    }
+
+    // Names need symbols, which need the classes
+    let symbol_table = symbol_table::new_symbol_table(&mut interpreter, symbol_table::INITIAL_SIZE);
+    interpreter
+        .space
+        .get_oop_at(special_objects_oop)
+        .slot_at_index_put(SpecialObjectIndexes::SymbolTable as usize, symbol_table);
+    for (position, kernel_class) in KERNEL_CLASSES.iter().enumerate() {
+        name_class(
+            &mut interpreter,
+            classes_and_metaclasses[position],
+            kernel_class.name,
+            kernel_class.instance_variables,
+        );
+    }
+
+    let special_selectors = new_special_selectors(&mut interpreter);
+    interpreter
+        .space
+        .get_oop_at(special_objects_oop)
+        .slot_at_index_put(
+            SpecialObjectIndexes::SpecialSelectors as usize,
+            special_selectors,
+        );
+    for (index, selector) in [
+        (SpecialObjectIndexes::SelectorCannotReturn, "cannotReturn:"),
+        (
+            SpecialObjectIndexes::SelectorDoesNotUnderstand,
+            "doesNotUnderstand:",
+        ),
+        (SpecialObjectIndexes::SelectorMustBeBoolean, "mustBeBoolean"),
+        (
+            SpecialObjectIndexes::SelectorAttemptToAssign,
+            "attemptToAssign:withIndex:",
+        ),
+    ] {
+        let selector = symbol_table::intern(&mut interpreter, selector);
+        interpreter
+            .space
+            .get_oop_at(special_objects_oop)
+            .slot_at_index_put(index as usize, selector);
+    }
+
+    if let Err(error) = file_in::file_in(&mut interpreter, file_in::KERNEL_SOURCE) {
+        panic!("The kernel does not compile: {}", error)
+    }
+    interpreter
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::bootstrap;
+    use crate::class_table::class_constants;
+    use crate::header_format_values::HeaderFormatValues;
+    use crate::oop_projections::oop_common::OopCommonState;
+    use crate::slot_content::SlotContent;
+    use crate::special_object_index::SpecialObjectIndexes;
+
+    #[test]
+    fn test_nil_true_false_are_distinct() {
+        let mut interpreter = bootstrap(20000);
+        let nil = interpreter.nil_object();
+        let true_object = interpreter.true_object();
+        let false_object = interpreter.false_object();
+
+        assert_ne!(nil, true_object);
+        assert_ne!(nil, false_object);
+        assert_ne!(true_object, false_object);
+    }
+
+    #[parameterized(name={ "UndefinedObject", "True", "False" }, special_object={ 1, 3, 2 })]
+    fn test_class_of_special_objects(name: &str, special_object: usize) {
+        let mut interpreter = bootstrap(20000);
+        let special_objects = interpreter.special_objects();
+        let object = interpreter
+            .space
+            .get_oop_at(special_objects)
+            .slot_at_index(special_object);
+
+        let class = interpreter.class_of(object);
+        assert_eq!(interpreter.class_named(name), Some(class));
+    }
+
+    #[test]
+    fn test_special_objects_array_is_an_array() {
+        let mut interpreter = bootstrap(20000);
+        let special_objects = interpreter.special_objects();
+        assert_eq!(
+            interpreter.class_of(special_objects),
+            interpreter.special_object(SpecialObjectIndexes::ClassArray)
+        );
+    }
+
+    #[test]
+    fn test_class_of_small_integer() {
+        let mut interpreter = bootstrap(20000);
+        let small_integer = SlotContent::small_integer(3);
+        let class = interpreter.class_of(small_integer);
+        assert_eq!(interpreter.class_named("SmallInteger"), Some(class));
+    }
+
+    #[test]
+    fn test_metaclass_loop() {
+        let mut interpreter = bootstrap(20000);
+        let metaclass = interpreter.class_named("Metaclass").unwrap();
+        let object = interpreter.class_named("Object").unwrap();
+        let object_class = interpreter.class_of(object);
+
+        assert_eq!(interpreter.class_of(object_class), metaclass);
+        let metaclass_class = interpreter.class_of(metaclass);
+        assert_eq!(interpreter.class_of(metaclass_class), metaclass);
+        assert_eq!(
+            interpreter
+                .space
+                .get_oop_at(object_class)
+                .slot_at_index(class_constants::THIS_CLASS_INDEX),
+            object
+        );
+    }
+
+    #[test]
+    fn test_superclass_chain() {
+        let mut interpreter = bootstrap(20000);
+        let nil = interpreter.nil_object();
+        let symbol = interpreter.class_named("Symbol").unwrap();
+        let byte_string = interpreter.class_named("ByteString").unwrap();
+        let object = interpreter.class_named("Object").unwrap();
+        let class = interpreter.class_named("Class").unwrap();
+
+        let superclass_of = |interpreter: &mut crate::interpreter::Interpreter, class: usize| {
+            interpreter
+                .space
+                .get_oop_at(class)
+                .slot_at_index(class_constants::SUPERCLASS_INDEX)
+        };
+        assert_eq!(superclass_of(&mut interpreter, symbol), byte_string);
+        assert_eq!(superclass_of(&mut interpreter, byte_string), object);
+        assert_eq!(superclass_of(&mut interpreter, object), nil);
+        let object_class = interpreter.class_of(object);
+        assert_eq!(superclass_of(&mut interpreter, object_class), class);
+    }
+
+    #[test]
+    fn test_instantiate_array() {
+        let mut interpreter = bootstrap(20000);
+        let array_class = interpreter.special_object(SpecialObjectIndexes::ClassArray);
+        let array = interpreter.instantiate_class(array_class, 4).unwrap();
+
+        assert_eq!(interpreter.class_of(array), array_class);
+        assert_eq!(
+            interpreter
+                .space
+                .get_oop_at(array)
+                .get_header()
+                .format_bits(),
+            HeaderFormatValues::IndexableWithoutSlotsFormat as usize
+        );
+    }
+
+    #[test]
+    fn test_context_class_describes_contexts() {
+        let mut interpreter = bootstrap(20000);
+        let context_class = interpreter.special_object(SpecialObjectIndexes::ClassContext);
+        assert_eq!(
+            interpreter
+                .class_format_of(context_class)
+                .number_of_fixed_slots(),
+            6
+        );
+    }
+
+    #[test]
+    fn test_bootstrapped_space_survives_garbage_collection() {
+        let mut interpreter = bootstrap(20000);
+        interpreter.collect_garbage();
+
+        assert!(interpreter.class_named("MethodDictionary").is_some());
+        assert_eq!(
+            crate::symbol_table::lookup(&mut interpreter, "Context"),
+            interpreter.class_named("Context").map(|class| interpreter
+                .space
+                .get_oop_at(class)
+                .slot_at_index(class_constants::NAME_INDEX))
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_bootstrap_in_a_mapped_space() {
+        use crate::bootstrap::bootstrap_in;
+        use crate::compiler::evaluate;
+        use crate::heap_verifier::verify_heap;
+        use crate::memory_space::MemorySpace;
+
+        let space = MemorySpace::mapped(1 << 22, 40000).unwrap();
+        let mut interpreter = bootstrap_in(space);
+        let nil = interpreter.nil_object();
+        assert_eq!(
+            interpreter.space.address_of(nil) as usize,
+            nil * std::mem::size_of::<usize>()
+        );
+
+        let segments = interpreter.space.segments().len();
+        let value = evaluate(&mut interpreter, "(Array new: 100000) size + 1").unwrap();
+        assert_eq!(value, SlotContent::small_integer(100001));
+        assert_eq!(interpreter.space.segments().len(), segments + 1);
+        interpreter.collect_garbage();
+        assert_eq!(interpreter.space.segments().len(), segments);
+        assert_eq!(verify_heap(&mut interpreter), vec![]);
+    }
+}
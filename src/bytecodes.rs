@@ -0,0 +1,125 @@
+// The Squeak V3 bytecode set, or the part of it the compiler emits.
+// Ranges give the first bytecode, the operand is added to it.
+pub mod bytecode_constants {
+    // 0-15, 16-31, 32-63
+    pub const PUSH_RECEIVER_VARIABLE: u8 = 0;
+    pub const PUSH_TEMPORARY: u8 = 16;
+    pub const PUSH_LITERAL_CONSTANT: u8 = 32;
+    // 64 to 95 push literal variables, unused while there are no globals
+
+    // 96-103, 104-111
+    pub const POP_STORE_RECEIVER_VARIABLE: u8 = 96;
+    pub const POP_STORE_TEMPORARY: u8 = 104;
+
+    pub const PUSH_RECEIVER: u8 = 112;
+    pub const PUSH_TRUE: u8 = 113;
+    pub const PUSH_FALSE: u8 = 114;
+    pub const PUSH_NIL: u8 = 115;
+    // 116-119 push -1, 0, 1 and 2
+    pub const PUSH_MINUS_ONE: u8 = 116;
+
+    pub const RETURN_RECEIVER: u8 = 120;
+    pub const RETURN_TRUE: u8 = 121;
+    pub const RETURN_FALSE: u8 = 122;
+    pub const RETURN_NIL: u8 = 123;
+    pub const RETURN_TOP: u8 = 124;
+    // Answers the top of the stack to the caller of the closure
+    pub const BLOCK_RETURN_TOP: u8 = 125;
+
+    // Followed by a descriptor byte: the variable type in the 2 high bits, the index in the others
+    pub const EXTENDED_PUSH: u8 = 128;
+    pub const EXTENDED_STORE: u8 = 129;
+    pub const EXTENDED_POP_STORE: u8 = 130;
+    // Followed by a byte: the number of arguments in the 3 high bits, the literal in the others
+    pub const SINGLE_EXTENDED_SEND: u8 = 131;
+    // Followed by the operation and the number of arguments, then the literal index
+    pub const DOUBLE_EXTENDED: u8 = 132;
+    pub const SINGLE_EXTENDED_SUPER: u8 = 133;
+
+    pub const POP: u8 = 135;
+    pub const DUPLICATE: u8 = 136;
+    pub const PUSH_THIS_CONTEXT: u8 = 137;
+    // Followed by a byte: pop the elements from the stack in the high bit, the size in the others
+    pub const PUSH_NEW_ARRAY: u8 = 138;
+
+    // Followed by the index in the temp vector, then the temporary holding the vector
+    pub const PUSH_REMOTE_TEMPORARY: u8 = 140;
+    pub const STORE_REMOTE_TEMPORARY: u8 = 141;
+    pub const POP_STORE_REMOTE_TEMPORARY: u8 = 142;
+    // Followed by the literal index of the CompiledBlock, then the number of copied values
+    pub const PUSH_FULL_CLOSURE: u8 = 143;
+
+    // 144-151 and 152-159, jump 1 to 8 bytes forward
+    pub const SHORT_JUMP: u8 = 144;
+    pub const SHORT_JUMP_IF_FALSE: u8 = 152;
+    // 160-167, the high bits of the offset are biased by 4, the low bits follow
+    pub const LONG_JUMP: u8 = 160;
+    // 168-171 and 172-175, forward only
+    pub const LONG_JUMP_IF_TRUE: u8 = 168;
+    pub const LONG_JUMP_IF_FALSE: u8 = 172;
+
+    // 176-207, see SPECIAL_SELECTORS
+    pub const SPECIAL_SEND: u8 = 176;
+    // 208-223, 224-239, 240-255, the literal index is added
+    pub const SEND_LITERAL_SELECTOR_0: u8 = 208;
+
+    // Variable types of the extended descriptors
+    pub const RECEIVER_VARIABLE_TYPE: u8 = 0;
+    pub const TEMPORARY_TYPE: u8 = 1;
+    pub const LITERAL_CONSTANT_TYPE: u8 = 2;
+
+    // Operations of the double extended bytecode
+    pub const DOUBLE_EXTENDED_SEND: u8 = 0;
+    pub const DOUBLE_EXTENDED_SUPER_SEND: u8 = 1;
+    pub const DOUBLE_EXTENDED_PUSH_LITERAL_CONSTANT: u8 = 3;
+}
+
+// Selectors sent by the bytecodes 176 to 207, with their number of arguments.
+// The image keeps them, as symbols, in the special selectors array.
+pub const SPECIAL_SELECTORS: [(&str, usize); 32] = [
+    ("+", 1),
+    ("-", 1),
+    ("<", 1),
+    (">", 1),
+    ("<=", 1),
+    (">=", 1),
+    ("=", 1),
+    ("~=", 1),
+    ("*", 1),
+    ("/", 1),
+    ("\\\\", 1),
+    ("@", 1),
+    ("bitShift:", 1),
+    ("//", 1),
+    ("bitAnd:", 1),
+    ("bitOr:", 1),
+    ("at:", 1),
+    ("at:put:", 2),
+    ("size", 0),
+    ("next", 0),
+    ("nextPut:", 1),
+    ("atEnd", 0),
+    ("==", 1),
+    ("class", 0),
+    ("blockCopy:", 1),
+    ("value", 0),
+    ("value:", 1),
+    ("do:", 1),
+    ("new", 0),
+    ("new:", 1),
+    ("x", 0),
+    ("y", 0),
+];
+
+// The primitive tried before sending a special selector, 0 to always send.
+// As in Squeak, only the arithmetic, identity and closure evaluation ones are short-circuited.
+pub const SPECIAL_SELECTOR_PRIMITIVES: [usize; 32] = [
+    1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 0, 17, 12, 14, 15, //
+    0, 0, 0, 0, 0, 0, 110, 111, 0, 201, 202, 0, 0, 0, 0, 0,
+];
+
+pub fn special_selector_index(selector: &str) -> Option<usize> {
+    SPECIAL_SELECTORS
+        .iter()
+        .position(|(special_selector, _)| *special_selector == selector)
+}
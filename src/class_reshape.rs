@@ -0,0 +1,434 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::class_table::{class_constants, ClassFormat};
+use crate::compiler::code_generator::instance_variables_of;
+use crate::forwarding::{self, BecomeError};
+use crate::header_format_values::HeaderFormatValues;
+use crate::heap_queries;
+use crate::interpreter::Interpreter;
+use crate::oop_builder::OopBuilder;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::pinning;
+use crate::special_object_index::SpecialObjectIndexes;
+use crate::symbol_table;
+
+// Changing the instance variables of a class rebuilds its instances and those of its subclasses.
+// The new instances are all allocated before anything changes, the slots are copied by name,
+// then the old instances are forwarded to the new ones in bulk.
+// Nothing collects garbage in between: the migration is atomic with respect to the GC.
+// The methods of the reshaped classes are compiled against the old slot indexes: they are removed,
+// the caller installs them again compiled against the new ones.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReshapeError {
+    // Two instance variables of the class or of one of its subclasses have the same name
+    DuplicateInstanceVariable(String),
+    // Bits objects have no slot for the instance variables
+    NotPointers(usize),
+    Become(BecomeError),
+}
+
+impl fmt::Display for ReshapeError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReshapeError::DuplicateInstanceVariable(name) => {
+                write!(formatter, "{} is already an instance variable", name)
+            }
+            ReshapeError::NotPointers(class) => write!(
+                formatter,
+                "the instances of the class at {} cannot have instance variables",
+                class
+            ),
+            ReshapeError::Become(error) => write!(formatter, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ReshapeError {}
+
+impl From<BecomeError> for ReshapeError {
+    fn from(error: BecomeError) -> Self {
+        ReshapeError::Become(error)
+    }
+}
+
+// The class and the classes inheriting from it
+fn classes_inheriting_from(interpreter: &mut Interpreter, class: usize) -> Vec<usize> {
+    let nil = interpreter.nil_object();
+    interpreter
+        .class_table
+        .classes()
+        .into_iter()
+        .filter(|candidate| {
+            let mut current_class = *candidate;
+            while current_class != nil {
+                if current_class == class {
+                    return true;
+                }
+                current_class = interpreter.superclass_of(current_class);
+            }
+            false
+        })
+        .collect()
+}
+
+fn check_no_duplicates(names: &[String]) -> Result<(), ReshapeError> {
+    for (index, name) in names.iter().enumerate() {
+        if names[..index].contains(name) {
+            return Err(ReshapeError::DuplicateInstanceVariable(name.clone()));
+        }
+    }
+    Ok(())
+}
+
+// A new instance in the new shape, with the slots of the old one copied by name.
+// None when the space cannot grow for it.
+fn migrated_copy_of(
+    interpreter: &mut Interpreter,
+    instance: usize,
+    old_names: &[String],
+    new_names: &[String],
+) -> Option<usize> {
+    let nil = interpreter.nil_object();
+    let old_oop = interpreter.space.get_oop_at(instance);
+    let header = old_oop.get_header();
+    let (class_index, format, hash) = (
+        header.class_index_bits(),
+        header.format_bits(),
+        header.hash_bits(),
+    );
+    let number_of_indexable_slots = old_oop.number_of_slots() - old_names.len();
+    let mut slots: Vec<usize> = new_names
+        .iter()
+        .map(|name| match old_names.iter().position(|old| old == name) {
+            Some(position) => old_oop.slot_at_index(position + 1),
+            None => nil,
+        })
+        .collect();
+    slots.extend(
+        (old_names.len() + 1..=old_oop.number_of_slots()).map(|index| old_oop.slot_at_index(index)),
+    );
+
+    let mut builder = OopBuilder::new();
+    builder.set_class_index(class_index);
+    builder.set_format(format);
+    builder.set_number_of_slots(new_names.len() + number_of_indexable_slots);
+    let copy = builder.try_build(&mut interpreter.space)?;
+    let mut copy_oop = interpreter.space.get_oop_at(copy);
+    copy_oop.get_header_mut().set_hash_bits(hash);
+    copy_oop.apply_header();
+    for (index, slot) in slots.into_iter().enumerate() {
+        copy_oop.slot_at_index_put(index + 1, slot);
+    }
+    Some(copy)
+}
+
+fn new_instance_variables_array(interpreter: &mut Interpreter, names: &[&str]) -> usize {
+    let symbols: Vec<usize> = names
+        .iter()
+        .map(|name| symbol_table::intern(interpreter, name))
+        .collect();
+    let array_class = interpreter.special_object(SpecialObjectIndexes::ClassArray);
+    let array = interpreter
+        .instantiate_class(array_class, symbols.len())
+        .unwrap();
+    let mut array_oop = interpreter.space.get_oop_at(array);
+    for (index, symbol) in symbols.into_iter().enumerate() {
+        array_oop.slot_at_index_put(index + 1, symbol);
+    }
+    array
+}
+
+// Gives the class its new instance variables, answers the number of migrated instances
+pub fn reshape_class(
+    interpreter: &mut Interpreter,
+    class: usize,
+    instance_variables: &[&str],
+) -> Result<usize, ReshapeError> {
+    let class_format = interpreter.class_format_of(class);
+    if class_format.instance_specification() >= HeaderFormatValues::I64BitIndexable as usize
+        && !instance_variables.is_empty()
+    {
+        return Err(ReshapeError::NotPointers(class));
+    }
+    let own_instance_variables = interpreter
+        .space
+        .get_oop_at(class)
+        .slot_at_index(class_constants::INSTANCE_VARIABLES_INDEX);
+    let number_of_own_instance_variables = if own_instance_variables == interpreter.nil_object() {
+        0
+    } else {
+        interpreter
+            .space
+            .get_oop_at(own_instance_variables)
+            .number_of_slots()
+    };
+    let inherited_slots = class_format.number_of_fixed_slots() - number_of_own_instance_variables;
+
+    // The names before and after, for the class and each of its subclasses
+    let mut shapes: Vec<(usize, Vec<String>, Vec<String>)> = Vec::new();
+    for affected_class in classes_inheriting_from(interpreter, class) {
+        let old_names = instance_variables_of(interpreter, affected_class);
+        let mut new_names = old_names[..inherited_slots].to_vec();
+        new_names.extend(instance_variables.iter().map(|name| name.to_string()));
+        new_names.extend_from_slice(&old_names[class_format.number_of_fixed_slots()..]);
+        check_no_duplicates(&new_names)?;
+        shapes.push((affected_class, old_names, new_names));
+    }
+    if shapes
+        .iter()
+        .all(|(_, old_names, new_names)| old_names == new_names)
+    {
+        return Ok(0);
+    }
+
+    let mut forwarded: (Vec<usize>, Vec<usize>) = (Vec::new(), Vec::new());
+    let mut replacements: HashMap<usize, usize> = HashMap::new();
+    for (affected_class, old_names, new_names) in &shapes {
+        let class_index = interpreter
+            .space
+            .get_oop_at(*affected_class)
+            .get_header()
+            .hash_bits();
+        for instance in heap_queries::all_instances_of(&mut interpreter.space, class_index) {
+            // The migrated copy would take the place of the pinned instance
+            if pinning::is_pinned(&mut interpreter.space, instance) {
+                return Err(ReshapeError::Become(BecomeError::Pinned(instance)));
+            }
+            let copy = migrated_copy_of(interpreter, instance, old_names, new_names)
+                .ok_or(ReshapeError::Become(BecomeError::OutOfMemory(instance)))?;
+            if interpreter.space.get_oop_at(instance).number_of_slots() == 0 {
+                replacements.insert(instance, copy);
+            } else {
+                forwarded.0.push(instance);
+                forwarded.1.push(copy);
+            }
+        }
+    }
+    let instance_variables_array = new_instance_variables_array(interpreter, instance_variables);
+
+    // Checks every instance before forwarding any
+    forwarding::forward_identities(interpreter, &forwarded.0, &forwarded.1)?;
+    forwarding::replace_references(interpreter, &replacements);
+    interpreter.space.get_oop_at(class).slot_at_index_put(
+        class_constants::INSTANCE_VARIABLES_INDEX,
+        instance_variables_array,
+    );
+    let nil = interpreter.nil_object();
+    for (affected_class, old_names, new_names) in &shapes {
+        let instance_specification = interpreter
+            .class_format_of(*affected_class)
+            .instance_specification();
+        let mut class_oop = interpreter.space.get_oop_at(*affected_class);
+        class_oop.slot_at_index_put(
+            class_constants::FORMAT_INDEX,
+            ClassFormat::new(instance_specification, new_names.len()).as_slot_value(),
+        );
+        if old_names != new_names {
+            class_oop.slot_at_index_put(class_constants::METHOD_DICTIONARY_INDEX, nil);
+        }
+    }
+    Ok(forwarded.0.len() + replacements.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::{bootstrap, define_class};
+    use crate::class_reshape::{reshape_class, ReshapeError};
+    use crate::compiler::code_generator::instance_variables_of;
+    use crate::compiler::{evaluate, install_method};
+    use crate::forwarding::BecomeError;
+    use crate::header_format_values::HeaderFormatValues;
+    use crate::heap_verifier::verify_heap;
+    use crate::interpreter::interpreter_test_support::keep_on_the_stack;
+    use crate::interpreter::Interpreter;
+    use crate::oop_projections::oop_common::OopCommonState;
+    use crate::pinning;
+    use crate::slot_content::SlotContent;
+
+    fn define_pointers_class(
+        interpreter: &mut Interpreter,
+        name: &str,
+        superclass: usize,
+        instance_variables: &[&str],
+    ) -> usize {
+        define_class(
+            interpreter,
+            name,
+            superclass,
+            HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+            instance_variables,
+        )
+    }
+
+    fn new_instance(interpreter: &mut Interpreter, class: usize, slots: &[usize]) -> usize {
+        let instance = interpreter.instantiate_class(class, 0).unwrap();
+        for (index, slot) in slots.iter().enumerate() {
+            interpreter
+                .space
+                .get_oop_at(instance)
+                .slot_at_index_put(index + 1, *slot);
+        }
+        instance
+    }
+
+    fn slots_of(interpreter: &mut Interpreter, instance: usize) -> Vec<usize> {
+        let an_oop = interpreter.space.get_oop_at(instance);
+        (1..=an_oop.number_of_slots())
+            .map(|index| an_oop.slot_at_index(index))
+            .collect()
+    }
+
+    #[test]
+    fn test_reshape_copies_the_slots_by_name() {
+        let mut interpreter = bootstrap(40000);
+        let object = interpreter.class_named("Object").unwrap();
+        let point = define_pointers_class(&mut interpreter, "Point", object, &["x", "y"]);
+        let instance = new_instance(
+            &mut interpreter,
+            point,
+            &[SlotContent::small_integer(3), SlotContent::small_integer(4)],
+        );
+        keep_on_the_stack(&mut interpreter, &[instance]);
+        let hash = interpreter.hash_of(instance);
+
+        assert_eq!(
+            reshape_class(&mut interpreter, point, &["y", "z", "x"]),
+            Ok(1)
+        );
+
+        let migrated = interpreter.stack_value(0);
+        let nil = interpreter.nil_object();
+        assert_ne!(migrated, instance);
+        assert_eq!(
+            slots_of(&mut interpreter, migrated),
+            vec![
+                SlotContent::small_integer(4),
+                nil,
+                SlotContent::small_integer(3)
+            ]
+        );
+        assert_eq!(interpreter.hash_of(migrated), hash);
+        assert_eq!(
+            interpreter.class_format_of(point).number_of_fixed_slots(),
+            3
+        );
+        assert_eq!(
+            instance_variables_of(&mut interpreter, point),
+            vec!["y", "z", "x"]
+        );
+    }
+
+    #[test]
+    fn test_reshape_migrates_the_subclass_instances() {
+        let mut interpreter = bootstrap(40000);
+        let object = interpreter.class_named("Object").unwrap();
+        let base = define_pointers_class(&mut interpreter, "Base", object, &["a"]);
+        let derived = define_pointers_class(&mut interpreter, "Derived", base, &["b"]);
+        let instance = new_instance(
+            &mut interpreter,
+            derived,
+            &[SlotContent::small_integer(1), SlotContent::small_integer(2)],
+        );
+        keep_on_the_stack(&mut interpreter, &[instance]);
+
+        assert_eq!(reshape_class(&mut interpreter, base, &["c", "a"]), Ok(1));
+
+        let migrated = interpreter.stack_value(0);
+        let nil = interpreter.nil_object();
+        assert_eq!(
+            slots_of(&mut interpreter, migrated),
+            vec![
+                nil,
+                SlotContent::small_integer(1),
+                SlotContent::small_integer(2)
+            ]
+        );
+        assert_eq!(
+            interpreter.class_format_of(derived).number_of_fixed_slots(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_reshape_migrates_the_instances_without_slots() {
+        let mut interpreter = bootstrap(40000);
+        let object = interpreter.class_named("Object").unwrap();
+        let empty = define_pointers_class(&mut interpreter, "Empty", object, &[]);
+        let instance = new_instance(&mut interpreter, empty, &[]);
+        let array_class = interpreter.class_named("Array").unwrap();
+        let holder = interpreter.instantiate_class(array_class, 1).unwrap();
+        interpreter
+            .space
+            .get_oop_at(holder)
+            .slot_at_index_put(1, instance);
+        keep_on_the_stack(&mut interpreter, &[instance, holder]);
+
+        assert_eq!(reshape_class(&mut interpreter, empty, &["next"]), Ok(1));
+
+        let migrated = interpreter.stack_value(1);
+        assert_eq!(slots_of(&mut interpreter, migrated).len(), 1);
+        assert_eq!(
+            interpreter.space.get_oop_at(holder).slot_at_index(1),
+            migrated
+        );
+        install_method(&mut interpreter, empty, "next ^next").unwrap();
+        install_method(&mut interpreter, empty, "next: anObject next := anObject").unwrap();
+        assert_eq!(
+            evaluate(&mut interpreter, "(Empty new next: 7; yourself) next").unwrap(),
+            SlotContent::small_integer(7)
+        );
+    }
+
+    #[test]
+    fn test_reshape_removes_the_methods_compiled_against_the_old_slots() {
+        let mut interpreter = bootstrap(40000);
+        let object = interpreter.class_named("Object").unwrap();
+        let point = define_pointers_class(&mut interpreter, "Point", object, &["x", "y"]);
+        install_method(&mut interpreter, point, "y ^y").unwrap();
+        install_method(&mut interpreter, point, "y: anObject y := anObject").unwrap();
+        install_method(&mut interpreter, object, "answerSeven ^7").unwrap();
+
+        reshape_class(&mut interpreter, point, &["x"]).unwrap();
+
+        assert_eq!(
+            evaluate(
+                &mut interpreter,
+                "[Point new y: 3; y] on: MessageNotUnderstood do: [:e | 7]"
+            )
+            .unwrap(),
+            SlotContent::small_integer(7)
+        );
+        assert_eq!(
+            evaluate(&mut interpreter, "Point new answerSeven").unwrap(),
+            SlotContent::small_integer(7)
+        );
+        assert_eq!(verify_heap(&mut interpreter), vec![]);
+    }
+
+    #[test]
+    fn test_reshape_errors_leave_the_class_unchanged() {
+        let mut interpreter = bootstrap(40000);
+        let object = interpreter.class_named("Object").unwrap();
+        let base = define_pointers_class(&mut interpreter, "Base", object, &["a"]);
+        define_pointers_class(&mut interpreter, "Derived", base, &["b"]);
+        let string = interpreter.class_named("ByteString").unwrap();
+        let instance = new_instance(&mut interpreter, base, &[SlotContent::small_integer(1)]);
+        let pinned = pinning::pin(&mut interpreter, instance).unwrap();
+
+        assert_eq!(
+            reshape_class(&mut interpreter, base, &["a", "c"]),
+            Err(ReshapeError::Become(BecomeError::Pinned(pinned)))
+        );
+        assert_eq!(
+            reshape_class(&mut interpreter, base, &["a", "b"]),
+            Err(ReshapeError::DuplicateInstanceVariable(String::from("b")))
+        );
+        assert_eq!(
+            reshape_class(&mut interpreter, string, &["a"]),
+            Err(ReshapeError::NotPointers(string))
+        );
+        assert_eq!(instance_variables_of(&mut interpreter, base), vec!["a"]);
+        assert_eq!(interpreter.class_format_of(base).number_of_fixed_slots(), 1);
+    }
+}
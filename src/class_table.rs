@@ -0,0 +1,153 @@
+use crate::memory_space::MemorySpace;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::slot_content::SlotContent;
+
+// Class slots, 1 based like the oop slots.
+pub mod class_constants {
+    pub const SUPERCLASS_INDEX: usize = 1;
+    pub const METHOD_DICTIONARY_INDEX: usize = 2;
+    pub const FORMAT_INDEX: usize = 3;
+    pub const INSTANCE_VARIABLES_INDEX: usize = 4;
+    pub const NAME_INDEX: usize = 5;
+    // Metaclasses keep their sole instance where classes keep their name
+    pub const THIS_CLASS_INDEX: usize = 5;
+    pub const NUMBER_OF_FIXED_SLOTS: usize = 5;
+}
+
+// The format slot of a class is a SmallInteger: the instance specification (the header format
+// of the instances) shifted by 16, and the number of fixed slots of the instances.
+#[derive(Debug, PartialEq)]
+pub struct ClassFormat {
+    instance_specification: usize,
+    number_of_fixed_slots: usize,
+}
+
+impl ClassFormat {
+    pub fn new(instance_specification: usize, number_of_fixed_slots: usize) -> Self {
+        Self {
+            instance_specification,
+            number_of_fixed_slots,
+        }
+    }
+
+    pub fn from_slot_value(slot_value: usize) -> Self {
+        let format = SlotContent::new(slot_value).as_small_integer() as usize;
+        Self::new(format >> 16, format & 0xFFFF)
+    }
+
+    pub fn as_slot_value(&self) -> usize {
+        let format = (self.instance_specification << 16) | self.number_of_fixed_slots;
+        SlotContent::small_integer(format as isize)
+    }
+
+    pub fn instance_specification(&self) -> usize {
+        self.instance_specification
+    }
+
+    pub fn number_of_fixed_slots(&self) -> usize {
+        self.number_of_fixed_slots
+    }
+}
+
+// Maps class indexes (found in the headers) to class oops.
+// As in Spur, the hash of a class is its index in the table.
+#[derive(Debug, Default)]
+pub struct ClassTable {
+    classes: Vec<Option<usize>>,
+}
+
+impl ClassTable {
+    // The lower indexes are kept for the special classes
+    pub const FIRST_FREE_INDEX: usize = 32;
+
+    pub fn new() -> Self {
+        Self {
+            classes: Vec::new(),
+        }
+    }
+
+    pub fn class_at_index(&self, class_index: usize) -> Option<usize> {
+        self.classes.get(class_index).copied().flatten()
+    }
+
+    pub fn register_at(&mut self, class_index: usize, class: usize, space: &mut MemorySpace) {
+        if self.classes.len() <= class_index {
+            self.classes.resize(class_index + 1, None);
+        }
+        self.classes[class_index] = Some(class);
+
+        let mut class_oop = space.get_oop_at(class);
+        class_oop.get_header_mut().set_hash_bits(class_index);
+        class_oop.apply_header();
+    }
+
+    pub fn register(&mut self, class: usize, space: &mut MemorySpace) -> usize {
+        let class_index = (ClassTable::FIRST_FREE_INDEX..)
+            .find(|index| self.class_at_index(*index).is_none())
+            .unwrap();
+        self.register_at(class_index, class, space);
+        class_index
+    }
+
+    pub fn classes(&self) -> Vec<usize> {
+        self.classes.iter().flatten().copied().collect()
+    }
+
+    // Every slot of the table, for saving it
+    pub fn entries(&self) -> &[Option<usize>] {
+        &self.classes
+    }
+
+    pub fn from_entries(entries: Vec<Option<usize>>) -> Self {
+        Self { classes: entries }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::class_table::{ClassFormat, ClassTable};
+    use crate::memory_space::MemorySpace;
+    use crate::oop_builder::OopBuilder;
+    use crate::oop_projections::oop_common::OopCommonState;
+
+    #[test]
+    fn test_class_format_round_trip() {
+        let format = ClassFormat::new(3, 6);
+        assert_eq!(ClassFormat::from_slot_value(format.as_slot_value()), format);
+    }
+
+    #[test]
+    fn test_register_sets_class_hash() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let mut class_table = ClassTable::new();
+        let class = OopBuilder::new().build(&mut space);
+
+        let class_index = class_table.register(class, &mut space);
+
+        assert_eq!(class_index, ClassTable::FIRST_FREE_INDEX);
+        assert_eq!(
+            space.get_oop_at(class).get_header().hash_bits(),
+            class_index
+        );
+        assert_eq!(class_table.class_at_index(class_index), Some(class));
+    }
+
+    #[test]
+    fn test_register_twice_uses_different_indexes() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let mut class_table = ClassTable::new();
+        let first_class = OopBuilder::new().build(&mut space);
+        let second_class = OopBuilder::new().build(&mut space);
+
+        assert_ne!(
+            class_table.register(first_class, &mut space),
+            class_table.register(second_class, &mut space)
+        );
+    }
+
+    #[test]
+    fn test_unregistered_index_has_no_class() {
+        let class_table = ClassTable::new();
+        assert_eq!(class_table.class_at_index(4), None);
+    }
+}
@@ -0,0 +1,839 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::allocator::{self, FreeSpaceCensus};
+use crate::benchmark::{self, BenchmarkConfiguration};
+use crate::bootstrap::bootstrap;
+use crate::compiler;
+use crate::garbage_collector;
+use crate::heap_export::{self, HeapExportFilter};
+use crate::heap_queries;
+use crate::heap_verifier;
+use crate::image;
+use crate::interpreter::Interpreter;
+use crate::memory_space::{Segment, SegmentKind};
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::repl::Repl;
+use crate::slot_content::SlotContent;
+
+// The heap inspector: each subcommand loads an image, then inspects it.
+// The repl evaluates expressions, in a freshly bootstrapped image without one.
+pub const USAGE: &str = "usage: fun_with_vm <command> <image> [options]
+commands:
+  repl [<image>]                    evaluates the expressions typed at the prompt
+  bench [--workload <name>] [--collector <name>] [--strategy <name>] [--heap <words>,...]
+      [--scale <count>] [--seed <number>] [--output <file>]
+                                    runs the allocation benchmarks, one JSON line per run
+  info <image>                      sizes and layout of the memory space
+  objects <image> [--class <name or index>] [--reachable] [--limit <count>]
+                                    lists the objects
+  show <image> <index>              decodes the header and the slots of an object
+  census <image>                    counts the objects and their words per class
+  verify <image>                    checks the heap, fails when it is inconsistent
+  gc <image> [--output <image>]     collects the garbage and writes the image back
+  fragmentation <image> [--workload <expression>] [--rounds <count>]
+                                    replays the workload under each allocation strategy,
+                                    collecting the garbage between the rounds.
+                                    The allocation buffer is off, the strategy places
+                                    each object
+  dot <image> [--class <name or index>] [--reachable] [--format dot|json|jsonl]
+      [--output <file>]             exports the heap graph";
+
+#[derive(Debug)]
+pub enum CliError {
+    Usage(String),
+    NoObject(usize),
+    // The number of problems the verifier found
+    CorruptedHeap(usize),
+    Io(io::Error),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(formatter, "{}", message),
+            CliError::NoObject(index) => write!(formatter, "no object starts at {}", index),
+            CliError::CorruptedHeap(problems) => write!(
+                formatter,
+                "the heap is corrupted, {} problems found, see verify",
+                problems
+            ),
+            CliError::Io(error) => write!(formatter, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<io::Error> for CliError {
+    fn from(error: io::Error) -> Self {
+        CliError::Io(error)
+    }
+}
+
+// Size of the memory space bootstrapped for the repl
+const REPL_MEMORY_WORDS: usize = 1 << 20;
+
+// The options with a value, the others are flags
+const VALUED_OPTIONS: [&str; 11] = [
+    "--class",
+    "--limit",
+    "--format",
+    "--output",
+    "--workload",
+    "--rounds",
+    "--collector",
+    "--strategy",
+    "--heap",
+    "--scale",
+    "--seed",
+];
+
+// Arrays of assorted sizes, every other one kept until the end of the round
+const DEFAULT_WORKLOAD: &str = "| all | all := Array new: 200. \
+    1 to: 200 do: [:index | all at: index put: (Array new: index * 7 \\\\ 31)]. \
+    1 to: 100 do: [:index | all at: index * 2 put: nil]. ^all";
+const DEFAULT_ROUNDS: usize = 3;
+
+// Each workload runs under each collector on each of these heaps, unless told otherwise
+const DEFAULT_BENCHMARK_HEAPS: [usize; 2] = [1 << 16, 1 << 18];
+const DEFAULT_BENCHMARK_SCALE: usize = 10;
+const DEFAULT_BENCHMARK_SEED: u64 = 1;
+
+struct Arguments {
+    positionals: Vec<String>,
+    options: BTreeMap<String, Option<String>>,
+}
+
+impl Arguments {
+    fn parse(arguments: &[String]) -> Result<Self, CliError> {
+        let mut positionals = Vec::new();
+        let mut options = BTreeMap::new();
+        let mut iterator = arguments.iter();
+        while let Some(argument) = iterator.next() {
+            if !argument.starts_with("--") {
+                positionals.push(argument.clone());
+            } else if VALUED_OPTIONS.contains(&argument.as_str()) {
+                let value = iterator
+                    .next()
+                    .ok_or_else(|| CliError::Usage(format!("{} expects a value", argument)))?;
+                options.insert(argument.clone(), Some(value.clone()));
+            } else {
+                options.insert(argument.clone(), None);
+            }
+        }
+        Ok(Self {
+            positionals,
+            options,
+        })
+    }
+
+    fn positional(&self, position: usize, name: &str) -> Result<&str, CliError> {
+        self.positionals
+            .get(position)
+            .map(|value| value.as_str())
+            .ok_or_else(|| CliError::Usage(format!("missing {}", name)))
+    }
+
+    fn value_of(&self, option: &str) -> Option<&str> {
+        self.options.get(option).and_then(|value| value.as_deref())
+    }
+
+    fn has_flag(&self, option: &str) -> bool {
+        self.options.contains_key(option)
+    }
+}
+
+fn parse_number(value: &str, name: &str) -> Result<usize, CliError> {
+    value
+        .parse()
+        .map_err(|_| CliError::Usage(format!("{} is not a valid {}", value, name)))
+}
+
+// A class index, or the name of a class
+fn class_index_named(interpreter: &mut Interpreter, value: &str) -> Result<usize, CliError> {
+    if let Ok(class_index) = value.parse() {
+        return Ok(class_index);
+    }
+    let class = interpreter
+        .class_named(value)
+        .ok_or_else(|| CliError::Usage(format!("unknown class {}", value)))?;
+    Ok(interpreter.space.get_oop_at(class).get_header().hash_bits())
+}
+
+fn export_filter(
+    interpreter: &mut Interpreter,
+    arguments: &Arguments,
+) -> Result<HeapExportFilter, CliError> {
+    let class_index = match arguments.value_of("--class") {
+        Some(value) => Some(class_index_named(interpreter, value)?),
+        None => None,
+    };
+    Ok(HeapExportFilter {
+        class_index,
+        reachable_only: arguments.has_flag("--reachable"),
+    })
+}
+
+pub fn describe_slot(interpreter: &mut Interpreter, value: usize) -> String {
+    let content = SlotContent::new(value);
+    if content.is_small_integer() {
+        format!("SmallInteger {}", content.as_small_integer())
+    } else if content.is_character() {
+        match char::from_u32(content.as_character()) {
+            Some(character) => format!("Character {:?}", character),
+            None => format!("Character {}", content.as_character()),
+        }
+    } else {
+        let class_index = interpreter.class_index_of(value);
+        let class_name = heap_export::class_name_of(interpreter, class_index);
+        format!("{} ({})", value, class_name.as_deref().unwrap_or("?"))
+    }
+}
+
+fn info<W: Write>(interpreter: &mut Interpreter, output: &mut W) -> Result<bool, CliError> {
+    let (mut objects, mut object_words) = (0, 0);
+    let (mut free_chunks, mut free_words, mut largest_free_chunk) = (0, 0, 0);
+    let (mut forwarders, mut pinned, mut fillers) = (0, 0, 0);
+    let mut iterator = interpreter.space.iter();
+    while let Some(headers) = iterator.next_headers(&mut interpreter.space) {
+        let size = headers.oop_size();
+        if headers.is_free_oop() {
+            free_chunks += 1;
+            free_words += size;
+            largest_free_chunk = largest_free_chunk.max(size);
+        } else if headers.get_header().is_forwarded() {
+            forwarders += 1;
+        } else if headers.get_header().is_filler() {
+            fillers += size;
+        } else {
+            objects += 1;
+            object_words += size;
+            pinned += headers.get_header().is_pinned() as usize;
+        }
+    }
+    let class_table = interpreter.class_table.entries();
+    let classes = class_table.iter().filter(|entry| entry.is_some()).count();
+    writeln!(
+        output,
+        "memory words: {}",
+        interpreter.space.number_of_words()
+    )?;
+    writeln!(output, "segments: {}", interpreter.space.segments().len())?;
+    let large_object_segments: Vec<&Segment> = interpreter
+        .space
+        .segments()
+        .iter()
+        .filter(|segment| segment.kind() == SegmentKind::LargeObject)
+        .collect();
+    writeln!(
+        output,
+        "large object space: {} objects in {} words",
+        large_object_segments.len(),
+        large_object_segments
+            .iter()
+            .map(|segment| segment.end() - segment.start())
+            .sum::<usize>()
+    )?;
+    writeln!(output, "objects: {} ({} words)", objects, object_words)?;
+    writeln!(
+        output,
+        "free chunks: {} ({} words)",
+        free_chunks, free_words
+    )?;
+    writeln!(output, "largest free chunk: {} words", largest_free_chunk)?;
+    let census = FreeSpaceCensus::of(&mut interpreter.space, SegmentKind::Objects);
+    writeln!(
+        output,
+        "allocation strategy: {}, fragmentation {:.3}",
+        interpreter.space.allocation_strategy_name(),
+        census.fragmentation()
+    )?;
+    writeln!(output, "fillers: {} words", fillers)?;
+    writeln!(output, "forwarders: {}", forwarders)?;
+    writeln!(output, "pinned objects: {}", pinned)?;
+    writeln!(output, "special objects: {}", interpreter.special_objects())?;
+    writeln!(
+        output,
+        "class table: {} classes in {} entries",
+        classes,
+        class_table.len()
+    )?;
+    Ok(true)
+}
+
+fn objects<W: Write>(
+    interpreter: &mut Interpreter,
+    arguments: &Arguments,
+    output: &mut W,
+) -> Result<bool, CliError> {
+    let filter = export_filter(interpreter, arguments)?;
+    let limit = match arguments.value_of("--limit") {
+        Some(value) => parse_number(value, "limit")?,
+        None => usize::MAX,
+    };
+    for index in heap_export::exported_indexes(interpreter, &filter)
+        .into_iter()
+        .take(limit)
+    {
+        let object = heap_export::exported_object(interpreter, index);
+        writeln!(
+            output,
+            "{} {} {} slots",
+            object.index,
+            object.class_name.as_deref().unwrap_or("?"),
+            object.number_of_slots
+        )?;
+    }
+    Ok(true)
+}
+
+pub fn show<W: Write>(
+    interpreter: &mut Interpreter,
+    index: usize,
+    output: &mut W,
+) -> Result<bool, CliError> {
+    if !heap_queries::all_objects(&mut interpreter.space).contains(&index) {
+        return Err(CliError::NoObject(index));
+    }
+    let object = heap_export::exported_object(interpreter, index);
+    writeln!(output, "object {}", object.index)?;
+    writeln!(
+        output,
+        "class index: {} ({})",
+        object.class_index,
+        object.class_name.as_deref().unwrap_or("?")
+    )?;
+    writeln!(
+        output,
+        "format: {}, slots: {}, hash: {}",
+        object.format, object.number_of_slots, object.hash
+    )?;
+    writeln!(
+        output,
+        "flags: immutable {}, pinned {}, marked {}, grey {}, remembered {}",
+        object.immutable, object.pinned, object.marked, object.grey, object.remembered
+    )?;
+
+    let an_oop = interpreter.space.get_oop_at(index);
+    if an_oop.get_header().contains_pointers() {
+        let slots: Vec<usize> = (1..=an_oop.number_of_slots())
+            .map(|slot| an_oop.slot_at_index(slot))
+            .collect();
+        for (position, slot) in slots.into_iter().enumerate() {
+            let description = describe_slot(interpreter, slot);
+            writeln!(output, "  {}: {}", position + 1, description)?;
+        }
+    } else if an_oop.get_header().is_compiled_method() {
+        // The literals referencing objects, the bytecodes are not decoded
+        for reference in object.references {
+            let description = describe_slot(interpreter, reference);
+            writeln!(output, "  literal: {}", description)?;
+        }
+    } else if let Some(string) = interpreter.string_value_of(index) {
+        writeln!(output, "bytes: {:?}", string)?;
+    }
+    writeln!(
+        output,
+        "referenced by: {:?}",
+        heap_queries::pointers_to(&mut interpreter.space, index)
+    )?;
+    Ok(true)
+}
+
+pub fn census<W: Write>(interpreter: &mut Interpreter, output: &mut W) -> Result<bool, CliError> {
+    // Count and words per class index
+    let mut census: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+    for index in heap_queries::all_objects(&mut interpreter.space) {
+        let an_oop = interpreter.space.get_oop_at(index);
+        let entry = census
+            .entry(an_oop.get_header().class_index_bits())
+            .or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 += an_oop.oop_size();
+    }
+    let mut rows: Vec<(usize, usize, usize)> = census
+        .into_iter()
+        .map(|(class_index, (count, words))| (class_index, count, words))
+        .collect();
+    rows.sort_by(|first, second| second.2.cmp(&first.2).then(first.0.cmp(&second.0)));
+    writeln!(
+        output,
+        "{:>8} {:>8} {:>8}  class",
+        "index", "objects", "words"
+    )?;
+    for (class_index, count, words) in rows {
+        let name = heap_export::class_name_of(interpreter, class_index);
+        writeln!(
+            output,
+            "{:>8} {:>8} {:>8}  {}",
+            class_index,
+            count,
+            words,
+            name.as_deref().unwrap_or("?")
+        )?;
+    }
+    Ok(true)
+}
+
+// The commands walking the object graph trust the headers and the pointers
+fn verified(interpreter: &mut Interpreter) -> Result<(), CliError> {
+    match heap_verifier::verify_heap(interpreter).len() {
+        0 => Ok(()),
+        problems => Err(CliError::CorruptedHeap(problems)),
+    }
+}
+
+fn verify<W: Write>(interpreter: &mut Interpreter, output: &mut W) -> Result<bool, CliError> {
+    let problems = heap_verifier::verify_heap(interpreter);
+    for problem in &problems {
+        writeln!(output, "{}", problem)?;
+    }
+    if problems.is_empty() {
+        writeln!(output, "heap is consistent")?;
+    } else {
+        writeln!(output, "{} problems found", problems.len())?;
+    }
+    Ok(problems.is_empty())
+}
+
+pub fn free_words_of(interpreter: &mut Interpreter) -> usize {
+    let mut free_words = 0;
+    let mut iterator = interpreter.space.iter();
+    while let Some(headers) = iterator.next_headers(&mut interpreter.space) {
+        if headers.is_free_oop() {
+            free_words += headers.oop_size();
+        }
+    }
+    free_words
+}
+
+fn gc<W: Write>(
+    interpreter: &mut Interpreter,
+    image_path: &str,
+    arguments: &Arguments,
+    output: &mut W,
+) -> Result<bool, CliError> {
+    let free_words_before = free_words_of(interpreter);
+    interpreter.collect_garbage();
+    let free_words_after = free_words_of(interpreter);
+    let output_path = arguments.value_of("--output").unwrap_or(image_path);
+    image::save_image(interpreter, Path::new(output_path))?;
+    writeln!(
+        output,
+        "reclaimed {} words, {} words free, written to {}",
+        free_words_after.saturating_sub(free_words_before),
+        free_words_after,
+        output_path
+    )?;
+    Ok(true)
+}
+
+// The free space of the moving segments once the workload ran under each strategy.
+// The objects bumped into the allocation buffer would bypass the strategy, so it is off.
+fn fragmentation<W: Write>(
+    image_path: &str,
+    arguments: &Arguments,
+    output: &mut W,
+) -> Result<bool, CliError> {
+    let workload = arguments.value_of("--workload").unwrap_or(DEFAULT_WORKLOAD);
+    let rounds = match arguments.value_of("--rounds") {
+        Some(value) => parse_number(value, "count")?,
+        None => DEFAULT_ROUNDS,
+    };
+    writeln!(
+        output,
+        "{:<10} {:>8} {:>10} {:>10} {:>14}",
+        "strategy", "chunks", "free words", "largest", "fragmentation"
+    )?;
+    for name in allocator::ALLOCATION_STRATEGIES {
+        let mut interpreter = image::load_image(Path::new(image_path))?;
+        interpreter
+            .space
+            .replace_allocation_strategy(allocator::allocation_strategy_named(name).unwrap());
+        interpreter.space.set_uses_allocation_buffer(false);
+        for round in 0..rounds {
+            if round > 0 {
+                interpreter.collect_garbage();
+            }
+            compiler::evaluate(&mut interpreter, workload)
+                .map_err(|error| CliError::Usage(format!("syntax error: {}", error)))?;
+        }
+        let census = FreeSpaceCensus::of(&mut interpreter.space, SegmentKind::Objects);
+        writeln!(
+            output,
+            "{:<10} {:>8} {:>10} {:>10} {:>14.3}",
+            name,
+            census.free_chunks,
+            census.free_words,
+            census.largest_free_chunk,
+            census.fragmentation()
+        )?;
+    }
+    Ok(true)
+}
+
+// The named workload and collector, or all of them
+fn bench<W: Write>(arguments: &Arguments, output: &mut W) -> Result<bool, CliError> {
+    let workloads = match arguments.value_of("--workload") {
+        Some(name) => vec![name],
+        None => benchmark::WORKLOADS.to_vec(),
+    };
+    let collectors = match arguments.value_of("--collector") {
+        Some(name) => vec![name],
+        None => garbage_collector::GARBAGE_COLLECTORS.to_vec(),
+    };
+    let heaps = match arguments.value_of("--heap") {
+        Some(value) => value
+            .split(',')
+            .map(|words| parse_number(words, "heap size"))
+            .collect::<Result<Vec<usize>, CliError>>()?,
+        None => DEFAULT_BENCHMARK_HEAPS.to_vec(),
+    };
+    let scale = match arguments.value_of("--scale") {
+        Some(value) => parse_number(value, "count")?,
+        None => DEFAULT_BENCHMARK_SCALE,
+    };
+    let seed = match arguments.value_of("--seed") {
+        Some(value) => parse_number(value, "seed")? as u64,
+        None => DEFAULT_BENCHMARK_SEED,
+    };
+    let mut file_output;
+    let writer: &mut dyn Write = match arguments.value_of("--output") {
+        Some(path) => {
+            file_output = BufWriter::new(File::create(path)?);
+            &mut file_output
+        }
+        None => output,
+    };
+    for workload in &workloads {
+        for collector in &collectors {
+            for heap_words in &heaps {
+                let configuration = BenchmarkConfiguration {
+                    workload: workload.to_string(),
+                    collector: collector.to_string(),
+                    allocation_strategy: arguments
+                        .value_of("--strategy")
+                        .unwrap_or("first-fit")
+                        .to_string(),
+                    heap_words: *heap_words,
+                    scale,
+                    seed,
+                };
+                let result = benchmark::run_benchmark(&configuration)
+                    .map_err(|error| CliError::Usage(error.to_string()))?;
+                writeln!(writer, "{}", result.as_json())?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(true)
+}
+
+fn dot<W: Write>(
+    interpreter: &mut Interpreter,
+    arguments: &Arguments,
+    output: &mut W,
+) -> Result<bool, CliError> {
+    let filter = export_filter(interpreter, arguments)?;
+    let format = arguments.value_of("--format").unwrap_or("dot");
+    let mut file_output;
+    let writer: &mut dyn Write = match arguments.value_of("--output") {
+        Some(path) => {
+            file_output = BufWriter::new(File::create(path)?);
+            &mut file_output
+        }
+        None => output,
+    };
+    match format {
+        "dot" => heap_export::write_dot(interpreter, &filter, writer)?,
+        "json" => heap_export::write_json(interpreter, &filter, writer)?,
+        "jsonl" => heap_export::write_jsonl(interpreter, &filter, writer)?,
+        _ => return Err(CliError::Usage(format!("unknown format {}", format))),
+    }
+    Ok(true)
+}
+
+// Answers whether the command succeeded, verify fails on an inconsistent heap
+pub fn run<W: Write>(arguments: &[String], output: &mut W) -> Result<bool, CliError> {
+    let arguments = Arguments::parse(arguments)?;
+    let command = arguments.positional(0, "command")?.to_string();
+    if command == "repl" {
+        let mut interpreter = match arguments.positionals.get(1) {
+            Some(image_path) => image::load_image(Path::new(image_path))?,
+            None => bootstrap(REPL_MEMORY_WORDS),
+        };
+        Repl::new(&mut interpreter).run(&mut io::stdin().lock(), output)?;
+        return Ok(true);
+    }
+    if command == "bench" {
+        return bench(&arguments, output);
+    }
+    let image_path = arguments.positional(1, "image")?.to_string();
+    let mut interpreter = image::load_image(Path::new(&image_path))?;
+    if ["objects", "show", "census", "gc", "dot"].contains(&command.as_str()) {
+        verified(&mut interpreter)?;
+    }
+    match command.as_str() {
+        "info" => info(&mut interpreter, output),
+        "objects" => objects(&mut interpreter, &arguments, output),
+        "show" => {
+            let index = parse_number(arguments.positional(2, "index")?, "index")?;
+            show(&mut interpreter, index, output)
+        }
+        "census" => census(&mut interpreter, output),
+        "verify" => verify(&mut interpreter, output),
+        "gc" => gc(&mut interpreter, &image_path, &arguments, output),
+        "dot" => dot(&mut interpreter, &arguments, output),
+        "fragmentation" => fragmentation(&image_path, &arguments, output),
+        _ => Err(CliError::Usage(format!("unknown command {}", command))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::bootstrap::bootstrap;
+    use crate::cli::{run, CliError};
+    use crate::image::{load_image, save_image};
+    use crate::oop_projections::oop_common::OopCommonState;
+    use crate::special_object_index::SpecialObjectIndexes;
+
+    // Each test gets its own image file
+    fn saved_image(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("funvm-cli-{}-{}.image", name, std::process::id()));
+        save_image(&mut bootstrap(40000), &path).unwrap();
+        path
+    }
+
+    fn run_command(arguments: &[&str]) -> (Result<bool, CliError>, String) {
+        let arguments: Vec<String> = arguments
+            .iter()
+            .map(|argument| argument.to_string())
+            .collect();
+        let mut output: Vec<u8> = Vec::new();
+        let result = run(&arguments, &mut output);
+        (result, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_info_reports_the_sizes() {
+        let path = saved_image("info");
+        let (result, output) = run_command(&["info", path.to_str().unwrap()]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.unwrap());
+        assert!(output.starts_with("memory words: "));
+        assert!(output.contains("forwarders: 0"));
+        assert!(output.contains("large object space: "));
+        assert!(output.contains("pinned objects: 0\n"));
+        assert!(output.contains("allocation strategy: first-fit, fragmentation "));
+    }
+
+    #[test]
+    fn test_fragmentation_compares_the_strategies() {
+        let path = saved_image("fragmentation");
+        let (result, output) =
+            run_command(&["fragmentation", path.to_str().unwrap(), "--rounds", "2"]);
+        let (syntax_error, _) =
+            run_command(&["fragmentation", path.to_str().unwrap(), "--workload", "3 +"]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.unwrap());
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("strategy "));
+        for (line, name) in
+            lines[1..]
+                .iter()
+                .zip(["first-fit", "next-fit", "best-fit", "worst-fit"])
+        {
+            assert!(line.starts_with(name));
+        }
+        assert!(matches!(syntax_error, Err(CliError::Usage(_))));
+    }
+
+    #[test]
+    fn test_bench_writes_a_json_line_per_run() {
+        let (result, output) = run_command(&[
+            "bench",
+            "--workload",
+            "churn",
+            "--heap",
+            "1024,4096",
+            "--scale",
+            "1",
+        ]);
+        let (unknown, _) = run_command(&["bench", "--collector", "copying", "--scale", "1"]);
+
+        assert!(result.unwrap());
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("{\"workload\":\"churn\",\"collector\":\"simple\","));
+        assert!(lines[0].contains("\"heap_words\":1024,"));
+        assert!(lines[1].contains("\"heap_words\":4096,"));
+        assert!(
+            matches!(unknown, Err(CliError::Usage(message)) if message == "unknown collector copying")
+        );
+    }
+
+    #[test]
+    fn test_objects_filters_by_class_name() {
+        let path = saved_image("objects");
+        let (result, output) = run_command(&[
+            "objects",
+            path.to_str().unwrap(),
+            "--class",
+            "Metaclass",
+            "--limit",
+            "3",
+        ]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.unwrap());
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.lines().all(|line| line.contains(" Metaclass ")));
+    }
+
+    #[test]
+    fn test_show_decodes_the_slots() {
+        let path = saved_image("show");
+        let special_objects = load_image(&path).unwrap().special_objects();
+        let (result, output) =
+            run_command(&["show", path.to_str().unwrap(), &special_objects.to_string()]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.unwrap());
+        assert!(output.contains("(Array)"));
+        assert!(output.contains("  1: "));
+        assert!(output.contains("(UndefinedObject)"));
+    }
+
+    #[test]
+    fn test_show_fails_without_an_object_at_the_index() {
+        let path = saved_image("show-nothing");
+        let special_objects = load_image(&path).unwrap().special_objects();
+        let (result, _) = run_command(&[
+            "show",
+            path.to_str().unwrap(),
+            &(special_objects + 1).to_string(),
+        ]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(CliError::NoObject(index)) if index == special_objects + 1));
+    }
+
+    #[test]
+    fn test_census_counts_the_classes() {
+        let path = saved_image("census");
+        let (result, output) = run_command(&["census", path.to_str().unwrap()]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.unwrap());
+        assert!(output.lines().any(|line| line.ends_with("  Symbol")));
+        assert!(output.lines().any(|line| line.ends_with("  Array class")));
+    }
+
+    #[test]
+    fn test_verify_fails_on_a_dangling_pointer() {
+        let path = saved_image("verify");
+        let (result, output) = run_command(&["verify", path.to_str().unwrap()]);
+        assert!(result.unwrap());
+        assert_eq!(output, "heap is consistent\n");
+
+        let mut interpreter = load_image(&path).unwrap();
+        let special_objects = interpreter.special_objects();
+        interpreter
+            .space
+            .get_oop_at(special_objects)
+            .slot_at_index_put(
+                SpecialObjectIndexes::SpecialSelectors as usize,
+                special_objects + 1,
+            );
+        save_image(&mut interpreter, &path).unwrap();
+        let (result, output) = run_command(&["verify", path.to_str().unwrap()]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(!result.unwrap());
+        assert!(output.ends_with("1 problems found\n"));
+    }
+
+    #[test]
+    fn test_graph_commands_refuse_a_corrupted_heap() {
+        let path = saved_image("corrupted");
+        let mut interpreter = load_image(&path).unwrap();
+        let special_objects = interpreter.special_objects();
+        interpreter
+            .space
+            .get_oop_at(special_objects)
+            .slot_at_index_put(
+                SpecialObjectIndexes::SpecialSelectors as usize,
+                special_objects + 1,
+            );
+        save_image(&mut interpreter, &path).unwrap();
+        let results: Vec<Result<bool, CliError>> = ["objects", "census", "gc", "dot"]
+            .iter()
+            .map(|command| run_command(&[command, path.to_str().unwrap()]).0)
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+
+        for result in results {
+            assert!(matches!(result, Err(CliError::CorruptedHeap(1))));
+        }
+    }
+
+    #[test]
+    fn test_gc_writes_the_image_back() {
+        let path = saved_image("gc");
+        let mut interpreter = load_image(&path).unwrap();
+        let array_class = interpreter.class_named("Array").unwrap();
+        let garbage = interpreter.instantiate_class(array_class, 10).unwrap();
+        save_image(&mut interpreter, &path).unwrap();
+
+        let (result, output) = run_command(&["gc", path.to_str().unwrap()]);
+        let mut collected = load_image(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.unwrap());
+        assert!(output.starts_with("reclaimed "));
+        assert!(collected.space.get_oop_at(garbage).is_free_oop());
+    }
+
+    #[test]
+    fn test_dot_exports_the_graph() {
+        let path = saved_image("dot");
+        let (result, output) = run_command(&[
+            "dot",
+            path.to_str().unwrap(),
+            "--class",
+            "Array",
+            "--format",
+            "jsonl",
+        ]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.unwrap());
+        assert!(output
+            .lines()
+            .all(|line| line.contains("\"class_name\":\"Array\"")));
+    }
+
+    #[test]
+    fn test_usage_errors() {
+        let path = saved_image("usage");
+        let (unknown_command, _) = run_command(&["frobnicate", path.to_str().unwrap()]);
+        let (missing_index, _) = run_command(&["show", path.to_str().unwrap()]);
+        let (unknown_format, _) = run_command(&["dot", path.to_str().unwrap(), "--format", "svg"]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(unknown_command, Err(CliError::Usage(_))));
+        assert!(matches!(missing_index, Err(CliError::Usage(_))));
+        assert!(matches!(unknown_format, Err(CliError::Usage(_))));
+        assert!(matches!(run_command(&["info"]).0, Err(CliError::Usage(_))));
+    }
+}
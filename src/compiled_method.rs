@@ -0,0 +1,150 @@
+use crate::slot_content::SlotContent;
+
+// CompiledMethod slots, 1 based like the oop slots.
+// The literals follow the header, then come the bytecodes.
+pub mod compiled_method_constants {
+    pub const HEADER_INDEX: usize = 1;
+    pub const FIRST_LITERAL_INDEX: usize = 2;
+
+    // Frame sizes, as in the Squeak contexts
+    pub const SMALL_FRAME_SIZE: usize = 16;
+    pub const LARGE_FRAME_SIZE: usize = 56;
+}
+
+// The header of a method is stored as a SmallInteger in its first slot.
+//  bits  0-15: number of literals
+//  bits 17-22: number of temporaries (arguments included)
+//  bit     23: large frame
+//  bits 24-27: number of arguments
+//  bits 32-47: primitive index (0 for none)
+#[derive(Debug, Default, PartialEq)]
+pub struct MethodHeader {
+    value: usize,
+}
+
+impl MethodHeader {
+    pub fn new(
+        number_of_arguments: usize,
+        number_of_temporaries: usize,
+        number_of_literals: usize,
+        primitive_index: usize,
+    ) -> Self {
+        let mut header = Self { value: 0 };
+        header.set_number_of_arguments(number_of_arguments);
+        header.set_number_of_temporaries(number_of_temporaries);
+        header.set_number_of_literals(number_of_literals);
+        header.set_primitive_index(primitive_index);
+        header
+    }
+
+    pub fn from_slot_value(slot_value: usize) -> Self {
+        Self {
+            value: SlotContent::new(slot_value).as_small_integer() as usize,
+        }
+    }
+
+    pub fn as_slot_value(&self) -> usize {
+        SlotContent::small_integer(self.value as isize)
+    }
+
+    // Fields
+    pub fn number_of_literals(&self) -> usize {
+        self.value & 0xFFFF
+    }
+
+    pub fn set_number_of_literals(&mut self, number_of_literals: usize) {
+        self.value = (self.value & !0xFFFF) | (number_of_literals & 0xFFFF);
+    }
+
+    pub fn number_of_temporaries(&self) -> usize {
+        (self.value & 0x7E0000) >> 17
+    }
+
+    pub fn set_number_of_temporaries(&mut self, number_of_temporaries: usize) {
+        self.value = (self.value & !0x7E0000) | ((number_of_temporaries << 17) & 0x7E0000);
+    }
+
+    pub fn is_large_frame(&self) -> bool {
+        self.value & 0x800000 != 0
+    }
+
+    pub fn set_large_frame(&mut self) {
+        self.value |= 0x800000;
+    }
+
+    pub fn number_of_arguments(&self) -> usize {
+        (self.value & 0xF000000) >> 24
+    }
+
+    pub fn set_number_of_arguments(&mut self, number_of_arguments: usize) {
+        self.value = (self.value & !0xF000000) | ((number_of_arguments << 24) & 0xF000000);
+    }
+
+    pub fn primitive_index(&self) -> usize {
+        (self.value & 0xFFFF00000000) >> 32
+    }
+
+    pub fn set_primitive_index(&mut self, primitive_index: usize) {
+        self.value = (self.value & !0xFFFF00000000) | ((primitive_index << 32) & 0xFFFF00000000);
+    }
+
+    // Testing
+    pub fn has_primitive(&self) -> bool {
+        self.primitive_index() != 0
+    }
+
+    // 1 based byte index of the first bytecode, right after the literals
+    pub fn initial_pc(&self) -> usize {
+        (compiled_method_constants::FIRST_LITERAL_INDEX - 1 + self.number_of_literals()) * 8 + 1
+    }
+
+    pub fn frame_size(&self) -> usize {
+        if self.is_large_frame() {
+            compiled_method_constants::LARGE_FRAME_SIZE
+        } else {
+            compiled_method_constants::SMALL_FRAME_SIZE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiled_method::{compiled_method_constants, MethodHeader};
+
+    #[test]
+    fn test_header_fields() {
+        let header = MethodHeader::new(2, 5, 3, 60);
+
+        assert_eq!(header.number_of_arguments(), 2);
+        assert_eq!(header.number_of_temporaries(), 5);
+        assert_eq!(header.number_of_literals(), 3);
+        assert_eq!(header.primitive_index(), 60);
+        assert!(!header.is_large_frame());
+    }
+
+    #[test]
+    fn test_header_slot_value_round_trip() {
+        let mut header = MethodHeader::new(1, 1, 10, 0);
+        header.set_large_frame();
+
+        let read_header = MethodHeader::from_slot_value(header.as_slot_value());
+
+        assert_eq!(read_header, header);
+        assert_eq!(
+            read_header.frame_size(),
+            compiled_method_constants::LARGE_FRAME_SIZE
+        );
+    }
+
+    #[test]
+    fn test_header_without_primitive() {
+        let header = MethodHeader::new(0, 0, 0, 0);
+        assert!(!header.has_primitive());
+    }
+
+    #[test]
+    fn test_initial_pc_skips_the_literals() {
+        let header = MethodHeader::new(0, 0, 2, 0);
+        assert_eq!(header.initial_pc(), 3 * 8 + 1);
+    }
+}
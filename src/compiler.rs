@@ -0,0 +1,373 @@
+use std::fmt;
+
+use crate::interpreter::Interpreter;
+use crate::method_dictionary;
+use crate::symbol_table;
+
+pub mod code_generator;
+pub mod file_in;
+pub mod parser;
+pub mod scanner;
+pub mod variable_analysis;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub message: String,
+    // Offset in the source, in characters
+    pub position: usize,
+}
+
+impl CompileError {
+    pub fn new(message: &str, position: usize) -> Self {
+        Self {
+            message: String::from(message),
+            position,
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{} (at {})", self.message, self.position)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+// Answers a CompiledMethod, not installed in its class
+pub fn compile_method(
+    interpreter: &mut Interpreter,
+    class: usize,
+    source: &str,
+) -> Result<usize, CompileError> {
+    let method = parser::parse_method(source)?;
+    code_generator::generate(interpreter, class, &method)
+}
+
+// Compiles the method and installs it in the method dictionary of the class
+pub fn install_method(
+    interpreter: &mut Interpreter,
+    class: usize,
+    source: &str,
+) -> Result<usize, CompileError> {
+    let method = parser::parse_method(source)?;
+    let compiled_method = code_generator::generate(interpreter, class, &method)?;
+    let selector = symbol_table::intern(interpreter, &method.selector);
+    method_dictionary::install_method(interpreter, class, selector, compiled_method);
+    Ok(compiled_method)
+}
+
+// Runs the statements with nil as receiver, answers the value of the last one
+pub fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<usize, CompileError> {
+    let method = parser::parse_expression(source)?;
+    let nil = interpreter.nil_object();
+    let undefined_object = interpreter.class_of(nil);
+    let compiled_method = code_generator::generate(interpreter, undefined_object, &method)?;
+    Ok(interpreter.run_method(compiled_method, nil, &[]))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block_closure::block_closure_constants;
+    use crate::bootstrap::{bootstrap, define_class};
+    use crate::compiler::{evaluate, install_method};
+    use crate::header_format_values::HeaderFormatValues;
+    use crate::interpreter::Interpreter;
+    use crate::slot_content::SlotContent;
+    use crate::stack_zone::context_constants;
+    use crate::symbol_table::intern;
+
+    fn evaluate_ok(interpreter: &mut Interpreter, source: &str) -> usize {
+        evaluate(interpreter, source).unwrap()
+    }
+
+    #[parameterized(source={
+        "3 + 4",
+        "3 + 4 * 2 - 7",
+        "(3 * 3) - (4 // 2)",
+        "| x | x := 5. x + 2",
+        "-3 abs + 4",
+        "16r10 - 9",
+        "20 // 3 + 1",
+        "3 > 2 ifTrue: [7] ifFalse: [0]",
+        "3 < 2 ifTrue: [0] ifFalse: [7]",
+        "| i | i := 0. [i < 7] whileTrue: [i := i + 1]. i",
+        "| sum | sum := 0. 1 to: 3 do: [:each | sum := sum + each]. sum + 1",
+        "(3 = 3) & (4 ~= 5) ifTrue: [7]",
+        "(3 = 4) | false ifTrue: [0] ifFalse: [7]",
+        "(true and: [7 > 3]) ifTrue: [7]",
+        "nil isNil ifTrue: [7]",
+        "[7] value",
+        "[:x | x + 4] value: 3",
+        "[:a :b :c :d | a + b + c + d] value: 1 value: 2 value: 2 value: 2",
+        "[:a :b | a + b] valueWithArguments: #(3 4)",
+        "([:x | [:y | x + y]] value: 3) value: 4",
+        "| a | a := 3. [:x | a + x] value: 4",
+        "| a | a := 0. [a := 7] value. a",
+        "| a | a := 1. [[a := a + 6] value] value. a",
+        "| sum | sum := 0. #(1 2 4) do: [:each | sum := sum + each]. sum",
+        "| i | i := 0. [i := i + 1. i < 7] whileTrue. i",
+        "| block | block := [7]. true ifTrue: block",
+        "[:x :y | x] numArgs + 5",
+        "[] value isNil ifTrue: [7]"
+    })]
+    fn test_evaluate_answers_seven(source: &str) {
+        let mut interpreter = bootstrap(40000);
+        assert_eq!(evaluate_ok(&mut interpreter, source), SlotContent::small_integer(7));
+    }
+
+    #[test]
+    fn test_evaluate_literals() {
+        let mut interpreter = bootstrap(40000);
+        let symbol = evaluate_ok(&mut interpreter, "#foo:bar:");
+        let string = evaluate_ok(&mut interpreter, "'hello'");
+        let character = evaluate_ok(&mut interpreter, "$a");
+
+        assert_eq!(symbol, intern(&mut interpreter, "foo:bar:"));
+        assert_eq!(
+            interpreter.string_value_of(string),
+            Some(String::from("hello"))
+        );
+        assert_eq!(
+            character,
+            SlotContent::from_character('a' as u32).get_content()
+        );
+    }
+
+    #[test]
+    fn test_evaluate_literal_array() {
+        let mut interpreter = bootstrap(40000);
+        let size = evaluate_ok(&mut interpreter, "#(1 $a foo (2 3)) size");
+        let last = evaluate_ok(&mut interpreter, "(#(1 $a foo (2 3)) at: 4) at: 2");
+
+        assert_eq!(size, SlotContent::small_integer(4));
+        assert_eq!(last, SlotContent::small_integer(3));
+    }
+
+    #[test]
+    fn test_installed_methods_are_sent() {
+        let mut interpreter = bootstrap(40000);
+        let object = interpreter.class_named("Object").unwrap();
+        let class = define_class(
+            &mut interpreter,
+            "Point",
+            object,
+            HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+            &["x", "y"],
+        );
+        install_method(&mut interpreter, class, "x ^x").unwrap();
+        install_method(&mut interpreter, class, "y ^y").unwrap();
+        install_method(&mut interpreter, class, "x: anX y: aY x := anX. y := aY").unwrap();
+        install_method(
+            &mut interpreter,
+            class,
+            "+ aPoint ^self class new x: x + aPoint x y: y + aPoint y; yourself",
+        )
+        .unwrap();
+
+        let x = evaluate_ok(
+            &mut interpreter,
+            "| a b | a := Point new x: 1 y: 2; yourself. b := Point new x: 3 y: 4; yourself. (a + b) x",
+        );
+        assert_eq!(x, SlotContent::small_integer(4));
+    }
+
+    #[test]
+    fn test_super_send_starts_above_the_method_class() {
+        let mut interpreter = bootstrap(40000);
+        let object = interpreter.class_named("Object").unwrap();
+        let zero_sized = HeaderFormatValues::ZeroSizedFormat as usize;
+        let base = define_class(&mut interpreter, "Base", object, zero_sized, &[]);
+        let derived = define_class(&mut interpreter, "Derived", base, zero_sized, &[]);
+        install_method(&mut interpreter, base, "value ^1").unwrap();
+        install_method(&mut interpreter, derived, "value ^super value + 10").unwrap();
+
+        let value = evaluate_ok(&mut interpreter, "Derived new value");
+        assert_eq!(value, SlotContent::small_integer(11));
+    }
+
+    #[test]
+    fn test_send_message_runs_a_compiled_method() {
+        let mut interpreter = bootstrap(40000);
+        let small_integer_class = interpreter.class_named("SmallInteger").unwrap();
+        install_method(
+            &mut interpreter,
+            small_integer_class,
+            "factorial self <= 1 ifTrue: [^1]. ^self * (self - 1) factorial",
+        )
+        .unwrap();
+        let selector = intern(&mut interpreter, "factorial");
+
+        let value = interpreter.send_message(SlotContent::small_integer(5), selector, &[]);
+        assert_eq!(value, SlotContent::small_integer(120));
+    }
+
+    #[test]
+    fn test_many_temporaries_get_a_large_frame() {
+        let mut interpreter = bootstrap(40000);
+        let temporaries: Vec<String> = (0..20).map(|index| format!("t{}", index)).collect();
+        let source = format!("| {} | t19 := 7. t0 := t19. t0", temporaries.join(" "));
+
+        assert_eq!(evaluate_ok(&mut interpreter, &source), SlotContent::small_integer(7));
+    }
+
+    #[test]
+    fn test_non_local_return_leaves_the_home_method() {
+        let mut interpreter = bootstrap(40000);
+        let small_integer_class = interpreter.class_named("SmallInteger").unwrap();
+        install_method(
+            &mut interpreter,
+            small_integer_class,
+            "firstAbove: anArray anArray do: [:each | each > self ifTrue: [^each]]. ^nil",
+        )
+        .unwrap();
+
+        let value = evaluate_ok(&mut interpreter, "(5 firstAbove: #(1 7 9)) + 0");
+        assert_eq!(value, SlotContent::small_integer(7));
+    }
+
+    #[test]
+    fn test_non_local_return_from_nested_closures() {
+        let mut interpreter = bootstrap(40000);
+        let object = interpreter.class_named("Object").unwrap();
+        install_method(
+            &mut interpreter,
+            object,
+            "seven [:x | [:y | ^x + y] value: 4] value: 3. ^0",
+        )
+        .unwrap();
+
+        assert_eq!(evaluate_ok(&mut interpreter, "nil seven"), SlotContent::small_integer(7));
+    }
+
+    #[test]
+    fn test_return_to_a_dead_home_context_sends_cannot_return() {
+        let mut interpreter = bootstrap(40000);
+        let object = interpreter.class_named("Object").unwrap();
+        let context = interpreter.class_named("Context").unwrap();
+        install_method(&mut interpreter, object, "escaper ^[:x | ^x]").unwrap();
+        install_method(
+            &mut interpreter,
+            context,
+            "cannotReturn: value ^value + 100",
+        )
+        .unwrap();
+
+        let value = evaluate_ok(&mut interpreter, "nil escaper value: 7");
+        assert_eq!(value, SlotContent::small_integer(107));
+    }
+
+    #[test]
+    fn test_closure_outlives_its_outer_context() {
+        let mut interpreter = bootstrap(40000);
+        let closure = evaluate_ok(&mut interpreter, "[:y | [:x | y + x]] value: 3");
+        let block_closure_class = interpreter.class_named("BlockClosure").unwrap();
+        assert_eq!(interpreter.class_of(closure), block_closure_class);
+
+        let closure_oop = interpreter.space.get_oop_at(closure);
+        let outer_context = closure_oop.slot_at_index(block_closure_constants::OUTER_CONTEXT_INDEX);
+        let copied_value =
+            closure_oop.slot_at_index(block_closure_constants::NUMBER_OF_FIXED_SLOTS + 1);
+        let pc = interpreter
+            .space
+            .get_oop_at(outer_context)
+            .slot_at_index(context_constants::PC_INDEX);
+        assert_eq!(copied_value, SlotContent::small_integer(3));
+        assert_eq!(pc, interpreter.nil_object());
+    }
+
+    #[parameterized(source={
+        "[Error signal. 0] on: Error do: [:e | 7]",
+        "[Error new signal: 'boom'] on: Error do: [:e | e messageText size + 3]",
+        "[ZeroDivide signal] on: Error do: [7]",
+        "[[Error signal] on: ZeroDivide do: [:e | 0]] on: Error do: [:e | 7]",
+        "[Error signal. 0] on: Error do: [:e | e return: 7]",
+        "([Notification signal] on: Notification do: [:e | e resume: 4]) + 3",
+        "(Notification signal) isNil ifTrue: [7]",
+        "[[Error signal] on: Error do: [:e | e resume: 0]] on: Error do: [:e | 7]",
+        "| count | count := 0. [count := count + 1. count < 7 ifTrue: [Error signal]. count] on: Error do: [:e | e retry]",
+        "[[Error signal] on: Error do: [:e | e pass]] on: Error do: [:e | 7]",
+        "[([Notification signal] on: Notification do: [:e | e outer + 3]) + 2] on: Notification do: [:e | e resume: 2]",
+        "[[Error signal] on: Error do: [:e | Error signal]] on: Error do: [:e | 7]",
+        "| a | a := 0. [a := 3] ensure: [a := a + 4]. a",
+        "| a | a := 0. [[Error signal] ensure: [a := 7]] on: Error do: [:e | 0]. a",
+        "| a | a := 0. [[Error signal] ifCurtailed: [a := 7]] on: Error do: [:e | 0]. a",
+        "| a | a := 7. [3] ifCurtailed: [a := 0]. a",
+        "| a | a := 0. [[[Error signal] ensure: [a := a + 3]] ensure: [a := a + 4]] on: Error do: [:e | 0]. a",
+        "[3 foo] on: MessageNotUnderstood do: [:e | e message selector == #foo ifTrue: [7]]",
+        "([3 foo] on: MessageNotUnderstood do: [:e | e resume: 4]) + 3",
+        "[3 ifTrue: [0]] on: Error do: [:e | 7]",
+        "[3 / 0] on: ZeroDivide do: [:e | 7]",
+        "[3 // 0] on: ZeroDivide do: [:e | 7]",
+        "[#(1 2) at: 3] on: Error do: [:e | 7]",
+        "[nil instVarAt: 1] on: Error do: [:e | 7]"
+    })]
+    fn test_exceptions_answer_seven(source: &str) {
+        let mut interpreter = bootstrap(40000);
+        assert_eq!(evaluate_ok(&mut interpreter, source), SlotContent::small_integer(7));
+        assert_eq!(interpreter.take_unhandled_exception(), None);
+    }
+
+    #[test]
+    fn test_unhandled_exception_is_kept_by_the_interpreter() {
+        let mut interpreter = bootstrap(40000);
+        let value = evaluate_ok(&mut interpreter, "3 foo. 4");
+        let exception = interpreter.take_unhandled_exception().unwrap();
+        let message_not_understood = interpreter.class_named("MessageNotUnderstood").unwrap();
+
+        assert_eq!(value, interpreter.nil_object());
+        assert_eq!(interpreter.class_of(exception), message_not_understood);
+        assert_eq!(interpreter.take_unhandled_exception(), None);
+    }
+
+    #[test]
+    fn test_unhandled_exception_runs_the_ensure_blocks() {
+        let mut interpreter = bootstrap(40000);
+        let object = interpreter.class_named("Object").unwrap();
+        define_class(
+            &mut interpreter,
+            "Counter",
+            object,
+            HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+            &["count"],
+        );
+        let counter = interpreter.class_named("Counter").unwrap();
+        install_method(&mut interpreter, counter, "count ^count").unwrap();
+        install_method(
+            &mut interpreter,
+            counter,
+            "run [Error signal: 'unhandled'] ensure: [count := 7]",
+        )
+        .unwrap();
+
+        let instance = evaluate_ok(&mut interpreter, "Counter new");
+        let run = intern(&mut interpreter, "run");
+        let count = intern(&mut interpreter, "count");
+        let value = interpreter.send_message(instance, run, &[]);
+        assert_eq!(value, interpreter.nil_object());
+        assert_eq!(
+            interpreter.send_message(instance, count, &[]),
+            SlotContent::small_integer(7)
+        );
+        assert!(interpreter.take_unhandled_exception().is_some());
+    }
+
+    #[parameterized(source={
+        "x := 3",
+        "Unknown new",
+        "[:y | y := 1]",
+        "3 +",
+        "thisContext := nil"
+    })]
+    fn test_compile_errors(source: &str) {
+        let mut interpreter = bootstrap(40000);
+        assert!(evaluate(&mut interpreter, source).is_err());
+    }
+
+    #[test]
+    fn test_assigning_an_argument_is_an_error() {
+        let mut interpreter = bootstrap(40000);
+        let object = interpreter.class_named("Object").unwrap();
+        assert!(install_method(&mut interpreter, object, "foo: x x := 3").is_err());
+    }
+}
@@ -0,0 +1,1170 @@
+use crate::bytecodes::{bytecode_constants, special_selector_index};
+use crate::class_table::class_constants;
+use crate::compiled_method::{compiled_method_constants, MethodHeader};
+use crate::compiler::parser::{Block, Literal, Method, Node, Primitive};
+use crate::compiler::variable_analysis::{
+    block_owner, free_variables, is_inlined, VariableAnalysis, METHOD_OWNER,
+};
+use crate::compiler::CompileError;
+use crate::immutability;
+use crate::interpreter::Interpreter;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::primitives::external_primitives::external_call_constants;
+use crate::slot_content::SlotContent;
+use crate::special_object_index::SpecialObjectIndexes;
+use crate::symbol_table;
+
+pub mod code_generator_constants {
+    // Bits of the method header
+    pub const MAX_ARGUMENTS: usize = 15;
+    pub const MAX_TEMPORARIES: usize = 63;
+    pub const MAX_LITERALS: usize = 256;
+
+    pub const EXTERNAL_CALL_PRIMITIVE: usize = 117;
+
+    // Long jumps: backward down to -1024, forward up to 1023
+    pub const MAX_JUMP: isize = 1023;
+    pub const MIN_JUMP: isize = -1024;
+
+    // Bytes of the closure bytecodes
+    pub const MAX_TEMP_VECTOR_SIZE: usize = 127;
+    pub const MAX_COPIED_VALUES: usize = 255;
+}
+
+const PSEUDO_VARIABLES: [&str; 3] = ["self", "super", "thisContext"];
+
+#[derive(Clone, Copy)]
+enum VariableKind {
+    Temporary(usize),
+    // Slot of the temp vector held by the temporary
+    Remote { vector: usize, index: usize },
+}
+
+struct Variable {
+    name: String,
+    kind: VariableKind,
+    assignable: bool,
+}
+
+struct Scope {
+    // Index of the code unit whose frame holds the variables
+    unit: usize,
+    variables: Vec<Variable>,
+}
+
+enum JumpKind {
+    Always,
+    IfTrue,
+    IfFalse,
+}
+
+fn block_of(node: &Node) -> &Block {
+    match node {
+        Node::Block(block) => block,
+        _ => unreachable!(),
+    }
+}
+
+// The AST has no positions, semantic errors point at the start of the source
+fn error(message: &str) -> CompileError {
+    CompileError::new(message, 0)
+}
+
+// The method, or one of its closures: each has its own frame, literals and bytecodes
+#[derive(Default)]
+struct CodeUnit {
+    is_closure: bool,
+    literals: Vec<usize>,
+    bytes: Vec<u8>,
+    number_of_temporaries: usize,
+    depth: usize,
+    max_depth: usize,
+    // The temporary holding the remote temporaries, if there are some
+    temp_vector: Option<usize>,
+    number_of_remote_temporaries: usize,
+    // CompiledBlocks of the closures created here, their outer code is this unit
+    compiled_blocks: Vec<usize>,
+}
+
+struct CodeGenerator<'a> {
+    interpreter: &'a mut Interpreter,
+    instance_variables: Vec<String>,
+    analysis: VariableAnalysis,
+    // Innermost unit last
+    units: Vec<CodeUnit>,
+    // Innermost scope last, blocks open a scope
+    scopes: Vec<Scope>,
+}
+
+impl<'a> CodeGenerator<'a> {
+    fn new(interpreter: &'a mut Interpreter, class: usize, analysis: VariableAnalysis) -> Self {
+        let instance_variables = instance_variables_of(interpreter, class);
+        Self {
+            interpreter,
+            instance_variables,
+            analysis,
+            units: Vec::new(),
+            scopes: Vec::new(),
+        }
+    }
+
+    fn unit(&mut self) -> &mut CodeUnit {
+        self.units.last_mut().unwrap()
+    }
+
+    // Stack bookkeeping, to size the frame
+    fn grow(&mut self, count: usize) {
+        let unit = self.unit();
+        unit.depth += count;
+        unit.max_depth = unit.max_depth.max(unit.depth);
+    }
+
+    fn shrink(&mut self, count: usize) {
+        self.unit().depth -= count;
+    }
+
+    fn emit(&mut self, byte: u8) {
+        self.unit().bytes.push(byte);
+    }
+
+    fn position(&mut self) -> usize {
+        self.unit().bytes.len()
+    }
+
+    // Scopes
+    fn push_scope(&mut self) {
+        self.scopes.push(Scope {
+            unit: self.units.len() - 1,
+            variables: Vec::new(),
+        });
+    }
+
+    fn new_temporary(&mut self) -> Result<usize, CompileError> {
+        let unit = self.unit();
+        let index = unit.number_of_temporaries;
+        if index >= code_generator_constants::MAX_TEMPORARIES {
+            return Err(error("Too many temporaries"));
+        }
+        unit.number_of_temporaries += 1;
+        Ok(index)
+    }
+
+    fn add_variable(
+        &mut self,
+        name: &str,
+        kind: VariableKind,
+        assignable: bool,
+    ) -> Result<(), CompileError> {
+        if PSEUDO_VARIABLES.contains(&name) || ["nil", "true", "false"].contains(&name) {
+            return Err(error(&format!("Cannot use {} as a variable name", name)));
+        }
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.variables.iter().any(|variable| variable.name == name) {
+            return Err(error(&format!("{} is declared twice", name)));
+        }
+        scope.variables.push(Variable {
+            name: String::from(name),
+            kind,
+            assignable,
+        });
+        Ok(())
+    }
+
+    // Captured temporaries assigned somewhere go to the temp vector
+    fn declare(
+        &mut self,
+        owner: usize,
+        name: &str,
+        assignable: bool,
+    ) -> Result<VariableKind, CompileError> {
+        let temp_vector = self.unit().temp_vector;
+        let kind = match temp_vector {
+            Some(vector) if assignable && self.analysis.is_remote(owner, name) => {
+                let unit = self.unit();
+                unit.number_of_remote_temporaries += 1;
+                VariableKind::Remote {
+                    vector,
+                    index: unit.number_of_remote_temporaries - 1,
+                }
+            }
+            _ => VariableKind::Temporary(self.new_temporary()?),
+        };
+        self.add_variable(name, kind, assignable)?;
+        Ok(kind)
+    }
+
+    // The variables of the enclosing units are reached through the copied values only
+    fn variable_named(&self, name: &str) -> Option<&Variable> {
+        let current_unit = self.units.len() - 1;
+        self.scopes
+            .iter()
+            .rev()
+            .take_while(|scope| scope.unit == current_unit)
+            .flat_map(|scope| scope.variables.iter())
+            .find(|variable| variable.name == name)
+    }
+
+    fn instance_variable_index(&self, name: &str) -> Option<usize> {
+        self.instance_variables
+            .iter()
+            .rposition(|instance_variable| instance_variable == name)
+    }
+
+    // The temp vector is created when the frame is entered
+    fn create_temp_vector(&mut self, frame_owner: usize) -> Result<(), CompileError> {
+        let size = self.analysis.remote_count(frame_owner);
+        if size == 0 {
+            return Ok(());
+        }
+        if size > code_generator_constants::MAX_TEMP_VECTOR_SIZE {
+            return Err(error("Too many captured temporaries"));
+        }
+        let vector = self.new_temporary()?;
+        self.emit(bytecode_constants::PUSH_NEW_ARRAY);
+        self.emit(size as u8);
+        self.grow(1);
+        self.emit_pop_store_temporary(vector)?;
+        self.unit().temp_vector = Some(vector);
+        Ok(())
+    }
+
+    // Literals
+    fn literal_index(&mut self, literal: usize) -> Result<usize, CompileError> {
+        let literals = &mut self.unit().literals;
+        if let Some(index) = literals.iter().position(|each| *each == literal) {
+            return Ok(index);
+        }
+        // Room is kept for the selector and the class
+        if literals.len() + 2 >= code_generator_constants::MAX_LITERALS {
+            return Err(error("Too many literals"));
+        }
+        literals.push(literal);
+        Ok(literals.len() - 1)
+    }
+
+    fn new_array_of(&mut self, elements: &[usize]) -> usize {
+        let array_class = self
+            .interpreter
+            .special_object(SpecialObjectIndexes::ClassArray);
+        let array = self
+            .interpreter
+            .instantiate_class(array_class, elements.len())
+            .expect("Array should be indexable");
+        let mut array_oop = self.interpreter.space.get_oop_at(array);
+        for (index, element) in elements.iter().enumerate() {
+            array_oop.slot_at_index_put(index + 1, *element);
+        }
+        array
+    }
+
+    // The strings, symbols and arrays are immutable, the elements of the arrays included
+    fn literal_object(&mut self, literal: &Literal) -> usize {
+        let object = match literal {
+            Literal::Nil => self.interpreter.nil_object(),
+            Literal::True => self.interpreter.true_object(),
+            Literal::False => self.interpreter.false_object(),
+            Literal::Integer(value) => SlotContent::small_integer(*value),
+            Literal::Character(value) => SlotContent::from_character(*value as u32).get_content(),
+            Literal::String(value) => {
+                let string_class = self
+                    .interpreter
+                    .special_object(SpecialObjectIndexes::ClassByteString);
+                self.interpreter
+                    .instantiate_class_with_bytes(string_class, value.as_bytes())
+                    .expect("ByteString should be bytes")
+            }
+            Literal::Symbol(value) => symbol_table::intern(self.interpreter, value),
+            Literal::Array(elements) => {
+                let elements: Vec<usize> = elements
+                    .iter()
+                    .map(|element| self.literal_object(element))
+                    .collect();
+                self.new_array_of(&elements)
+            }
+        };
+        if matches!(
+            literal,
+            Literal::String(_) | Literal::Symbol(_) | Literal::Array(_)
+        ) {
+            immutability::set_immutable_bit_of(&mut self.interpreter.space, object, true);
+        }
+        object
+    }
+
+    // Pushes
+    fn push_literal_constant(&mut self, literal: usize) -> Result<(), CompileError> {
+        let index = self.literal_index(literal)?;
+        if index < 32 {
+            self.emit(bytecode_constants::PUSH_LITERAL_CONSTANT + index as u8);
+        } else if index < 64 {
+            self.emit(bytecode_constants::EXTENDED_PUSH);
+            self.emit((bytecode_constants::LITERAL_CONSTANT_TYPE << 6) | index as u8);
+        } else {
+            self.emit(bytecode_constants::DOUBLE_EXTENDED);
+            self.emit(bytecode_constants::DOUBLE_EXTENDED_PUSH_LITERAL_CONSTANT << 5);
+            self.emit(index as u8);
+        }
+        self.grow(1);
+        Ok(())
+    }
+
+    fn push_literal(&mut self, literal: &Literal) -> Result<(), CompileError> {
+        match literal {
+            Literal::Nil => self.emit(bytecode_constants::PUSH_NIL),
+            Literal::True => self.emit(bytecode_constants::PUSH_TRUE),
+            Literal::False => self.emit(bytecode_constants::PUSH_FALSE),
+            Literal::Integer(value) if (-1..=2).contains(value) => {
+                self.emit((bytecode_constants::PUSH_MINUS_ONE as isize + value + 1) as u8)
+            }
+            _ => {
+                let literal = self.literal_object(literal);
+                return self.push_literal_constant(literal);
+            }
+        }
+        self.grow(1);
+        Ok(())
+    }
+
+    fn push_short_or_extended(&mut self, short_base: u8, variable_type: u8, index: usize) {
+        if index < 16 {
+            self.emit(short_base + index as u8);
+        } else {
+            self.emit(bytecode_constants::EXTENDED_PUSH);
+            self.emit((variable_type << 6) | index as u8);
+        }
+        self.grow(1);
+    }
+
+    fn push_temporary(&mut self, kind: VariableKind) {
+        match kind {
+            VariableKind::Temporary(index) => self.push_short_or_extended(
+                bytecode_constants::PUSH_TEMPORARY,
+                bytecode_constants::TEMPORARY_TYPE,
+                index,
+            ),
+            VariableKind::Remote { vector, index } => {
+                self.emit(bytecode_constants::PUSH_REMOTE_TEMPORARY);
+                self.emit(index as u8);
+                self.emit(vector as u8);
+                self.grow(1);
+            }
+        }
+    }
+
+    fn push_variable(&mut self, name: &str) -> Result<(), CompileError> {
+        match name {
+            "self" | "super" => {
+                self.emit(bytecode_constants::PUSH_RECEIVER);
+                self.grow(1);
+                return Ok(());
+            }
+            "thisContext" => {
+                self.emit(bytecode_constants::PUSH_THIS_CONTEXT);
+                self.grow(1);
+                return Ok(());
+            }
+            _ => {}
+        }
+        if let Some(kind) = self.variable_named(name).map(|variable| variable.kind) {
+            self.push_temporary(kind);
+            return Ok(());
+        }
+        if let Some(index) = self.instance_variable_index(name) {
+            if index >= 64 {
+                return Err(error("Too many instance variables"));
+            }
+            self.push_short_or_extended(
+                bytecode_constants::PUSH_RECEIVER_VARIABLE,
+                bytecode_constants::RECEIVER_VARIABLE_TYPE,
+                index,
+            );
+            return Ok(());
+        }
+        // There are no global variables yet, only classes
+        match self.interpreter.class_named(name) {
+            Some(class) => self.push_literal_constant(class),
+            None => Err(error(&format!("Undeclared variable {}", name))),
+        }
+    }
+
+    // Stores, the value stays on the stack unless it is popped
+    fn store_variable(&mut self, name: &str, pop: bool) -> Result<(), CompileError> {
+        let (variable_type, index, short_base) = if let Some(variable) = self.variable_named(name) {
+            if !variable.assignable {
+                return Err(error(&format!("Cannot assign to the argument {}", name)));
+            }
+            match variable.kind {
+                VariableKind::Temporary(index) => (
+                    bytecode_constants::TEMPORARY_TYPE,
+                    index,
+                    bytecode_constants::POP_STORE_TEMPORARY,
+                ),
+                VariableKind::Remote { vector, index } => {
+                    self.store_remote_temporary(vector, index, pop);
+                    return Ok(());
+                }
+            }
+        } else if let Some(index) = self.instance_variable_index(name) {
+            (
+                bytecode_constants::RECEIVER_VARIABLE_TYPE,
+                index,
+                bytecode_constants::POP_STORE_RECEIVER_VARIABLE,
+            )
+        } else if PSEUDO_VARIABLES.contains(&name) {
+            return Err(error(&format!("Cannot assign to {}", name)));
+        } else if self.interpreter.class_named(name).is_some() {
+            return Err(error(&format!("Cannot assign to the class {}", name)));
+        } else {
+            return Err(error(&format!("Undeclared variable {}", name)));
+        };
+        if index >= 64 {
+            return Err(error("Too many instance variables"));
+        }
+
+        if pop && index < 8 {
+            self.emit(short_base + index as u8);
+        } else {
+            self.emit(if pop {
+                bytecode_constants::EXTENDED_POP_STORE
+            } else {
+                bytecode_constants::EXTENDED_STORE
+            });
+            self.emit((variable_type << 6) | index as u8);
+        }
+        if pop {
+            self.shrink(1);
+        }
+        Ok(())
+    }
+
+    fn store_remote_temporary(&mut self, vector: usize, index: usize, pop: bool) {
+        self.emit(if pop {
+            bytecode_constants::POP_STORE_REMOTE_TEMPORARY
+        } else {
+            bytecode_constants::STORE_REMOTE_TEMPORARY
+        });
+        self.emit(index as u8);
+        self.emit(vector as u8);
+        if pop {
+            self.shrink(1);
+        }
+    }
+
+    // Jumps are all long, their offset is patched once the target is known
+    fn jump_forward(&mut self, kind: JumpKind) -> usize {
+        let position = self.position();
+        self.emit(match kind {
+            JumpKind::Always => bytecode_constants::LONG_JUMP,
+            JumpKind::IfTrue => bytecode_constants::LONG_JUMP_IF_TRUE,
+            JumpKind::IfFalse => bytecode_constants::LONG_JUMP_IF_FALSE,
+        });
+        self.emit(0);
+        if !matches!(kind, JumpKind::Always) {
+            self.shrink(1);
+        }
+        position
+    }
+
+    fn patch_jump(&mut self, position: usize) -> Result<(), CompileError> {
+        let bytes = &mut self.unit().bytes;
+        let offset = (bytes.len() - position - 2) as isize;
+        if offset > code_generator_constants::MAX_JUMP {
+            return Err(error("Jump too long"));
+        }
+        if bytes[position] == bytecode_constants::LONG_JUMP {
+            bytes[position] += (offset / 256 + 4) as u8;
+        } else {
+            bytes[position] += (offset / 256) as u8;
+        }
+        bytes[position + 1] = (offset % 256) as u8;
+        Ok(())
+    }
+
+    fn jump_back_to(&mut self, target: usize) -> Result<(), CompileError> {
+        let offset = target as isize - (self.position() + 2) as isize;
+        if offset < code_generator_constants::MIN_JUMP {
+            return Err(error("Jump too long"));
+        }
+        let biased = offset + 4 * 256;
+        self.emit(bytecode_constants::LONG_JUMP + (biased / 256) as u8);
+        self.emit((biased % 256) as u8);
+        Ok(())
+    }
+
+    // Statements
+    fn generate_statements(&mut self, statements: &[Node]) -> Result<(), CompileError> {
+        for statement in statements {
+            self.generate_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    // Statements leave nothing on the stack
+    fn generate_statement(&mut self, statement: &Node) -> Result<(), CompileError> {
+        match statement {
+            Node::Assignment(name, value) => {
+                self.generate_expression(value)?;
+                self.store_variable(name, true)
+            }
+            Node::Return(_) => {
+                self.generate_expression(statement)?;
+                self.shrink(1);
+                Ok(())
+            }
+            _ => {
+                self.generate_expression(statement)?;
+                self.emit(bytecode_constants::POP);
+                self.shrink(1);
+                Ok(())
+            }
+        }
+    }
+
+    // Expressions leave their value on the stack.
+    // A return counts as a value, the code after it is never reached.
+    fn generate_expression(&mut self, node: &Node) -> Result<(), CompileError> {
+        match node {
+            Node::Literal(literal) => self.push_literal(literal),
+            Node::Variable(name) => self.push_variable(name),
+            Node::Assignment(name, value) => {
+                self.generate_expression(value)?;
+                self.store_variable(name, false)
+            }
+            Node::Message {
+                receiver,
+                selector,
+                arguments,
+            } => self.generate_message(receiver, selector, arguments),
+            Node::Cascade { receiver, messages } => self.generate_cascade(receiver, messages),
+            // Already on the stack, duplicated by the cascade
+            Node::CascadeReceiver => Ok(()),
+            Node::Block(block) => self.generate_closure(block),
+            Node::Return(value) => self.generate_return(value),
+        }
+    }
+
+    // In a closure the return is non-local, it may send cannotReturn: to thisContext
+    fn generate_return(&mut self, value: &Node) -> Result<(), CompileError> {
+        let special_return = match value {
+            Node::Variable(name) if name == "self" => Some(bytecode_constants::RETURN_RECEIVER),
+            Node::Literal(Literal::True) => Some(bytecode_constants::RETURN_TRUE),
+            Node::Literal(Literal::False) => Some(bytecode_constants::RETURN_FALSE),
+            Node::Literal(Literal::Nil) => Some(bytecode_constants::RETURN_NIL),
+            _ => None,
+        };
+        match special_return {
+            Some(bytecode) => {
+                self.emit(bytecode);
+                self.grow(1);
+            }
+            None => {
+                self.generate_expression(value)?;
+                self.emit(bytecode_constants::RETURN_TOP);
+            }
+        }
+        if self.unit().is_closure {
+            // cannotReturn: is sent with thisContext under the value,
+            // what it answers is returned to the caller of the closure
+            self.grow(1);
+            self.shrink(1);
+            self.emit(bytecode_constants::BLOCK_RETURN_TOP);
+        }
+        Ok(())
+    }
+
+    fn generate_cascade(&mut self, receiver: &Node, messages: &[Node]) -> Result<(), CompileError> {
+        if *receiver == Node::Variable(String::from("super")) {
+            return Err(error("Cannot cascade to super"));
+        }
+        self.generate_expression(receiver)?;
+        let (last, others) = messages.split_last().unwrap();
+        for message in others {
+            self.emit(bytecode_constants::DUPLICATE);
+            self.grow(1);
+            self.generate_expression(message)?;
+            self.emit(bytecode_constants::POP);
+            self.shrink(1);
+        }
+        self.generate_expression(last)
+    }
+
+    fn generate_message(
+        &mut self,
+        receiver: &Node,
+        selector: &str,
+        arguments: &[Node],
+    ) -> Result<(), CompileError> {
+        if is_inlined(receiver, selector, arguments) {
+            return self.generate_inlined(receiver, selector, arguments);
+        }
+
+        let is_super = *receiver == Node::Variable(String::from("super"));
+        self.generate_expression(receiver)?;
+        for argument in arguments {
+            self.generate_expression(argument)?;
+        }
+        let argument_count = arguments.len();
+        if !is_super {
+            if let Some(index) = special_selector_index(selector) {
+                self.emit(bytecode_constants::SPECIAL_SEND + index as u8);
+                self.shrink(argument_count);
+                return Ok(());
+            }
+        }
+
+        let selector = symbol_table::intern(self.interpreter, selector);
+        let index = self.literal_index(selector)?;
+        if argument_count > 31 {
+            return Err(error("Too many arguments"));
+        }
+        if !is_super && index < 16 && argument_count <= 2 {
+            self.emit(
+                bytecode_constants::SEND_LITERAL_SELECTOR_0
+                    + (argument_count as u8) * 16
+                    + index as u8,
+            );
+        } else if index < 32 && argument_count < 8 {
+            self.emit(if is_super {
+                bytecode_constants::SINGLE_EXTENDED_SUPER
+            } else {
+                bytecode_constants::SINGLE_EXTENDED_SEND
+            });
+            self.emit(((argument_count as u8) << 5) | index as u8);
+        } else {
+            self.emit(bytecode_constants::DOUBLE_EXTENDED);
+            let operation = if is_super {
+                bytecode_constants::DOUBLE_EXTENDED_SUPER_SEND
+            } else {
+                bytecode_constants::DOUBLE_EXTENDED_SEND
+            };
+            self.emit((operation << 5) | argument_count as u8);
+            self.emit(index as u8);
+        }
+        self.shrink(argument_count);
+        Ok(())
+    }
+
+    // Control structures whose arguments are literal blocks are compiled to jumps,
+    // see is_inlined
+    fn generate_inlined(
+        &mut self,
+        receiver: &Node,
+        selector: &str,
+        arguments: &[Node],
+    ) -> Result<(), CompileError> {
+        match selector {
+            "ifTrue:" | "ifFalse:" => {
+                self.generate_expression(receiver)?;
+                let kind = if selector == "ifTrue:" {
+                    JumpKind::IfFalse
+                } else {
+                    JumpKind::IfTrue
+                };
+                let skip_block = self.jump_forward(kind);
+                self.generate_inlined_block(block_of(&arguments[0]))?;
+                let skip_nil = self.jump_forward(JumpKind::Always);
+                self.shrink(1);
+                self.patch_jump(skip_block)?;
+                self.push_literal(&Literal::Nil)?;
+                self.patch_jump(skip_nil)
+            }
+            "ifTrue:ifFalse:" | "ifFalse:ifTrue:" => {
+                self.generate_expression(receiver)?;
+                let kind = if selector == "ifTrue:ifFalse:" {
+                    JumpKind::IfFalse
+                } else {
+                    JumpKind::IfTrue
+                };
+                let skip_first = self.jump_forward(kind);
+                self.generate_inlined_block(block_of(&arguments[0]))?;
+                let skip_second = self.jump_forward(JumpKind::Always);
+                self.shrink(1);
+                self.patch_jump(skip_first)?;
+                self.generate_inlined_block(block_of(&arguments[1]))?;
+                self.patch_jump(skip_second)
+            }
+            "and:" | "or:" => {
+                self.generate_expression(receiver)?;
+                let (kind, shortcut) = if selector == "and:" {
+                    (JumpKind::IfFalse, Literal::False)
+                } else {
+                    (JumpKind::IfTrue, Literal::True)
+                };
+                let skip_block = self.jump_forward(kind);
+                self.generate_inlined_block(block_of(&arguments[0]))?;
+                let skip_shortcut = self.jump_forward(JumpKind::Always);
+                self.shrink(1);
+                self.patch_jump(skip_block)?;
+                self.push_literal(&shortcut)?;
+                self.patch_jump(skip_shortcut)
+            }
+            "whileTrue:" | "whileFalse:" | "whileTrue" | "whileFalse" => {
+                let kind = if selector.starts_with("whileTrue") {
+                    JumpKind::IfFalse
+                } else {
+                    JumpKind::IfTrue
+                };
+                let loop_start = self.position();
+                self.generate_inlined_block(block_of(receiver))?;
+                let exit = self.jump_forward(kind);
+                if let Some(body) = arguments.first() {
+                    self.generate_inlined_block(block_of(body))?;
+                    self.emit(bytecode_constants::POP);
+                    self.shrink(1);
+                }
+                self.jump_back_to(loop_start)?;
+                self.patch_jump(exit)?;
+                self.push_literal(&Literal::Nil)
+            }
+            "to:do:" => self.generate_to_do(receiver, &arguments[0], block_of(&arguments[1])),
+            _ => unreachable!(),
+        }
+    }
+
+    // The loop counter is the block argument, the limit is kept in a hidden temporary
+    fn generate_to_do(
+        &mut self,
+        start: &Node,
+        limit: &Node,
+        block: &Block,
+    ) -> Result<(), CompileError> {
+        self.generate_expression(start)?;
+        self.generate_expression(limit)?;
+        self.push_scope();
+        let limit_index = self.new_temporary()?;
+        self.emit_pop_store_temporary(limit_index)?;
+        let counter = self.declare(block_owner(block), &block.arguments[0], false)?;
+        let counter = match counter {
+            VariableKind::Temporary(index) => index,
+            VariableKind::Remote { .. } => unreachable!(),
+        };
+        self.emit_pop_store_temporary(counter)?;
+
+        let loop_start = self.position();
+        self.push_temporary(VariableKind::Temporary(counter));
+        self.push_temporary(VariableKind::Temporary(limit_index));
+        self.emit(bytecode_constants::SPECIAL_SEND + special_selector_index("<=").unwrap() as u8);
+        self.shrink(1);
+        let exit = self.jump_forward(JumpKind::IfFalse);
+        self.generate_block_body(block)?;
+        self.emit(bytecode_constants::POP);
+        self.shrink(1);
+        self.push_temporary(VariableKind::Temporary(counter));
+        self.push_literal(&Literal::Integer(1))?;
+        self.emit(bytecode_constants::SPECIAL_SEND + special_selector_index("+").unwrap() as u8);
+        self.shrink(1);
+        self.emit_pop_store_temporary(counter)?;
+        self.jump_back_to(loop_start)?;
+        self.patch_jump(exit)?;
+        self.scopes.pop();
+        self.push_literal(&Literal::Nil)
+    }
+
+    // Bypasses the check on arguments, the loop counter is written by the loop only
+    fn emit_pop_store_temporary(&mut self, index: usize) -> Result<(), CompileError> {
+        if index < 8 {
+            self.emit(bytecode_constants::POP_STORE_TEMPORARY + index as u8);
+        } else {
+            self.emit(bytecode_constants::EXTENDED_POP_STORE);
+            self.emit((bytecode_constants::TEMPORARY_TYPE << 6) | index as u8);
+        }
+        self.shrink(1);
+        Ok(())
+    }
+
+    fn generate_inlined_block(&mut self, block: &Block) -> Result<(), CompileError> {
+        self.push_scope();
+        let result = self.generate_block_body(block);
+        self.scopes.pop();
+        result
+    }
+
+    // Temporaries of inlined blocks live in the frame of the method or closure.
+    // The body leaves the value of its last statement, nil if it has none.
+    fn generate_block_body(&mut self, block: &Block) -> Result<(), CompileError> {
+        for temporary in &block.temporaries {
+            self.declare(block_owner(block), temporary, true)?;
+        }
+        match block.statements.split_last() {
+            None => self.push_literal(&Literal::Nil),
+            Some((last, others)) => {
+                self.generate_statements(others)?;
+                self.generate_expression(last)
+            }
+        }
+    }
+
+    // The closure copies the temporaries it reads, and the temp vectors of the
+    // remote temporaries it uses. Its frame holds its arguments, then the copies.
+    fn generate_closure(&mut self, block: &Block) -> Result<(), CompileError> {
+        if block.arguments.len() > code_generator_constants::MAX_ARGUMENTS {
+            return Err(error("Too many arguments"));
+        }
+        let mut copied_values: Vec<usize> = Vec::new();
+        let mut captured: Vec<(String, VariableKind, bool)> = Vec::new();
+        for name in free_variables(block) {
+            let (kind, assignable) = match self.variable_named(&name) {
+                Some(variable) => (variable.kind, variable.assignable),
+                // Instance variables, classes or undeclared
+                None => continue,
+            };
+            let copied = match kind {
+                VariableKind::Temporary(index) => index,
+                VariableKind::Remote { vector, .. } => vector,
+            };
+            let position = match copied_values.iter().position(|each| *each == copied) {
+                Some(position) => position,
+                None => {
+                    copied_values.push(copied);
+                    copied_values.len() - 1
+                }
+            };
+            let copy = block.arguments.len() + position;
+            let kind = match kind {
+                VariableKind::Temporary(_) => VariableKind::Temporary(copy),
+                VariableKind::Remote { index, .. } => VariableKind::Remote {
+                    vector: copy,
+                    index,
+                },
+            };
+            captured.push((name, kind, assignable));
+        }
+        if copied_values.len() > code_generator_constants::MAX_COPIED_VALUES {
+            return Err(error("Too many copied values"));
+        }
+        for copied in &copied_values {
+            self.push_temporary(VariableKind::Temporary(*copied));
+        }
+
+        self.units.push(CodeUnit {
+            is_closure: true,
+            ..Default::default()
+        });
+        self.push_scope();
+        for argument in &block.arguments {
+            self.declare(block_owner(block), argument, false)?;
+        }
+        self.unit().number_of_temporaries += copied_values.len();
+        for (name, kind, assignable) in &captured {
+            self.add_variable(name, *kind, *assignable)?;
+        }
+        self.create_temp_vector(block_owner(block))?;
+        let body = self.generate_block_body(block);
+        self.scopes.pop();
+        body?;
+        if !matches!(block.statements.last(), Some(Node::Return(_))) {
+            self.emit(bytecode_constants::BLOCK_RETURN_TOP);
+        }
+        let unit = self.units.pop().unwrap();
+        let header = header_of(&unit, block.arguments.len(), 1, 0)?;
+        let compiled_block_class = self
+            .interpreter
+            .special_object(SpecialObjectIndexes::ClassCompiledBlock);
+        let mut literals = unit.literals;
+        // The outer code, set once it is built
+        literals.push(self.interpreter.nil_object());
+        let compiled_block = new_compiled_code(
+            self.interpreter,
+            compiled_block_class,
+            &header,
+            &literals,
+            &unit.bytes,
+        );
+        set_outer_code(self.interpreter, &unit.compiled_blocks, compiled_block);
+
+        self.unit().compiled_blocks.push(compiled_block);
+        let index = self.literal_index(compiled_block)?;
+        self.emit(bytecode_constants::PUSH_FULL_CLOSURE);
+        self.emit(index as u8);
+        self.emit(copied_values.len() as u8);
+        self.shrink(copied_values.len());
+        self.grow(1);
+        Ok(())
+    }
+
+    fn generate_method(
+        &mut self,
+        method: &Method,
+    ) -> Result<(MethodHeader, CodeUnit), CompileError> {
+        if method.arguments.len() > code_generator_constants::MAX_ARGUMENTS {
+            return Err(error("Too many arguments"));
+        }
+        self.units.push(CodeUnit::default());
+        self.push_scope();
+        for argument in &method.arguments {
+            self.declare(METHOD_OWNER, argument, false)?;
+        }
+        self.create_temp_vector(METHOD_OWNER)?;
+        for temporary in &method.temporaries {
+            self.declare(METHOD_OWNER, temporary, true)?;
+        }
+
+        let primitive_index = match &method.primitive {
+            None => 0,
+            Some(Primitive::Numbered(index)) => *index,
+            Some(Primitive::Named { module, function }) => {
+                let module = symbol_table::intern(self.interpreter, module);
+                let function = symbol_table::intern(self.interpreter, function);
+                let mut description =
+                    vec![self.interpreter.nil_object(); external_call_constants::NUMBER_OF_SLOTS];
+                description[external_call_constants::MODULE_NAME_INDEX - 1] = module;
+                description[external_call_constants::FUNCTION_NAME_INDEX - 1] = function;
+                description[external_call_constants::CACHED_INDEX_INDEX - 1] =
+                    SlotContent::small_integer(external_call_constants::NOT_RESOLVED);
+                let description = self.new_array_of(&description);
+                self.literal_index(description)?;
+                code_generator_constants::EXTERNAL_CALL_PRIMITIVE
+            }
+        };
+
+        self.generate_statements(&method.statements)?;
+        if !matches!(method.statements.last(), Some(Node::Return(_))) {
+            self.emit(bytecode_constants::RETURN_RECEIVER);
+        }
+        self.scopes.pop();
+        let unit = self.units.pop().unwrap();
+        let header = header_of(&unit, method.arguments.len(), 2, primitive_index)?;
+        Ok((header, unit))
+    }
+}
+
+// The header of the code of the unit, with the extra literals appended to its own
+fn header_of(
+    unit: &CodeUnit,
+    number_of_arguments: usize,
+    number_of_extra_literals: usize,
+    primitive_index: usize,
+) -> Result<MethodHeader, CompileError> {
+    let mut header = MethodHeader::new(
+        number_of_arguments,
+        unit.number_of_temporaries,
+        unit.literals.len() + number_of_extra_literals,
+        primitive_index,
+    );
+    let frame_size = unit.number_of_temporaries + unit.max_depth;
+    if frame_size > compiled_method_constants::LARGE_FRAME_SIZE {
+        return Err(error("The method needs too large a frame"));
+    }
+    if frame_size > compiled_method_constants::SMALL_FRAME_SIZE {
+        header.set_large_frame();
+    }
+    Ok(header)
+}
+
+// Answers a new CompiledMethod or CompiledBlock
+fn new_compiled_code(
+    interpreter: &mut Interpreter,
+    class: usize,
+    header: &MethodHeader,
+    literals: &[usize],
+    bytes: &[u8],
+) -> usize {
+    let first_byte_index = header.initial_pc();
+    let compiled_code = interpreter
+        .instantiate_class(class, first_byte_index - 1 + bytes.len())
+        .expect("Compiled code should be bytes");
+    let mut compiled_code_oop = interpreter.space.get_oop_at(compiled_code);
+    compiled_code_oop.slot_at_index_put(
+        compiled_method_constants::HEADER_INDEX,
+        header.as_slot_value(),
+    );
+    for (index, literal) in literals.iter().enumerate() {
+        compiled_code_oop.slot_at_index_put(
+            compiled_method_constants::FIRST_LITERAL_INDEX + index,
+            *literal,
+        );
+    }
+    for (index, byte) in bytes.iter().enumerate() {
+        compiled_code_oop.byte_at_index_put(first_byte_index + index, *byte);
+    }
+    compiled_code
+}
+
+// The last literal of a CompiledBlock is the code it is nested in
+fn set_outer_code(interpreter: &mut Interpreter, compiled_blocks: &[usize], outer_code: usize) {
+    for compiled_block in compiled_blocks {
+        let header = interpreter.method_header_of(*compiled_block);
+        interpreter
+            .space
+            .get_oop_at(*compiled_block)
+            .slot_at_index_put(
+                compiled_method_constants::FIRST_LITERAL_INDEX + header.number_of_literals() - 1,
+                outer_code,
+            );
+    }
+}
+
+// The names of the instance variables, those of the superclasses first
+pub fn instance_variables_of(interpreter: &mut Interpreter, class: usize) -> Vec<String> {
+    let nil = interpreter.nil_object();
+    let mut classes = Vec::new();
+    let mut current_class = class;
+    while current_class != nil {
+        classes.push(current_class);
+        current_class = interpreter.superclass_of(current_class);
+    }
+
+    let mut names = Vec::new();
+    for class in classes.into_iter().rev() {
+        let instance_variables = interpreter
+            .space
+            .get_oop_at(class)
+            .slot_at_index(class_constants::INSTANCE_VARIABLES_INDEX);
+        if instance_variables == nil {
+            continue;
+        }
+        let size = interpreter
+            .space
+            .get_oop_at(instance_variables)
+            .number_of_slots();
+        for index in 1..=size {
+            let name = interpreter
+                .space
+                .get_oop_at(instance_variables)
+                .slot_at_index(index);
+            names.push(interpreter.string_value_of(name).unwrap_or_default());
+        }
+    }
+    names
+}
+
+// Answers a new CompiledMethod for the method of the class.
+// Its last two literals are its selector and its class.
+pub fn generate(
+    interpreter: &mut Interpreter,
+    class: usize,
+    method: &Method,
+) -> Result<usize, CompileError> {
+    let mut generator = CodeGenerator::new(interpreter, class, VariableAnalysis::of_method(method));
+    let (header, unit) = generator.generate_method(method)?;
+    let selector = symbol_table::intern(interpreter, &method.selector);
+    let mut literals = unit.literals;
+    literals.push(selector);
+    literals.push(class);
+
+    let compiled_method_class =
+        interpreter.special_object(SpecialObjectIndexes::ClassCompiledMethod);
+    let compiled_method = new_compiled_code(
+        interpreter,
+        compiled_method_class,
+        &header,
+        &literals,
+        &unit.bytes,
+    );
+    set_outer_code(interpreter, &unit.compiled_blocks, compiled_method);
+    Ok(compiled_method)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::bootstrap;
+    use crate::bytecodes::bytecode_constants;
+    use crate::compiler::compile_method;
+
+    fn bytecodes_of(source: &str) -> Vec<u8> {
+        let mut interpreter = bootstrap(40000);
+        let object = interpreter.class_named("Object").unwrap();
+        let method = compile_method(&mut interpreter, object, source).unwrap();
+        let first_byte_index = interpreter.method_header_of(method).initial_pc();
+        let method_oop = interpreter.space.get_oop_at(method);
+        (first_byte_index..=method_oop.number_of_bytes())
+            .map(|index| method_oop.byte_at_index(index))
+            .collect()
+    }
+
+    #[test]
+    fn test_return_self_is_implicit() {
+        assert_eq!(
+            bytecodes_of("foo"),
+            vec![bytecode_constants::RETURN_RECEIVER]
+        );
+    }
+
+    #[test]
+    fn test_special_returns() {
+        assert_eq!(
+            bytecodes_of("foo ^nil"),
+            vec![bytecode_constants::RETURN_NIL]
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_uses_special_sends() {
+        assert_eq!(
+            bytecodes_of("foo: x ^x + 1"),
+            vec![
+                bytecode_constants::PUSH_TEMPORARY,
+                bytecode_constants::PUSH_MINUS_ONE + 2,
+                bytecode_constants::SPECIAL_SEND,
+                bytecode_constants::RETURN_TOP
+            ]
+        );
+    }
+
+    #[test]
+    fn test_statement_assignment_pops() {
+        assert_eq!(
+            bytecodes_of("foo | a | a := 2"),
+            vec![
+                bytecode_constants::PUSH_MINUS_ONE + 3,
+                bytecode_constants::POP_STORE_TEMPORARY,
+                bytecode_constants::RETURN_RECEIVER
+            ]
+        );
+    }
+
+    #[test]
+    fn test_if_true_is_inlined() {
+        let bytecodes = bytecodes_of("foo: x ^x ifTrue: [1]");
+        assert_eq!(bytecodes[1], bytecode_constants::LONG_JUMP_IF_FALSE);
+        assert!(!bytecodes.iter().any(|byte| *byte >= 208));
+    }
+
+    #[test]
+    fn test_literal_selector_send() {
+        let bytecodes = bytecodes_of("foo ^self bar");
+        assert_eq!(
+            bytecodes,
+            vec![
+                bytecode_constants::PUSH_RECEIVER,
+                bytecode_constants::SEND_LITERAL_SELECTOR_0,
+                bytecode_constants::RETURN_TOP
+            ]
+        );
+    }
+
+    #[test]
+    fn test_block_is_a_full_closure() {
+        assert_eq!(
+            bytecodes_of("foo ^[1]"),
+            vec![
+                bytecode_constants::PUSH_FULL_CLOSURE,
+                0,
+                0,
+                bytecode_constants::RETURN_TOP
+            ]
+        );
+    }
+
+    #[test]
+    fn test_captured_assigned_temporary_goes_to_a_temp_vector() {
+        let bytecodes = bytecodes_of("foo | a | [a := 1]. ^a");
+        assert_eq!(
+            bytecodes[..3],
+            [
+                bytecode_constants::PUSH_NEW_ARRAY,
+                1,
+                bytecode_constants::POP_STORE_TEMPORARY
+            ]
+        );
+        assert_eq!(
+            bytecodes[bytecodes.len() - 4..],
+            [
+                bytecode_constants::PUSH_REMOTE_TEMPORARY,
+                0,
+                0,
+                bytecode_constants::RETURN_TOP
+            ]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use crate::compiler::scanner::{scan, Token};
+use crate::compiler::{evaluate, install_method, CompileError};
+use crate::interpreter::Interpreter;
+
+pub const KERNEL_SOURCE: &str = include_str!("../../kernel/kernel.st");
+
+struct Chunk {
+    text: String,
+    // Offset of the chunk in the source, in characters
+    position: usize,
+}
+
+// Chunks end with a bang, doubled bangs stand for a bang in the chunk
+fn chunks_of(source: &str) -> Vec<Chunk> {
+    let characters: Vec<char> = source.chars().collect();
+    let mut chunks = Vec::new();
+    let mut text = String::new();
+    let mut position = 0;
+    let mut index = 0;
+    while index < characters.len() {
+        if characters[index] == '!' {
+            if characters.get(index + 1) == Some(&'!') {
+                text.push('!');
+                index += 2;
+                continue;
+            }
+            chunks.push(Chunk {
+                text: std::mem::take(&mut text),
+                position,
+            });
+            index += 1;
+            position = index;
+            continue;
+        }
+        text.push(characters[index]);
+        index += 1;
+    }
+    if !text.trim().is_empty() {
+        chunks.push(Chunk { text, position });
+    }
+    chunks
+}
+
+// Answers the class the methods of "Foo methodsFor: 'category'" or
+// "Foo class methodsFor: 'category'" go to, None for other chunks
+fn methods_for(
+    interpreter: &mut Interpreter,
+    chunk: &Chunk,
+) -> Result<Option<usize>, CompileError> {
+    let tokens: Vec<Token> = match scan(&chunk.text) {
+        Ok(tokens) => tokens.into_iter().map(|scanned| scanned.token).collect(),
+        Err(_) => return Ok(None),
+    };
+    let (class_name, is_meta) = match tokens.as_slice() {
+        [Token::Identifier(name), Token::Keyword(keyword), Token::String(_), Token::End]
+            if keyword == "methodsFor:" =>
+        {
+            (name, false)
+        }
+        [Token::Identifier(name), Token::Identifier(class), Token::Keyword(keyword), Token::String(_), Token::End]
+            if class == "class" && keyword == "methodsFor:" =>
+        {
+            (name, true)
+        }
+        _ => return Ok(None),
+    };
+    match interpreter.class_named(class_name) {
+        Some(class) if is_meta => Ok(Some(interpreter.class_of(class))),
+        Some(class) => Ok(Some(class)),
+        None => Err(CompileError::new(
+            &format!("Unknown class {}", class_name),
+            chunk.position,
+        )),
+    }
+}
+
+// Evaluates the chunks of the source, in the chunk format of Smalltalk file outs.
+// The chunks after a methodsFor: chunk are methods, up to the next empty chunk.
+pub fn file_in(interpreter: &mut Interpreter, source: &str) -> Result<(), CompileError> {
+    let mut current_class: Option<usize> = None;
+    for chunk in chunks_of(source) {
+        let in_source = |error: CompileError| {
+            CompileError::new(&error.message, chunk.position + error.position)
+        };
+        if chunk.text.trim().is_empty() {
+            current_class = None;
+            continue;
+        }
+        match current_class {
+            Some(class) => {
+                install_method(interpreter, class, &chunk.text).map_err(in_source)?;
+            }
+            None => {
+                current_class = methods_for(interpreter, &chunk)?;
+                if current_class.is_none() {
+                    evaluate(interpreter, &chunk.text).map_err(in_source)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::bootstrap;
+    use crate::compiler::evaluate;
+    use crate::compiler::file_in::{chunks_of, file_in};
+    use crate::slot_content::SlotContent;
+
+    #[test]
+    fn test_doubled_bangs_are_escaped() {
+        let chunks: Vec<String> = chunks_of("a!! b! c!")
+            .into_iter()
+            .map(|chunk| chunk.text)
+            .collect();
+        assert_eq!(chunks, vec!["a! b", " c"]);
+    }
+
+    #[test]
+    fn test_file_in_methods() {
+        let mut interpreter = bootstrap(40000);
+        file_in(
+            &mut interpreter,
+            "!SmallInteger methodsFor: 'testing'!\ndouble\n\t^self * 2!\ntriple\n\t^self * 3! !\n\
+             !SmallInteger class methodsFor: 'constants'!\nseven\n\t^7! !",
+        )
+        .unwrap();
+
+        let value = evaluate(&mut interpreter, "SmallInteger seven double triple").unwrap();
+        assert_eq!(value, SlotContent::small_integer(42));
+    }
+
+    #[test]
+    fn test_error_position_is_in_the_source() {
+        let mut interpreter = bootstrap(40000);
+        let error = file_in(&mut interpreter, "3 + 4!\n3 + !").unwrap_err();
+        assert_eq!(error.position, 11);
+    }
+
+    #[test]
+    fn test_unknown_class_is_an_error() {
+        let mut interpreter = bootstrap(40000);
+        assert!(file_in(&mut interpreter, "!Unknown methodsFor: 'x'!\nfoo! !").is_err());
+    }
+}
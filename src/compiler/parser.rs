@@ -0,0 +1,668 @@
+use crate::compiler::scanner::{scan, ScannedToken, Token};
+use crate::compiler::CompileError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Nil,
+    True,
+    False,
+    Integer(isize),
+    Character(char),
+    String(String),
+    Symbol(String),
+    Array(Vec<Literal>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub arguments: Vec<String>,
+    pub temporaries: Vec<String>,
+    pub statements: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Literal(Literal),
+    // self, super and thisContext included
+    Variable(String),
+    Assignment(String, Box<Node>),
+    Message {
+        receiver: Box<Node>,
+        selector: String,
+        arguments: Vec<Node>,
+    },
+    // The receiver is evaluated once, each message is sent to it
+    Cascade {
+        receiver: Box<Node>,
+        messages: Vec<Node>,
+    },
+    // Stands for the receiver of the cascade in its messages
+    CascadeReceiver,
+    Block(Block),
+    Return(Box<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Primitive {
+    Numbered(usize),
+    Named { module: String, function: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Method {
+    pub selector: String,
+    pub arguments: Vec<String>,
+    pub temporaries: Vec<String>,
+    pub primitive: Option<Primitive>,
+    pub statements: Vec<Node>,
+}
+
+struct Parser {
+    tokens: Vec<ScannedToken>,
+    index: usize,
+}
+
+impl Parser {
+    fn new(source: &str) -> Result<Self, CompileError> {
+        Ok(Self {
+            tokens: scan(source)?,
+            index: 0,
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index].token
+    }
+
+    fn peek_next(&self) -> &Token {
+        let index = (self.index + 1).min(self.tokens.len() - 1);
+        &self.tokens[index].token
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.index].token.clone();
+        if token != Token::End {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn error(&self, message: &str) -> CompileError {
+        CompileError::new(message, self.tokens[self.index].position)
+    }
+
+    fn expect(&mut self, token: Token, message: &str) -> Result<(), CompileError> {
+        if *self.peek() == token {
+            self.advance();
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    fn is_bar(&self) -> bool {
+        *self.peek() == Token::BinarySelector(String::from("|"))
+    }
+
+    fn expect_identifier(&mut self, message: &str) -> Result<String, CompileError> {
+        match self.peek().clone() {
+            Token::Identifier(name) => {
+                self.advance();
+                Ok(name)
+            }
+            _ => Err(self.error(message)),
+        }
+    }
+
+    fn expect_end(&mut self) -> Result<(), CompileError> {
+        self.expect(Token::End, "End of source expected")
+    }
+
+    // Method
+    fn parse_method(&mut self) -> Result<Method, CompileError> {
+        let (selector, arguments) = self.parse_message_pattern()?;
+        let mut primitive = self.parse_pragmas()?;
+        let temporaries = self.parse_temporaries()?;
+        if primitive.is_none() {
+            primitive = self.parse_pragmas()?;
+        }
+        let statements = self.parse_statements()?;
+        self.expect_end()?;
+        Ok(Method {
+            selector,
+            arguments,
+            temporaries,
+            primitive,
+            statements,
+        })
+    }
+
+    fn parse_message_pattern(&mut self) -> Result<(String, Vec<String>), CompileError> {
+        match self.peek().clone() {
+            Token::Identifier(selector) => {
+                self.advance();
+                Ok((selector, Vec::new()))
+            }
+            Token::BinarySelector(selector) => {
+                self.advance();
+                let argument = self.expect_identifier("Argument name expected")?;
+                Ok((selector, vec![argument]))
+            }
+            Token::Keyword(_) => {
+                let mut selector = String::new();
+                let mut arguments = Vec::new();
+                while let Token::Keyword(keyword) = self.peek().clone() {
+                    self.advance();
+                    selector.push_str(&keyword);
+                    arguments.push(self.expect_identifier("Argument name expected")?);
+                }
+                Ok((selector, arguments))
+            }
+            _ => Err(self.error("Message pattern expected")),
+        }
+    }
+
+    // <primitive: 60> or <primitive: 'primitiveName' module: 'PluginName'>
+    fn parse_pragmas(&mut self) -> Result<Option<Primitive>, CompileError> {
+        if *self.peek() != Token::BinarySelector(String::from("<")) {
+            return Ok(None);
+        }
+        self.advance();
+        self.expect(
+            Token::Keyword(String::from("primitive:")),
+            "Only primitive pragmas are supported",
+        )?;
+        let primitive = match self.advance() {
+            Token::Integer(index) if index > 0 => Primitive::Numbered(index as usize),
+            Token::String(function) => {
+                self.expect(
+                    Token::Keyword(String::from("module:")),
+                    "Module name expected",
+                )?;
+                match self.advance() {
+                    Token::String(module) => Primitive::Named { module, function },
+                    _ => return Err(self.error("Module name expected")),
+                }
+            }
+            _ => return Err(self.error("Primitive number or name expected")),
+        };
+        self.expect(
+            Token::BinarySelector(String::from(">")),
+            "> expected to close the pragma",
+        )?;
+        Ok(Some(primitive))
+    }
+
+    fn parse_temporaries(&mut self) -> Result<Vec<String>, CompileError> {
+        let mut temporaries = Vec::new();
+        if !self.is_bar() {
+            return Ok(temporaries);
+        }
+        self.advance();
+        while let Token::Identifier(name) = self.peek().clone() {
+            self.advance();
+            temporaries.push(name);
+        }
+        if !self.is_bar() {
+            return Err(self.error("| expected to close the temporaries"));
+        }
+        self.advance();
+        Ok(temporaries)
+    }
+
+    // Statements end at a closing bracket or at the end of the source.
+    // Nothing may follow a return.
+    fn parse_statements(&mut self) -> Result<Vec<Node>, CompileError> {
+        let mut statements = Vec::new();
+        loop {
+            match self.peek() {
+                Token::End | Token::RightBracket => return Ok(statements),
+                Token::Period => {
+                    self.advance();
+                    continue;
+                }
+                _ => {}
+            }
+            let statement = self.parse_statement()?;
+            let is_return = matches!(statement, Node::Return(_));
+            statements.push(statement);
+            match self.peek() {
+                Token::Period => {
+                    self.advance();
+                }
+                Token::End | Token::RightBracket => return Ok(statements),
+                _ => return Err(self.error(". expected between statements")),
+            }
+            if is_return && !matches!(self.peek(), Token::End | Token::RightBracket) {
+                return Err(self.error("Statement after a return"));
+            }
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Node, CompileError> {
+        if *self.peek() == Token::Caret {
+            self.advance();
+            return Ok(Node::Return(Box::new(self.parse_expression()?)));
+        }
+        self.parse_expression()
+    }
+
+    fn parse_expression(&mut self) -> Result<Node, CompileError> {
+        if let (Token::Identifier(name), Token::Assignment) =
+            (self.peek().clone(), self.peek_next())
+        {
+            self.advance();
+            self.advance();
+            return Ok(Node::Assignment(name, Box::new(self.parse_expression()?)));
+        }
+
+        let expression = self.parse_keyword_expression()?;
+        if *self.peek() != Token::Semicolon {
+            return Ok(expression);
+        }
+
+        // The receiver of the last message is the receiver of the whole cascade
+        let (receiver, first_message) = match expression {
+            Node::Message {
+                receiver,
+                selector,
+                arguments,
+            } => (
+                receiver,
+                Node::Message {
+                    receiver: Box::new(Node::CascadeReceiver),
+                    selector,
+                    arguments,
+                },
+            ),
+            _ => return Err(self.error("Cascade without a message")),
+        };
+        let mut messages = vec![first_message];
+        while *self.peek() == Token::Semicolon {
+            self.advance();
+            let message = self.parse_keyword_continuation(Node::CascadeReceiver)?;
+            if message == Node::CascadeReceiver {
+                return Err(self.error("Message expected in the cascade"));
+            }
+            messages.push(message);
+        }
+        Ok(Node::Cascade { receiver, messages })
+    }
+
+    fn parse_keyword_expression(&mut self) -> Result<Node, CompileError> {
+        let primary = self.parse_primary()?;
+        self.parse_keyword_continuation(primary)
+    }
+
+    fn parse_keyword_continuation(&mut self, receiver: Node) -> Result<Node, CompileError> {
+        let receiver = self.parse_binary_continuation(receiver)?;
+        if !matches!(self.peek(), Token::Keyword(_)) {
+            return Ok(receiver);
+        }
+        let mut selector = String::new();
+        let mut arguments = Vec::new();
+        while let Token::Keyword(keyword) = self.peek().clone() {
+            self.advance();
+            selector.push_str(&keyword);
+            let argument = self.parse_primary()?;
+            arguments.push(self.parse_binary_continuation(argument)?);
+        }
+        Ok(Node::Message {
+            receiver: Box::new(receiver),
+            selector,
+            arguments,
+        })
+    }
+
+    fn parse_binary_continuation(&mut self, receiver: Node) -> Result<Node, CompileError> {
+        let mut receiver = self.parse_unary_continuation(receiver)?;
+        while let Token::BinarySelector(selector) = self.peek().clone() {
+            self.advance();
+            let argument = self.parse_primary()?;
+            let argument = self.parse_unary_continuation(argument)?;
+            receiver = Node::Message {
+                receiver: Box::new(receiver),
+                selector,
+                arguments: Vec::new(),
+            };
+            if let Node::Message { arguments, .. } = &mut receiver {
+                arguments.push(argument);
+            }
+        }
+        Ok(receiver)
+    }
+
+    fn parse_unary_continuation(&mut self, receiver: Node) -> Result<Node, CompileError> {
+        let mut receiver = receiver;
+        while let Token::Identifier(selector) = self.peek().clone() {
+            self.advance();
+            receiver = Node::Message {
+                receiver: Box::new(receiver),
+                selector,
+                arguments: Vec::new(),
+            };
+        }
+        Ok(receiver)
+    }
+
+    fn parse_primary(&mut self) -> Result<Node, CompileError> {
+        match self.peek().clone() {
+            Token::Identifier(name) => {
+                self.advance();
+                Ok(match name.as_str() {
+                    "nil" => Node::Literal(Literal::Nil),
+                    "true" => Node::Literal(Literal::True),
+                    "false" => Node::Literal(Literal::False),
+                    _ => Node::Variable(name),
+                })
+            }
+            Token::LeftParenthesis => {
+                self.advance();
+                let expression = self.parse_expression()?;
+                self.expect(Token::RightParenthesis, ") expected")?;
+                Ok(expression)
+            }
+            Token::LeftBracket => self.parse_block(),
+            _ => Ok(Node::Literal(self.parse_literal()?)),
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<Literal, CompileError> {
+        match self.peek().clone() {
+            Token::Integer(value) => {
+                self.advance();
+                Ok(Literal::Integer(value))
+            }
+            Token::Character(value) => {
+                self.advance();
+                Ok(Literal::Character(value))
+            }
+            Token::String(value) => {
+                self.advance();
+                Ok(Literal::String(value))
+            }
+            Token::Symbol(value) => {
+                self.advance();
+                Ok(Literal::Symbol(value))
+            }
+            Token::LiteralArrayStart => {
+                self.advance();
+                self.parse_literal_array_elements()
+            }
+            _ => Err(self.error("Expression expected")),
+        }
+    }
+
+    // Inside literal arrays, words are symbols and parentheses make nested arrays
+    fn parse_literal_array_elements(&mut self) -> Result<Literal, CompileError> {
+        let mut elements = Vec::new();
+        loop {
+            let element = match self.peek().clone() {
+                Token::RightParenthesis => {
+                    self.advance();
+                    return Ok(Literal::Array(elements));
+                }
+                Token::LeftParenthesis | Token::LiteralArrayStart => {
+                    self.advance();
+                    self.parse_literal_array_elements()?
+                }
+                Token::Identifier(name) => {
+                    self.advance();
+                    match name.as_str() {
+                        "nil" => Literal::Nil,
+                        "true" => Literal::True,
+                        "false" => Literal::False,
+                        _ => Literal::Symbol(name),
+                    }
+                }
+                Token::Keyword(keyword) => {
+                    self.advance();
+                    Literal::Symbol(keyword)
+                }
+                Token::BinarySelector(selector) => {
+                    self.advance();
+                    Literal::Symbol(selector)
+                }
+                Token::End => return Err(self.error(") expected to close the literal array")),
+                _ => self.parse_literal()?,
+            };
+            elements.push(element);
+        }
+    }
+
+    fn parse_block(&mut self) -> Result<Node, CompileError> {
+        self.expect(Token::LeftBracket, "[ expected")?;
+        let mut arguments = Vec::new();
+        while *self.peek() == Token::Colon {
+            self.advance();
+            arguments.push(self.expect_identifier("Block argument name expected")?);
+        }
+        if !arguments.is_empty() && *self.peek() != Token::RightBracket {
+            if !self.is_bar() {
+                return Err(self.error("| expected after the block arguments"));
+            }
+            self.advance();
+        }
+        let temporaries = self.parse_temporaries()?;
+        let statements = self.parse_statements()?;
+        self.expect(Token::RightBracket, "] expected")?;
+        Ok(Node::Block(Block {
+            arguments,
+            temporaries,
+            statements,
+        }))
+    }
+}
+
+pub fn parse_method(source: &str) -> Result<Method, CompileError> {
+    Parser::new(source)?.parse_method()
+}
+
+// Temporaries and statements, without message pattern.
+// The value of the last statement is answered.
+pub fn parse_expression(source: &str) -> Result<Method, CompileError> {
+    let mut parser = Parser::new(source)?;
+    let temporaries = parser.parse_temporaries()?;
+    let mut statements = parser.parse_statements()?;
+    parser.expect_end()?;
+    if let Some(last) = statements.pop() {
+        statements.push(match last {
+            Node::Return(_) => last,
+            _ => Node::Return(Box::new(last)),
+        });
+    }
+    Ok(Method {
+        selector: String::from("DoIt"),
+        arguments: Vec::new(),
+        temporaries,
+        primitive: None,
+        statements,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::parser::{parse_expression, parse_method, Literal, Node, Primitive};
+
+    fn variable(name: &str) -> Box<Node> {
+        Box::new(Node::Variable(String::from(name)))
+    }
+
+    fn integer(value: isize) -> Node {
+        Node::Literal(Literal::Integer(value))
+    }
+
+    #[test]
+    fn test_parse_keyword_pattern() {
+        let method = parse_method("at: index put: value ^value").unwrap();
+        assert_eq!(method.selector, "at:put:");
+        assert_eq!(method.arguments, vec!["index", "value"]);
+    }
+
+    #[test]
+    fn test_parse_binary_pattern() {
+        let method = parse_method("+ aNumber ^self").unwrap();
+        assert_eq!(method.selector, "+");
+        assert_eq!(method.arguments, vec!["aNumber"]);
+    }
+
+    #[test]
+    fn test_parse_primitives() {
+        let numbered = parse_method("size <primitive: 62> ^0").unwrap();
+        let named =
+            parse_method("double | a | <primitive: 'primitiveDouble' module: 'TestPlugin'>")
+                .unwrap();
+
+        assert_eq!(numbered.primitive, Some(Primitive::Numbered(62)));
+        assert_eq!(
+            named.primitive,
+            Some(Primitive::Named {
+                module: String::from("TestPlugin"),
+                function: String::from("primitiveDouble")
+            })
+        );
+        assert_eq!(named.temporaries, vec!["a"]);
+    }
+
+    #[test]
+    fn test_precedence() {
+        let method = parse_expression("a foo: b + c bar").unwrap();
+        assert_eq!(
+            method.statements,
+            vec![Node::Return(Box::new(Node::Message {
+                receiver: variable("a"),
+                selector: String::from("foo:"),
+                arguments: vec![Node::Message {
+                    receiver: variable("b"),
+                    selector: String::from("+"),
+                    arguments: vec![Node::Message {
+                        receiver: variable("c"),
+                        selector: String::from("bar"),
+                        arguments: vec![],
+                    }],
+                }],
+            }))]
+        );
+    }
+
+    #[test]
+    fn test_binary_messages_are_left_associative() {
+        let method = parse_expression("1 + 2 * 3").unwrap();
+        assert_eq!(
+            method.statements,
+            vec![Node::Return(Box::new(Node::Message {
+                receiver: Box::new(Node::Message {
+                    receiver: Box::new(integer(1)),
+                    selector: String::from("+"),
+                    arguments: vec![integer(2)],
+                }),
+                selector: String::from("*"),
+                arguments: vec![integer(3)],
+            }))]
+        );
+    }
+
+    #[test]
+    fn test_cascade_goes_to_the_receiver_of_the_last_message() {
+        let method = parse_expression("a b; c; d: 1").unwrap();
+        assert_eq!(
+            method.statements,
+            vec![Node::Return(Box::new(Node::Cascade {
+                receiver: variable("a"),
+                messages: vec![
+                    Node::Message {
+                        receiver: Box::new(Node::CascadeReceiver),
+                        selector: String::from("b"),
+                        arguments: vec![],
+                    },
+                    Node::Message {
+                        receiver: Box::new(Node::CascadeReceiver),
+                        selector: String::from("c"),
+                        arguments: vec![],
+                    },
+                    Node::Message {
+                        receiver: Box::new(Node::CascadeReceiver),
+                        selector: String::from("d:"),
+                        arguments: vec![integer(1)],
+                    },
+                ],
+            }))]
+        );
+    }
+
+    #[test]
+    fn test_assignment_and_block() {
+        let method = parse_expression("| x | x := [:each | | t | t := each]").unwrap();
+        assert_eq!(method.temporaries, vec!["x"]);
+        match &method.statements[0] {
+            Node::Return(assignment) => match assignment.as_ref() {
+                Node::Assignment(name, value) => {
+                    assert_eq!(name, "x");
+                    match value.as_ref() {
+                        Node::Block(block) => {
+                            assert_eq!(block.arguments, vec!["each"]);
+                            assert_eq!(block.temporaries, vec!["t"]);
+                            assert_eq!(block.statements.len(), 1);
+                        }
+                        _ => panic!("block expected"),
+                    }
+                }
+                _ => panic!("assignment expected"),
+            },
+            _ => panic!("return expected"),
+        }
+    }
+
+    #[test]
+    fn test_literal_array() {
+        let method = parse_expression("#(1 $a 'b' foo at:put: (nil true) #(2))").unwrap();
+        assert_eq!(
+            method.statements,
+            vec![Node::Return(Box::new(Node::Literal(Literal::Array(vec![
+                Literal::Integer(1),
+                Literal::Character('a'),
+                Literal::String(String::from("b")),
+                Literal::Symbol(String::from("foo")),
+                Literal::Symbol(String::from("at:")),
+                Literal::Symbol(String::from("put:")),
+                Literal::Array(vec![Literal::Nil, Literal::True]),
+                Literal::Array(vec![Literal::Integer(2)]),
+            ]))))]
+        );
+    }
+
+    #[parameterized(source={ "#(1 -5)", "#(-1 -2)", "#((3 -4) -5)" }, elements={
+        vec![Literal::Integer(1), Literal::Integer(-5)],
+        vec![Literal::Integer(-1), Literal::Integer(-2)],
+        vec![
+            Literal::Array(vec![Literal::Integer(3), Literal::Integer(-4)]),
+            Literal::Integer(-5),
+        ]
+    })]
+    fn test_minus_before_a_digit_in_a_literal_array_is_a_sign(
+        source: &str,
+        elements: Vec<Literal>,
+    ) {
+        let method = parse_expression(source).unwrap();
+        assert_eq!(
+            method.statements,
+            vec![Node::Return(Box::new(Node::Literal(Literal::Array(
+                elements
+            ))))]
+        );
+    }
+
+    #[parameterized(source={
+        "foo ^1 2",
+        "foo ^1. 2",
+        "foo (1",
+        "foo [:x 1]",
+        "foo <bar: 1>",
+        "foo | a",
+        "1 foo",
+        "foo x; y"
+    })]
+    fn test_parse_errors(source: &str) {
+        assert!(parse_method(source).is_err());
+    }
+}
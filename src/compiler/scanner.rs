@@ -0,0 +1,418 @@
+use crate::compiler::CompileError;
+use crate::slot_content::SlotContent;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Identifier(String),
+    // With its colon, "at:"
+    Keyword(String),
+    BinarySelector(String),
+    Integer(isize),
+    Character(char),
+    String(String),
+    Symbol(String),
+    // "#("
+    LiteralArrayStart,
+    Assignment,
+    Caret,
+    Colon,
+    Period,
+    Semicolon,
+    LeftParenthesis,
+    RightParenthesis,
+    LeftBracket,
+    RightBracket,
+    End,
+}
+
+impl Token {
+    // After an operand, a minus is a binary selector rather than the sign of a number
+    fn ends_operand(&self) -> bool {
+        matches!(
+            self,
+            Token::Identifier(_)
+                | Token::Integer(_)
+                | Token::Character(_)
+                | Token::String(_)
+                | Token::Symbol(_)
+                | Token::RightParenthesis
+                | Token::RightBracket
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScannedToken {
+    pub token: Token,
+    // Offset of the token in the source, in characters
+    pub position: usize,
+}
+
+const BINARY_CHARACTERS: &str = "+-*/\\<>=~@%&?,|";
+
+struct Scanner {
+    characters: Vec<char>,
+    position: usize,
+    // How many literal arrays, nested ones included, the scanner is in
+    literal_array_depth: usize,
+}
+
+impl Scanner {
+    fn peek(&self) -> Option<char> {
+        self.characters.get(self.position).copied()
+    }
+
+    fn peek_next(&self) -> Option<char> {
+        self.characters.get(self.position + 1).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let character = self.peek();
+        self.position += 1;
+        character
+    }
+
+    fn error(&self, message: &str, position: usize) -> CompileError {
+        CompileError::new(message, position)
+    }
+
+    fn skip_separators_and_comments(&mut self) -> Result<(), CompileError> {
+        loop {
+            match self.peek() {
+                Some(character) if character.is_whitespace() => {
+                    self.advance();
+                }
+                Some('"') => {
+                    let start = self.position;
+                    self.advance();
+                    loop {
+                        match self.advance() {
+                            Some('"') => break,
+                            Some(_) => {}
+                            None => return Err(self.error("Unterminated comment", start)),
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn scan_word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(character) = self.peek() {
+            if character.is_alphanumeric() || character == '_' {
+                word.push(character);
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        word
+    }
+
+    fn scan_identifier_or_keyword(&mut self) -> Token {
+        let word = self.scan_word();
+        if self.peek() == Some(':') && self.peek_next() != Some('=') {
+            self.advance();
+            Token::Keyword(word + ":")
+        } else {
+            Token::Identifier(word)
+        }
+    }
+
+    fn scan_digits(&mut self, radix: u32) -> String {
+        let mut digits = String::new();
+        while let Some(character) = self.peek() {
+            if character.is_digit(radix) {
+                digits.push(character);
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        digits
+    }
+
+    // Decimal, or with a radix as in 16r1F
+    fn scan_integer(&mut self, negative: bool) -> Result<Token, CompileError> {
+        let start = self.position;
+        let mut digits = self.scan_digits(10);
+        let mut radix = 10;
+        if self.peek() == Some('r') {
+            radix = digits
+                .parse::<u32>()
+                .ok()
+                .filter(|radix| (2..=36).contains(radix))
+                .ok_or_else(|| self.error("Invalid radix", start))?;
+            self.advance();
+            digits = self.scan_digits(radix);
+            if digits.is_empty() {
+                return Err(self.error("Digits expected after the radix", self.position));
+            }
+        }
+        let value = isize::from_str_radix(&digits, radix)
+            .ok()
+            .map(|value| if negative { -value } else { value })
+            .filter(|value| SlotContent::is_small_integer_value(*value))
+            .ok_or_else(|| self.error("Integer does not fit in a SmallInteger", start))?;
+        Ok(Token::Integer(value))
+    }
+
+    // Quotes are doubled inside strings
+    fn scan_quoted(&mut self) -> Result<String, CompileError> {
+        let start = self.position;
+        self.advance();
+        let mut text = String::new();
+        loop {
+            match self.advance() {
+                Some('\'') if self.peek() == Some('\'') => {
+                    self.advance();
+                    text.push('\'');
+                }
+                Some('\'') => return Ok(text),
+                Some(character) => text.push(character),
+                None => return Err(self.error("Unterminated string", start)),
+            }
+        }
+    }
+
+    fn scan_binary_selector(&mut self) -> String {
+        let mut selector = String::new();
+        // A bar stands on its own, it also delimits temporaries
+        if self.peek() == Some('|') {
+            self.advance();
+            return String::from("|");
+        }
+        while let Some(character) = self.peek() {
+            if character != '|' && BINARY_CHARACTERS.contains(character) {
+                selector.push(character);
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        selector
+    }
+
+    fn scan_symbol(&mut self) -> Result<Token, CompileError> {
+        let start = self.position;
+        self.advance();
+        match self.peek() {
+            Some('(') => {
+                self.advance();
+                Ok(Token::LiteralArrayStart)
+            }
+            Some('\'') => Ok(Token::Symbol(self.scan_quoted()?)),
+            Some(character) if character.is_alphabetic() || character == '_' => {
+                // Keyword selectors are scanned whole, as in #at:put:
+                let mut symbol = self.scan_word();
+                while self.peek() == Some(':') {
+                    self.advance();
+                    symbol.push(':');
+                    symbol.push_str(&self.scan_word());
+                }
+                Ok(Token::Symbol(symbol))
+            }
+            Some(character) if BINARY_CHARACTERS.contains(character) => {
+                Ok(Token::Symbol(self.scan_binary_selector()))
+            }
+            _ => Err(self.error("Invalid symbol", start)),
+        }
+    }
+
+    fn scan_token(&mut self, previous: Option<&Token>) -> Result<Token, CompileError> {
+        let start = self.position;
+        let character = match self.peek() {
+            Some(character) => character,
+            None => return Ok(Token::End),
+        };
+
+        if character.is_alphabetic() || character == '_' {
+            return Ok(self.scan_identifier_or_keyword());
+        }
+        if character.is_ascii_digit() {
+            return self.scan_integer(false);
+        }
+        // Literal arrays have no messages, a minus before a digit is always a sign there
+        let after_operand =
+            self.literal_array_depth == 0 && previous.is_some_and(Token::ends_operand);
+        if character == '-'
+            && !after_operand
+            && self.peek_next().is_some_and(|next| next.is_ascii_digit())
+        {
+            self.advance();
+            return self.scan_integer(true);
+        }
+
+        match character {
+            '$' => {
+                self.advance();
+                match self.advance() {
+                    Some(value) => Ok(Token::Character(value)),
+                    None => Err(self.error("Character expected after $", start)),
+                }
+            }
+            '\'' => Ok(Token::String(self.scan_quoted()?)),
+            '#' => self.scan_symbol(),
+            ':' if self.peek_next() == Some('=') => {
+                self.position += 2;
+                Ok(Token::Assignment)
+            }
+            _ if BINARY_CHARACTERS.contains(character) => {
+                Ok(Token::BinarySelector(self.scan_binary_selector()))
+            }
+            _ => {
+                self.advance();
+                match character {
+                    '^' => Ok(Token::Caret),
+                    ':' => Ok(Token::Colon),
+                    '.' => Ok(Token::Period),
+                    ';' => Ok(Token::Semicolon),
+                    '(' => Ok(Token::LeftParenthesis),
+                    ')' => Ok(Token::RightParenthesis),
+                    '[' => Ok(Token::LeftBracket),
+                    ']' => Ok(Token::RightBracket),
+                    _ => Err(self.error(&format!("Unexpected character {}", character), start)),
+                }
+            }
+        }
+    }
+}
+
+// The tokens of the source, the last one is always End
+pub fn scan(source: &str) -> Result<Vec<ScannedToken>, CompileError> {
+    let mut scanner = Scanner {
+        characters: source.chars().collect(),
+        position: 0,
+        literal_array_depth: 0,
+    };
+    let mut tokens: Vec<ScannedToken> = Vec::new();
+    loop {
+        scanner.skip_separators_and_comments()?;
+        let position = scanner.position;
+        let token = scanner.scan_token(tokens.last().map(|scanned| &scanned.token))?;
+        match token {
+            Token::LiteralArrayStart => scanner.literal_array_depth += 1,
+            Token::LeftParenthesis if scanner.literal_array_depth > 0 => {
+                scanner.literal_array_depth += 1
+            }
+            Token::RightParenthesis if scanner.literal_array_depth > 0 => {
+                scanner.literal_array_depth -= 1
+            }
+            _ => {}
+        }
+        let is_end = token == Token::End;
+        tokens.push(ScannedToken { token, position });
+        if is_end {
+            return Ok(tokens);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::scanner::{scan, Token};
+
+    fn tokens(source: &str) -> Vec<Token> {
+        scan(source)
+            .unwrap()
+            .into_iter()
+            .map(|scanned| scanned.token)
+            .collect()
+    }
+
+    #[test]
+    fn test_scan_message_pattern() {
+        assert_eq!(
+            tokens("at: index put: value"),
+            vec![
+                Token::Keyword(String::from("at:")),
+                Token::Identifier(String::from("index")),
+                Token::Keyword(String::from("put:")),
+                Token::Identifier(String::from("value")),
+                Token::End
+            ]
+        );
+    }
+
+    #[parameterized(source={ "42", "-42", "16r2A", "2r101010" }, value={ 42, -42, 42, 42 })]
+    fn test_scan_integer(source: &str, value: isize) {
+        assert_eq!(tokens(source), vec![Token::Integer(value), Token::End]);
+    }
+
+    #[test]
+    fn test_minus_after_an_operand_is_a_selector() {
+        assert_eq!(
+            tokens("x-1"),
+            vec![
+                Token::Identifier(String::from("x")),
+                Token::BinarySelector(String::from("-")),
+                Token::Integer(1),
+                Token::End
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scan_literals() {
+        assert_eq!(
+            tokens("$a 'it''s' #foo:bar: #+ #'hello world' #("),
+            vec![
+                Token::Character('a'),
+                Token::String(String::from("it's")),
+                Token::Symbol(String::from("foo:bar:")),
+                Token::Symbol(String::from("+")),
+                Token::Symbol(String::from("hello world")),
+                Token::LiteralArrayStart,
+                Token::End
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comments_are_skipped() {
+        assert_eq!(
+            tokens("\"a comment\" ^ x := y"),
+            vec![
+                Token::Caret,
+                Token::Identifier(String::from("x")),
+                Token::Assignment,
+                Token::Identifier(String::from("y")),
+                Token::End
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bars_are_scanned_alone() {
+        assert_eq!(
+            tokens("||"),
+            vec![
+                Token::BinarySelector(String::from("|")),
+                Token::BinarySelector(String::from("|")),
+                Token::End
+            ]
+        );
+    }
+
+    #[test]
+    fn test_block_argument() {
+        assert_eq!(
+            tokens("[:each | ]"),
+            vec![
+                Token::LeftBracket,
+                Token::Colon,
+                Token::Identifier(String::from("each")),
+                Token::BinarySelector(String::from("|")),
+                Token::RightBracket,
+                Token::End
+            ]
+        );
+    }
+
+    #[parameterized(source={ "'unterminated", "\"unterminated", "99999999999999999999", "{" })]
+    fn test_scan_errors(source: &str) {
+        assert!(scan(source).is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::parser::{Block, Method, Node};
+
+// Variables are told apart by the block declaring them, and their name.
+// The block is identified by its address, the method by 0.
+pub type DeclarationKey = (usize, String);
+
+pub const METHOD_OWNER: usize = 0;
+
+pub fn block_owner(block: &Block) -> usize {
+    block as *const Block as usize
+}
+
+// Messages compiled to jumps rather than sent, their literal blocks get no closure
+pub fn is_inlined(receiver: &Node, selector: &str, arguments: &[Node]) -> bool {
+    let is_block = |node: &Node, number_of_arguments: usize| matches!(node, Node::Block(block) if block.arguments.len() == number_of_arguments);
+    if *receiver == Node::Variable(String::from("super")) {
+        return false;
+    }
+    match selector {
+        "ifTrue:" | "ifFalse:" | "and:" | "or:" => is_block(&arguments[0], 0),
+        "ifTrue:ifFalse:" | "ifFalse:ifTrue:" => {
+            is_block(&arguments[0], 0) && is_block(&arguments[1], 0)
+        }
+        "whileTrue" | "whileFalse" => is_block(receiver, 0),
+        "whileTrue:" | "whileFalse:" => is_block(receiver, 0) && is_block(&arguments[0], 0),
+        "to:do:" => is_block(&arguments[1], 1),
+        _ => false,
+    }
+}
+
+struct Declaration {
+    key: DeclarationKey,
+    // Depth of the closure declaring it, 0 for the method
+    closure_depth: usize,
+}
+
+#[derive(Default)]
+struct Walker {
+    scopes: Vec<Vec<Declaration>>,
+    // The method or closures whose frame hold the declarations, innermost last
+    frame_owners: Vec<usize>,
+    captured: HashSet<DeclarationKey>,
+    assigned: HashSet<DeclarationKey>,
+    // The method or closure holding each declaration
+    frame_owner_of: HashMap<DeclarationKey, usize>,
+    // Names used but declared outside of the walk
+    free: Vec<String>,
+}
+
+impl Walker {
+    fn closure_depth(&self) -> usize {
+        self.frame_owners.len() - 1
+    }
+
+    fn declare(&mut self, owner: usize, names: &[String]) {
+        let closure_depth = self.closure_depth();
+        let frame_owner = *self.frame_owners.last().unwrap();
+        for name in names {
+            let key = (owner, name.clone());
+            self.frame_owner_of.insert(key.clone(), frame_owner);
+            self.scopes
+                .last_mut()
+                .unwrap()
+                .push(Declaration { key, closure_depth });
+        }
+    }
+
+    fn reference(&mut self, name: &str, is_assignment: bool) {
+        if ["self", "super", "thisContext"].contains(&name) {
+            return;
+        }
+        let closure_depth = self.closure_depth();
+        let declaration = self
+            .scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter())
+            .find(|declaration| declaration.key.1 == name);
+        match declaration {
+            Some(declaration) => {
+                let key = declaration.key.clone();
+                if declaration.closure_depth < closure_depth {
+                    self.captured.insert(key.clone());
+                }
+                if is_assignment {
+                    self.assigned.insert(key);
+                }
+            }
+            None => {
+                if !self.free.iter().any(|free| free == name) {
+                    self.free.push(String::from(name));
+                }
+            }
+        }
+    }
+
+    fn visit_statements(&mut self, statements: &[Node]) {
+        for statement in statements {
+            self.visit(statement);
+        }
+    }
+
+    fn visit(&mut self, node: &Node) {
+        match node {
+            Node::Literal(_) | Node::CascadeReceiver => {}
+            Node::Variable(name) => self.reference(name, false),
+            Node::Assignment(name, value) => {
+                self.visit(value);
+                self.reference(name, true);
+            }
+            Node::Message {
+                receiver,
+                selector,
+                arguments,
+            } => {
+                if is_inlined(receiver, selector, arguments) {
+                    self.visit_inlined_or_node(receiver);
+                    for argument in arguments {
+                        self.visit_inlined_or_node(argument);
+                    }
+                } else {
+                    self.visit(receiver);
+                    self.visit_statements(arguments);
+                }
+            }
+            Node::Cascade { receiver, messages } => {
+                self.visit(receiver);
+                self.visit_statements(messages);
+            }
+            Node::Block(block) => self.visit_closure(block),
+            Node::Return(value) => self.visit(value),
+        }
+    }
+
+    fn visit_inlined_or_node(&mut self, node: &Node) {
+        match node {
+            Node::Block(block) => self.visit_inlined_block(block),
+            _ => self.visit(node),
+        }
+    }
+
+    fn visit_inlined_block(&mut self, block: &Block) {
+        self.scopes.push(Vec::new());
+        self.declare(block_owner(block), &block.arguments);
+        self.declare(block_owner(block), &block.temporaries);
+        self.visit_statements(&block.statements);
+        self.scopes.pop();
+    }
+
+    fn visit_closure(&mut self, block: &Block) {
+        self.frame_owners.push(block_owner(block));
+        self.visit_inlined_block(block);
+        self.frame_owners.pop();
+    }
+}
+
+// Temporaries captured by a closure are copied in it when they are never assigned.
+// The others are remote: they live in an Array, the temp vector, shared by the frames.
+pub struct VariableAnalysis {
+    remote: HashSet<DeclarationKey>,
+    // Number of remote temporaries, by the method or closure holding their temp vector
+    remote_counts: HashMap<usize, usize>,
+}
+
+impl VariableAnalysis {
+    pub fn of_method(method: &Method) -> Self {
+        let mut walker = Walker {
+            frame_owners: vec![METHOD_OWNER],
+            scopes: vec![Vec::new()],
+            ..Default::default()
+        };
+        walker.declare(METHOD_OWNER, &method.arguments);
+        walker.declare(METHOD_OWNER, &method.temporaries);
+        walker.visit_statements(&method.statements);
+
+        let remote: HashSet<DeclarationKey> = walker
+            .captured
+            .intersection(&walker.assigned)
+            .cloned()
+            .collect();
+        let mut remote_counts = HashMap::new();
+        for key in &remote {
+            *remote_counts.entry(walker.frame_owner_of[key]).or_insert(0) += 1;
+        }
+        Self {
+            remote,
+            remote_counts,
+        }
+    }
+
+    pub fn is_remote(&self, owner: usize, name: &str) -> bool {
+        self.remote.contains(&(owner, String::from(name)))
+    }
+
+    pub fn remote_count(&self, frame_owner: usize) -> usize {
+        self.remote_counts.get(&frame_owner).copied().unwrap_or(0)
+    }
+}
+
+// The names a closure uses without declaring them, in order of appearance.
+// They include the names used by the closures it contains.
+pub fn free_variables(block: &Block) -> Vec<String> {
+    let mut walker = Walker {
+        frame_owners: vec![block_owner(block)],
+        ..Default::default()
+    };
+    walker.visit_inlined_block(block);
+    walker.free
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::parser::{parse_method, Node};
+    use crate::compiler::variable_analysis::{free_variables, VariableAnalysis, METHOD_OWNER};
+
+    #[test]
+    fn test_captured_and_assigned_temporary_is_remote() {
+        let method = parse_method("foo | a b c | a := 1. [a := b + c]. b").unwrap();
+        let analysis = VariableAnalysis::of_method(&method);
+
+        assert!(analysis.is_remote(METHOD_OWNER, "a"));
+        assert!(!analysis.is_remote(METHOD_OWNER, "b"));
+        assert!(!analysis.is_remote(METHOD_OWNER, "c"));
+        assert_eq!(analysis.remote_count(METHOD_OWNER), 1);
+    }
+
+    #[test]
+    fn test_inlined_blocks_do_not_capture() {
+        let method = parse_method("foo | a | true ifTrue: [a := 1]. ^a").unwrap();
+        let analysis = VariableAnalysis::of_method(&method);
+
+        assert!(!analysis.is_remote(METHOD_OWNER, "a"));
+    }
+
+    #[test]
+    fn test_free_variables_include_nested_closures() {
+        let method = parse_method("foo: x | a | ^[:y | | b | b := y. [a + b + x + self]]").unwrap();
+        let block = match &method.statements[0] {
+            Node::Return(value) => match value.as_ref() {
+                Node::Block(block) => block,
+                _ => panic!("block expected"),
+            },
+            _ => panic!("return expected"),
+        };
+
+        assert_eq!(free_variables(block), vec!["a", "x"]);
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::compiled_method::{compiled_method_constants, MethodHeader};
+use crate::heap_queries;
+use crate::interpreter::Interpreter;
+use crate::memory_space::MemorySpace;
+use crate::oop_builder::OopBuilder;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::pinning;
+use crate::slot_content::SlotContent;
+use crate::special_class_index::SpecialClassIndexes;
+
+// Spur style become: the objects whose identity goes to another object turn into forwarders.
+// A forwarder keeps its size, its class index becomes Forwarded and its first slot the target.
+// The roots are followed right away, the heap lazily when the interpreter reads a slot,
+// and the next garbage collection removes the forwarders left.
+// Identity hashes stay with the references, hashed collections do not need a rehash.
+pub mod forwarder_constants {
+    pub const TARGET_INDEX: usize = 1;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BecomeError {
+    // The two sides of a become do not have the same number of objects
+    SizeMismatch(usize, usize),
+    Immediate(usize),
+    // Classes keep their class index in their hash, contexts may be married to a frame
+    Unsupported(usize),
+    // Without a slot, there is nowhere to put the forwarding pointer
+    NoRoomForForwarder(usize),
+    // A pinned object only takes another identity in place
+    Pinned(usize),
+    // Immutable objects keep their contents, becoming a forwarder included
+    Immutable(usize),
+    // The space cannot grow for the copy of the object
+    OutOfMemory(usize),
+}
+
+impl fmt::Display for BecomeError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BecomeError::SizeMismatch(from_size, to_size) => write!(
+                formatter,
+                "cannot become {} objects into {} objects",
+                from_size, to_size
+            ),
+            BecomeError::Immediate(oop) => write!(formatter, "{:#x} is an immediate", oop),
+            BecomeError::Unsupported(oop) => {
+                write!(formatter, "the object at {} cannot become another", oop)
+            }
+            BecomeError::NoRoomForForwarder(oop) => write!(
+                formatter,
+                "the object at {} has no slot for a forwarding pointer",
+                oop
+            ),
+            BecomeError::Pinned(oop) => {
+                write!(formatter, "the object at {} is pinned and cannot move", oop)
+            }
+            BecomeError::Immutable(oop) => {
+                write!(formatter, "the object at {} is immutable", oop)
+            }
+            BecomeError::OutOfMemory(oop) => {
+                write!(formatter, "no room for a copy of the object at {}", oop)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BecomeError {}
+
+pub fn is_forwarded(space: &mut MemorySpace, oop: usize) -> bool {
+    SlotContent::new(oop).is_slot_oop() && space.get_oop_at(oop).get_header().is_forwarded()
+}
+
+// Answers the object at the end of the forwarding chain
+pub fn follow_forwarded(space: &mut MemorySpace, oop: usize) -> usize {
+    let mut target = oop;
+    while is_forwarded(space, target) {
+        target = space
+            .get_oop_at(target)
+            .slot_at_index(forwarder_constants::TARGET_INDEX);
+    }
+    target
+}
+
+fn check_becomable(interpreter: &mut Interpreter, oop: usize) -> Result<(), BecomeError> {
+    if SlotContent::new(oop).is_slot_immediate() {
+        return Err(BecomeError::Immediate(oop));
+    }
+    let class_index = interpreter.class_index_of(oop);
+    let hash = hash_bits_of(&mut interpreter.space, oop);
+    if class_index == SpecialClassIndexes::Context as usize
+        || interpreter.class_table.class_at_index(hash) == Some(oop)
+    {
+        return Err(BecomeError::Unsupported(oop));
+    }
+    Ok(())
+}
+
+fn check_room_for_forwarder(interpreter: &mut Interpreter, oop: usize) -> Result<(), BecomeError> {
+    if interpreter.space.get_oop_at(oop).number_of_slots() == 0 {
+        return Err(BecomeError::NoRoomForForwarder(oop));
+    }
+    Ok(())
+}
+
+fn check_not_pinned(interpreter: &mut Interpreter, oop: usize) -> Result<(), BecomeError> {
+    if interpreter.space.get_oop_at(oop).get_header().is_pinned() {
+        return Err(BecomeError::Pinned(oop));
+    }
+    Ok(())
+}
+
+fn check_mutable(interpreter: &mut Interpreter, oop: usize) -> Result<(), BecomeError> {
+    if interpreter
+        .space
+        .get_oop_at(oop)
+        .get_header()
+        .is_immutable()
+    {
+        return Err(BecomeError::Immutable(oop));
+    }
+    Ok(())
+}
+
+fn oop_size_of(space: &mut MemorySpace, oop: usize) -> usize {
+    space.get_oop_at(oop).oop_size()
+}
+
+fn become_forwarder_to(space: &mut MemorySpace, oop: usize, target: usize) {
+    let mut an_oop = space.get_oop_at(oop);
+    an_oop.get_header_mut().become_forwarder();
+    an_oop.apply_header();
+    an_oop.slot_at_index_put(forwarder_constants::TARGET_INDEX, target);
+}
+
+fn set_hash_of(space: &mut MemorySpace, oop: usize, hash: usize) {
+    let mut an_oop = space.get_oop_at(oop);
+    an_oop.get_header_mut().set_hash_bits(hash);
+    an_oop.apply_header();
+}
+
+fn hash_bits_of(space: &mut MemorySpace, oop: usize) -> usize {
+    space.get_oop_at(oop).get_header().hash_bits()
+}
+
+// A new object with the headers and the slots of the original
+fn copy_of(space: &mut MemorySpace, oop: usize) -> usize {
+    let size = oop_size_of(space, oop);
+    let mut builder = OopBuilder::new();
+    builder.set_number_of_slots(space.get_oop_at(oop).number_of_slots());
+    let copy = builder.build(space);
+    for offset in 0..size {
+        space[copy + offset] = space[oop + offset];
+    }
+    copy
+}
+
+// The objects of from take the identity of the objects of to.
+// The objects of to keep their identity hash.
+pub fn forward_identities(
+    interpreter: &mut Interpreter,
+    from: &[usize],
+    to: &[usize],
+) -> Result<(), BecomeError> {
+    if from.len() != to.len() {
+        return Err(BecomeError::SizeMismatch(from.len(), to.len()));
+    }
+    for (source, target) in from.iter().zip(to) {
+        check_becomable(interpreter, *source)?;
+        check_becomable(interpreter, *target)?;
+        if source != target {
+            check_room_for_forwarder(interpreter, *source)?;
+            check_not_pinned(interpreter, *source)?;
+            check_mutable(interpreter, *source)?;
+        }
+    }
+
+    for (source, target) in from.iter().zip(to) {
+        let target = follow_forwarded(&mut interpreter.space, *target);
+        if *source != target {
+            become_forwarder_to(&mut interpreter.space, *source, target);
+        }
+    }
+    interpreter.follow_forwarded_roots();
+    Ok(())
+}
+
+// The objects of first and second swap their identities.
+// Objects of the same size swap their contents, the others are copied then forwarded.
+pub fn exchange_identities(
+    interpreter: &mut Interpreter,
+    first: &[usize],
+    second: &[usize],
+) -> Result<(), BecomeError> {
+    if first.len() != second.len() {
+        return Err(BecomeError::SizeMismatch(first.len(), second.len()));
+    }
+    for (one, other) in first.iter().zip(second) {
+        check_becomable(interpreter, *one)?;
+        check_becomable(interpreter, *other)?;
+        if one != other {
+            check_mutable(interpreter, *one)?;
+            check_mutable(interpreter, *other)?;
+        }
+        if oop_size_of(&mut interpreter.space, *one) != oop_size_of(&mut interpreter.space, *other)
+        {
+            check_room_for_forwarder(interpreter, *one)?;
+            check_room_for_forwarder(interpreter, *other)?;
+            check_not_pinned(interpreter, *one)?;
+            check_not_pinned(interpreter, *other)?;
+        }
+    }
+
+    for (one, other) in first.iter().zip(second) {
+        let space = &mut interpreter.space;
+        let (one, other) = (
+            follow_forwarded(space, *one),
+            follow_forwarded(space, *other),
+        );
+        if one == other {
+            continue;
+        }
+        let one_hash = hash_bits_of(space, one);
+        let other_hash = hash_bits_of(space, other);
+        let size = oop_size_of(space, one);
+        if size == oop_size_of(space, other) {
+            let one_pinned = space.get_oop_at(one).get_header().is_pinned();
+            let other_pinned = space.get_oop_at(other).get_header().is_pinned();
+            for offset in 0..size {
+                let word = space[one + offset];
+                space[one + offset] = space[other + offset];
+                space[other + offset] = word;
+            }
+            set_hash_of(space, one, one_hash);
+            set_hash_of(space, other, other_hash);
+            // The pinning belongs to the address, not to the contents
+            pinning::set_pinned_bit_of(space, one, one_pinned);
+            pinning::set_pinned_bit_of(space, other, other_pinned);
+        } else {
+            let one_copy = copy_of(space, one);
+            let other_copy = copy_of(space, other);
+            set_hash_of(space, one_copy, other_hash);
+            set_hash_of(space, other_copy, one_hash);
+            become_forwarder_to(space, one, other_copy);
+            become_forwarder_to(space, other, one_copy);
+        }
+    }
+    interpreter.follow_forwarded_roots();
+    Ok(())
+}
+
+// The objects without slots cannot become forwarders, the references to them are replaced
+// right away by a walk over the heap and the roots
+pub fn replace_references(interpreter: &mut Interpreter, replacements: &HashMap<usize, usize>) {
+    if replacements.is_empty() {
+        return;
+    }
+    for object in heap_queries::all_objects(&mut interpreter.space) {
+        let an_oop = interpreter.space.get_oop_at(object);
+        let slot_indexes = if an_oop.get_header().contains_pointers() {
+            1..an_oop.number_of_slots() + 1
+        } else if an_oop.get_header().is_compiled_method() {
+            let number_of_literals = MethodHeader::from_slot_value(
+                an_oop.slot_at_index(compiled_method_constants::HEADER_INDEX),
+            )
+            .number_of_literals();
+            compiled_method_constants::FIRST_LITERAL_INDEX
+                ..compiled_method_constants::FIRST_LITERAL_INDEX + number_of_literals
+        } else {
+            continue;
+        };
+        let mut an_oop = interpreter.space.get_oop_at(object);
+        for index in slot_indexes {
+            if let Some(replacement) = replacements.get(&an_oop.slot_at_index(index)) {
+                an_oop.slot_at_index_put_ignoring_immutability(index, *replacement);
+            }
+        }
+    }
+    interpreter.map_roots(&mut |_space, oop| *replacements.get(&oop).unwrap_or(&oop));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::{bootstrap, define_class};
+    use crate::compiler::{evaluate, install_method};
+    use crate::forwarding::{
+        exchange_identities, follow_forwarded, forward_identities, is_forwarded, BecomeError,
+    };
+    use crate::header_format_values::HeaderFormatValues;
+    use crate::immutability;
+    use crate::interpreter::interpreter_test_support::new_array;
+    use crate::oop_projections::oop_common::OopCommonState;
+    use crate::slot_content::SlotContent;
+
+    #[test]
+    fn test_forward_identity_leaves_a_forwarder() {
+        let mut interpreter = bootstrap(40000);
+        let source = new_array(&mut interpreter, &[SlotContent::small_integer(1)]);
+        let target = new_array(
+            &mut interpreter,
+            &[SlotContent::small_integer(2), SlotContent::small_integer(3)],
+        );
+        let holder = new_array(&mut interpreter, &[source]);
+
+        forward_identities(&mut interpreter, &[source], &[target]).unwrap();
+
+        assert!(is_forwarded(&mut interpreter.space, source));
+        assert_eq!(follow_forwarded(&mut interpreter.space, source), target);
+        assert_eq!(interpreter.fetch_pointer(holder, 1), target);
+        assert_eq!(
+            interpreter.space.get_oop_at(holder).slot_at_index(1),
+            target
+        );
+    }
+
+    #[test]
+    fn test_garbage_collection_removes_the_forwarders() {
+        let mut interpreter = bootstrap(40000);
+        let source = new_array(&mut interpreter, &[SlotContent::small_integer(1)]);
+        let target = new_array(&mut interpreter, &[SlotContent::small_integer(2)]);
+        let holder = new_array(&mut interpreter, &[source]);
+        let nil = interpreter.nil_object();
+        interpreter
+            .stack_zone
+            .push_frame(nil, nil, 1, &mut interpreter.space);
+        interpreter.push(holder);
+
+        forward_identities(&mut interpreter, &[source], &[target]).unwrap();
+        interpreter.collect_garbage();
+
+        assert_eq!(
+            interpreter.space.get_oop_at(holder).slot_at_index(1),
+            target
+        );
+        assert!(interpreter.space.get_oop_at(source).is_free_oop());
+        assert!(!interpreter.space.get_oop_at(target).is_free_oop());
+    }
+
+    #[parameterized(first_size={ 2, 1 }, second_size={ 2, 3 })]
+    fn test_exchange_identities_swaps_the_references(first_size: usize, second_size: usize) {
+        let mut interpreter = bootstrap(40000);
+        let first_elements: Vec<usize> = (0..first_size)
+            .map(|_| SlotContent::small_integer(1))
+            .collect();
+        let second_elements: Vec<usize> = (0..second_size)
+            .map(|_| SlotContent::small_integer(2))
+            .collect();
+        let first = new_array(&mut interpreter, &first_elements);
+        let second = new_array(&mut interpreter, &second_elements);
+        let holder = new_array(&mut interpreter, &[first, second]);
+        let first_hash = interpreter.hash_of(first);
+
+        exchange_identities(&mut interpreter, &[first], &[second]).unwrap();
+
+        let now_first = interpreter.fetch_pointer(holder, 1);
+        let now_second = interpreter.fetch_pointer(holder, 2);
+        assert_eq!(
+            interpreter.space.get_oop_at(now_first).number_of_slots(),
+            second_size
+        );
+        assert_eq!(
+            interpreter.space.get_oop_at(now_second).number_of_slots(),
+            first_size
+        );
+        assert_eq!(interpreter.hash_of(now_first), first_hash);
+    }
+
+    #[test]
+    fn test_become_errors() {
+        let mut interpreter = bootstrap(40000);
+        let array = new_array(&mut interpreter, &[]);
+        let other = new_array(&mut interpreter, &[SlotContent::small_integer(1)]);
+        let object = interpreter.class_named("Object").unwrap();
+
+        assert_eq!(
+            forward_identities(&mut interpreter, &[array], &[]),
+            Err(BecomeError::SizeMismatch(1, 0))
+        );
+        assert_eq!(
+            forward_identities(&mut interpreter, &[SlotContent::small_integer(3)], &[array]),
+            Err(BecomeError::Immediate(SlotContent::small_integer(3)))
+        );
+        assert_eq!(
+            forward_identities(&mut interpreter, &[array], &[other]),
+            Err(BecomeError::NoRoomForForwarder(array))
+        );
+        assert_eq!(
+            exchange_identities(&mut interpreter, &[object], &[other]),
+            Err(BecomeError::Unsupported(object))
+        );
+        immutability::set_immutable_bit_of(&mut interpreter.space, other, true);
+        assert_eq!(
+            exchange_identities(&mut interpreter, &[array], &[other]),
+            Err(BecomeError::Immutable(other))
+        );
+        assert_eq!(
+            forward_identities(&mut interpreter, &[other], &[array]),
+            Err(BecomeError::Immutable(other))
+        );
+        assert!(!is_forwarded(&mut interpreter.space, array));
+    }
+
+    #[test]
+    fn test_sends_to_a_forwarded_receiver_reach_the_target() {
+        let mut interpreter = bootstrap(40000);
+        let object = interpreter.class_named("Object").unwrap();
+        let box_class = define_class(
+            &mut interpreter,
+            "Box",
+            object,
+            HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+            &["contents"],
+        );
+        install_method(&mut interpreter, box_class, "contents ^contents").unwrap();
+        install_method(
+            &mut interpreter,
+            box_class,
+            "contents: anObject contents := anObject",
+        )
+        .unwrap();
+
+        let value = evaluate(
+            &mut interpreter,
+            "| a b holder | a := Box new contents: 3; yourself. b := Box new contents: 7; yourself. holder := Box new contents: a; yourself. a becomeForward: b. holder contents contents",
+        )
+        .unwrap();
+        assert_eq!(value, SlotContent::small_integer(7));
+    }
+
+    #[parameterized(source={
+        "| a b | a := Array new: 2. b := Array new: 3. a become: b. a size + b size + 2",
+        "| a b | a := Array with: 1. b := Array with: 3. a become: b. (a at: 1) + (b at: 1) + 3",
+        "| a b | a := Array with: 1. b := Array with: 7. a becomeForward: b. a at: 1",
+        "| a b | a := Array with: 1. [a becomeForward: 3] on: Error do: [:e | 7]",
+        "[#(1) becomeForward: (Array with: 2)] on: Error do: [:e | 7]"
+    })]
+    fn test_become_from_the_kernel_answers_seven(source: &str) {
+        let mut interpreter = bootstrap(40000);
+        assert_eq!(
+            evaluate(&mut interpreter, source).unwrap(),
+            SlotContent::small_integer(7)
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::memory_space::{MemorySpace, SegmentKind};
+use crate::oop_projections::oop_carcass::OopCarcass;
+use crate::oop_projections::oop_common::OopCommonState;
+
+// The words no object uses are free chunks of at least MINIMUM_FREE_CHUNK_SIZE words:
+// a header and a slot, the room a forwarder needs.
+// The shorter leftovers are fillers, objects without slots nothing references.
+// They are never allocated into, the collector merges them with the free chunks around them.
+// A chunk covers exactly its words, so the sizes of the chunks and of the objects
+// add up to the words of the segments, bridges excluded.
+pub const MINIMUM_FREE_CHUNK_SIZE: usize = 2;
+
+// The header of a free chunk, or of a filler when there are too few words
+pub fn free_carcass_of_size(size: usize) -> OopCarcass {
+    let mut carcass = OopCarcass::default();
+    if size < MINIMUM_FREE_CHUNK_SIZE {
+        carcass.get_header_mut().become_filler();
+    } else {
+        carcass.get_header_mut().become_free_oop();
+    }
+    carcass.set_oop_size(size);
+    carcass
+}
+
+// The free chunks of a space by address and by size, so that the allocation strategies
+// do not walk the objects to find them
+#[derive(Debug, Default)]
+pub struct FreeChunkIndex {
+    // The size and the kind of the segment of the chunk at each index
+    by_index: BTreeMap<usize, (usize, SegmentKind)>,
+    by_size: BTreeSet<(usize, usize)>,
+}
+
+impl FreeChunkIndex {
+    pub fn insert(&mut self, index: usize, size: usize, kind: SegmentKind) {
+        self.remove(index);
+        self.by_index.insert(index, (size, kind));
+        self.by_size.insert((size, index));
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if let Some((size, _)) = self.by_index.remove(&index) {
+            self.by_size.remove(&(size, index));
+        }
+    }
+
+    // The index and the size of the chunks of that kind from that index, in address order
+    pub fn from_index(
+        &self,
+        kind: SegmentKind,
+        from: usize,
+    ) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.by_index
+            .range(from..)
+            .filter(move |(_, (_, chunk_kind))| *chunk_kind == kind)
+            .map(|(&index, &(size, _))| (index, size))
+    }
+
+    // The smallest chunk of that kind with at least that many words, the first one of its size
+    pub fn smallest_fitting(&self, kind: SegmentKind, number_of_usize: usize) -> Option<usize> {
+        self.by_size
+            .range((number_of_usize, 0)..)
+            .map(|&(_, index)| index)
+            .find(|index| self.kind_at(*index) == kind)
+    }
+
+    // The largest chunk of that kind, the first one of its size
+    pub fn largest(&self, kind: SegmentKind) -> Option<(usize, usize)> {
+        let &(size, _) = self
+            .by_size
+            .iter()
+            .rev()
+            .find(|(_, index)| self.kind_at(*index) == kind)?;
+        self.by_size
+            .range((size, 0)..)
+            .find(|(_, index)| self.kind_at(*index) == kind)
+            .map(|&(size, index)| (index, size))
+    }
+
+    fn kind_at(&self, index: usize) -> SegmentKind {
+        self.by_index[&index].1
+    }
+}
+
+pub fn write_free_words(index: usize, size: usize, space: &mut MemorySpace) {
+    if size > 0 {
+        free_carcass_of_size(size).apply_at_index_on_space(index, space);
+    }
+    if size >= MINIMUM_FREE_CHUNK_SIZE {
+        space.add_free_chunk(index, size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::allocator::{allocation_strategy_named, ALLOCATION_STRATEGIES};
+    use crate::free_chunk::{free_carcass_of_size, FreeChunkIndex, MINIMUM_FREE_CHUNK_SIZE};
+    use crate::garbage_collector::simple_garbage_collector;
+    use crate::header::Header;
+    use crate::memory_space::{memory_space_constants, MemorySpace, SegmentKind};
+    use crate::oop_builder::OopBuilder;
+    use crate::oop_projections::oop_common::{OopCommonState, OopNavigation};
+    use crate::slot_content::SlotContent;
+
+    // Allocates objects of that many slots, keeping some of them,
+    // and collects the others after the steps asking for it
+    #[derive(Debug, Clone)]
+    struct Step {
+        number_of_slots: usize,
+        kept: bool,
+        collect: bool,
+    }
+
+    fn steps() -> impl Strategy<Value = Vec<Step>> {
+        let number_of_slots = prop_oneof![0..4usize, 250..260usize, 0..600usize];
+        let step = (number_of_slots, any::<bool>(), prop::bool::weighted(0.2)).prop_map(
+            |(number_of_slots, kept, collect)| Step {
+                number_of_slots,
+                kept,
+                collect,
+            },
+        );
+        prop::collection::vec(step, 1..80)
+    }
+
+    // Answers the words of the chunks met by the walk, checking the free ones on the way
+    fn walked_words(space: &mut MemorySpace) -> usize {
+        let mut words = 0;
+        let mut iterator = space.iter();
+        while let Some(headers) = iterator.next_headers(space) {
+            let size = headers.oop_size();
+            if headers.get_header().is_free_space() {
+                assert_eq!(headers.is_free_oop(), size >= MINIMUM_FREE_CHUNK_SIZE);
+            }
+            words += size;
+        }
+        words
+    }
+
+    #[parameterized(size = { 1, 2, 3, 254, 255, 256, 257, 258, 1000 })]
+    fn test_chunks_cover_exactly_their_words(size: usize) {
+        let carcass = free_carcass_of_size(size);
+
+        assert_eq!(carcass.oop_size(), size);
+        assert_eq!(carcass.is_free_oop(), size >= MINIMUM_FREE_CHUNK_SIZE);
+        assert_eq!(
+            carcass.get_header().is_filler(),
+            size < MINIMUM_FREE_CHUNK_SIZE
+        );
+    }
+
+    #[test]
+    fn test_the_256_words_chunk_has_an_extra_header() {
+        let carcass = free_carcass_of_size(256);
+
+        assert!(carcass.get_header().has_extra_slot_header());
+        assert_eq!(carcass.number_of_slots(), Header::MAX_NUMBER_OF_SLOTS);
+    }
+
+    #[test]
+    fn test_index_finds_the_chunks_by_size() {
+        let mut index = FreeChunkIndex::default();
+        for (chunk, size, kind) in [
+            (10, 8, SegmentKind::Objects),
+            (30, 4, SegmentKind::Objects),
+            (50, 4, SegmentKind::Objects),
+            (70, 20, SegmentKind::Pinned),
+            (90, 8, SegmentKind::Objects),
+        ] {
+            index.insert(chunk, size, kind);
+        }
+        index.remove(10);
+
+        assert_eq!(index.smallest_fitting(SegmentKind::Objects, 3), Some(30));
+        assert_eq!(index.smallest_fitting(SegmentKind::Objects, 5), Some(90));
+        assert_eq!(index.smallest_fitting(SegmentKind::Objects, 9), None);
+        assert_eq!(index.largest(SegmentKind::Objects), Some((90, 8)));
+        assert_eq!(index.largest(SegmentKind::Pinned), Some((70, 20)));
+        assert_eq!(
+            index
+                .from_index(SegmentKind::Objects, 40)
+                .collect::<Vec<_>>(),
+            vec![(50, 4), (90, 8)]
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_chunks_add_up_to_the_words_of_the_space(
+            steps in steps(),
+            strategy in 0..ALLOCATION_STRATEGIES.len(),
+            // The free chunks cross the one header limit as the first objects are allocated
+            space_size in prop_oneof![250..262usize, 1000..1010usize],
+        ) {
+            let mut space = MemorySpace::for_bit_size(space_size);
+            space.replace_allocation_strategy(
+                allocation_strategy_named(ALLOCATION_STRATEGIES[strategy]).unwrap(),
+            );
+            let mut builder = OopBuilder::new();
+            builder.set_slots_value(SlotContent::small_integer(0));
+            let mut kept = Vec::new();
+            for step in steps {
+                builder.set_number_of_slots(step.number_of_slots);
+                let object = builder.build(&mut space);
+                prop_assert_eq!(space.get_oop_at(object).number_of_slots(), step.number_of_slots);
+                if step.kept {
+                    kept.push(object);
+                }
+                if step.collect {
+                    simple_garbage_collector::collect_from_roots(kept.clone(), &mut space);
+                }
+                let bridges = space.segments().len() * memory_space_constants::BRIDGE_SIZE;
+                prop_assert_eq!(walked_words(&mut space), space.number_of_words() - bridges);
+            }
+        }
+
+        #[test]
+        fn test_index_keeps_up_with_the_allocations(
+            steps in steps(),
+            strategy in 0..ALLOCATION_STRATEGIES.len(),
+        ) {
+            let mut space = MemorySpace::for_bit_size(1000);
+            space.replace_allocation_strategy(
+                allocation_strategy_named(ALLOCATION_STRATEGIES[strategy]).unwrap(),
+            );
+            let mut builder = OopBuilder::new();
+            builder.set_slots_value(SlotContent::small_integer(0));
+            let mut kept = Vec::new();
+            for step in steps {
+                builder.set_number_of_slots(step.number_of_slots);
+                kept.push(builder.build(&mut space));
+                if !step.kept {
+                    kept.pop();
+                }
+                if step.collect {
+                    simple_garbage_collector::collect_from_roots(kept.clone(), &mut space);
+                }
+            }
+            let mut indexed: Vec<(usize, usize)> = Vec::new();
+            for kind in [SegmentKind::Objects, SegmentKind::LargeObject, SegmentKind::Pinned] {
+                indexed.extend(space.free_chunks().from_index(kind, 0));
+            }
+            indexed.sort();
+            // The rest of the allocation buffer is not a free chunk until the walk retires it
+            let buffer = space.allocation_buffer();
+            let mut walked = Vec::new();
+            let mut iterator = space.iter();
+            while let Some(headers) = iterator.next_headers(&mut space) {
+                let in_buffer = buffer.next < buffer.limit && headers.get_index() == buffer.next;
+                if headers.is_free_oop() && !in_buffer {
+                    walked.push((headers.get_index(), headers.oop_size()));
+                }
+            }
+            prop_assert_eq!(indexed, walked);
+        }
+    }
+}
@@ -1,20 +1,59 @@
-mod simple_garbage_collector {
+use crate::memory_space::MemorySpace;
+
+pub mod simple_garbage_collector {
+    use crate::compiled_method::{compiled_method_constants, MethodHeader};
+    use crate::forwarding;
+    use crate::free_chunk;
+    use crate::header_format_values::HeaderFormatValues;
     use crate::memory_space::MemorySpace;
     use crate::oop_projections::oop_common::*;
     use crate::oop_projections::oop_slice::OopSlice;
     use crate::slot_content::SlotContent;
 
-    #[allow(dead_code)]
     pub fn collect_from_roots(roots: Vec<usize>, space: &mut MemorySpace) {
         mark_oops_from_roots(roots, space);
         sweep_oops(space);
         merge_free_oops(space);
+        space.release_empty_segments();
+    }
+
+    // Weak objects only keep the objects something else keeps,
+    // the slots referencing the reclaimed objects become nil.
+    // All their slots are weak, the kernel weak objects keep SmallIntegers in their fixed slots.
+    pub fn collect_from_roots_clearing_weak_slots(
+        roots: Vec<usize>,
+        nil: usize,
+        space: &mut MemorySpace,
+    ) {
+        let weak_oops = mark_strong_oops_from_roots(roots, space);
+        clear_unmarked_weak_slots(&weak_oops, nil, space);
+        sweep_oops(space);
+        merge_free_oops(space);
+        space.release_empty_segments();
     }
 
+    // The slots of the weak objects are marked like the others
     pub fn mark_oops_from_roots(roots: Vec<usize>, space: &mut MemorySpace) {
+        mark(roots, space, false);
+    }
+
+    // Answers the marked weak objects, whose slots are not followed
+    pub fn mark_strong_oops_from_roots(roots: Vec<usize>, space: &mut MemorySpace) -> Vec<usize> {
+        mark(roots, space, true)
+    }
+
+    fn is_weak(an_oop: &OopSlice) -> bool {
+        an_oop.get_header().format_bits()
+            == HeaderFormatValues::WeakIndexableWithSlotsFormat as usize
+    }
+
+    // Forwarders are not marked, the references to them are replaced by their targets
+    fn mark(roots: Vec<usize>, space: &mut MemorySpace, skip_weak_slots: bool) -> Vec<usize> {
         let mut oop_to_mark: Vec<usize> = roots.clone();
+        let mut weak_oops: Vec<usize> = Vec::new();
 
-        while let Some(an_oop_index) = oop_to_mark.pop() {
+        while let Some(mut an_oop_index) = oop_to_mark.pop() {
+            an_oop_index = forwarding::follow_forwarded(space, an_oop_index);
             let mut an_oop: OopSlice = space.get_oop_at(an_oop_index);
             if an_oop.get_header().marked_bit() != 1 {
                 //println!("Marking {}", an_oop_index);
@@ -22,7 +61,61 @@ mod simple_garbage_collector {
                 an_oop.get_header_mut().set_marked_bit();
                 an_oop.apply_header();
 
-                an_oop.slots_select_into(SlotContent::is_slot_oop, &mut oop_to_mark);
+                if skip_weak_slots && is_weak(&an_oop) {
+                    weak_oops.push(an_oop_index);
+                } else if an_oop.get_header().contains_pointers() {
+                    let number_of_slots = an_oop.number_of_slots();
+                    for index in 1..=number_of_slots {
+                        let slot_value = follow_forwarded_slot(space, an_oop_index, index);
+                        if SlotContent::new(slot_value).is_slot_oop() {
+                            oop_to_mark.push(slot_value);
+                        }
+                    }
+                } else if an_oop.get_header().is_compiled_method() {
+                    let number_of_literals = MethodHeader::from_slot_value(
+                        an_oop.slot_at_index(compiled_method_constants::HEADER_INDEX),
+                    )
+                    .number_of_literals();
+                    for index in 0..number_of_literals {
+                        let literal = follow_forwarded_slot(
+                            space,
+                            an_oop_index,
+                            compiled_method_constants::FIRST_LITERAL_INDEX + index,
+                        );
+                        if SlotContent::new(literal).is_slot_oop() {
+                            oop_to_mark.push(literal);
+                        }
+                    }
+                }
+            }
+        }
+        weak_oops
+    }
+
+    fn follow_forwarded_slot(space: &mut MemorySpace, oop_index: usize, index: usize) -> usize {
+        let slot_value = space.get_oop_at(oop_index).slot_at_index(index);
+        if !forwarding::is_forwarded(space, slot_value) {
+            return slot_value;
+        }
+        let target = forwarding::follow_forwarded(space, slot_value);
+        space
+            .get_oop_at(oop_index)
+            .slot_at_index_put_ignoring_immutability(index, target);
+        target
+    }
+
+    pub fn clear_unmarked_weak_slots(weak_oops: &[usize], nil: usize, space: &mut MemorySpace) {
+        for weak_oop_index in weak_oops {
+            let number_of_slots = space.get_oop_at(*weak_oop_index).number_of_slots();
+            for index in 1..=number_of_slots {
+                let slot_value = follow_forwarded_slot(space, *weak_oop_index, index);
+                if SlotContent::new(slot_value).is_slot_oop()
+                    && space.get_oop_at(slot_value).get_header().marked_bit() != 1
+                {
+                    space
+                        .get_oop_at(*weak_oop_index)
+                        .slot_at_index_put_ignoring_immutability(index, nil);
+                }
             }
         }
     }
@@ -34,7 +127,12 @@ mod simple_garbage_collector {
                 current_oop.get_header_mut().unset_marked_bit();
                 current_oop.apply_header(space);
             } else {
-                current_oop.become_free_oop(space);
+                // The objects without slots are too small for a free chunk, they become fillers
+                free_chunk::write_free_words(
+                    current_oop.get_index(),
+                    current_oop.oop_size(),
+                    space,
+                );
             }
         }
     }
@@ -45,7 +143,11 @@ mod simple_garbage_collector {
 
         while let Some(next_oop_headers) = iter.peak_next_headers(space) {
             iter.go_to_next(space);
-            if current_oop_headers.is_free_oop() && next_oop_headers.is_free_oop() {
+            // The free chunks at both ends of a bridge are in different segments
+            if current_oop_headers.get_header().is_free_space()
+                && next_oop_headers.get_header().is_free_space()
+                && current_oop_headers.next_oop_index() == next_oop_headers.get_index()
+            {
                 current_oop_headers.merge_with(next_oop_headers, space);
             } else {
                 current_oop_headers = next_oop_headers;
@@ -54,9 +156,43 @@ mod simple_garbage_collector {
     }
 }
 
+// What the benchmarks need of a collector, to compare the ones to come with this one
+pub trait GarbageCollector {
+    fn name(&self) -> &'static str;
+
+    // A moving collector updates the roots to the new places of the objects
+    fn collect(&mut self, roots: &mut [usize], space: &mut MemorySpace);
+}
+
+pub const GARBAGE_COLLECTORS: [&str; 1] = ["simple"];
+
+pub fn garbage_collector_named(name: &str) -> Option<Box<dyn GarbageCollector>> {
+    match name {
+        "simple" => Some(Box::new(SimpleGarbageCollector)),
+        _ => None,
+    }
+}
+
+// Marks from the roots, sweeps in place and merges the free chunks, nothing moves
+#[derive(Debug, Default)]
+pub struct SimpleGarbageCollector;
+
+impl GarbageCollector for SimpleGarbageCollector {
+    fn name(&self) -> &'static str {
+        "simple"
+    }
+
+    fn collect(&mut self, roots: &mut [usize], space: &mut MemorySpace) {
+        simple_garbage_collector::collect_from_roots(roots.to_vec(), space);
+    }
+}
+
 #[cfg(test)]
+#[allow(clippy::vec_init_then_push)]
 mod tests {
+    use crate::compiled_method::{compiled_method_constants, MethodHeader};
     use crate::garbage_collector::simple_garbage_collector;
+    use crate::header_format_values::HeaderFormatValues;
     use crate::memory_space::MemorySpace;
     use crate::oop_builder::OopBuilder;
     use crate::oop_projections::oop_common::{oop_utilities, OopCommonState};
@@ -96,6 +232,36 @@ mod tests {
             assert_eq!(iter.next(&mut space).unwrap().get_header().marked_bit(), 1);
         }
 
+        #[parameterized(space_size={ 240, 1000 })]
+        fn test_mark_literals_but_not_bytecodes_of_compiled_method(space_size: usize) {
+            let mut space = MemorySpace::for_bit_size(space_size);
+            let mut builder = OopBuilder::new();
+            builder.set_format(HeaderFormatValues::CompiledMethodFormat as usize);
+            builder.set_number_of_slots(3);
+            let method = builder.build(&mut space);
+            builder.reset();
+            let literal = builder.build(&mut space);
+            let bytecodes_looking_like_an_oop = builder.build(&mut space);
+            let mut method_oop = space.get_oop_at(method);
+            method_oop.slot_at_index_put(
+                compiled_method_constants::HEADER_INDEX,
+                MethodHeader::new(0, 0, 1, 0).as_slot_value(),
+            );
+            method_oop.slot_at_index_put(compiled_method_constants::FIRST_LITERAL_INDEX, literal);
+            method_oop.slot_at_index_put(3, bytecodes_looking_like_an_oop);
+
+            simple_garbage_collector::mark_oops_from_roots(vec![method], &mut space);
+
+            assert_eq!(space.get_oop_at(literal).get_header().marked_bit(), 1);
+            assert_eq!(
+                space
+                    .get_oop_at(bytecodes_looking_like_an_oop)
+                    .get_header()
+                    .marked_bit(),
+                0
+            );
+        }
+
         #[parameterized(space_size={ 240, 1000 })]
         fn test_sweep_clears_marked_bit(space_size: usize) {
             let mut space = MemorySpace::for_bit_size(space_size);
@@ -111,6 +277,67 @@ mod tests {
         }
     }
 
+    mod weak_tests {
+        use super::*;
+
+        fn build_weak_oop_referencing(referent: usize, space: &mut MemorySpace) -> usize {
+            let mut builder = OopBuilder::new();
+            builder.set_format(HeaderFormatValues::WeakIndexableWithSlotsFormat as usize);
+            builder.set_number_of_slots(1);
+            let weak_oop = builder.build(space);
+            space.get_oop_at(weak_oop).slot_at_index_put(1, referent);
+            weak_oop
+        }
+
+        #[parameterized(space_size={ 240, 1000 })]
+        fn test_weak_slot_of_unreferenced_object_becomes_nil(space_size: usize) {
+            let mut space = MemorySpace::for_bit_size(space_size);
+            let builder = OopBuilder::new();
+            let nil = builder.build(&mut space);
+            let referent = builder.build(&mut space);
+            let weak_oop = build_weak_oop_referencing(referent, &mut space);
+
+            simple_garbage_collector::collect_from_roots_clearing_weak_slots(
+                vec![nil, weak_oop],
+                nil,
+                &mut space,
+            );
+
+            assert_eq!(space.get_oop_at(weak_oop).slot_at_index(1), nil);
+            assert!(space.get_oop_at(referent).get_header().is_free_space());
+        }
+
+        #[parameterized(space_size={ 240, 1000 })]
+        fn test_weak_slot_of_referenced_object_is_kept(space_size: usize) {
+            let mut space = MemorySpace::for_bit_size(space_size);
+            let builder = OopBuilder::new();
+            let nil = builder.build(&mut space);
+            let referent = builder.build(&mut space);
+            let weak_oop = build_weak_oop_referencing(referent, &mut space);
+
+            simple_garbage_collector::collect_from_roots_clearing_weak_slots(
+                vec![nil, weak_oop, referent],
+                nil,
+                &mut space,
+            );
+
+            assert_eq!(space.get_oop_at(weak_oop).slot_at_index(1), referent);
+            assert!(!space.get_oop_at(referent).is_free_oop());
+        }
+
+        #[parameterized(space_size={ 240, 1000 })]
+        fn test_plain_collection_keeps_weak_referents(space_size: usize) {
+            let mut space = MemorySpace::for_bit_size(space_size);
+            let builder = OopBuilder::new();
+            let referent = builder.build(&mut space);
+            let weak_oop = build_weak_oop_referencing(referent, &mut space);
+
+            simple_garbage_collector::collect_from_roots(vec![weak_oop], &mut space);
+
+            assert!(!space.get_oop_at(referent).is_free_oop());
+        }
+    }
+
     mod sweep_tests {
         use super::*;
 
@@ -124,7 +351,8 @@ mod tests {
 
             simple_garbage_collector::collect_from_roots(roots, &mut space);
 
-            assert!(space.first_oop().is_free_oop());
+            // Without slots, it is too small for a free chunk
+            assert!(space.first_oop().get_header().is_filler());
         }
 
         #[parameterized(space_size={ 240, 1000 })]
@@ -0,0 +1,34 @@
+use crate::interpreter::Interpreter;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::slot_content::SlotContent;
+
+// The method dictionaries and the symbol table start with their tally, then their other fixed
+// slots. The keys are hashed into the indexable slots past them.
+pub mod hashed_collection_constants {
+    pub const TALLY_INDEX: usize = 1;
+}
+
+pub fn capacity_of(
+    interpreter: &mut Interpreter,
+    collection: usize,
+    number_of_fixed_slots: usize,
+) -> usize {
+    interpreter.space.get_oop_at(collection).number_of_slots() - number_of_fixed_slots
+}
+
+pub fn tally_of(interpreter: &mut Interpreter, collection: usize) -> usize {
+    SlotContent::new(
+        interpreter
+            .space
+            .get_oop_at(collection)
+            .slot_at_index(hashed_collection_constants::TALLY_INDEX),
+    )
+    .as_small_integer() as usize
+}
+
+pub fn set_tally(interpreter: &mut Interpreter, collection: usize, tally: usize) {
+    interpreter.space.get_oop_at(collection).slot_at_index_put(
+        hashed_collection_constants::TALLY_INDEX,
+        SlotContent::small_integer(tally as isize),
+    );
+}
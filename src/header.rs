@@ -1,5 +1,27 @@
+use std::fmt;
+
+use crate::header_format_values::HeaderFormatValues;
 use crate::special_class_index::SpecialClassIndexes;
 
+// More slots than the header can count, they go to the extra header
+#[derive(Debug, Clone, PartialEq)]
+pub struct TooManySlotsForHeader {
+    pub number_of_slots: usize,
+}
+
+impl fmt::Display for TooManySlotsForHeader {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "Tried to set number of slots {} directly in the header. Headers only support {} slots",
+            self.number_of_slots,
+            Header::MAX_NUMBER_OF_SLOTS
+        )
+    }
+}
+
+impl std::error::Error for TooManySlotsForHeader {}
+
 #[derive(Debug)]
 pub struct Header {
     pub header_value: usize,
@@ -24,10 +46,20 @@ impl Header {
     }
 
     pub fn set_number_of_slots_bits(&mut self, number_of_slots: usize) {
+        if let Err(error) = self.try_set_number_of_slots_bits(number_of_slots) {
+            panic!("{}", error)
+        }
+    }
+
+    pub fn try_set_number_of_slots_bits(
+        &mut self,
+        number_of_slots: usize,
+    ) -> Result<(), TooManySlotsForHeader> {
         if number_of_slots > Header::MAX_NUMBER_OF_SLOTS {
-            panic!("Tried to set number of slots {} directly in the header. Headers only support {} slots", number_of_slots , Header::MAX_NUMBER_OF_SLOTS)
+            return Err(TooManySlotsForHeader { number_of_slots });
         }
         self.header_value = (self.header_value & 0xFFFFFFFFFFFFFF00) | number_of_slots;
+        Ok(())
     }
 
     pub fn set_number_of_slots_to_max(&mut self) {
@@ -59,12 +91,21 @@ impl Header {
     }
 
     // Individual Bits
+    // Right below the format, as in Spur. The remembered bit has bit 40 to itself.
     pub fn immutable_bit(&self) -> usize {
-        (self.header_value & 0x10000000000) >> 40
+        (self.header_value & 0x400000000) >> 34
     }
 
     pub fn set_immutable_bit(&mut self) {
-        self.header_value |= 0x10000000000;
+        self.header_value |= 0x400000000;
+    }
+
+    pub fn unset_immutable_bit(&mut self) {
+        self.header_value &= !0x400000000;
+    }
+
+    pub fn is_immutable(&self) -> bool {
+        self.immutable_bit() == 1
     }
 
     pub fn marked_bit(&self) -> usize {
@@ -87,6 +128,14 @@ impl Header {
         self.header_value |= 0x200000000;
     }
 
+    pub fn unset_pinned_bit(&mut self) {
+        self.header_value &= !0x200000000;
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        self.pinned_bit() == 1
+    }
+
     pub fn grey_bit(&self) -> usize {
         (self.header_value & 0x100000000) >> 32
     }
@@ -116,11 +165,49 @@ impl Header {
         self.class_index_bits() == SpecialClassIndexes::FreeObject as usize
     }
 
+    pub fn is_filler(&self) -> bool {
+        self.class_index_bits() == SpecialClassIndexes::Filler as usize
+    }
+
+    // The free chunks and the fillers, no object is there
+    pub fn is_free_space(&self) -> bool {
+        self.is_free_oop() || self.is_filler()
+    }
+
+    // Forwarders keep the object they forward to in their first slot
+    pub fn is_forwarded(&self) -> bool {
+        self.class_index_bits() == SpecialClassIndexes::Forwarded as usize
+    }
+
+    pub fn is_segment_bridge(&self) -> bool {
+        self.class_index_bits() == SpecialClassIndexes::SegmentBridge as usize
+    }
+
+    // Bits objects (bytes, words) have slots that are not oops
+    pub fn contains_pointers(&self) -> bool {
+        self.format_bits() < HeaderFormatValues::I64BitIndexable as usize
+    }
+
+    // Compiled methods are bytes, except for their header and literals
+    pub fn is_compiled_method(&self) -> bool {
+        self.format_bits() >= HeaderFormatValues::CompiledMethodFormat as usize
+    }
+
     // reclaiming
     pub fn become_free_oop(&mut self) {
         self.set_class_index_bits(SpecialClassIndexes::FreeObject as usize);
     }
 
+    // For the free words too few for a free chunk
+    pub fn become_filler(&mut self) {
+        self.set_class_index_bits(SpecialClassIndexes::Filler as usize);
+    }
+
+    // forwarding
+    pub fn become_forwarder(&mut self) {
+        self.set_class_index_bits(SpecialClassIndexes::Forwarded as usize);
+    }
+
     pub fn header_size(&self) -> usize {
         if self.has_extra_slot_header() {
             1 + 1
@@ -138,7 +225,9 @@ impl Default for Header {
 
 #[cfg(test)]
 mod tests {
-    use crate::Header;
+    use crate::header::Header;
+    use crate::header::TooManySlotsForHeader;
+    use crate::header_format_values::HeaderFormatValues;
 
     #[test]
     fn test_class_index() {
@@ -175,7 +264,7 @@ mod tests {
     #[test]
     fn test_immutable_bit() {
         let header = Header {
-            header_value: 0x10000000000,
+            header_value: 0x400000000,
         };
         assert_eq!(header.immutable_bit(), 1);
     }
@@ -223,7 +312,7 @@ mod tests {
     #[test]
     fn test_not_immutable_bit() {
         let header = Header {
-            header_value: 0xFFFFFEFFFFFFFFFF,
+            header_value: 0xFFFFFFFBFFFFFFFF,
         };
         assert_eq!(header.immutable_bit(), 0);
     }
@@ -320,6 +409,19 @@ mod tests {
         assert_eq!(header.immutable_bit(), 1);
     }
 
+    #[test]
+    fn test_immutable_and_remembered_bits_are_distinct() {
+        let mut header = Header::new();
+        header.set_remembered_bit();
+        assert_eq!(header.immutable_bit(), 0);
+        header.set_immutable_bit();
+        header.unset_remembered_bit();
+        assert!(header.is_immutable());
+        assert_eq!(header.format_bits(), 0);
+        header.unset_immutable_bit();
+        assert_eq!(header.get_value(), 0);
+    }
+
     #[test]
     fn test_set_marked_bit() {
         let mut header = Header::new();
@@ -335,6 +437,27 @@ mod tests {
         assert_eq!(header.number_of_slots_bits(), number_of_slots);
     }
 
+    #[test]
+    fn test_try_set_too_many_slots_keeps_the_header() {
+        let mut header = Header::new();
+        header.set_number_of_slots_bits(42);
+        let result = header.try_set_number_of_slots_bits(Header::MAX_NUMBER_OF_SLOTS + 1);
+
+        assert_eq!(
+            result,
+            Err(TooManySlotsForHeader {
+                number_of_slots: Header::MAX_NUMBER_OF_SLOTS + 1
+            })
+        );
+        assert_eq!(header.number_of_slots_bits(), 42);
+    }
+
+    #[test]
+    #[should_panic(expected = "Headers only support")]
+    fn test_set_too_many_slots_panics() {
+        Header::new().set_number_of_slots_bits(Header::MAX_NUMBER_OF_SLOTS + 1);
+    }
+
     #[test]
     fn test_set_pinned_bit() {
         let mut header = Header::new();
@@ -342,6 +465,16 @@ mod tests {
         assert_eq!(header.pinned_bit(), 1);
     }
 
+    #[test]
+    fn test_unset_pinned_bit() {
+        let mut header = Header::new();
+        header.set_pinned_bit();
+        header.set_remembered_bit();
+        header.unset_pinned_bit();
+        assert!(!header.is_pinned());
+        assert_eq!(header.remembered_bit(), 1);
+    }
+
     #[test]
     fn test_set_remembered_bit() {
         let mut header = Header::new();
@@ -413,6 +546,36 @@ mod tests {
         assert_eq!(header.number_of_slots_bits(), 42);
     }
 
+    #[test]
+    fn test_become_forwarder_is_forwarded() {
+        let mut header = Header::new();
+        header.set_number_of_slots_bits(3);
+        header.become_forwarder();
+        assert!(header.is_forwarded());
+        assert_eq!(header.number_of_slots_bits(), 3);
+    }
+
+    #[test]
+    fn test_zero_format_contains_pointers() {
+        let header = Header::new();
+        assert!(header.contains_pointers());
+    }
+
+    #[test]
+    fn test_byte_format_does_not_contain_pointers() {
+        let mut header = Header::new();
+        header.set_format_bits(HeaderFormatValues::I8BitIndexable as usize);
+        assert!(!header.contains_pointers());
+    }
+
+    #[parameterized(format={ 24, 31 })]
+    fn test_compiled_method_format(format: usize) {
+        let mut header = Header::new();
+        header.set_format_bits(format);
+        assert!(header.is_compiled_method());
+        assert!(!header.contains_pointers());
+    }
+
     #[test]
     fn test_small_oop_does_not_have_extra_header() {
         let mut header = Header::new();
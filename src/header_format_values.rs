@@ -1,14 +1,18 @@
-enum HeaderFormatValues{
-	ZeroSizedFormat = 0, // nil, true false
-	NonIndexableWithSlotsFormat = 1, // Point
-	IndexableWithoutSlotsFormat  = 2, // Array
-	IndexableWithSlotsFormat = 3, // MethodContext 
-	WeakIndexableWithSlotsFormat = 4, // Weak Array
-	WeakNonIndexableWithSlotsFormat = 5, // Ephemerons
-	// 6 is unused
-	ImmediateFormat = 7, // Smallinteger, Characters, BoxedFloats
-	// 8 is unused
-	I64BitIndexable = 9,
-
-	//todo, can we do ranges ? 
+#[repr(usize)]
+pub enum HeaderFormatValues {
+    ZeroSizedFormat = 0,                 // nil, true false
+    NonIndexableWithSlotsFormat = 1,     // Point
+    IndexableWithoutSlotsFormat = 2,     // Array
+    IndexableWithSlotsFormat = 3,        // MethodContext
+    WeakIndexableWithSlotsFormat = 4,    // Weak Array
+    WeakNonIndexableWithSlotsFormat = 5, // Ephemerons
+    // 6 is unused
+    ImmediateFormat = 7, // Smallinteger, Characters, BoxedFloats
+    // 8 is unused
+    I64BitIndexable = 9,
+    // 16 to 23, the low bits are the number of unused bytes in the last slot
+    I8BitIndexable = 16, // ByteString, ByteSymbol
+    // 24 to 31, same as bytes
+    CompiledMethodFormat = 24,
+    //todo, can we do ranges ?
 }
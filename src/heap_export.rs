@@ -0,0 +1,339 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::class_table::class_constants;
+use crate::header_format_values::HeaderFormatValues;
+use crate::heap_queries;
+use crate::interpreter::Interpreter;
+use crate::oop_projections::oop_common::OopCommonState;
+
+// Dumps the objects of the heap and the pointers between them.
+// Graphviz DOT is for the small heaps, the JSON and JSON lines outputs are written object by object.
+
+// Which objects get exported, all of them by default
+#[derive(Debug, Default, Clone)]
+pub struct HeapExportFilter {
+    pub class_index: Option<usize>,
+    // Only the objects the next garbage collection keeps
+    pub reachable_only: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ExportedObject {
+    pub index: usize,
+    pub class_index: usize,
+    pub class_name: Option<String>,
+    pub format: usize,
+    pub number_of_slots: usize,
+    pub hash: usize,
+    pub immutable: bool,
+    pub pinned: bool,
+    pub marked: bool,
+    pub grey: bool,
+    pub remembered: bool,
+    pub weak: bool,
+    // The objects the slots reference, weak slots included
+    pub references: Vec<usize>,
+}
+
+// Metaclasses keep their class where classes keep their name, they answer "Foo class"
+pub fn class_name_of(interpreter: &mut Interpreter, class_index: usize) -> Option<String> {
+    let class = interpreter.class_table.class_at_index(class_index)?;
+    let name = interpreter
+        .space
+        .get_oop_at(class)
+        .try_slot_at_index(class_constants::NAME_INDEX)
+        .ok()?;
+    if let Some(name) = interpreter.string_value_of(name) {
+        return Some(name);
+    }
+    let this_class_name = interpreter
+        .space
+        .get_oop_at(name)
+        .try_slot_at_index(class_constants::NAME_INDEX)
+        .ok()?;
+    interpreter
+        .string_value_of(this_class_name)
+        .map(|name| format!("{} class", name))
+}
+
+// The indexes of the exported objects, in the order of the heap
+pub fn exported_indexes(interpreter: &mut Interpreter, filter: &HeapExportFilter) -> Vec<usize> {
+    let reachable: Option<HashSet<usize>> = if filter.reachable_only {
+        Some(heap_queries::reachable_objects(interpreter))
+    } else {
+        None
+    };
+    let objects = match filter.class_index {
+        Some(class_index) => heap_queries::all_instances_of(&mut interpreter.space, class_index),
+        None => heap_queries::all_objects(&mut interpreter.space),
+    };
+    objects
+        .into_iter()
+        .filter(|oop| {
+            reachable
+                .as_ref()
+                .is_none_or(|reachable| reachable.contains(oop))
+        })
+        .collect()
+}
+
+pub fn exported_object(interpreter: &mut Interpreter, index: usize) -> ExportedObject {
+    let an_oop = interpreter.space.get_oop_at(index);
+    let header = an_oop.get_header();
+    let class_index = header.class_index_bits();
+    let mut object = ExportedObject {
+        index,
+        class_index,
+        class_name: None,
+        format: header.format_bits(),
+        number_of_slots: an_oop.number_of_slots(),
+        hash: header.hash_bits(),
+        immutable: header.immutable_bit() == 1,
+        pinned: header.pinned_bit() == 1,
+        marked: header.marked_bit() == 1,
+        grey: header.grey_bit() == 1,
+        remembered: header.remembered_bit() == 1,
+        weak: header.format_bits() == HeaderFormatValues::WeakIndexableWithSlotsFormat as usize,
+        references: Vec::new(),
+    };
+    object.class_name = class_name_of(interpreter, class_index);
+    object.references = heap_queries::references_of(&mut interpreter.space, index, true);
+    object
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for character in value.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            character if (character as u32) < 0x20 => {
+                escaped.push_str(&format!("\\u{:04x}", character as u32))
+            }
+            character => escaped.push(character),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn as_json(object: &ExportedObject) -> String {
+    let class_name = match &object.class_name {
+        Some(name) => json_string(name),
+        None => String::from("null"),
+    };
+    let references: Vec<String> = object
+        .references
+        .iter()
+        .map(|reference| reference.to_string())
+        .collect();
+    format!(
+        "{{\"index\":{},\"class_index\":{},\"class_name\":{},\"format\":{},\"slots\":{},\"hash\":{},\
+         \"immutable\":{},\"pinned\":{},\"marked\":{},\"grey\":{},\"remembered\":{},\"weak\":{},\
+         \"references\":[{}]}}",
+        object.index,
+        object.class_index,
+        class_name,
+        object.format,
+        object.number_of_slots,
+        object.hash,
+        object.immutable,
+        object.pinned,
+        object.marked,
+        object.grey,
+        object.remembered,
+        object.weak,
+        references.join(",")
+    )
+}
+
+// One JSON object per line
+pub fn write_jsonl<W: Write + ?Sized>(
+    interpreter: &mut Interpreter,
+    filter: &HeapExportFilter,
+    writer: &mut W,
+) -> io::Result<()> {
+    for index in exported_indexes(interpreter, filter) {
+        let object = exported_object(interpreter, index);
+        writeln!(writer, "{}", as_json(&object))?;
+    }
+    writer.flush()
+}
+
+// A JSON array of the objects
+pub fn write_json<W: Write + ?Sized>(
+    interpreter: &mut Interpreter,
+    filter: &HeapExportFilter,
+    writer: &mut W,
+) -> io::Result<()> {
+    writeln!(writer, "[")?;
+    for (position, index) in exported_indexes(interpreter, filter)
+        .into_iter()
+        .enumerate()
+    {
+        if position > 0 {
+            writeln!(writer, ",")?;
+        }
+        let object = exported_object(interpreter, index);
+        write!(writer, "{}", as_json(&object))?;
+    }
+    writeln!(writer, "\n]")?;
+    writer.flush()
+}
+
+// Only the edges between exported objects are drawn, the weak ones dashed
+pub fn write_dot<W: Write + ?Sized>(
+    interpreter: &mut Interpreter,
+    filter: &HeapExportFilter,
+    writer: &mut W,
+) -> io::Result<()> {
+    let indexes = exported_indexes(interpreter, filter);
+    let exported: HashSet<usize> = indexes.iter().copied().collect();
+    writeln!(writer, "digraph heap {{")?;
+    writeln!(writer, "  node [shape=box, fontname=\"monospace\"];")?;
+    for index in indexes {
+        let object = exported_object(interpreter, index);
+        let class_name = object
+            .class_name
+            .clone()
+            .unwrap_or_else(|| format!("class {}", object.class_index));
+        writeln!(
+            writer,
+            "  o{} [label={}];",
+            object.index,
+            json_string(&format!(
+                "{} {}\nformat {} slots {}",
+                object.index, class_name, object.format, object.number_of_slots
+            ))
+        )?;
+        for reference in &object.references {
+            if !exported.contains(reference) {
+                continue;
+            }
+            if object.weak {
+                writeln!(
+                    writer,
+                    "  o{} -> o{} [style=dashed];",
+                    object.index, reference
+                )?;
+            } else {
+                writeln!(writer, "  o{} -> o{};", object.index, reference)?;
+            }
+        }
+    }
+    writeln!(writer, "}}")?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::bootstrap;
+    use crate::heap_export::{
+        exported_indexes, exported_object, write_dot, write_json, write_jsonl, HeapExportFilter,
+    };
+    use crate::heap_queries::all_objects;
+    use crate::interpreter::interpreter_test_support::new_array;
+    use crate::special_class_index::SpecialClassIndexes;
+
+    fn array_filter() -> HeapExportFilter {
+        HeapExportFilter {
+            class_index: Some(SpecialClassIndexes::Array as usize),
+            reachable_only: false,
+        }
+    }
+
+    #[test]
+    fn test_exported_object_decodes_the_header() {
+        let mut interpreter = bootstrap(40000);
+        let element = new_array(&mut interpreter, &[]);
+        let array = new_array(&mut interpreter, &[element, element]);
+
+        let object = exported_object(&mut interpreter, array);
+        assert_eq!(object.class_index, SpecialClassIndexes::Array as usize);
+        assert_eq!(object.class_name.as_deref(), Some("Array"));
+        assert_eq!(object.number_of_slots, 2);
+        assert_eq!(object.references, vec![element, element]);
+        assert!(!object.weak && !object.marked);
+    }
+
+    #[test]
+    fn test_metaclasses_are_named_after_their_class() {
+        let mut interpreter = bootstrap(40000);
+        let array_class = interpreter.class_named("Array").unwrap();
+
+        let object = exported_object(&mut interpreter, array_class);
+        assert_eq!(object.class_name.as_deref(), Some("Array class"));
+    }
+
+    #[test]
+    fn test_reachable_filter_leaves_the_garbage_out() {
+        let mut interpreter = bootstrap(40000);
+        let garbage = new_array(&mut interpreter, &[]);
+        let filter = HeapExportFilter {
+            class_index: None,
+            reachable_only: true,
+        };
+
+        let indexes = exported_indexes(&mut interpreter, &filter);
+        assert!(!indexes.contains(&garbage));
+        assert!(indexes.contains(&interpreter.special_objects()));
+        assert!(indexes.len() < all_objects(&mut interpreter.space).len());
+    }
+
+    #[test]
+    fn test_jsonl_has_a_line_per_object() {
+        let mut interpreter = bootstrap(40000);
+        let element = new_array(&mut interpreter, &[]);
+        let array = new_array(&mut interpreter, &[element]);
+        let mut output: Vec<u8> = Vec::new();
+
+        write_jsonl(&mut interpreter, &array_filter(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines.len(),
+            exported_indexes(&mut interpreter, &array_filter()).len()
+        );
+        assert!(lines.contains(
+            &format!(
+            "{{\"index\":{},\"class_index\":7,\"class_name\":\"Array\",\"format\":2,\"slots\":1,\
+             \"hash\":0,\"immutable\":false,\"pinned\":false,\"marked\":false,\"grey\":false,\
+             \"remembered\":false,\"weak\":false,\"references\":[{}]}}",
+            array, element
+        )
+            .as_str()
+        ));
+    }
+
+    #[test]
+    fn test_json_is_an_array() {
+        let mut interpreter = bootstrap(40000);
+        let mut output: Vec<u8> = Vec::new();
+
+        write_json(&mut interpreter, &array_filter(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("[\n{\"index\":"));
+        assert!(output.ends_with("}\n]\n"));
+    }
+
+    #[test]
+    fn test_dot_draws_the_edges_between_exported_objects() {
+        let mut interpreter = bootstrap(40000);
+        let element = new_array(&mut interpreter, &[]);
+        let nil = interpreter.nil_object();
+        let array = new_array(&mut interpreter, &[element, nil]);
+        let mut output: Vec<u8> = Vec::new();
+
+        write_dot(&mut interpreter, &array_filter(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("digraph heap {"));
+        assert!(output.contains(&format!("  o{} -> o{};", array, element)));
+        assert!(!output.contains(&format!("-> o{};", nil)));
+    }
+}
@@ -0,0 +1,280 @@
+// Drives a bare memory space with random sequences of allocations, stores, frees and collections,
+// next to a model of the heap in Rust collections.
+// After each operation the objects of the space are those of the model, with the same slots,
+// and the heap verifies. Proptest shrinks the failing sequences down to a minimal one.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use proptest::prelude::*;
+use proptest::sample::Index;
+
+use crate::allocator::{allocation_strategy_named, ALLOCATION_STRATEGIES};
+use crate::garbage_collector::simple_garbage_collector;
+use crate::header_format_values::HeaderFormatValues;
+use crate::heap_queries;
+use crate::heap_verifier::verify_space;
+use crate::memory_space::MemorySpace;
+use crate::oop_builder::OopBuilder;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::slot_content::SlotContent;
+use crate::special_class_index::SpecialClassIndexes;
+
+// All the objects are arrays, the collector follows their slots
+const MODEL_CLASS_INDEX: usize = SpecialClassIndexes::Array as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ModelSlot {
+    Integer(isize),
+    // The identifier of an object of the model
+    Reference(usize),
+}
+
+// The indexes pick among the objects of the model when the operation is applied
+#[derive(Debug, Clone)]
+enum Operation {
+    Allocate {
+        number_of_slots: usize,
+        root: bool,
+        pinned: bool,
+    },
+    StoreInteger {
+        object: Index,
+        slot: Index,
+        value: isize,
+    },
+    StoreReference {
+        object: Index,
+        slot: Index,
+        target: Index,
+    },
+    // An object no root reaches and no object references
+    FreeGarbage {
+        object: Index,
+    },
+    DropRoot {
+        root: Index,
+    },
+    Collect,
+}
+
+#[derive(Default)]
+struct HeapModel {
+    // The slots of the objects still in the space, by identifier
+    objects: BTreeMap<usize, Vec<ModelSlot>>,
+    // Where each of them is in the space
+    indexes: BTreeMap<usize, usize>,
+    roots: Vec<usize>,
+    next_identifier: usize,
+}
+
+impl HeapModel {
+    fn identifiers(&self) -> Vec<usize> {
+        self.objects.keys().copied().collect()
+    }
+
+    fn reachable(&self) -> HashSet<usize> {
+        let mut reachable = HashSet::new();
+        let mut to_visit = self.roots.clone();
+        while let Some(identifier) = to_visit.pop() {
+            if reachable.insert(identifier) {
+                for slot in &self.objects[&identifier] {
+                    if let ModelSlot::Reference(target) = slot {
+                        to_visit.push(*target);
+                    }
+                }
+            }
+        }
+        reachable
+    }
+
+    fn is_referenced(&self, identifier: usize) -> bool {
+        self.objects
+            .values()
+            .flatten()
+            .any(|slot| *slot == ModelSlot::Reference(identifier))
+    }
+
+    fn root_indexes(&self) -> Vec<usize> {
+        self.roots
+            .iter()
+            .map(|identifier| self.indexes[identifier])
+            .collect()
+    }
+
+    fn slot_value(&self, slot: ModelSlot) -> usize {
+        match slot {
+            ModelSlot::Integer(value) => SlotContent::small_integer(value),
+            ModelSlot::Reference(identifier) => self.indexes[&identifier],
+        }
+    }
+
+    fn apply(&mut self, operation: &Operation, space: &mut MemorySpace) {
+        match *operation {
+            Operation::Allocate {
+                number_of_slots,
+                root,
+                pinned,
+            } => {
+                let mut builder = OopBuilder::new();
+                builder.set_class_index(MODEL_CLASS_INDEX);
+                builder.set_format(HeaderFormatValues::IndexableWithoutSlotsFormat as usize);
+                builder.set_number_of_slots(number_of_slots);
+                builder.set_slots_value(self.slot_value(ModelSlot::Integer(0)));
+                builder.set_pinned(pinned);
+                let index = builder.build(space);
+
+                let identifier = self.next_identifier;
+                self.next_identifier += 1;
+                self.objects
+                    .insert(identifier, vec![ModelSlot::Integer(0); number_of_slots]);
+                self.indexes.insert(identifier, index);
+                if root {
+                    self.roots.push(identifier);
+                }
+            }
+            Operation::StoreInteger {
+                object,
+                slot,
+                value,
+            } => self.store(space, object, slot, ModelSlot::Integer(value)),
+            Operation::StoreReference {
+                object,
+                slot,
+                target,
+            } => {
+                let identifiers = self.identifiers();
+                if !identifiers.is_empty() {
+                    let target = identifiers[target.index(identifiers.len())];
+                    self.store(space, object, slot, ModelSlot::Reference(target));
+                }
+            }
+            Operation::FreeGarbage { object } => {
+                let reachable = self.reachable();
+                let candidates: Vec<usize> = self
+                    .identifiers()
+                    .into_iter()
+                    .filter(|identifier| {
+                        !reachable.contains(identifier) && !self.is_referenced(*identifier)
+                    })
+                    .collect();
+                if !candidates.is_empty() {
+                    let identifier = candidates[object.index(candidates.len())];
+                    space
+                        .get_oop_at(self.indexes[&identifier])
+                        .become_free_oop();
+                    self.objects.remove(&identifier);
+                    self.indexes.remove(&identifier);
+                }
+            }
+            Operation::DropRoot { root } => {
+                if !self.roots.is_empty() {
+                    self.roots.remove(root.index(self.roots.len()));
+                }
+            }
+            Operation::Collect => {
+                simple_garbage_collector::collect_from_roots(self.root_indexes(), space);
+                let reachable = self.reachable();
+                self.objects
+                    .retain(|identifier, _| reachable.contains(identifier));
+                self.indexes
+                    .retain(|identifier, _| reachable.contains(identifier));
+            }
+        }
+    }
+
+    fn store(&mut self, space: &mut MemorySpace, object: Index, slot: Index, value: ModelSlot) {
+        let identifiers = self.identifiers();
+        if identifiers.is_empty() {
+            return;
+        }
+        let identifier = identifiers[object.index(identifiers.len())];
+        let number_of_slots = self.objects[&identifier].len();
+        if number_of_slots == 0 {
+            return;
+        }
+        let slot = slot.index(number_of_slots);
+        let slot_value = self.slot_value(value);
+        space
+            .get_oop_at(self.indexes[&identifier])
+            .slot_at_index_put(slot + 1, slot_value);
+        self.objects.get_mut(&identifier).unwrap()[slot] = value;
+    }
+
+    fn check(&self, space: &mut MemorySpace) -> Result<(), TestCaseError> {
+        let objects: BTreeSet<usize> = heap_queries::all_objects(space).into_iter().collect();
+        let expected: BTreeSet<usize> = self.indexes.values().copied().collect();
+        prop_assert_eq!(objects, expected);
+
+        for (identifier, slots) in &self.objects {
+            let index = self.indexes[identifier];
+            let an_oop = space.get_oop_at(index);
+            prop_assert_eq!(an_oop.number_of_slots(), slots.len());
+            for (position, slot) in slots.iter().enumerate() {
+                prop_assert_eq!(
+                    an_oop.slot_at_index(position + 1),
+                    self.slot_value(*slot),
+                    "slot {} of the object at {}",
+                    position + 1,
+                    index
+                );
+            }
+        }
+
+        let problems = verify_space(
+            space,
+            &|class_index| class_index == MODEL_CLASS_INDEX,
+            &self.root_indexes(),
+        );
+        prop_assert!(problems.is_empty(), "{:?}", problems);
+        Ok(())
+    }
+}
+
+fn operation() -> impl Strategy<Value = Operation> {
+    let number_of_slots = prop_oneof![0..4usize, 250..260usize, 0..600usize];
+    prop_oneof![
+        4 => (number_of_slots, any::<bool>(), prop::bool::weighted(0.1)).prop_map(
+            |(number_of_slots, root, pinned)| Operation::Allocate {
+                number_of_slots,
+                root,
+                pinned,
+            }
+        ),
+        3 => (any::<Index>(), any::<Index>(), -1000..1000isize).prop_map(
+            |(object, slot, value)| Operation::StoreInteger {
+                object,
+                slot,
+                value,
+            }
+        ),
+        3 => (any::<Index>(), any::<Index>(), any::<Index>()).prop_map(
+            |(object, slot, target)| Operation::StoreReference {
+                object,
+                slot,
+                target,
+            }
+        ),
+        1 => any::<Index>().prop_map(|object| Operation::FreeGarbage { object }),
+        1 => any::<Index>().prop_map(|root| Operation::DropRoot { root }),
+        1 => Just(Operation::Collect),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn test_heap_follows_the_model(
+        operations in prop::collection::vec(operation(), 1..100),
+        space_size in prop_oneof![Just(240usize), Just(1000usize)],
+        strategy in 0..ALLOCATION_STRATEGIES.len(),
+    ) {
+        let mut space = MemorySpace::for_bit_size(space_size);
+        space.replace_allocation_strategy(
+            allocation_strategy_named(ALLOCATION_STRATEGIES[strategy]).unwrap(),
+        );
+        let mut model = HeapModel::default();
+        for operation in &operations {
+            model.apply(operation, &mut space);
+            model.check(&mut space)?;
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::compiled_method::{compiled_method_constants, MethodHeader};
+use crate::forwarding;
+use crate::header_format_values::HeaderFormatValues;
+use crate::interpreter::Interpreter;
+use crate::memory_space::MemorySpace;
+use crate::oop_projections::oop_common::{OopCommonState, OopNavigation};
+use crate::slot_content::SlotContent;
+
+// Queries over the whole heap, to hunt down the objects that should be gone.
+// Forwarders are not objects anymore, the queries skip them and follow the references to them.
+
+// The objects of the heap, free chunks, fillers and forwarders excluded
+pub fn all_objects(space: &mut MemorySpace) -> Vec<usize> {
+    let mut iterator = space.iter();
+    let mut objects = Vec::new();
+    while let Some(headers) = iterator.next_headers(space) {
+        if !headers.get_header().is_free_space() && !headers.get_header().is_forwarded() {
+            objects.push(headers.get_index());
+        }
+    }
+    objects
+}
+
+pub fn all_instances_of(space: &mut MemorySpace, class_index: usize) -> Vec<usize> {
+    all_objects(space)
+        .into_iter()
+        .filter(|oop| space.get_oop_at(*oop).get_header().class_index_bits() == class_index)
+        .collect()
+}
+
+// The objects the slots of the object reference, the literals for the compiled methods
+pub fn references_of(space: &mut MemorySpace, oop: usize, include_weak: bool) -> Vec<usize> {
+    let an_oop = space.get_oop_at(oop);
+    let header = an_oop.get_header();
+    let slot_indexes = if header.format_bits()
+        == HeaderFormatValues::WeakIndexableWithSlotsFormat as usize
+        && !include_weak
+    {
+        return Vec::new();
+    } else if header.contains_pointers() {
+        1..an_oop.number_of_slots() + 1
+    } else if header.is_compiled_method() {
+        let number_of_literals = MethodHeader::from_slot_value(
+            an_oop.slot_at_index(compiled_method_constants::HEADER_INDEX),
+        )
+        .number_of_literals();
+        compiled_method_constants::FIRST_LITERAL_INDEX
+            ..compiled_method_constants::FIRST_LITERAL_INDEX + number_of_literals
+    } else {
+        return Vec::new();
+    };
+    let references: Vec<usize> = slot_indexes
+        .map(|index| an_oop.slot_at_index(index))
+        .filter(|slot| SlotContent::new(*slot).is_slot_oop())
+        .collect();
+    references
+        .into_iter()
+        .map(|reference| forwarding::follow_forwarded(space, reference))
+        .collect()
+}
+
+// The objects with a slot referencing the target, weak slots included
+pub fn pointers_to(space: &mut MemorySpace, target: usize) -> Vec<usize> {
+    all_objects(space)
+        .into_iter()
+        .filter(|oop| references_of(space, *oop, true).contains(&target))
+        .collect()
+}
+
+// The objects the roots reach through strong references, what the next collection keeps
+pub fn reachable_objects(interpreter: &mut Interpreter) -> HashSet<usize> {
+    let mut reached: HashSet<usize> = HashSet::new();
+    let mut to_visit: Vec<usize> = interpreter.roots();
+    while let Some(oop) = to_visit.pop() {
+        let oop = forwarding::follow_forwarded(&mut interpreter.space, oop);
+        if SlotContent::new(oop).is_slot_oop() && reached.insert(oop) {
+            to_visit.extend(references_of(&mut interpreter.space, oop, false));
+        }
+    }
+    reached
+}
+
+// A shortest chain of strong references from a root to the target, both included.
+// None when the next garbage collection reclaims the target.
+pub fn why_is_this_alive(interpreter: &mut Interpreter, target: usize) -> Option<Vec<usize>> {
+    let target = forwarding::follow_forwarded(&mut interpreter.space, target);
+    // Each reached object with the object it was reached from, None for the roots
+    let mut reached_from: HashMap<usize, Option<usize>> = HashMap::new();
+    let mut to_visit: VecDeque<usize> = VecDeque::new();
+    for root in interpreter.roots() {
+        let root = forwarding::follow_forwarded(&mut interpreter.space, root);
+        if !SlotContent::new(root).is_slot_oop() {
+            continue;
+        }
+        if let Entry::Vacant(entry) = reached_from.entry(root) {
+            entry.insert(None);
+            to_visit.push_back(root);
+        }
+    }
+
+    while let Some(oop) = to_visit.pop_front() {
+        if oop == target {
+            let mut path = vec![target];
+            while let Some(Some(previous)) = reached_from.get(path.last().unwrap()) {
+                path.push(*previous);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for reference in references_of(&mut interpreter.space, oop, false) {
+            if let Entry::Vacant(entry) = reached_from.entry(reference) {
+                entry.insert(Some(oop));
+                to_visit.push_back(reference);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::{bootstrap, define_class};
+    use crate::compiler::evaluate;
+    use crate::forwarding::forward_identities;
+    use crate::header_format_values::HeaderFormatValues;
+    use crate::heap_queries::{
+        all_instances_of, all_objects, pointers_to, reachable_objects, why_is_this_alive,
+    };
+    use crate::interpreter::interpreter_test_support::{keep_on_the_stack, new_array};
+    use crate::oop_projections::oop_common::OopCommonState;
+    use crate::slot_content::SlotContent;
+    use crate::special_class_index::SpecialClassIndexes;
+
+    #[test]
+    fn test_all_objects_skips_the_free_chunks() {
+        let mut interpreter = bootstrap(40000);
+        let objects = all_objects(&mut interpreter.space);
+
+        assert!(objects.contains(&interpreter.nil_object()));
+        assert!(objects
+            .iter()
+            .all(|oop| !interpreter.space.get_oop_at(*oop).is_free_oop()));
+    }
+
+    #[test]
+    fn test_all_instances_of_a_class_index() {
+        let mut interpreter = bootstrap(40000);
+        let before = all_instances_of(&mut interpreter.space, SpecialClassIndexes::Array as usize);
+        let first = new_array(&mut interpreter, &[]);
+        let second = new_array(&mut interpreter, &[first]);
+
+        let after = all_instances_of(&mut interpreter.space, SpecialClassIndexes::Array as usize);
+        assert_eq!(after.len(), before.len() + 2);
+        assert!(after.contains(&first) && after.contains(&second));
+    }
+
+    #[test]
+    fn test_pointers_to_follows_the_forwarders() {
+        let mut interpreter = bootstrap(40000);
+        let one = SlotContent::small_integer(1);
+        let target = new_array(&mut interpreter, &[one]);
+        let source = new_array(&mut interpreter, &[one]);
+        let direct = new_array(&mut interpreter, &[target, one]);
+        let through_forwarder = new_array(&mut interpreter, &[source]);
+
+        forward_identities(&mut interpreter, &[source], &[target]).unwrap();
+
+        let mut pointers = pointers_to(&mut interpreter.space, target);
+        pointers.sort();
+        assert_eq!(pointers, vec![direct, through_forwarder]);
+    }
+
+    #[test]
+    fn test_why_is_this_alive_answers_the_shortest_path() {
+        let mut interpreter = bootstrap(40000);
+        let target = new_array(&mut interpreter, &[]);
+        let middle = new_array(&mut interpreter, &[target]);
+        let long_way = new_array(&mut interpreter, &[middle]);
+        let holder = new_array(&mut interpreter, &[long_way, middle]);
+        keep_on_the_stack(&mut interpreter, &[holder]);
+
+        assert_eq!(
+            why_is_this_alive(&mut interpreter, target),
+            Some(vec![holder, middle, target])
+        );
+    }
+
+    #[test]
+    fn test_garbage_is_not_alive() {
+        let mut interpreter = bootstrap(40000);
+        let target = new_array(&mut interpreter, &[]);
+        new_array(&mut interpreter, &[target]);
+
+        assert_eq!(why_is_this_alive(&mut interpreter, target), None);
+        assert!(!reachable_objects(&mut interpreter).contains(&target));
+        interpreter.collect_garbage();
+        assert!(interpreter.space.get_oop_at(target).is_free_oop());
+    }
+
+    #[test]
+    fn test_all_instances_from_the_kernel() {
+        let mut interpreter = bootstrap(40000);
+        let object = interpreter.class_named("Object").unwrap();
+        define_class(
+            &mut interpreter,
+            "Box",
+            object,
+            HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+            &["contents"],
+        );
+
+        let value = evaluate(&mut interpreter, "Box new. Box new. Box allInstances size").unwrap();
+        assert_eq!(value, SlotContent::small_integer(2));
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::compiled_method::{compiled_method_constants, MethodHeader};
+use crate::forwarding::forwarder_constants;
+use crate::free_chunk;
+use crate::header::Header;
+use crate::interpreter::Interpreter;
+use crate::memory_space::{memory_space_constants, MemorySpace};
+use crate::oop_projections::oop_common::{OopCommonState, OopNavigation};
+use crate::oop_projections::oop_headers::OopHeaders;
+use crate::slot_content::SlotContent;
+
+// Checks the heap is consistent, for the tools and the tests.
+// The walk of a segment stops at the first object running over its bridge, the rest cannot be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeapProblem {
+    ObjectOutOfSpace {
+        index: usize,
+        size: usize,
+    },
+    UnknownClassIndex {
+        index: usize,
+        class_index: usize,
+    },
+    // A slot referencing something else than the start of an object
+    DanglingPointer {
+        index: usize,
+        slot: usize,
+        value: usize,
+    },
+    // A root referencing something else than the start of an object
+    DanglingRoot {
+        value: usize,
+    },
+    // Marks only live during a collection
+    LeftMarked {
+        index: usize,
+    },
+    // A segment not ending with a bridge to the next one
+    BrokenBridge {
+        index: usize,
+    },
+    // A pinned object where a collector may move it
+    MovablePinnedObject {
+        index: usize,
+    },
+    // A free chunk under the minimum size, or a filler as big as a free chunk
+    MisSizedFreeSpace {
+        index: usize,
+        size: usize,
+    },
+}
+
+impl fmt::Display for HeapProblem {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeapProblem::ObjectOutOfSpace { index, size } => write!(
+                formatter,
+                "the object at {} of {} words runs out of the memory space",
+                index, size
+            ),
+            HeapProblem::UnknownClassIndex { index, class_index } => write!(
+                formatter,
+                "the object at {} has the unknown class index {}",
+                index, class_index
+            ),
+            HeapProblem::DanglingPointer { index, slot, value } => write!(
+                formatter,
+                "slot {} of the object at {} references {}, which is not an object",
+                slot, index, value
+            ),
+            HeapProblem::DanglingRoot { value } => {
+                write!(formatter, "the root {} is not an object", value)
+            }
+            HeapProblem::LeftMarked { index } => {
+                write!(formatter, "the object at {} is still marked", index)
+            }
+            HeapProblem::BrokenBridge { index } => {
+                write!(
+                    formatter,
+                    "the bridge at {} does not lead to the next segment",
+                    index
+                )
+            }
+            HeapProblem::MovablePinnedObject { index } => write!(
+                formatter,
+                "the object at {} is pinned outside of the non moving segments",
+                index
+            ),
+            HeapProblem::MisSizedFreeSpace { index, size } => write!(
+                formatter,
+                "the free space at {} has {} words, the free chunks have at least {}, the fillers less",
+                index,
+                size,
+                free_chunk::MINIMUM_FREE_CHUNK_SIZE
+            ),
+        }
+    }
+}
+
+// The slot indexes holding oops: all the slots of the pointers objects, the literals of the
+// methods, the target of the forwarders
+fn pointer_slot_indexes(headers: &OopHeaders, first_slot: usize) -> std::ops::Range<usize> {
+    let header = headers.get_header();
+    if header.is_forwarded() {
+        forwarder_constants::TARGET_INDEX..forwarder_constants::TARGET_INDEX + 1
+    } else if header.contains_pointers() {
+        1..headers.number_of_slots() + 1
+    } else if header.is_compiled_method() {
+        let number_of_literals = MethodHeader::from_slot_value(first_slot).number_of_literals();
+        compiled_method_constants::FIRST_LITERAL_INDEX
+            ..compiled_method_constants::FIRST_LITERAL_INDEX + number_of_literals
+    } else {
+        0..0
+    }
+}
+
+pub fn verify_heap(interpreter: &mut Interpreter) -> Vec<HeapProblem> {
+    let roots = interpreter.roots();
+    let class_table = &interpreter.class_table;
+    verify_space(
+        &mut interpreter.space,
+        &|class_index| class_table.class_at_index(class_index).is_some(),
+        &roots,
+    )
+}
+
+// The same checks on a space without an interpreter around it,
+// the caller tells which class indexes are known and which objects are the roots
+pub fn verify_space(
+    space: &mut MemorySpace,
+    is_known_class_index: &dyn Fn(usize) -> bool,
+    roots: &[usize],
+) -> Vec<HeapProblem> {
+    let mut problems = Vec::new();
+    // The segments are walked word by word, the free chunk of the allocation buffer included
+    space.retire_allocation_buffer();
+    // The start and the bridge of each segment
+    let segments: Vec<(usize, usize)> = space
+        .segments()
+        .iter()
+        .map(|segment| (segment.start(), segment.bridge_index()))
+        .collect();
+
+    // Each object start, with whether it is a free chunk
+    let mut starts: HashMap<usize, bool> = HashMap::new();
+    let mut objects: Vec<OopHeaders> = Vec::new();
+    for (position, (start, end)) in segments.iter().copied().enumerate() {
+        let mut index = start;
+        while index < end {
+            let header = Header {
+                header_value: space[index],
+            };
+            if header.has_extra_slot_header() && index + 1 >= end {
+                problems.push(HeapProblem::ObjectOutOfSpace { index, size: 2 });
+                break;
+            }
+            let headers = OopHeaders::new(index, space);
+            let size = headers.oop_size();
+            if index + size > end {
+                problems.push(HeapProblem::ObjectOutOfSpace { index, size });
+                break;
+            }
+            let is_free_space = headers.get_header().is_free_space();
+            if is_free_space
+                && headers.is_free_oop() != (size >= free_chunk::MINIMUM_FREE_CHUNK_SIZE)
+            {
+                problems.push(HeapProblem::MisSizedFreeSpace { index, size });
+            }
+            starts.insert(index, is_free_space);
+            if !is_free_space {
+                objects.push(headers);
+            }
+            index += size;
+        }
+
+        let bridge = Header {
+            header_value: space[end],
+        };
+        let target = segments
+            .get(position + 1)
+            .map_or(end + memory_space_constants::BRIDGE_SIZE, |next| next.0);
+        if !bridge.is_segment_bridge()
+            || space[end + memory_space_constants::BRIDGE_TARGET_INDEX] != target
+        {
+            problems.push(HeapProblem::BrokenBridge { index: end });
+        }
+    }
+
+    let is_object = |value: usize| starts.get(&value) == Some(&false);
+    for headers in &objects {
+        let index = headers.get_index();
+        let header = headers.get_header();
+        let class_index = header.class_index_bits();
+        if !header.is_forwarded() && !is_known_class_index(class_index) {
+            problems.push(HeapProblem::UnknownClassIndex { index, class_index });
+        }
+        if header.marked_bit() == 1 {
+            problems.push(HeapProblem::LeftMarked { index });
+        }
+        if header.is_pinned() && space.is_in_moving_space(index) {
+            problems.push(HeapProblem::MovablePinnedObject { index });
+        }
+        let first_slot = if headers.number_of_slots() > 0 {
+            space[index + header.header_size()]
+        } else {
+            0
+        };
+        for slot in pointer_slot_indexes(headers, first_slot) {
+            let value = space[index + header.header_size() + slot - 1];
+            if SlotContent::new(value).is_slot_oop() && !is_object(value) {
+                problems.push(HeapProblem::DanglingPointer { index, slot, value });
+            }
+        }
+    }
+
+    for &value in roots {
+        if SlotContent::new(value).is_slot_oop() && !is_object(value) {
+            problems.push(HeapProblem::DanglingRoot { value });
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::bootstrap;
+    use crate::heap_verifier::{verify_heap, HeapProblem};
+    use crate::memory_space::memory_space_constants;
+    use crate::oop_projections::oop_common::{OopCommonState, OopNavigation};
+    use crate::special_class_index::SpecialClassIndexes;
+
+    #[test]
+    fn test_bootstrapped_heap_is_consistent() {
+        let mut interpreter = bootstrap(40000);
+        assert_eq!(verify_heap(&mut interpreter), vec![]);
+        interpreter.collect_garbage();
+        assert_eq!(verify_heap(&mut interpreter), vec![]);
+    }
+
+    #[test]
+    fn test_dangling_pointer_is_found() {
+        let mut interpreter = bootstrap(40000);
+        let array_class = interpreter.class_named("Array").unwrap();
+        let array = interpreter.instantiate_class(array_class, 1).unwrap();
+        interpreter
+            .space
+            .get_oop_at(array)
+            .slot_at_index_put(1, array + 1);
+
+        assert_eq!(
+            verify_heap(&mut interpreter),
+            vec![HeapProblem::DanglingPointer {
+                index: array,
+                slot: 1,
+                value: array + 1
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unknown_class_index_and_marks_are_found() {
+        let mut interpreter = bootstrap(40000);
+        let array_class = interpreter.class_named("Array").unwrap();
+        let array = interpreter.instantiate_class(array_class, 1).unwrap();
+        let mut array_oop = interpreter.space.get_oop_at(array);
+        array_oop.get_header_mut().set_class_index_bits(4000);
+        array_oop.get_header_mut().set_marked_bit();
+        array_oop.get_header_mut().set_pinned_bit();
+        array_oop.apply_header();
+
+        assert_eq!(
+            verify_heap(&mut interpreter),
+            vec![
+                HeapProblem::UnknownClassIndex {
+                    index: array,
+                    class_index: 4000
+                },
+                HeapProblem::LeftMarked { index: array },
+                HeapProblem::MovablePinnedObject { index: array }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mis_sized_free_space_is_found() {
+        let mut interpreter = bootstrap(40000);
+        let object_class = interpreter.class_named("Object").unwrap();
+        let array_class = interpreter.class_named("Array").unwrap();
+        let empty = interpreter.instantiate_class(object_class, 0).unwrap();
+        let array = interpreter.instantiate_class(array_class, 1).unwrap();
+        let mut empty_oop = interpreter.space.get_oop_at(empty);
+        empty_oop.get_header_mut().become_free_oop();
+        empty_oop.apply_header();
+        let mut array_oop = interpreter.space.get_oop_at(array);
+        array_oop
+            .get_header_mut()
+            .set_class_index_bits(SpecialClassIndexes::Filler as usize);
+        array_oop.apply_header();
+
+        assert_eq!(
+            verify_heap(&mut interpreter),
+            vec![
+                HeapProblem::MisSizedFreeSpace {
+                    index: empty,
+                    size: 1
+                },
+                HeapProblem::MisSizedFreeSpace {
+                    index: array,
+                    size: 2
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_object_out_of_space_stops_the_walk() {
+        let mut interpreter = bootstrap(40000);
+        let mut iterator = interpreter.space.iter();
+        let mut last = 0;
+        while let Some(headers) = iterator.next_headers(&mut interpreter.space) {
+            last = headers.get_index();
+        }
+        let mut last_oop = interpreter.space.get_oop_at(last);
+        let number_of_slots = last_oop.number_of_slots();
+        last_oop.set_number_of_slots(number_of_slots + 300);
+        let size = last_oop.oop_size();
+        last_oop.apply_header();
+
+        assert_eq!(
+            verify_heap(&mut interpreter),
+            vec![HeapProblem::ObjectOutOfSpace { index: last, size }]
+        );
+    }
+
+    #[test]
+    fn test_segments_are_walked_through_their_bridges() {
+        let mut interpreter = bootstrap(40000);
+        let array_class = interpreter.class_named("Array").unwrap();
+        let segments = interpreter.space.segments().len();
+        let big = interpreter.instantiate_class(array_class, 50000).unwrap();
+        assert_eq!(interpreter.space.segments().len(), segments + 1);
+        assert_eq!(verify_heap(&mut interpreter), vec![]);
+
+        let bridge = interpreter.space.segments()[0].bridge_index();
+        interpreter.space[bridge + memory_space_constants::BRIDGE_TARGET_INDEX] = big + 1;
+        assert_eq!(
+            verify_heap(&mut interpreter),
+            vec![HeapProblem::BrokenBridge { index: bridge }]
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use crate::class_table::ClassTable;
+use crate::interpreter::Interpreter;
+use crate::memory_space::{memory_space_constants, MemorySpace, SegmentKind};
+use crate::primitives::external_primitives;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+// An image is a sequence of little endian 64 bits words:
+// magic, version, number of segments, special objects oop,
+// number of class table entries, the entries (0 for a free entry, the oop plus one otherwise),
+// then for each segment its start, its kind (1 for a large object, 2 for pinned objects), its number of words
+// and its words, bridge included.
+// The stack is not saved, a loaded image starts with an empty stack zone.
+pub mod image_constants {
+    pub const MAGIC: u64 = u64::from_le_bytes(*b"FUNVMIMG");
+    pub const VERSION: u64 = 4;
+}
+
+fn write_word<W: Write>(writer: &mut W, word: usize) -> io::Result<()> {
+    writer.write_all(&(word as u64).to_le_bytes())
+}
+
+fn read_word<R: Read>(reader: &mut R) -> io::Result<usize> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes) as usize)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// The free chunk of the allocation buffer gets its header first
+pub fn write_image<W: Write>(interpreter: &mut Interpreter, writer: &mut W) -> io::Result<()> {
+    interpreter.space.retire_allocation_buffer();
+    write_word(writer, image_constants::MAGIC as usize)?;
+    write_word(writer, image_constants::VERSION as usize)?;
+
+    let segments = interpreter.space.segments();
+    write_word(writer, segments.len())?;
+    write_word(writer, interpreter.special_objects())?;
+
+    let entries = interpreter.class_table.entries();
+    write_word(writer, entries.len())?;
+    for entry in entries {
+        write_word(writer, entry.map_or(0, |class| class + 1))?;
+    }
+
+    for segment in segments {
+        write_word(writer, segment.start())?;
+        write_word(
+            writer,
+            match segment.kind() {
+                SegmentKind::Objects => 0,
+                SegmentKind::LargeObject => 1,
+                SegmentKind::Pinned => 2,
+            },
+        )?;
+        let words = interpreter.space.segment_words(segment);
+        write_word(writer, words.len())?;
+        for word in words {
+            write_word(writer, *word)?;
+        }
+    }
+    writer.flush()
+}
+
+pub fn read_image<R: Read>(reader: &mut R) -> io::Result<Interpreter> {
+    if read_word(reader)? as u64 != image_constants::MAGIC {
+        return Err(invalid_data("not an image"));
+    }
+    let version = read_word(reader)? as u64;
+    if version != image_constants::VERSION {
+        return Err(invalid_data(&format!(
+            "unsupported image version {}",
+            version
+        )));
+    }
+
+    let number_of_segments = read_word(reader)?;
+    let special_objects = read_word(reader)?;
+
+    let number_of_entries = read_word(reader)?;
+    let mut entries = Vec::new();
+    for _ in 0..number_of_entries {
+        let entry = match read_word(reader)? {
+            0 => None,
+            class => Some(class - 1),
+        };
+        entries.push(entry);
+    }
+
+    let mut segments = Vec::new();
+    let mut end = 0;
+    for _ in 0..number_of_segments {
+        let start = read_word(reader)?;
+        let kind = match read_word(reader)? {
+            0 => SegmentKind::Objects,
+            1 => SegmentKind::LargeObject,
+            2 => SegmentKind::Pinned,
+            _ => return Err(invalid_data("unknown segment kind")),
+        };
+        let number_of_words = read_word(reader)?;
+        if start < end || number_of_words < memory_space_constants::BRIDGE_SIZE + 1 {
+            return Err(invalid_data("overlapping or empty segments"));
+        }
+        end = start + number_of_words;
+        let mut words = Vec::new();
+        for _ in 0..number_of_words {
+            words.push(read_word(reader)?);
+        }
+        segments.push((start, kind, words));
+    }
+    if segments.is_empty() {
+        return Err(invalid_data("no memory segments"));
+    }
+    let space = MemorySpace::from_segments(segments);
+    if space.segment_containing(special_objects).is_none() {
+        return Err(invalid_data("special objects outside of the memory"));
+    }
+
+    let mut interpreter =
+        Interpreter::new(space, special_objects, ClassTable::from_entries(entries));
+    // The cached resolutions belong to the plugins of the VM that saved the image
+    external_primitives::flush_external_primitive_caches(&mut interpreter);
+    Ok(interpreter)
+}
+
+pub fn save_image(interpreter: &mut Interpreter, path: &Path) -> io::Result<()> {
+    write_image(interpreter, &mut BufWriter::new(File::create(path)?))
+}
+
+pub fn load_image(path: &Path) -> io::Result<Interpreter> {
+    read_image(&mut BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::bootstrap;
+    use crate::image::{load_image, read_image, save_image, write_image};
+    use crate::interpreter::Interpreter;
+    use crate::pinning;
+    use crate::special_object_index::SpecialObjectIndexes;
+    use crate::symbol_table;
+    use std::io::ErrorKind;
+
+    fn words_of(interpreter: &Interpreter) -> Vec<usize> {
+        interpreter
+            .space
+            .segments()
+            .iter()
+            .flat_map(|segment| interpreter.space.segment_words(segment).to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_keeps_the_memory() {
+        let mut interpreter = bootstrap(20000);
+        let mut bytes: Vec<u8> = Vec::new();
+        write_image(&mut interpreter, &mut bytes).unwrap();
+
+        let loaded = read_image(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(loaded.space.segments(), interpreter.space.segments());
+        assert_eq!(words_of(&loaded), words_of(&interpreter));
+        assert_eq!(loaded.special_objects(), interpreter.special_objects());
+        assert_eq!(
+            loaded.class_table.entries(),
+            interpreter.class_table.entries()
+        );
+    }
+
+    #[test]
+    fn test_loaded_image_is_usable() {
+        let mut interpreter = bootstrap(20000);
+        let symbol = symbol_table::intern(&mut interpreter, "saved");
+        let mut bytes: Vec<u8> = Vec::new();
+        write_image(&mut interpreter, &mut bytes).unwrap();
+
+        let mut loaded = read_image(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(symbol_table::lookup(&mut loaded, "saved"), Some(symbol));
+        assert_eq!(symbol_table::intern(&mut loaded, "saved"), symbol);
+        let array_class = loaded.special_object(SpecialObjectIndexes::ClassArray);
+        let array = loaded.instantiate_class(array_class, 3).unwrap();
+        assert_eq!(loaded.class_of(array), array_class);
+    }
+
+    #[test]
+    fn test_round_trip_keeps_the_segments() {
+        let mut interpreter = bootstrap(20000);
+        let array_class = interpreter.special_object(SpecialObjectIndexes::ClassArray);
+        let big = interpreter.instantiate_class(array_class, 30000).unwrap();
+        let small = interpreter.instantiate_class(array_class, 3).unwrap();
+        let pinned = pinning::pin(&mut interpreter, small).unwrap();
+        let mut bytes: Vec<u8> = Vec::new();
+        write_image(&mut interpreter, &mut bytes).unwrap();
+
+        let mut loaded = read_image(&mut bytes.as_slice()).unwrap();
+
+        assert!(loaded.space.is_in_large_object_space(big));
+        assert!(pinning::is_pinned(&mut loaded.space, pinned));
+        assert!(!loaded.space.is_in_moving_space(pinned));
+        assert_eq!(loaded.space.segments(), interpreter.space.segments());
+        assert_eq!(words_of(&loaded), words_of(&interpreter));
+        assert_eq!(loaded.class_of(big), array_class);
+    }
+
+    #[test]
+    fn test_bad_magic_is_rejected() {
+        let bytes = [0u8; 64];
+        let error = read_image(&mut bytes.as_slice()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_truncated_image_is_rejected() {
+        let mut interpreter = bootstrap(20000);
+        let mut bytes: Vec<u8> = Vec::new();
+        write_image(&mut interpreter, &mut bytes).unwrap();
+        bytes.truncate(bytes.len() / 2);
+
+        let error = read_image(&mut bytes.as_slice()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_save_and_load_file() {
+        let mut interpreter = bootstrap(20000);
+        let path = std::env::temp_dir().join(format!("funvm-test-{}.image", std::process::id()));
+
+        save_image(&mut interpreter, &path).unwrap();
+        let loaded = load_image(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.space.segments(), interpreter.space.segments());
+        assert_eq!(words_of(&loaded), words_of(&interpreter));
+    }
+}
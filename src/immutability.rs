@@ -0,0 +1,128 @@
+use crate::memory_space::MemorySpace;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::slot_content::SlotContent;
+
+// Immutable objects refuse the stores of the primitives and of the bytecodes, and do not become
+// other objects. A store bytecode into an immutable receiver sends attemptToAssign:withIndex:,
+// the failing primitives fall back on signalling a ModificationForbidden that can retry the store.
+// The VM still rewrites their references to forwarders: the objects designated stay the same.
+// The literals of the methods are immutable.
+
+pub fn is_immutable(space: &mut MemorySpace, oop: usize) -> bool {
+    SlotContent::new(oop).is_slot_oop() && space.get_oop_at(oop).get_header().is_immutable()
+}
+
+pub fn set_immutable_bit_of(space: &mut MemorySpace, oop: usize, immutable: bool) {
+    let mut an_oop = space.get_oop_at(oop);
+    if immutable {
+        an_oop.get_header_mut().set_immutable_bit();
+    } else {
+        an_oop.get_header_mut().unset_immutable_bit();
+    }
+    an_oop.apply_header();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::{bootstrap, define_class};
+    use crate::compiler::{evaluate, install_method};
+    use crate::header_format_values::HeaderFormatValues;
+    use crate::heap_export;
+    use crate::interpreter::Interpreter;
+    use crate::slot_content::SlotContent;
+
+    fn interpreter_with_box() -> Interpreter {
+        let mut interpreter = bootstrap(40000);
+        let object = interpreter.class_named("Object").unwrap();
+        let box_class = define_class(
+            &mut interpreter,
+            "Box",
+            object,
+            HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+            &["contents"],
+        );
+        for source in [
+            "contents ^contents",
+            "contents: anObject contents := anObject",
+            "replace: anObject contents := anObject. ^contents",
+            "swap: anObject ^contents := anObject",
+        ] {
+            install_method(&mut interpreter, box_class, source).unwrap();
+        }
+        interpreter
+    }
+
+    #[parameterized(source_and_expected = {
+        ("#(1 2) isReadOnlyObject", "true"),
+        ("'abc' isReadOnlyObject", "true"),
+        ("(#(1 (2 3)) at: 2) isReadOnlyObject", "true"),
+        ("#abc isReadOnlyObject", "true"),
+        ("(Array new: 1) isReadOnlyObject", "false"),
+        ("3 isReadOnlyObject", "false"),
+        ("(Array new: 1) beReadOnlyObject; isReadOnlyObject", "true"),
+        ("| a | a := Array new: 1. a beReadOnlyObject. a beWritableObject", "true"),
+    })]
+    fn test_read_only_objects(source_and_expected: (&str, &str)) {
+        let (source, expected) = source_and_expected;
+        let mut interpreter = bootstrap(40000);
+        let value = evaluate(&mut interpreter, source).unwrap();
+        let expected = interpreter.boolean_object(expected == "true");
+        assert_eq!(value, expected);
+    }
+
+    #[parameterized(source_and_expected = {
+        ("[#(1 2) at: 1 put: 3] on: ModificationForbidden do: [:e | e fieldIndex + e newValue]", 4),
+        ("| s | s := 'abc'. [s at: 2 put: 65] on: ModificationForbidden do: [:e | e object size]", 3),
+        ("| a | a := Array with: 1. a beReadOnlyObject. \
+          [a at: 1 put: 5] on: ModificationForbidden do: [:e | a beWritableObject. e retryModification]. \
+          a at: 1", 5),
+        ("| b | b := Box new contents: 1; yourself. b beReadOnlyObject. \
+          [b instVarAt: 1 put: 2] on: ModificationForbidden do: [:e | e return: 7]", 7),
+    })]
+    fn test_failed_stores_signal_modification_forbidden(source_and_expected: (&str, isize)) {
+        let (source, expected) = source_and_expected;
+        let mut interpreter = interpreter_with_box();
+        assert_eq!(
+            evaluate(&mut interpreter, source).unwrap(),
+            SlotContent::small_integer(expected)
+        );
+    }
+
+    #[parameterized(source_and_expected = {
+        // The store is retried, the method goes on after it
+        ("| b | b := Box new contents: 1; yourself. b beReadOnlyObject. \
+          [b replace: 7] on: ModificationForbidden do: [:e | b beWritableObject. e retryModification]", 7),
+        // Resuming skips the store, the method goes on after it
+        ("| b | b := Box new contents: 1; yourself. b beReadOnlyObject. \
+          [b replace: 7] on: ModificationForbidden do: [:e | e resume: nil]", 1),
+        // The value of a store expression stays on the stack
+        ("| b | b := Box new contents: 1; yourself. b beReadOnlyObject. \
+          [b swap: 7] on: ModificationForbidden do: [:e | e resume: nil]", 7),
+        ("| b | b := Box new contents: 1; yourself. b beReadOnlyObject. \
+          [b replace: 7] on: ModificationForbidden do: [:e | e fieldIndex + e newValue]", 8),
+    })]
+    fn test_store_bytecodes_into_read_only_receivers(source_and_expected: (&str, isize)) {
+        let (source, expected) = source_and_expected;
+        let mut interpreter = interpreter_with_box();
+        assert_eq!(
+            evaluate(&mut interpreter, source).unwrap(),
+            SlotContent::small_integer(expected)
+        );
+    }
+
+    #[test]
+    fn test_unhandled_modification_forbidden() {
+        let mut interpreter = interpreter_with_box();
+        evaluate(
+            &mut interpreter,
+            "| b | b := Box new. b beReadOnlyObject. b contents: 3",
+        )
+        .unwrap();
+
+        let exception = interpreter.take_unhandled_exception().unwrap();
+        assert_eq!(
+            heap_export::exported_object(&mut interpreter, exception).class_name,
+            Some(String::from("ModificationForbidden"))
+        );
+    }
+}
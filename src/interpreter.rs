@@ -0,0 +1,1255 @@
+use crate::block_closure::block_closure_constants;
+use crate::bytecodes::{bytecode_constants, SPECIAL_SELECTORS, SPECIAL_SELECTOR_PRIMITIVES};
+use crate::class_table::{class_constants, ClassFormat, ClassTable};
+use crate::compiled_method::{compiled_method_constants, MethodHeader};
+use crate::forwarding;
+use crate::garbage_collector::simple_garbage_collector;
+use crate::header_format_values::HeaderFormatValues;
+use crate::immutability;
+use crate::memory_space::{memory_space_constants, MemorySpace};
+use crate::method_dictionary;
+use crate::oop_builder::OopBuilder;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::primitive_plugin::{PluginRegistry, PrimitivePlugin};
+use crate::primitives::external_primitives;
+use crate::primitives::{PrimitiveResult, PrimitiveTable};
+use crate::slot_content::SlotContent;
+use crate::special_class_index::SpecialClassIndexes;
+use crate::special_object_index::SpecialObjectIndexes;
+use crate::stack_zone::{context_constants, StackZone};
+use crate::symbol_table;
+
+// Message slots, 1 based like the oop slots
+pub mod message_constants {
+    pub const SELECTOR_INDEX: usize = 1;
+    pub const ARGUMENTS_INDEX: usize = 2;
+}
+
+pub struct Interpreter {
+    pub space: MemorySpace,
+    pub stack_zone: StackZone,
+    pub class_table: ClassTable,
+    pub plugins: PluginRegistry,
+    primitive_table: PrimitiveTable,
+    special_objects: usize,
+    last_hash: usize,
+    // The method being executed, as seen by its primitive
+    new_method: usize,
+    // The last exception nobody handled, its evaluation was abandoned
+    unhandled_exception: Option<usize>,
+}
+
+impl Interpreter {
+    pub const NUMBER_OF_STACK_PAGES: usize = 8;
+    pub const STACK_PAGE_SIZE: usize = 512;
+
+    pub fn new(mut space: MemorySpace, special_objects: usize, class_table: ClassTable) -> Self {
+        let nil = space
+            .get_oop_at(special_objects)
+            .slot_at_index(SpecialObjectIndexes::Nil as usize);
+        Self {
+            space,
+            stack_zone: StackZone::new(
+                Interpreter::NUMBER_OF_STACK_PAGES,
+                Interpreter::STACK_PAGE_SIZE,
+                nil,
+            ),
+            class_table,
+            plugins: PluginRegistry::new(),
+            primitive_table: PrimitiveTable::new(),
+            special_objects,
+            last_hash: 1,
+            new_method: nil,
+            unhandled_exception: None,
+        }
+    }
+
+    pub fn register_plugin(&mut self, plugin: Box<dyn PrimitivePlugin>) {
+        self.plugins.register(plugin);
+        external_primitives::flush_external_primitive_caches(self);
+    }
+
+    // Special objects
+    pub fn special_objects(&self) -> usize {
+        self.special_objects
+    }
+
+    pub fn special_object(&mut self, index: SpecialObjectIndexes) -> usize {
+        self.space
+            .get_oop_at(self.special_objects)
+            .slot_at_index(index as usize)
+    }
+
+    pub fn nil_object(&mut self) -> usize {
+        self.special_object(SpecialObjectIndexes::Nil)
+    }
+
+    pub fn true_object(&mut self) -> usize {
+        self.special_object(SpecialObjectIndexes::True)
+    }
+
+    pub fn false_object(&mut self) -> usize {
+        self.special_object(SpecialObjectIndexes::False)
+    }
+
+    pub fn boolean_object(&mut self, value: bool) -> usize {
+        if value {
+            self.true_object()
+        } else {
+            self.false_object()
+        }
+    }
+
+    // Stack, as seen by the primitives
+    pub fn stack_value(&self, offset: usize) -> usize {
+        self.stack_zone.stack_value(offset)
+    }
+
+    pub fn push(&mut self, value: usize) {
+        self.stack_zone.push(value);
+    }
+
+    pub fn pop(&mut self) -> usize {
+        self.stack_zone.pop()
+    }
+
+    pub fn pop_then_push(&mut self, count: usize, value: usize) {
+        self.stack_zone.pop_n(count);
+        self.stack_zone.push(value);
+    }
+
+    // Objects
+    pub fn class_index_of(&mut self, oop: usize) -> usize {
+        let content = SlotContent::new(oop);
+        if content.is_small_integer() {
+            SpecialClassIndexes::SmallInteger as usize
+        } else if content.is_character() {
+            SpecialClassIndexes::Character as usize
+        } else {
+            self.space.get_oop_at(oop).get_header().class_index_bits()
+        }
+    }
+
+    pub fn class_of(&mut self, oop: usize) -> usize {
+        let class_index = self.class_index_of(oop);
+        match self.class_table.class_at_index(class_index) {
+            Some(class) => class,
+            None => self.nil_object(),
+        }
+    }
+
+    pub fn class_format_of(&mut self, class: usize) -> ClassFormat {
+        ClassFormat::from_slot_value(
+            self.space
+                .get_oop_at(class)
+                .slot_at_index(class_constants::FORMAT_INDEX),
+        )
+    }
+
+    // Answers None for non bytes objects
+    pub fn string_value_of(&mut self, oop: usize) -> Option<String> {
+        if SlotContent::new(oop).is_slot_immediate() {
+            return None;
+        }
+        let an_oop = self.space.get_oop_at(oop);
+        if an_oop.get_header().contains_pointers() {
+            return None;
+        }
+        let bytes: Vec<u8> = (1..=an_oop.number_of_bytes())
+            .map(|index| an_oop.byte_at_index(index))
+            .collect();
+        Some(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    // There are no globals yet, classes are found through the class table.
+    // Metaclasses keep a class where classes keep their name, so they never match.
+    pub fn class_named(&mut self, name: &str) -> Option<usize> {
+        self.class_table.classes().into_iter().find(|class| {
+            let class_name = self
+                .space
+                .get_oop_at(*class)
+                .slot_at_index(class_constants::NAME_INDEX);
+            self.string_value_of(class_name).as_deref() == Some(name)
+        })
+    }
+
+    // Same generator as Squeak, the hash lives in 22 bits of the header
+    pub fn hash_of(&mut self, oop: usize) -> usize {
+        let mut an_oop = self.space.get_oop_at(oop);
+        let hash = an_oop.get_header().hash_bits();
+        if hash != 0 {
+            return hash;
+        }
+
+        let mut new_hash = (self.last_hash * 16807) % 0x3FFFFF;
+        if new_hash == 0 {
+            new_hash = 1;
+        }
+        self.last_hash = new_hash;
+        an_oop.get_header_mut().set_hash_bits(new_hash);
+        an_oop.apply_header();
+        new_hash
+    }
+
+    // Answers None when the class cannot have that many indexable slots,
+    // when they are more than an object may have, or when the space cannot grow for them
+    pub fn instantiate_class(&mut self, class: usize, indexable_size: usize) -> Option<usize> {
+        let class_format = self.class_format_of(class);
+        let instance_specification = class_format.instance_specification();
+        let number_of_fixed_slots = class_format.number_of_fixed_slots();
+        let class_index = self.space.get_oop_at(class).get_header().hash_bits();
+
+        let mut builder = OopBuilder::new();
+        builder.set_class_index(class_index);
+        if instance_specification <= HeaderFormatValues::NonIndexableWithSlotsFormat as usize {
+            if indexable_size != 0 {
+                return None;
+            }
+            builder.set_format(instance_specification);
+            builder.set_number_of_slots(number_of_fixed_slots);
+            builder.set_slots_value(self.nil_object());
+        } else if instance_specification
+            <= HeaderFormatValues::WeakIndexableWithSlotsFormat as usize
+        {
+            let number_of_slots = number_of_fixed_slots.checked_add(indexable_size)?;
+            if number_of_slots > memory_space_constants::MAX_NUMBER_OF_SLOTS {
+                return None;
+            }
+            builder.set_format(instance_specification);
+            builder.set_number_of_slots(number_of_slots);
+            builder.set_slots_value(self.nil_object());
+        } else if instance_specification >= HeaderFormatValues::I8BitIndexable as usize {
+            let number_of_slots = indexable_size.div_ceil(8);
+            if number_of_slots > memory_space_constants::MAX_NUMBER_OF_SLOTS {
+                return None;
+            }
+            let unused_bytes = number_of_slots * 8 - indexable_size;
+            builder.set_format((instance_specification & !7) + unused_bytes);
+            builder.set_number_of_slots(number_of_slots);
+            builder.set_slots_value(0);
+        } else {
+            return None;
+        }
+        builder.try_build(&mut self.space)
+    }
+
+    pub fn instantiate_class_with_bytes(&mut self, class: usize, bytes: &[u8]) -> Option<usize> {
+        let instance = self.instantiate_class(class, bytes.len())?;
+        let mut instance_oop = self.space.get_oop_at(instance);
+        for (index, byte) in bytes.iter().enumerate() {
+            instance_oop.byte_at_index_put(index + 1, *byte);
+        }
+        Some(instance)
+    }
+
+    // Reads a pointer slot, a forwarder found there is replaced by its target
+    pub fn fetch_pointer(&mut self, object: usize, index: usize) -> usize {
+        let value = self.space.get_oop_at(object).slot_at_index(index);
+        if !forwarding::is_forwarded(&mut self.space, value) {
+            return value;
+        }
+        let target = forwarding::follow_forwarded(&mut self.space, value);
+        self.space
+            .get_oop_at(object)
+            .slot_at_index_put_ignoring_immutability(index, target);
+        target
+    }
+
+    // Execution
+    pub fn method_header_of(&mut self, method: usize) -> MethodHeader {
+        MethodHeader::from_slot_value(
+            self.space
+                .get_oop_at(method)
+                .slot_at_index(compiled_method_constants::HEADER_INDEX),
+        )
+    }
+
+    // The receiver and the arguments are on the stack.
+    // The primitive of the method runs first, the method is activated only if it fails.
+    pub fn new_method(&self) -> usize {
+        self.new_method
+    }
+
+    pub fn execute_method(&mut self, method: usize, argument_count: usize) {
+        self.new_method = method;
+        let primitive_index = self.method_header_of(method).primitive_index();
+        if let Some(primitive) = self.primitive_table.primitive_at(primitive_index) {
+            if primitive(self, argument_count) == PrimitiveResult::Success {
+                return;
+            }
+        }
+        self.activate_method(method, argument_count);
+    }
+
+    pub fn activate_method(&mut self, method: usize, argument_count: usize) {
+        let header = self.method_header_of(method);
+        let receiver = self.stack_value(argument_count);
+        let arguments: Vec<usize> = (0..argument_count)
+            .rev()
+            .map(|offset| self.stack_value(offset))
+            .collect();
+        self.stack_zone.pop_n(argument_count + 1);
+
+        self.stack_zone
+            .push_frame(method, receiver, header.frame_size(), &mut self.space);
+        self.stack_zone.set_pc(header.initial_pc());
+        for argument in arguments {
+            self.push(argument);
+        }
+        let nil = self.nil_object();
+        for _ in argument_count..header.number_of_temporaries() {
+            self.push(nil);
+        }
+    }
+
+    // Lookup
+    pub fn superclass_of(&mut self, class: usize) -> usize {
+        self.space
+            .get_oop_at(class)
+            .slot_at_index(class_constants::SUPERCLASS_INDEX)
+    }
+
+    pub fn lookup_method(&mut self, class: usize, selector: usize) -> Option<usize> {
+        let nil = self.nil_object();
+        let mut current_class = class;
+        while current_class != nil {
+            let dictionary = self
+                .space
+                .get_oop_at(current_class)
+                .slot_at_index(class_constants::METHOD_DICTIONARY_INDEX);
+            if dictionary != nil {
+                if let Some(method) = method_dictionary::lookup(self, dictionary, selector) {
+                    return Some(method);
+                }
+            }
+            current_class = self.superclass_of(current_class);
+        }
+        None
+    }
+
+    // Compiled methods end their literals with their selector, then their class.
+    // Compiled blocks end theirs with their outer code, up to the method.
+    pub fn method_class_of(&mut self, method: usize) -> usize {
+        let compiled_block_class = self.special_object(SpecialObjectIndexes::ClassCompiledBlock);
+        let mut code = method;
+        loop {
+            let number_of_literals = self.method_header_of(code).number_of_literals();
+            let last_literal = self.space.get_oop_at(code).slot_at_index(
+                compiled_method_constants::FIRST_LITERAL_INDEX + number_of_literals - 1,
+            );
+            if self.class_of(code) != compiled_block_class {
+                return last_literal;
+            }
+            code = last_literal;
+        }
+    }
+
+    fn name_of_class(&mut self, class: usize) -> String {
+        let name = self
+            .space
+            .get_oop_at(class)
+            .slot_at_index(class_constants::NAME_INDEX);
+        match self.string_value_of(name) {
+            Some(name) => name,
+            None => String::from("a metaclass"),
+        }
+    }
+
+    // The receiver and the arguments are on the stack
+    // A forwarded receiver is replaced by its target, as in Spur
+    pub fn send(&mut self, selector: usize, argument_count: usize) {
+        let mut receiver = self.stack_value(argument_count);
+        if forwarding::is_forwarded(&mut self.space, receiver) {
+            receiver = forwarding::follow_forwarded(&mut self.space, receiver);
+            self.stack_zone.stack_value_put(argument_count, receiver);
+        }
+        let class = self.class_of(receiver);
+        self.send_to_class(selector, argument_count, class);
+    }
+
+    pub fn super_send(&mut self, selector: usize, argument_count: usize) {
+        let method_class = self.method_class_of(self.stack_zone.method());
+        let superclass = self.superclass_of(method_class);
+        self.send_to_class(selector, argument_count, superclass);
+    }
+
+    // A failed lookup sends doesNotUnderstand: with a Message in place of the arguments.
+    // Only classes not understanding doesNotUnderstand: either make the VM panic.
+    fn send_to_class(&mut self, selector: usize, argument_count: usize, class: usize) {
+        if let Some(method) = self.lookup_method(class, selector) {
+            self.execute_method(method, argument_count);
+            return;
+        }
+        let does_not_understand =
+            self.special_object(SpecialObjectIndexes::SelectorDoesNotUnderstand);
+        if selector != does_not_understand {
+            if let Some(method) = self.lookup_method(class, does_not_understand) {
+                let message = self.new_message(selector, argument_count);
+                self.push(message);
+                self.execute_method(method, 1);
+                return;
+            }
+        }
+        panic!(
+            "{} does not understand #{}",
+            self.name_of_class(class),
+            self.string_value_of(selector).unwrap_or_default()
+        )
+    }
+
+    // Pops the arguments of the send into a new Message
+    fn new_message(&mut self, selector: usize, argument_count: usize) -> usize {
+        let arguments: Vec<usize> = (0..argument_count)
+            .rev()
+            .map(|offset| self.stack_value(offset))
+            .collect();
+        self.stack_zone.pop_n(argument_count);
+
+        let array_class = self.special_object(SpecialObjectIndexes::ClassArray);
+        let array = self
+            .instantiate_class(array_class, argument_count)
+            .expect("Array should be indexable");
+        for (index, argument) in arguments.iter().enumerate() {
+            self.space
+                .get_oop_at(array)
+                .slot_at_index_put(index + 1, *argument);
+        }
+        let message_class = self.special_object(SpecialObjectIndexes::ClassMessage);
+        let message = self
+            .instantiate_class(message_class, 0)
+            .expect("Message should have slots");
+        let mut message_oop = self.space.get_oop_at(message);
+        message_oop.slot_at_index_put(message_constants::SELECTOR_INDEX, selector);
+        message_oop.slot_at_index_put(message_constants::ARGUMENTS_INDEX, array);
+        message
+    }
+
+    // Leaves the current frame, the value goes on the stack of its sender
+    pub fn return_value(&mut self, value: usize) {
+        self.stack_zone.pop_frame(&mut self.space);
+        self.push(value);
+    }
+
+    // Closures
+    fn push_full_closure(&mut self, literal_index: usize, number_of_copied_values: usize) {
+        let compiled_block = self.literal_at(literal_index);
+        let copied_values: Vec<usize> = (0..number_of_copied_values)
+            .rev()
+            .map(|offset| self.stack_value(offset))
+            .collect();
+        self.stack_zone.pop_n(number_of_copied_values);
+
+        let block_closure_class = self.special_object(SpecialObjectIndexes::ClassBlockClosure);
+        let closure = self
+            .instantiate_class(block_closure_class, number_of_copied_values)
+            .expect("BlockClosure should be indexable");
+        let outer_context = self.stack_zone.this_context(&mut self.space);
+        let number_of_arguments = self.method_header_of(compiled_block).number_of_arguments();
+        let receiver = self.stack_zone.receiver();
+        let mut closure_oop = self.space.get_oop_at(closure);
+        closure_oop.slot_at_index_put(block_closure_constants::OUTER_CONTEXT_INDEX, outer_context);
+        closure_oop.slot_at_index_put(
+            block_closure_constants::COMPILED_BLOCK_INDEX,
+            compiled_block,
+        );
+        closure_oop.slot_at_index_put(
+            block_closure_constants::NUMBER_OF_ARGUMENTS_INDEX,
+            SlotContent::small_integer(number_of_arguments as isize),
+        );
+        closure_oop.slot_at_index_put(block_closure_constants::RECEIVER_INDEX, receiver);
+        for (index, value) in copied_values.iter().enumerate() {
+            closure_oop.slot_at_index_put(
+                block_closure_constants::NUMBER_OF_FIXED_SLOTS + 1 + index,
+                *value,
+            );
+        }
+        self.push(closure);
+    }
+
+    // The closure and the arguments are on the stack.
+    // The frame holds the arguments, then the copied values, then the temporaries.
+    pub fn activate_closure(&mut self, closure: usize, argument_count: usize) {
+        let compiled_block =
+            self.fetch_pointer(closure, block_closure_constants::COMPILED_BLOCK_INDEX);
+        let receiver = self.fetch_pointer(closure, block_closure_constants::RECEIVER_INDEX);
+        let number_of_slots = self.space.get_oop_at(closure).number_of_slots();
+        let copied_values: Vec<usize> = (block_closure_constants::NUMBER_OF_FIXED_SLOTS + 1
+            ..=number_of_slots)
+            .map(|index| self.fetch_pointer(closure, index))
+            .collect();
+        let header = self.method_header_of(compiled_block);
+        let arguments: Vec<usize> = (0..argument_count)
+            .rev()
+            .map(|offset| self.stack_value(offset))
+            .collect();
+        self.stack_zone.pop_n(argument_count + 1);
+
+        self.stack_zone.push_frame(
+            compiled_block,
+            receiver,
+            header.frame_size(),
+            &mut self.space,
+        );
+        self.stack_zone.set_pc(header.initial_pc());
+        self.stack_zone.set_closure_or_nil(closure);
+        for value in arguments.into_iter().chain(copied_values) {
+            self.push(value);
+        }
+        let nil = self.nil_object();
+        while self.stack_zone.stack_pointer() < header.number_of_temporaries() {
+            self.push(nil);
+        }
+    }
+
+    // The context of the method the closure was created in, through the nested closures
+    fn home_context_of(&mut self, closure: usize) -> usize {
+        let nil = self.nil_object();
+        let mut context = self
+            .space
+            .get_oop_at(closure)
+            .slot_at_index(block_closure_constants::OUTER_CONTEXT_INDEX);
+        loop {
+            let closure_or_nil = self.stack_zone.context_slot_at(
+                context,
+                context_constants::CLOSURE_OR_NIL_INDEX,
+                &mut self.space,
+            );
+            if closure_or_nil == nil {
+                return context;
+            }
+            context = self
+                .space
+                .get_oop_at(closure_or_nil)
+                .slot_at_index(block_closure_constants::OUTER_CONTEXT_INDEX);
+        }
+    }
+
+    // Returns from the home context of the closure being executed.
+    // When the home context is dead, or not a sender of the current context anymore,
+    // the current context is sent cannotReturn: instead.
+    fn non_local_return(&mut self, value: usize) {
+        let home_context = self.home_context_of(self.stack_zone.closure_or_nil());
+        let pc = self.stack_zone.context_slot_at(
+            home_context,
+            context_constants::PC_INDEX,
+            &mut self.space,
+        );
+        if !SlotContent::new(pc).is_small_integer()
+            || !self
+                .stack_zone
+                .is_on_sender_chain(home_context, &mut self.space)
+        {
+            let context = self.stack_zone.this_context(&mut self.space);
+            let selector = self.special_object(SpecialObjectIndexes::SelectorCannotReturn);
+            self.push(context);
+            self.push(value);
+            self.send(selector, 1);
+            return;
+        }
+
+        while !self.stack_zone.is_current_context(home_context) {
+            self.stack_zone.pop_frame(&mut self.space);
+        }
+        self.return_value(value);
+    }
+
+    // Method returns from a closure are non-local
+    fn method_return(&mut self, value: usize) {
+        let nil = self.nil_object();
+        if self.stack_zone.closure_or_nil() == nil {
+            self.return_value(value);
+        } else {
+            self.non_local_return(value);
+        }
+    }
+
+    // Exceptions
+    pub fn set_unhandled_exception(&mut self, exception: usize) {
+        self.unhandled_exception = Some(exception);
+    }
+
+    pub fn take_unhandled_exception(&mut self) -> Option<usize> {
+        self.unhandled_exception.take()
+    }
+
+    // Entry points from outside the interpreter.
+    // They push a frame without method to send from, and run the bytecodes until it is back.
+    pub fn send_message(&mut self, receiver: usize, selector: usize, arguments: &[usize]) -> usize {
+        self.push_caller_frame(receiver, arguments);
+        self.send(selector, arguments.len());
+        self.return_to_caller_frame()
+    }
+
+    pub fn run_method(&mut self, method: usize, receiver: usize, arguments: &[usize]) -> usize {
+        self.push_caller_frame(receiver, arguments);
+        self.execute_method(method, arguments.len());
+        self.return_to_caller_frame()
+    }
+
+    fn push_caller_frame(&mut self, receiver: usize, arguments: &[usize]) {
+        let nil = self.nil_object();
+        self.stack_zone
+            .push_frame(nil, nil, arguments.len() + 1, &mut self.space);
+        self.push(receiver);
+        for argument in arguments {
+            self.push(*argument);
+        }
+    }
+
+    fn return_to_caller_frame(&mut self) -> usize {
+        self.interpret();
+        let result = self.pop();
+        self.stack_zone.pop_frame(&mut self.space);
+        result
+    }
+
+    // Bytecodes
+    pub fn interpret(&mut self) {
+        let nil = self.nil_object();
+        while self.stack_zone.method() != nil {
+            self.interpret_next_bytecode();
+        }
+    }
+
+    fn fetch_byte(&mut self) -> u8 {
+        let pc = self.stack_zone.pc();
+        let byte = self
+            .space
+            .get_oop_at(self.stack_zone.method())
+            .byte_at_index(pc);
+        self.stack_zone.set_pc(pc + 1);
+        byte
+    }
+
+    fn jump(&mut self, offset: isize) {
+        let pc = self.stack_zone.pc() as isize + offset;
+        self.stack_zone.set_pc(pc as usize);
+    }
+
+    fn jump_if(&mut self, condition: bool, offset: isize) {
+        let value = self.pop();
+        if value == self.boolean_object(condition) {
+            self.jump(offset);
+        } else if value != self.boolean_object(!condition) {
+            // As in Squeak, the execution goes on after the jump if mustBeBoolean answers
+            let selector = self.special_object(SpecialObjectIndexes::SelectorMustBeBoolean);
+            self.push(value);
+            self.send(selector, 0);
+        }
+    }
+
+    // The compiler only emits the literal indexes of the method
+    fn literal_at(&mut self, index: usize) -> usize {
+        let method_oop = self.space.get_oop_at(self.stack_zone.method());
+        let literal = unsafe {
+            method_oop
+                .slot_at_index_unchecked(compiled_method_constants::FIRST_LITERAL_INDEX + index)
+        };
+        if !forwarding::is_forwarded(&mut self.space, literal) {
+            return literal;
+        }
+        self.fetch_pointer(
+            self.stack_zone.method(),
+            compiled_method_constants::FIRST_LITERAL_INDEX + index,
+        )
+    }
+
+    // A context married to a frame is read and written through its frame, as in Cog
+    fn receiver_variable_at(&mut self, index: usize) -> usize {
+        let receiver = self.stack_zone.receiver();
+        if self.class_index_of(receiver) == SpecialClassIndexes::Context as usize {
+            return self
+                .stack_zone
+                .context_slot_at(receiver, index, &mut self.space);
+        }
+        // The compiler only emits the instance variable indexes of the method class
+        let value = unsafe {
+            self.space
+                .get_oop_at(receiver)
+                .slot_at_index_unchecked(index)
+        };
+        if !forwarding::is_forwarded(&mut self.space, value) {
+            return value;
+        }
+        self.fetch_pointer(receiver, index)
+    }
+
+    fn receiver_variable_at_put(&mut self, index: usize, value: usize) {
+        let receiver = self.stack_zone.receiver();
+        if self.class_index_of(receiver) == SpecialClassIndexes::Context as usize {
+            self.stack_zone
+                .context_slot_at_put(receiver, index, value, &mut self.space);
+            return;
+        }
+        if immutability::is_immutable(&mut self.space, receiver) {
+            self.cannot_assign(receiver, index, value);
+            return;
+        }
+        unsafe {
+            self.space
+                .get_oop_at(receiver)
+                .slot_at_index_put_unchecked(index, value)
+        }
+    }
+
+    // As in Cog, the receiver is sent attemptToAssign:withIndex:, which answers nothing:
+    // the execution goes on after the store, the value is left on the stack unless popped
+    fn cannot_assign(&mut self, receiver: usize, index: usize, value: usize) {
+        let selector = self.special_object(SpecialObjectIndexes::SelectorAttemptToAssign);
+        self.push(receiver);
+        self.push(value);
+        self.push(SlotContent::small_integer(index as isize));
+        self.send(selector, 2);
+    }
+
+    fn push_variable(&mut self, variable_type: u8, index: usize) {
+        let value = match variable_type {
+            bytecode_constants::RECEIVER_VARIABLE_TYPE => self.receiver_variable_at(index + 1),
+            bytecode_constants::TEMPORARY_TYPE => self.stack_zone.temp_at(index),
+            bytecode_constants::LITERAL_CONSTANT_TYPE => self.literal_at(index),
+            _ => panic!("Unknown variable type {}", variable_type),
+        };
+        self.push(value);
+    }
+
+    // The value is popped before the store, which may send a message
+    fn store_variable(&mut self, variable_type: u8, index: usize, pop: bool) {
+        let value = if pop { self.pop() } else { self.stack_value(0) };
+        match variable_type {
+            bytecode_constants::RECEIVER_VARIABLE_TYPE => {
+                self.receiver_variable_at_put(index + 1, value)
+            }
+            bytecode_constants::TEMPORARY_TYPE => self.stack_zone.temp_at_put(index, value),
+            _ => panic!("Cannot store into variable type {}", variable_type),
+        }
+    }
+
+    fn special_send(&mut self, index: usize) {
+        let (_, argument_count) = SPECIAL_SELECTORS[index];
+        let primitive_index = SPECIAL_SELECTOR_PRIMITIVES[index];
+        if let Some(primitive) = self.primitive_table.primitive_at(primitive_index) {
+            if primitive(self, argument_count) == PrimitiveResult::Success {
+                return;
+            }
+        }
+        let special_selectors = self.special_object(SpecialObjectIndexes::SpecialSelectors);
+        let selector = self
+            .space
+            .get_oop_at(special_selectors)
+            .slot_at_index(index * 2 + 1);
+        self.send(selector, argument_count);
+    }
+
+    fn interpret_next_bytecode(&mut self) {
+        let bytecode = self.fetch_byte();
+        match bytecode {
+            0..=15 => self.push_variable(
+                bytecode_constants::RECEIVER_VARIABLE_TYPE,
+                bytecode as usize,
+            ),
+            16..=31 => self.push_variable(
+                bytecode_constants::TEMPORARY_TYPE,
+                (bytecode - bytecode_constants::PUSH_TEMPORARY) as usize,
+            ),
+            32..=63 => self.push_variable(
+                bytecode_constants::LITERAL_CONSTANT_TYPE,
+                (bytecode - bytecode_constants::PUSH_LITERAL_CONSTANT) as usize,
+            ),
+            96..=103 => {
+                self.store_variable(
+                    bytecode_constants::RECEIVER_VARIABLE_TYPE,
+                    (bytecode - bytecode_constants::POP_STORE_RECEIVER_VARIABLE) as usize,
+                    true,
+                );
+            }
+            104..=111 => {
+                self.store_variable(
+                    bytecode_constants::TEMPORARY_TYPE,
+                    (bytecode - bytecode_constants::POP_STORE_TEMPORARY) as usize,
+                    true,
+                );
+            }
+            bytecode_constants::PUSH_RECEIVER => self.push(self.stack_zone.receiver()),
+            bytecode_constants::PUSH_TRUE => {
+                let true_object = self.true_object();
+                self.push(true_object);
+            }
+            bytecode_constants::PUSH_FALSE => {
+                let false_object = self.false_object();
+                self.push(false_object);
+            }
+            bytecode_constants::PUSH_NIL => {
+                let nil = self.nil_object();
+                self.push(nil);
+            }
+            116..=119 => {
+                let value = bytecode as isize - bytecode_constants::PUSH_MINUS_ONE as isize - 1;
+                self.push(SlotContent::small_integer(value));
+            }
+            bytecode_constants::RETURN_RECEIVER => self.method_return(self.stack_zone.receiver()),
+            bytecode_constants::RETURN_TRUE => {
+                let true_object = self.true_object();
+                self.method_return(true_object);
+            }
+            bytecode_constants::RETURN_FALSE => {
+                let false_object = self.false_object();
+                self.method_return(false_object);
+            }
+            bytecode_constants::RETURN_NIL => {
+                let nil = self.nil_object();
+                self.method_return(nil);
+            }
+            bytecode_constants::RETURN_TOP => {
+                let value = self.pop();
+                self.method_return(value);
+            }
+            bytecode_constants::BLOCK_RETURN_TOP => {
+                let value = self.pop();
+                self.return_value(value);
+            }
+            bytecode_constants::EXTENDED_PUSH
+            | bytecode_constants::EXTENDED_STORE
+            | bytecode_constants::EXTENDED_POP_STORE => {
+                let descriptor = self.fetch_byte();
+                let variable_type = descriptor >> 6;
+                let index = (descriptor & 0x3F) as usize;
+                if bytecode == bytecode_constants::EXTENDED_PUSH {
+                    self.push_variable(variable_type, index);
+                } else {
+                    self.store_variable(
+                        variable_type,
+                        index,
+                        bytecode == bytecode_constants::EXTENDED_POP_STORE,
+                    );
+                }
+            }
+            bytecode_constants::SINGLE_EXTENDED_SEND
+            | bytecode_constants::SINGLE_EXTENDED_SUPER => {
+                let descriptor = self.fetch_byte();
+                let selector = self.literal_at((descriptor & 0x1F) as usize);
+                let argument_count = (descriptor >> 5) as usize;
+                if bytecode == bytecode_constants::SINGLE_EXTENDED_SEND {
+                    self.send(selector, argument_count);
+                } else {
+                    self.super_send(selector, argument_count);
+                }
+            }
+            bytecode_constants::DOUBLE_EXTENDED => {
+                let descriptor = self.fetch_byte();
+                let literal_index = self.fetch_byte() as usize;
+                let literal = self.literal_at(literal_index);
+                let argument_count = (descriptor & 0x1F) as usize;
+                match descriptor >> 5 {
+                    bytecode_constants::DOUBLE_EXTENDED_SEND => self.send(literal, argument_count),
+                    bytecode_constants::DOUBLE_EXTENDED_SUPER_SEND => {
+                        self.super_send(literal, argument_count)
+                    }
+                    bytecode_constants::DOUBLE_EXTENDED_PUSH_LITERAL_CONSTANT => self.push(literal),
+                    operation => panic!("Unknown double extended operation {}", operation),
+                }
+            }
+            bytecode_constants::POP => {
+                self.pop();
+            }
+            bytecode_constants::DUPLICATE => self.push(self.stack_value(0)),
+            bytecode_constants::PUSH_THIS_CONTEXT => {
+                let context = self.stack_zone.this_context(&mut self.space);
+                self.push(context);
+            }
+            bytecode_constants::PUSH_NEW_ARRAY => {
+                let descriptor = self.fetch_byte();
+                let size = (descriptor & 0x7F) as usize;
+                let array_class = self.special_object(SpecialObjectIndexes::ClassArray);
+                let array = self
+                    .instantiate_class(array_class, size)
+                    .expect("Array should be indexable");
+                if descriptor >> 7 == 1 {
+                    for index in (1..=size).rev() {
+                        let element = self.pop();
+                        self.space
+                            .get_oop_at(array)
+                            .slot_at_index_put(index, element);
+                    }
+                }
+                self.push(array);
+            }
+            bytecode_constants::PUSH_REMOTE_TEMPORARY
+            | bytecode_constants::STORE_REMOTE_TEMPORARY
+            | bytecode_constants::POP_STORE_REMOTE_TEMPORARY => {
+                let index = self.fetch_byte() as usize + 1;
+                let temp_vector_index = self.fetch_byte() as usize;
+                let temp_vector = self.stack_zone.temp_at(temp_vector_index);
+                if bytecode == bytecode_constants::PUSH_REMOTE_TEMPORARY {
+                    let value = self.fetch_pointer(temp_vector, index);
+                    self.push(value);
+                } else {
+                    let value = self.stack_value(0);
+                    self.space
+                        .get_oop_at(temp_vector)
+                        .slot_at_index_put(index, value);
+                    if bytecode == bytecode_constants::POP_STORE_REMOTE_TEMPORARY {
+                        self.pop();
+                    }
+                }
+            }
+            bytecode_constants::PUSH_FULL_CLOSURE => {
+                let literal_index = self.fetch_byte() as usize;
+                let number_of_copied_values = self.fetch_byte() as usize;
+                self.push_full_closure(literal_index, number_of_copied_values);
+            }
+            144..=151 => self.jump((bytecode & 7) as isize + 1),
+            152..=159 => self.jump_if(false, (bytecode & 7) as isize + 1),
+            160..=167 => {
+                let offset = ((bytecode & 7) as isize - 4) * 256 + self.fetch_byte() as isize;
+                self.jump(offset);
+            }
+            168..=175 => {
+                let offset = (bytecode & 3) as isize * 256 + self.fetch_byte() as isize;
+                self.jump_if(bytecode < bytecode_constants::LONG_JUMP_IF_FALSE, offset);
+            }
+            176..=207 => self.special_send((bytecode - bytecode_constants::SPECIAL_SEND) as usize),
+            208..=255 => {
+                let selector = self.literal_at((bytecode & 0xF) as usize);
+                let argument_count =
+                    ((bytecode - bytecode_constants::SEND_LITERAL_SELECTOR_0) >> 4) as usize;
+                self.send(selector, argument_count);
+            }
+            _ => panic!("Unknown bytecode {}", bytecode),
+        }
+    }
+
+    // GC support
+    pub fn roots(&self) -> Vec<usize> {
+        let mut roots = vec![self.special_objects];
+        roots.extend(self.class_table.classes());
+        roots.extend(self.stack_zone.roots());
+        roots.extend(self.unhandled_exception);
+        roots
+    }
+
+    // After a become, the roots do not wait for the lazy forwarding
+    pub fn follow_forwarded_roots(&mut self) {
+        self.map_roots(&mut forwarding::follow_forwarded);
+    }
+
+    pub fn map_roots(&mut self, map: &mut dyn FnMut(&mut MemorySpace, usize) -> usize) {
+        self.special_objects = map(&mut self.space, self.special_objects);
+        self.new_method = map(&mut self.space, self.new_method);
+        if let Some(exception) = self.unhandled_exception {
+            self.unhandled_exception = Some(map(&mut self.space, exception));
+        }
+        self.stack_zone.map_oops(&mut self.space, map);
+    }
+
+    // The symbols only the symbol table references are reclaimed,
+    // the forwarders are replaced by their targets.
+    pub fn collect_garbage(&mut self) {
+        self.follow_forwarded_roots();
+        let nil = self.nil_object();
+        simple_garbage_collector::collect_from_roots_clearing_weak_slots(
+            self.roots(),
+            nil,
+            &mut self.space,
+        );
+        symbol_table::rehash(self);
+    }
+}
+
+// Helpers to build the minimal set of objects the interpreter needs, while there is no image.
+#[cfg(test)]
+pub mod interpreter_test_support {
+    use crate::class_table::{class_constants, ClassFormat, ClassTable};
+    use crate::compiled_method::{compiled_method_constants, MethodHeader};
+    use crate::header_format_values::HeaderFormatValues;
+    use crate::interpreter::Interpreter;
+    use crate::memory_space::MemorySpace;
+    use crate::oop_builder::OopBuilder;
+    use crate::special_class_index::SpecialClassIndexes;
+
+    pub fn new_interpreter() -> Interpreter {
+        let mut space = MemorySpace::for_bit_size(10000);
+        let mut builder = OopBuilder::new();
+        let nil = builder.build(&mut space);
+        let false_object = builder.build(&mut space);
+        let true_object = builder.build(&mut space);
+        builder.set_number_of_slots(3);
+        let special_objects = builder.build(&mut space);
+        let mut special_objects_oop = space.get_oop_at(special_objects);
+        special_objects_oop.slot_at_index_put(1, nil);
+        special_objects_oop.slot_at_index_put(2, false_object);
+        special_objects_oop.slot_at_index_put(3, true_object);
+
+        let mut interpreter = Interpreter::new(space, special_objects, ClassTable::new());
+        let small_integer_class = new_class(&mut interpreter, 0, 0);
+        let character_class = new_class(&mut interpreter, 0, 0);
+        interpreter.class_table.register_at(
+            SpecialClassIndexes::SmallInteger as usize,
+            small_integer_class,
+            &mut interpreter.space,
+        );
+        interpreter.class_table.register_at(
+            SpecialClassIndexes::Character as usize,
+            character_class,
+            &mut interpreter.space,
+        );
+
+        // The stack of the caller, to push receivers and arguments onto
+        let nil = interpreter.nil_object();
+        interpreter
+            .stack_zone
+            .push_frame(nil, nil, 16, &mut interpreter.space);
+        interpreter
+    }
+
+    pub fn new_class(
+        interpreter: &mut Interpreter,
+        instance_specification: usize,
+        number_of_fixed_slots: usize,
+    ) -> usize {
+        let nil = interpreter.nil_object();
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(class_constants::NUMBER_OF_FIXED_SLOTS);
+        builder.set_slots_value(nil);
+        let class = builder.build(&mut interpreter.space);
+        interpreter.space.get_oop_at(class).slot_at_index_put(
+            class_constants::FORMAT_INDEX,
+            ClassFormat::new(instance_specification, number_of_fixed_slots).as_slot_value(),
+        );
+        interpreter
+            .class_table
+            .register(class, &mut interpreter.space);
+        class
+    }
+
+    pub fn new_method(
+        interpreter: &mut Interpreter,
+        number_of_arguments: usize,
+        primitive_index: usize,
+    ) -> usize {
+        new_method_with_literals(interpreter, number_of_arguments, primitive_index, &[])
+    }
+
+    pub fn new_method_with_literals(
+        interpreter: &mut Interpreter,
+        number_of_arguments: usize,
+        primitive_index: usize,
+        literals: &[usize],
+    ) -> usize {
+        let mut builder = OopBuilder::new();
+        builder.set_class_index(SpecialClassIndexes::CompiledMethod as usize);
+        builder.set_number_of_slots(1 + literals.len());
+        let method = builder.build(&mut interpreter.space);
+
+        let mut method_oop = interpreter.space.get_oop_at(method);
+        let header = MethodHeader::new(
+            number_of_arguments,
+            number_of_arguments,
+            literals.len(),
+            primitive_index,
+        );
+        method_oop.slot_at_index_put(
+            compiled_method_constants::HEADER_INDEX,
+            header.as_slot_value(),
+        );
+        for (index, literal) in literals.iter().enumerate() {
+            method_oop.slot_at_index_put(
+                compiled_method_constants::FIRST_LITERAL_INDEX + index,
+                *literal,
+            );
+        }
+        method
+    }
+
+    // In a frame of their own, the objects are roots until it is popped
+    pub fn keep_on_the_stack(interpreter: &mut Interpreter, objects: &[usize]) {
+        let nil = interpreter.nil_object();
+        interpreter
+            .stack_zone
+            .push_frame(nil, nil, objects.len(), &mut interpreter.space);
+        for object in objects {
+            interpreter.push(*object);
+        }
+    }
+
+    // An instance of the Array class index, registered or not
+    pub fn new_array(interpreter: &mut Interpreter, elements: &[usize]) -> usize {
+        let mut builder = OopBuilder::new();
+        builder.set_class_index(SpecialClassIndexes::Array as usize);
+        builder.set_format(HeaderFormatValues::IndexableWithoutSlotsFormat as usize);
+        builder.set_number_of_slots(elements.len());
+        let array = builder.build(&mut interpreter.space);
+
+        let mut array_oop = interpreter.space.get_oop_at(array);
+        for (index, element) in elements.iter().enumerate() {
+            array_oop.slot_at_index_put(index + 1, *element);
+        }
+        array
+    }
+
+    pub fn new_byte_object(interpreter: &mut Interpreter, text: &str) -> usize {
+        let mut builder = OopBuilder::new();
+        let number_of_slots = text.len().div_ceil(8);
+        builder.set_number_of_slots(number_of_slots);
+        builder.set_format(
+            HeaderFormatValues::I8BitIndexable as usize + number_of_slots * 8 - text.len(),
+        );
+        builder.set_slots_value(0);
+        let byte_object = builder.build(&mut interpreter.space);
+
+        let mut byte_object_oop = interpreter.space.get_oop_at(byte_object);
+        for (index, byte) in text.bytes().enumerate() {
+            byte_object_oop.byte_at_index_put(index + 1, byte);
+        }
+        byte_object
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::header_format_values::HeaderFormatValues;
+    use crate::interpreter::interpreter_test_support::{
+        new_byte_object, new_class, new_interpreter, new_method,
+    };
+    use crate::oop_projections::oop_common::OopCommonState;
+    use crate::slot_content::SlotContent;
+
+    #[test]
+    fn test_execute_method_without_primitive_activates_it() {
+        let mut interpreter = new_interpreter();
+        let method = new_method(&mut interpreter, 1, 0);
+        interpreter.push(SlotContent::small_integer(3));
+        interpreter.push(SlotContent::small_integer(4));
+
+        interpreter.execute_method(method, 1);
+
+        assert_eq!(interpreter.stack_zone.depth(), 2);
+        assert_eq!(interpreter.stack_zone.method(), method);
+        assert_eq!(
+            interpreter.stack_zone.receiver(),
+            SlotContent::small_integer(3)
+        );
+        assert_eq!(
+            interpreter.stack_zone.temp_at(0),
+            SlotContent::small_integer(4)
+        );
+    }
+
+    #[test]
+    fn test_failing_primitive_falls_through_to_the_method() {
+        let mut interpreter = new_interpreter();
+        // SmallInteger + fails on a non SmallInteger argument
+        let method = new_method(&mut interpreter, 1, 1);
+        let nil = interpreter.nil_object();
+        interpreter.push(SlotContent::small_integer(3));
+        interpreter.push(nil);
+
+        interpreter.execute_method(method, 1);
+
+        assert_eq!(interpreter.stack_zone.depth(), 2);
+        assert_eq!(interpreter.stack_zone.temp_at(0), nil);
+    }
+
+    #[test]
+    fn test_succeeding_primitive_does_not_activate_the_method() {
+        let mut interpreter = new_interpreter();
+        let method = new_method(&mut interpreter, 1, 1);
+        interpreter.push(SlotContent::small_integer(3));
+        interpreter.push(SlotContent::small_integer(4));
+
+        interpreter.execute_method(method, 1);
+
+        assert_eq!(interpreter.stack_zone.depth(), 1);
+        assert_eq!(interpreter.stack_value(0), SlotContent::small_integer(7));
+    }
+
+    #[test]
+    fn test_instantiate_class_fills_slots_with_nil() {
+        let mut interpreter = new_interpreter();
+        let class = new_class(
+            &mut interpreter,
+            HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+            2,
+        );
+
+        let instance = interpreter.instantiate_class(class, 0).unwrap();
+
+        let nil = interpreter.nil_object();
+        assert_eq!(interpreter.class_of(instance), class);
+        assert_eq!(interpreter.space.get_oop_at(instance).slot_at_index(2), nil);
+    }
+
+    #[test]
+    fn test_instantiate_non_indexable_class_with_size_fails() {
+        let mut interpreter = new_interpreter();
+        let class = new_class(
+            &mut interpreter,
+            HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+            2,
+        );
+
+        assert_eq!(interpreter.instantiate_class(class, 3), None);
+    }
+
+    #[test]
+    fn test_instantiate_byte_class_rounds_up_to_slots() {
+        let mut interpreter = new_interpreter();
+        let class = new_class(
+            &mut interpreter,
+            HeaderFormatValues::I8BitIndexable as usize,
+            0,
+        );
+
+        let instance = interpreter.instantiate_class(class, 9).unwrap();
+
+        let instance_oop = interpreter.space.get_oop_at(instance);
+        assert_eq!(instance_oop.number_of_slots(), 2);
+        assert_eq!(
+            instance_oop.get_header().format_bits(),
+            HeaderFormatValues::I8BitIndexable as usize + 7
+        );
+    }
+
+    #[test]
+    fn test_string_value_of_bytes() {
+        let mut interpreter = new_interpreter();
+        let byte_object = new_byte_object(&mut interpreter, "hello world");
+
+        assert_eq!(
+            interpreter.string_value_of(byte_object),
+            Some(String::from("hello world"))
+        );
+    }
+
+    #[test]
+    fn test_string_value_of_pointers() {
+        let mut interpreter = new_interpreter();
+        let special_objects = interpreter.special_objects();
+        assert_eq!(interpreter.string_value_of(special_objects), None);
+    }
+
+    #[test]
+    fn test_hash_is_stable() {
+        let mut interpreter = new_interpreter();
+        let class = new_class(&mut interpreter, 0, 0);
+        let instance = interpreter.instantiate_class(class, 0).unwrap();
+
+        let hash = interpreter.hash_of(instance);
+        assert_ne!(hash, 0);
+        assert_eq!(interpreter.hash_of(instance), hash);
+    }
+
+    #[test]
+    fn test_collect_garbage_keeps_stack_and_classes() {
+        let mut interpreter = new_interpreter();
+        let class = new_class(&mut interpreter, 0, 0);
+        let kept = interpreter.instantiate_class(class, 0).unwrap();
+        let garbage = interpreter.instantiate_class(class, 0).unwrap();
+        interpreter.push(kept);
+
+        interpreter.collect_garbage();
+
+        assert!(!interpreter.space.get_oop_at(class).is_free_oop());
+        assert!(!interpreter.space.get_oop_at(kept).is_free_oop());
+        assert!(interpreter.space.get_oop_at(garbage).is_free_oop());
+    }
+}
@@ -3,48 +3,62 @@
 extern crate parameterized;
 
 pub mod allocator;
+pub mod benchmark;
+pub mod block_closure;
+pub mod bootstrap;
+pub mod bytecodes;
+pub mod class_reshape;
+pub mod class_table;
+pub mod cli;
+pub mod compiled_method;
+pub mod compiler;
+pub mod forwarding;
+pub mod free_chunk;
 pub mod garbage_collector;
+pub mod hashed_collection;
 pub mod header;
+pub mod header_format_values;
+pub mod heap_export;
+#[cfg(test)]
+mod heap_model_checker;
+pub mod heap_queries;
+pub mod heap_verifier;
+pub mod image;
+pub mod immutability;
+pub mod interpreter;
+#[cfg(target_os = "linux")]
+pub mod mapped_memory;
 pub mod memory_space;
 pub mod memory_space_access;
+pub mod method_dictionary;
 pub mod oop_builder;
 mod oop_projections;
+pub mod pinning;
+pub mod primitive_plugin;
+pub mod primitives;
+pub mod repl;
 
 pub mod slot_content;
 pub mod special_class_index;
-use crate::header::Header;
-use crate::memory_space::MemorySpace;
-//use crate::oop::Oop;
-
-use crate::oop_builder::OopBuilder;
-
-//pub mod header_format_values;
+pub mod special_object_index;
+pub mod stack_zone;
+pub mod symbol_table;
+use std::io::{self, Write};
 
 fn main() {
-    let memory_space_size: usize = 240;
-    let mut space = MemorySpace::for_bit_size(memory_space_size);
-    space.report();
-
-    // let mut oop = Oop { memory: space, index: 0 };
-    // println!("first oop number of slots, should have all slots in space(240): {}", oop.header_value());
-    // println!("first oop class index, should be free (= 1): {}", oop.get_header() .class_index_bits());
-
-    let header = Header {
-        header_value: usize::MAX,
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    let mut output = io::stdout().lock();
+    let exit_code = match cli::run(&arguments, &mut output) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(error) => {
+            eprintln!("{}", error);
+            if let cli::CliError::Usage(_) = error {
+                eprintln!("{}", cli::USAGE);
+            }
+            2
+        }
     };
-    println!(
-        "number of slots in full header: {} (should be 255)",
-        header.number_of_slots_bits()
-    );
-    //oop.set_header(header);
-    //println!("number of slots of oop with full header (should be 255): {}", oop.get_header().number_of_slots_bits());
-
-    //getting ownership back
-    //let mut space = oop.memory;
-    let mut builder = OopBuilder::new();
-    builder.set_class_index(3);
-    builder.build(&mut space);
-
-    // let oop = Oop { memory: space, index: 0 };
-    // println!("class index of builder allocated oop (should be 3): {}", oop.get_header().class_index_bits());
+    output.flush().ok();
+    std::process::exit(exit_code);
 }
@@ -0,0 +1,194 @@
+use std::io;
+
+// Anonymous memory mappings, for a memory space whose words are at fixed machine addresses.
+// The whole range is reserved up front without being usable, then committed as the space grows.
+// The C library std links against provides the calls, there is no binding crate.
+// The constants are the Linux ones.
+mod system {
+    use std::ffi::{c_int, c_long, c_void};
+
+    pub const PROT_NONE: c_int = 0;
+    pub const PROT_READ: c_int = 1;
+    pub const PROT_WRITE: c_int = 2;
+    pub const MAP_PRIVATE: c_int = 0x02;
+    pub const MAP_ANONYMOUS: c_int = 0x20;
+    pub const MADV_DONTNEED: c_int = 4;
+    pub const SC_PAGESIZE: c_int = 30;
+    pub const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+    extern "C" {
+        pub fn mmap(
+            address: *mut c_void,
+            length: usize,
+            protection: c_int,
+            flags: c_int,
+            file_descriptor: c_int,
+            offset: c_long,
+        ) -> *mut c_void;
+        pub fn munmap(address: *mut c_void, length: usize) -> c_int;
+        pub fn mprotect(address: *mut c_void, length: usize, protection: c_int) -> c_int;
+        pub fn madvise(address: *mut c_void, length: usize, advice: c_int) -> c_int;
+        pub fn sysconf(name: c_int) -> c_long;
+    }
+}
+
+const WORD_SIZE: usize = std::mem::size_of::<usize>();
+
+#[derive(Debug)]
+pub struct MappedRegion {
+    base: *mut usize,
+    reserved_bytes: usize,
+    // Always a multiple of the page size, the committed words are readable and writable
+    committed_bytes: usize,
+    page_size: usize,
+}
+
+impl MappedRegion {
+    // The reservation is rounded up to whole pages
+    pub fn reserve(number_of_words: usize) -> io::Result<Self> {
+        let page_size = unsafe { system::sysconf(system::SC_PAGESIZE) } as usize;
+        let reserved_bytes = (number_of_words * WORD_SIZE).next_multiple_of(page_size);
+        let base = unsafe {
+            system::mmap(
+                std::ptr::null_mut(),
+                reserved_bytes,
+                system::PROT_NONE,
+                system::MAP_PRIVATE | system::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if base == system::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            base: base as *mut usize,
+            reserved_bytes,
+            committed_bytes: 0,
+            page_size,
+        })
+    }
+
+    // Page aligned, so the words are aligned too
+    pub fn base_address(&self) -> usize {
+        self.base as usize
+    }
+
+    // The index of the first word: the indexes of the words are their addresses in words
+    pub fn first_index(&self) -> usize {
+        self.base_address() / WORD_SIZE
+    }
+
+    pub fn reserved_words(&self) -> usize {
+        self.reserved_bytes / WORD_SIZE
+    }
+
+    pub fn committed_words(&self) -> usize {
+        self.committed_bytes / WORD_SIZE
+    }
+
+    // Makes at least the first words of the region usable, they start zeroed
+    pub fn commit(&mut self, number_of_words: usize) -> io::Result<()> {
+        let bytes = (number_of_words * WORD_SIZE).next_multiple_of(self.page_size);
+        if bytes <= self.committed_bytes {
+            return Ok(());
+        }
+        if bytes > self.reserved_bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::OutOfMemory,
+                format!(
+                    "{} words do not fit in the {} reserved ones",
+                    number_of_words,
+                    self.reserved_words()
+                ),
+            ));
+        }
+        let result = unsafe {
+            system::mprotect(
+                (self.base as *mut u8).add(self.committed_bytes) as *mut _,
+                bytes - self.committed_bytes,
+                system::PROT_READ | system::PROT_WRITE,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.committed_bytes = bytes;
+        Ok(())
+    }
+
+    // Gives the whole pages of the words back to the system.
+    // They stay usable: the system hands out zeroed pages when they are touched again.
+    pub fn release(&mut self, first_word: usize, end_word: usize) -> io::Result<()> {
+        let first_byte = (first_word * WORD_SIZE).next_multiple_of(self.page_size);
+        let end_byte =
+            (end_word * WORD_SIZE).min(self.committed_bytes) / self.page_size * self.page_size;
+        if first_byte >= end_byte {
+            return Ok(());
+        }
+        let result = unsafe {
+            system::madvise(
+                (self.base as *mut u8).add(first_byte) as *mut _,
+                end_byte - first_byte,
+                system::MADV_DONTNEED,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn words(&self) -> &[usize] {
+        unsafe { std::slice::from_raw_parts(self.base, self.committed_words()) }
+    }
+
+    pub fn words_mut(&mut self) -> &mut [usize] {
+        unsafe { std::slice::from_raw_parts_mut(self.base, self.committed_words()) }
+    }
+}
+
+impl Drop for MappedRegion {
+    fn drop(&mut self) {
+        unsafe {
+            system::munmap(self.base as *mut _, self.reserved_bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mapped_memory::{MappedRegion, WORD_SIZE};
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_reserved_words_are_committed_lazily() {
+        let mut region = MappedRegion::reserve(1 << 20).unwrap();
+        assert_eq!(region.committed_words(), 0);
+        assert_eq!(region.base_address() % WORD_SIZE, 0);
+
+        region.commit(10).unwrap();
+        assert!(region.committed_words() >= 10);
+        assert!(region.committed_words() < region.reserved_words());
+        region.words_mut()[9] = 42;
+        assert_eq!(region.words()[9], 42);
+    }
+
+    #[test]
+    fn test_commit_past_the_reservation_fails() {
+        let mut region = MappedRegion::reserve(1000).unwrap();
+        let error = region.commit(region.reserved_words() + 1).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::OutOfMemory);
+    }
+
+    #[test]
+    fn test_released_pages_come_back_zeroed() {
+        let mut region = MappedRegion::reserve(1 << 16).unwrap();
+        region.commit(1 << 16).unwrap();
+        let last = region.committed_words() - 1;
+        region.words_mut()[last] = 42;
+
+        region.release(0, region.committed_words()).unwrap();
+        assert_eq!(region.words()[last], 0);
+    }
+}
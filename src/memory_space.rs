@@ -1,81 +1,595 @@
+use crate::allocator::{AllocationBuffer, AllocationStrategy, FirstFit};
+use crate::free_chunk::{self, FreeChunkIndex};
+use crate::header::Header;
+use crate::header_format_values::HeaderFormatValues;
+#[cfg(target_os = "linux")]
+use crate::mapped_memory::MappedRegion;
 use crate::memory_space_access::memory_space_access;
 use crate::memory_space_access::MemorySpaceIterator;
 use crate::oop_builder::OopBuilder;
-use crate::oop_projections::oop_common::oop_utilities::how_many_headers_for;
+use crate::oop_projections::oop_common::{OopCommonState, OopNavigation};
 use crate::oop_projections::oop_slice::OopSlice;
 use crate::special_class_index::SpecialClassIndexes;
 
+// The space is made of segments added when the allocation runs out of room,
+// and released once the garbage collection empties them.
+// Segments never move, the indexes of the objects stay valid while the space grows and shrinks.
+// The words are either in vectors, one per segment, or in a memory mapping.
+// The indexes count words in both, the oops are indexes and not machine addresses.
+// In a memory mapping the index of a word is its address divided by the word size,
+// address_of and index_at_address are the only conversions between the two.
+pub mod memory_space_constants {
+    // The bridge header, then the start of the next segment
+    pub const BRIDGE_SIZE: usize = 2;
+    pub const BRIDGE_TARGET_INDEX: usize = 1;
+    // The large object segments are a whole number of 4 KiB pages
+    pub const LARGE_OBJECT_PAGE_WORDS: usize = 512;
+    // The allocation of objects with more slots fails, 2 GiB of slots
+    pub const MAX_NUMBER_OF_SLOTS: usize = 1 << 28;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SegmentKind {
+    Objects,
+    // A single large object, the rest of the pages is not for the other objects.
+    // The segment goes away with the object, large objects never move.
+    LargeObject,
+    // The pinned objects, whose address the native code may keep.
+    // Like the large objects, they never move.
+    Pinned,
+}
+
+// A range of the indexes of the space.
+// Its last words are a bridge, whose slot is where the next segment starts,
+// or the end of the segment for the last one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    start: usize,
+    size: usize,
+    kind: SegmentKind,
+}
+
+impl Segment {
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    // Index right after the bridge
+    pub fn end(&self) -> usize {
+        self.start + self.size
+    }
+
+    pub fn bridge_index(&self) -> usize {
+        self.end() - memory_space_constants::BRIDGE_SIZE
+    }
+
+    pub fn kind(&self) -> SegmentKind {
+        self.kind
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        self.start <= index && index < self.end()
+    }
+}
+
+#[derive(Debug)]
+enum Storage {
+    // The words of each segment
+    Vectors(Vec<Vec<usize>>),
+    // The segments are in the region in address order, the new ones fill the holes the released
+    // ones left before going past the last one
+    #[cfg(target_os = "linux")]
+    Mapped(MappedRegion),
+}
+
 #[derive(Debug)]
 pub struct MemorySpace {
-    memory_vector: Vec<usize>,
+    segments: Vec<Segment>,
+    storage: Storage,
+    // The objects with more slots go to a segment of their own
+    large_object_threshold: usize,
+    allocation_strategy: Box<dyn AllocationStrategy>,
+    allocation_buffer: AllocationBuffer,
+    // Without the buffer the strategy places every object
+    uses_allocation_buffer: bool,
+    // None until a walk of the heap finds them again: the walks forget them,
+    // the collectors free and merge chunks while they walk
+    free_chunks: Option<FreeChunkIndex>,
 }
 
 impl MemorySpace {
     pub fn for_bit_size(memory_space_size: usize) -> Self {
         let mut res: Self = Self {
-            memory_vector: vec![0; memory_space_size],
+            segments: Vec::new(),
+            storage: Storage::Vectors(Vec::new()),
+            large_object_threshold: Header::MAX_NUMBER_OF_SLOTS,
+            allocation_strategy: Box::new(FirstFit),
+            allocation_buffer: AllocationBuffer::default(),
+            uses_allocation_buffer: true,
+            free_chunks: None,
         };
+        res.add_segment_of(memory_space_size, SegmentKind::Objects)
+            .expect("Couldn't allocate the memory space");
+        res
+    }
 
-        // set first oop to be free & have all the slots in the space
-        let mut builder = OopBuilder::new();
-        builder.set_class_index(SpecialClassIndexes::FreeObject as usize);
-        builder.set_number_of_slots(memory_space_size - how_many_headers_for(memory_space_size));
-        builder.build_oop_at(0, &mut res);
+    // Reserves the address range of the segments to come, the first one is committed right away
+    #[cfg(target_os = "linux")]
+    pub fn mapped(reserved_words: usize, memory_space_size: usize) -> std::io::Result<Self> {
+        let mut region = MappedRegion::reserve(reserved_words)?;
+        region.commit(memory_space_size + memory_space_constants::BRIDGE_SIZE)?;
+        let mut res: Self = Self {
+            segments: Vec::new(),
+            storage: Storage::Mapped(region),
+            large_object_threshold: Header::MAX_NUMBER_OF_SLOTS,
+            allocation_strategy: Box::new(FirstFit),
+            allocation_buffer: AllocationBuffer::default(),
+            uses_allocation_buffer: true,
+            free_chunks: None,
+        };
+        // The words of the first segment are already committed
+        res.add_segment_of(memory_space_size, SegmentKind::Objects)
+            .expect("The first segment should be committed");
+        Ok(res)
+    }
 
-        res
+    // The words are taken as they are, when loading an image for instance.
+    // Each segment comes with its start, its kind and its words, bridge included.
+    pub fn from_segments(segments: Vec<(usize, SegmentKind, Vec<usize>)>) -> Self {
+        let (segments, vectors) = segments
+            .into_iter()
+            .map(|(start, kind, words)| {
+                (
+                    Segment {
+                        start,
+                        size: words.len(),
+                        kind,
+                    },
+                    words,
+                )
+            })
+            .unzip();
+        Self {
+            segments,
+            storage: Storage::Vectors(vectors),
+            large_object_threshold: Header::MAX_NUMBER_OF_SLOTS,
+            allocation_strategy: Box::new(FirstFit),
+            allocation_buffer: AllocationBuffer::default(),
+            uses_allocation_buffer: true,
+            free_chunks: None,
+        }
+    }
+
+    // By default the objects needing the extra slot header are large
+    pub fn large_object_threshold(&self) -> usize {
+        self.large_object_threshold
+    }
+
+    pub fn set_large_object_threshold(&mut self, number_of_slots: usize) {
+        self.large_object_threshold = number_of_slots;
+    }
+
+    pub fn allocation_strategy_name(&self) -> &'static str {
+        self.allocation_strategy.name()
+    }
+
+    // Answers the strategy used so far
+    pub fn replace_allocation_strategy(
+        &mut self,
+        strategy: Box<dyn AllocationStrategy>,
+    ) -> Box<dyn AllocationStrategy> {
+        std::mem::replace(&mut self.allocation_strategy, strategy)
+    }
+
+    pub fn uses_allocation_buffer(&self) -> bool {
+        self.uses_allocation_buffer
+    }
+
+    // The buffer in use is retired when it is turned off
+    pub fn set_uses_allocation_buffer(&mut self, uses_allocation_buffer: bool) {
+        if !uses_allocation_buffer {
+            self.retire_allocation_buffer();
+        }
+        self.uses_allocation_buffer = uses_allocation_buffer;
+    }
+
+    pub fn allocation_buffer(&self) -> AllocationBuffer {
+        self.allocation_buffer
+    }
+
+    // The words are those of a free chunk, the rest of the buffer in use is a free chunk again
+    pub fn set_allocation_buffer(&mut self, buffer: AllocationBuffer) {
+        self.return_allocation_buffer();
+        self.allocation_buffer = buffer;
+    }
+
+    // Answers where that many words start, None when the buffer is too small for them
+    pub fn bump_allocate(&mut self, number_of_words: usize) -> Option<usize> {
+        let index = self.allocation_buffer.next;
+        if self.allocation_buffer.limit - index < number_of_words {
+            return None;
+        }
+        self.allocation_buffer.next = index + number_of_words;
+        Some(index)
+    }
+
+    // Writes the header of the free chunk the buffer left, so that the heap can be walked.
+    // The free chunks are forgotten, what comes next may free or merge some.
+    pub fn retire_allocation_buffer(&mut self) {
+        self.return_allocation_buffer();
+        self.free_chunks = None;
+    }
+
+    fn return_allocation_buffer(&mut self) {
+        let AllocationBuffer { next, limit } = std::mem::take(&mut self.allocation_buffer);
+        free_chunk::write_free_words(next, limit - next, self);
+    }
+
+    // The heap is walked when the free chunks are not known
+    pub fn free_chunks(&mut self) -> &FreeChunkIndex {
+        if self.free_chunks.is_none() {
+            let mut free_chunks = FreeChunkIndex::default();
+            let mut iter = self.iter();
+            while let Some(oop) = iter.next_headers(self) {
+                if oop.is_free_oop() {
+                    let kind = self.segment_containing(oop.get_index()).unwrap().kind;
+                    free_chunks.insert(oop.get_index(), oop.oop_size(), kind);
+                }
+            }
+            self.free_chunks = Some(free_chunks);
+        }
+        self.free_chunks.as_ref().unwrap()
+    }
+
+    // Records the free chunk written there, when the free chunks are known
+    pub fn add_free_chunk(&mut self, index: usize, size: usize) {
+        let kind = self.segment_containing(index).map(|segment| segment.kind);
+        if let (Some(free_chunks), Some(kind)) = (&mut self.free_chunks, kind) {
+            free_chunks.insert(index, size, kind);
+        }
+    }
+
+    // The free chunk is about to be allocated into
+    pub fn take_free_chunk(&mut self, index: usize) {
+        if let Some(free_chunks) = &mut self.free_chunks {
+            free_chunks.remove(index);
+        }
+    }
+
+    // Large objects are alone in their segment
+    pub fn is_in_large_object_space(&self, index: usize) -> bool {
+        self.segment_containing(index)
+            .is_some_and(|segment| segment.kind == SegmentKind::LargeObject)
+    }
+
+    // Only the objects of the Objects segments may be moved by a collector
+    pub fn is_in_moving_space(&self, index: usize) -> bool {
+        self.segment_containing(index)
+            .is_some_and(|segment| segment.kind == SegmentKind::Objects)
+    }
+
+    // Where the word is in the memory of the process, for the native code
+    pub fn address_of(&self, index: usize) -> *const usize {
+        &self[index]
+    }
+
+    // The index of the word at that address, None when the space has no word there
+    pub fn index_at_address(&self, address: *const usize) -> Option<usize> {
+        let word_size = std::mem::size_of::<usize>();
+        let address = address as usize;
+        if !address.is_multiple_of(word_size) {
+            return None;
+        }
+        self.segments
+            .iter()
+            .map(|segment| {
+                let first_address = self.address_of(segment.start) as usize;
+                (segment, first_address)
+            })
+            .find(|(segment, first_address)| {
+                (*first_address..*first_address + segment.size * word_size).contains(&address)
+            })
+            .map(|(segment, first_address)| segment.start + (address - first_address) / word_size)
+    }
+
+    // The words of the objects of the segment, followed by its bridge
+    pub fn segment_words(&self, segment: &Segment) -> &[usize] {
+        &self[segment.start..segment.end()]
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    // The words of all the segments, bridges included
+    pub fn number_of_words(&self) -> usize {
+        self.segments.iter().map(|segment| segment.size).sum()
     }
 
     pub fn get_start_index(&self) -> usize {
-        0
+        self.segments[0].start
     }
 
+    // Index of the last word of the last object, the last bridge is not part of the objects
     pub fn get_end_index(&self) -> usize {
-        self.memory_vector.capacity() - 1 // 0 based
+        self.segments.last().unwrap().bridge_index() - 1 // 0 based
     }
 
-    pub fn first_oop(&mut self) -> OopSlice {
+    pub fn first_oop(&mut self) -> OopSlice<'_> {
         memory_space_access::first_oop(self)
     }
 
-    pub fn get_oop_at(&mut self, index: usize) -> OopSlice {
+    pub fn get_oop_at(&mut self, index: usize) -> OopSlice<'_> {
         memory_space_access::oop_at_index(index, self)
     }
 
-    pub fn iter(&self) -> MemorySpaceIterator {
-        MemorySpaceIterator::default()
+    // The walk needs the header of the free chunk of the allocation buffer
+    pub fn iter(&mut self) -> MemorySpaceIterator {
+        self.retire_allocation_buffer();
+        MemorySpaceIterator::starting_at(self.get_start_index())
     }
 
     pub fn report(&self) {
-        println!("memory_vector = {}", self.memory_vector.len());
+        println!(
+            "segments = {}, words = {}",
+            self.segments.len(),
+            self.number_of_words()
+        );
+    }
+
+    pub fn segment_containing(&self, index: usize) -> Option<&Segment> {
+        let position = self
+            .segments
+            .partition_point(|segment| segment.end() <= index);
+        self.segments
+            .get(position)
+            .filter(|segment| segment.contains(index))
+    }
+
+    // Where the objects continue: past the bridge when the index is one
+    pub fn skip_bridge(&self, index: usize) -> usize {
+        let first = &self.segments[0];
+        if first.start <= index && index < first.bridge_index() {
+            return index;
+        }
+        match self.segment_containing(index) {
+            Some(segment) if segment.bridge_index() == index => {
+                self[index + memory_space_constants::BRIDGE_TARGET_INDEX]
+            }
+            _ => index,
+        }
+    }
+
+    // Adds a segment with a free chunk of at least that many words, answers the chunk.
+    // The segments are at least as big as the first one.
+    // None when the system does not give the words.
+    pub fn grow(&mut self, number_of_words: usize) -> Option<usize> {
+        let first_segment_size = self.segments[0].size - memory_space_constants::BRIDGE_SIZE;
+        self.add_segment_of(
+            first_segment_size.max(number_of_words),
+            SegmentKind::Objects,
+        )
+    }
+
+    // Adds a segment for a large object, answers the free chunk where it goes
+    pub fn grow_for_large_object(&mut self, number_of_words: usize) -> Option<usize> {
+        let size = (number_of_words + memory_space_constants::BRIDGE_SIZE)
+            .next_multiple_of(memory_space_constants::LARGE_OBJECT_PAGE_WORDS);
+        self.add_segment_of(
+            size - memory_space_constants::BRIDGE_SIZE,
+            SegmentKind::LargeObject,
+        )
+    }
+
+    // Adds a segment for pinned objects, answers the free chunk where the first one goes.
+    // The segment is a whole number of pages, the pinned objects to come share it.
+    pub fn grow_for_pinned(&mut self, number_of_words: usize) -> Option<usize> {
+        let size = (number_of_words + memory_space_constants::BRIDGE_SIZE)
+            .next_multiple_of(memory_space_constants::LARGE_OBJECT_PAGE_WORDS);
+        self.add_segment_of(
+            size - memory_space_constants::BRIDGE_SIZE,
+            SegmentKind::Pinned,
+        )
+    }
+
+    // Answers how many words were released.
+    // The first segment is kept, the space is never empty.
+    pub fn release_empty_segments(&mut self) -> usize {
+        self.retire_allocation_buffer();
+        let before = self.number_of_words();
+        let mut position = 1;
+        while position < self.segments.len() {
+            if self.is_segment_empty(position) {
+                let segment = self.segments.remove(position);
+                match &mut self.storage {
+                    Storage::Vectors(vectors) => {
+                        vectors.remove(position);
+                    }
+                    #[cfg(target_os = "linux")]
+                    Storage::Mapped(region) => {
+                        let first_index = region.first_index();
+                        // The words stay usable, the segments to come reuse the hole
+                        region
+                            .release(segment.start - first_index, segment.end() - first_index)
+                            .expect("Released pages should go back to the system");
+                    }
+                }
+            } else {
+                position += 1;
+            }
+        }
+        self.link_bridges();
+        before - self.number_of_words()
+    }
+
+    // A single free chunk up to the bridge
+    fn is_segment_empty(&mut self, position: usize) -> bool {
+        let start = self.segments[position].start;
+        let bridge_index = self.segments[position].bridge_index();
+        let first_oop = self.get_oop_at(start);
+        first_oop.is_free_oop() && first_oop.next_oop_index() == bridge_index
+    }
+
+    // None when the words cannot be allocated or committed, the space is left as it was
+    fn add_segment_of(&mut self, memory_space_size: usize, kind: SegmentKind) -> Option<usize> {
+        let size = memory_space_size.checked_add(memory_space_constants::BRIDGE_SIZE)?;
+        let start = match &mut self.storage {
+            Storage::Vectors(vectors) => {
+                let mut words = Vec::new();
+                words.try_reserve_exact(size).ok()?;
+                words.resize(size, 0);
+                vectors.push(words);
+                self.segments.last().map_or(0, |segment| segment.end())
+            }
+            #[cfg(target_os = "linux")]
+            Storage::Mapped(region) => {
+                let first_index = region.first_index();
+                let start = match self
+                    .segments
+                    .windows(2)
+                    .find(|pair| pair[1].start - pair[0].end() >= size)
+                {
+                    Some(pair) => pair[0].end(),
+                    None => self
+                        .segments
+                        .last()
+                        .map_or(first_index, |segment| segment.end()),
+                };
+                region.commit(start + size - first_index).ok()?;
+                start
+            }
+        };
+        let position = self
+            .segments
+            .partition_point(|segment| segment.start < start);
+        self.segments
+            .insert(position, Segment { start, size, kind });
+
+        // A single free chunk up to the bridge
+        free_chunk::write_free_words(start, memory_space_size, self);
+
+        let mut bridge_builder = OopBuilder::new();
+        bridge_builder.set_class_index(SpecialClassIndexes::SegmentBridge as usize);
+        bridge_builder.set_format(HeaderFormatValues::I64BitIndexable as usize);
+        bridge_builder.set_number_of_slots(1);
+        bridge_builder.build_oop_at(start + memory_space_size, self);
+        self.link_bridges();
+        Some(start)
+    }
+
+    fn link_bridges(&mut self) {
+        for position in 0..self.segments.len() {
+            let target = match self.segments.get(position + 1) {
+                Some(next) => next.start,
+                None => self.segments[position].end(),
+            };
+            let bridge_index = self.segments[position].bridge_index();
+            self[bridge_index + memory_space_constants::BRIDGE_TARGET_INDEX] = target;
+        }
+    }
+
+    // The words holding the index, and the position of the index in them
+    fn locate(&self, index: usize) -> (&[usize], usize) {
+        let position = self.position_of(index);
+        match &self.storage {
+            Storage::Vectors(vectors) => {
+                (&vectors[position], index - self.segments[position].start)
+            }
+            #[cfg(target_os = "linux")]
+            Storage::Mapped(region) => (region.words(), index - region.first_index()),
+        }
+    }
+
+    fn locate_mut(&mut self, index: usize) -> (&mut [usize], usize) {
+        let position = self.position_of(index);
+        match &mut self.storage {
+            Storage::Vectors(vectors) => (
+                &mut vectors[position],
+                index - self.segments[position].start,
+            ),
+            #[cfg(target_os = "linux")]
+            Storage::Mapped(region) => {
+                let first_index = region.first_index();
+                (region.words_mut(), index - first_index)
+            }
+        }
+    }
+
+    fn position_of(&self, index: usize) -> usize {
+        // Most of the objects live in the first segment
+        if self.segments[0].contains(index) {
+            return 0;
+        }
+        let position = self
+            .segments
+            .partition_point(|segment| segment.end() <= index);
+        match self.segments.get(position) {
+            Some(segment) if segment.start <= index => position,
+            _ => panic!("{} is outside of the memory space segments", index),
+        }
     }
 }
 
-impl<Idx> std::ops::Index<Idx> for MemorySpace
-where
-    Idx: std::slice::SliceIndex<[usize]>,
-{
-    type Output = Idx::Output;
+impl std::ops::Index<usize> for MemorySpace {
+    type Output = usize;
 
-    fn index(&self, index: Idx) -> &Self::Output {
-        &self.memory_vector[index]
+    fn index(&self, index: usize) -> &Self::Output {
+        let (words, offset) = self.locate(index);
+        &words[offset]
     }
 }
 
-impl<Idx> std::ops::IndexMut<Idx> for MemorySpace
-where
-    Idx: std::slice::SliceIndex<[usize]>,
-{
-    fn index_mut(&mut self, index: Idx) -> &mut Self::Output {
-        &mut self.memory_vector[index]
+impl std::ops::IndexMut<usize> for MemorySpace {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        let (words, offset) = self.locate_mut(index);
+        &mut words[offset]
+    }
+}
+
+// Ranges never span segments, objects live in a single one
+impl std::ops::Index<std::ops::Range<usize>> for MemorySpace {
+    type Output = [usize];
+
+    fn index(&self, range: std::ops::Range<usize>) -> &Self::Output {
+        let (words, offset) = self.locate(range.start);
+        &words[offset..offset + range.len()]
+    }
+}
+
+impl std::ops::IndexMut<std::ops::Range<usize>> for MemorySpace {
+    fn index_mut(&mut self, range: std::ops::Range<usize>) -> &mut Self::Output {
+        let (words, offset) = self.locate_mut(range.start);
+        &mut words[offset..offset + range.len()]
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::garbage_collector::simple_garbage_collector;
     use crate::header::Header;
-    use crate::memory_space::MemorySpace;
+    use crate::memory_space::{memory_space_constants, MemorySpace, SegmentKind};
+    use crate::oop_builder::OopBuilder;
     use crate::oop_projections::oop_common::OopCommonState;
     use crate::oop_projections::oop_common::OopNavigation;
+    use crate::slot_content::SlotContent;
+
+    fn build_with_slots(number_of_slots: usize, space: &mut MemorySpace) -> usize {
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(number_of_slots);
+        builder.set_slots_value(SlotContent::small_integer(0));
+        builder.build(space)
+    }
+
+    fn walked_objects(space: &mut MemorySpace) -> Vec<usize> {
+        let mut iter = space.iter();
+        let mut objects = Vec::new();
+        while let Some(headers) = iter.next_headers(space) {
+            if !headers.get_header().is_free_space() {
+                objects.push(headers.get_index());
+            }
+        }
+        objects
+    }
 
     #[test]
     fn test_unfilled_space_first_oop_is_free() {
@@ -104,7 +618,6 @@ mod tests {
     }
 
     //In the following test cases, we test when the global free oop needs to go to use the extra header
-    // Unfortunately, at the moment, it's a bit clumsy and we simply loose one slot.
     #[test]
     fn test_allocate_lower_bound_edge_case() {
         let mut space = MemorySpace::for_bit_size(Header::MAX_NUMBER_OF_SLOTS - 1);
@@ -123,7 +636,16 @@ mod tests {
     #[test]
     fn test_allocate_middle_bound_edge_case() {
         let mut space = MemorySpace::for_bit_size(Header::MAX_NUMBER_OF_SLOTS + 1);
-        assert_eq!(space.first_oop().oop_size(), Header::MAX_NUMBER_OF_SLOTS);
+        assert_eq!(
+            space.first_oop().oop_size(),
+            Header::MAX_NUMBER_OF_SLOTS + 1
+        );
+    }
+
+    #[parameterized(size = { 256, 257, 258 })]
+    fn test_allocate_upper_bound_edge_case(size: usize) {
+        let mut space = MemorySpace::for_bit_size(size);
+        assert_eq!(space.first_oop().oop_size(), size);
     }
 
     #[test]
@@ -143,4 +665,217 @@ mod tests {
         let space = MemorySpace::for_bit_size(Header::MAX_NUMBER_OF_SLOTS + 1);
         assert_eq!(space.get_end_index(), Header::MAX_NUMBER_OF_SLOTS);
     }
+
+    #[test]
+    fn test_allocation_grows_the_space_by_a_segment() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let first = build_with_slots(200, &mut space);
+        let second = build_with_slots(100, &mut space);
+
+        assert_eq!(space.segments().len(), 2);
+        assert_eq!(second, 240 + memory_space_constants::BRIDGE_SIZE);
+        assert_eq!(space.segment_containing(second).unwrap().start(), second);
+        assert_eq!(walked_objects(&mut space), vec![first, second]);
+    }
+
+    #[test]
+    fn test_big_objects_get_a_segment_of_their_size() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let big = build_with_slots(1000, &mut space);
+
+        assert_eq!(space.get_oop_at(big).number_of_slots(), 1000);
+        assert!(space.get_end_index() >= big + 1000);
+    }
+
+    #[test]
+    fn test_references_across_segments_survive_the_collection() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let root = build_with_slots(200, &mut space);
+        let other = build_with_slots(100, &mut space);
+        space.get_oop_at(root).slot_at_index_put(1, other);
+
+        simple_garbage_collector::collect_from_roots(vec![root], &mut space);
+
+        assert_eq!(space.segments().len(), 2);
+        assert_eq!(space.get_oop_at(root).slot_at_index(1), other);
+        assert!(!space.get_oop_at(other).is_free_oop());
+    }
+
+    #[test]
+    fn test_collection_releases_the_empty_segments() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let root = build_with_slots(200, &mut space);
+        build_with_slots(100, &mut space);
+        let words = space.number_of_words();
+
+        simple_garbage_collector::collect_from_roots(vec![root], &mut space);
+
+        assert_eq!(space.segments().len(), 1);
+        assert_eq!(
+            space.number_of_words(),
+            words - 240 - memory_space_constants::BRIDGE_SIZE
+        );
+        assert_eq!(walked_objects(&mut space), vec![root]);
+    }
+
+    #[test]
+    fn test_bridges_lead_over_released_segments() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let first = build_with_slots(200, &mut space);
+        let middle = build_with_slots(200, &mut space);
+        let last = build_with_slots(200, &mut space);
+        space.get_oop_at(middle).become_free_oop();
+        simple_garbage_collector::merge_free_oops(&mut space);
+
+        assert_eq!(
+            space.release_empty_segments(),
+            240 + memory_space_constants::BRIDGE_SIZE
+        );
+        let first_bridge = space.segments()[0].bridge_index();
+        assert_eq!(
+            space[first_bridge + memory_space_constants::BRIDGE_TARGET_INDEX],
+            last
+        );
+        assert_eq!(walked_objects(&mut space), vec![first, last]);
+        // The new segments go after the last one, never in the holes
+        assert_eq!(
+            build_with_slots(200, &mut space),
+            3 * (240 + memory_space_constants::BRIDGE_SIZE)
+        );
+    }
+
+    #[test]
+    fn test_addresses_convert_back_to_indexes() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let first = build_with_slots(200, &mut space);
+        let second = build_with_slots(100, &mut space);
+
+        for index in [first, second, second + 5] {
+            assert_eq!(space.index_at_address(space.address_of(index)), Some(index));
+        }
+        let misaligned = (space.address_of(first) as usize + 1) as *const usize;
+        assert_eq!(space.index_at_address(misaligned), None);
+        assert_eq!(space.index_at_address(std::ptr::null()), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_mapped_indexes_are_word_addresses() {
+        let mut space = MemorySpace::mapped(1 << 16, 240).unwrap();
+        let first = build_with_slots(200, &mut space);
+        let second = build_with_slots(100, &mut space);
+        space.get_oop_at(first).slot_at_index_put(1, second);
+
+        assert_eq!(first, space.get_start_index());
+        assert_eq!(
+            space.address_of(first) as usize,
+            first * std::mem::size_of::<usize>()
+        );
+        assert_eq!(
+            space.address_of(second) as usize,
+            second * std::mem::size_of::<usize>()
+        );
+        assert_eq!(
+            space.index_at_address(space.address_of(second)),
+            Some(second)
+        );
+        assert_eq!(space.segments().len(), 2);
+        assert_eq!(walked_objects(&mut space), vec![first, second]);
+
+        simple_garbage_collector::collect_from_roots(vec![first], &mut space);
+        assert_eq!(space.get_oop_at(first).slot_at_index(1), second);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_mapped_space_reuses_the_released_ranges() {
+        let mut space = MemorySpace::mapped(4096, 240).unwrap();
+        let root = build_with_slots(10, &mut space);
+        let large = build_with_slots(1000, &mut space);
+        let last = build_with_slots(1000, &mut space);
+        space.get_oop_at(root).slot_at_index_put(1, last);
+
+        // Without the reuse, the third round goes past the reservation
+        for _ in 0..10 {
+            simple_garbage_collector::collect_from_roots(vec![root], &mut space);
+            assert_eq!(space.segments().len(), 2);
+            assert_eq!(build_with_slots(1000, &mut space), large);
+            assert_eq!(walked_objects(&mut space), vec![root, large, last]);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[should_panic(expected = "Couldn't grow the memory space")]
+    fn test_mapped_space_grows_within_its_reservation() {
+        let mut space = MemorySpace::mapped(1000, 240).unwrap();
+        build_with_slots(100_000, &mut space);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_mapped_space_fails_to_grow_past_its_reservation() {
+        let mut space = MemorySpace::mapped(1000, 240).unwrap();
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(100_000);
+
+        assert_eq!(builder.try_build(&mut space), None);
+        assert_eq!(space.segments().len(), 1);
+        assert_eq!(build_with_slots(10, &mut space), space.get_start_index());
+    }
+
+    #[test]
+    fn test_space_fails_to_grow_past_the_system_memory() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(1 << 60);
+
+        assert_eq!(builder.try_build(&mut space), None);
+        assert_eq!(space.segments().len(), 1);
+    }
+
+    #[test]
+    fn test_large_objects_get_pages_of_their_own() {
+        let mut space = MemorySpace::for_bit_size(240);
+        build_with_slots(200, &mut space);
+        let large = build_with_slots(300, &mut space);
+        // Fits in the rest of the large object pages, not in the first segment
+        let small = build_with_slots(100, &mut space);
+
+        let kinds: Vec<SegmentKind> = space
+            .segments()
+            .iter()
+            .map(|segment| segment.kind())
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                SegmentKind::Objects,
+                SegmentKind::LargeObject,
+                SegmentKind::Objects
+            ]
+        );
+        assert!(space.is_in_large_object_space(large));
+        assert!(!space.is_in_large_object_space(small));
+        assert_eq!(
+            space.segments()[1].end() - space.segments()[1].start(),
+            memory_space_constants::LARGE_OBJECT_PAGE_WORDS
+        );
+    }
+
+    #[test]
+    fn test_large_objects_are_swept_in_place() {
+        let mut space = MemorySpace::for_bit_size(240);
+        space.set_large_object_threshold(50);
+        let root = build_with_slots(100, &mut space);
+        let garbage = build_with_slots(100, &mut space);
+        let kept = build_with_slots(100, &mut space);
+        space.get_oop_at(root).slot_at_index_put(1, kept);
+
+        simple_garbage_collector::collect_from_roots(vec![root], &mut space);
+
+        assert!(space.segment_containing(garbage).is_none());
+        assert_eq!(walked_objects(&mut space), vec![root, kept]);
+        assert_eq!(space.get_oop_at(root).slot_at_index(1), kept);
+    }
 }
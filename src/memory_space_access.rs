@@ -4,6 +4,7 @@ use crate::oop_projections::oop_common::{OopCommonState, OopNavigation};
 use crate::oop_projections::oop_headers::OopHeaders;
 use crate::oop_projections::oop_slice::OopSlice;
 
+// The bridges ending the segments are skipped, the walk goes on where they lead
 pub struct MemorySpaceIterator {
     current_index: usize,
 }
@@ -13,19 +14,26 @@ impl MemorySpaceIterator {
         Self { current_index: 0 }
     }
 
+    pub fn starting_at(index: usize) -> Self {
+        Self {
+            current_index: index,
+        }
+    }
+
     pub fn next<'a>(&mut self, space: &'a mut MemorySpace) -> Option<OopSlice<'a>> {
         if self.current_index > space.get_end_index() {
             return None;
         }
 
-        let res = oop_at_index(self.current_index, space);
-        self.current_index = res.next_oop_index();
-        Some(res)
+        let index = self.current_index;
+        let next_index = oop_header_at_index(index, space).next_oop_index();
+        self.current_index = space.skip_bridge(next_index);
+        Some(oop_at_index(index, space))
     }
 
     pub fn go_to_next(&mut self, space: &mut MemorySpace) {
         let res = oop_header_at_index(self.current_index, space);
-        self.current_index = res.next_oop_index();
+        self.current_index = space.skip_bridge(res.next_oop_index());
     }
 
     pub fn peak_next_headers(&self, space: &mut MemorySpace) -> Option<OopHeaders> {
@@ -42,7 +50,7 @@ impl MemorySpaceIterator {
         }
 
         let res = oop_header_at_index(self.current_index, space);
-        self.current_index = res.next_oop_index();
+        self.current_index = space.skip_bridge(res.next_oop_index());
         Some(res)
     }
 }
@@ -53,6 +61,7 @@ impl Default for MemorySpaceIterator {
     }
 }
 
+#[allow(clippy::module_inception)]
 pub mod memory_space_access {
     use super::*;
 
@@ -61,15 +70,15 @@ pub mod memory_space_access {
     }
 
     pub fn first_oop_header(space: &mut MemorySpace) -> OopHeaders {
-        oop_header_at_index(0, space)
+        oop_header_at_index(space.get_start_index(), space)
     }
 
-    pub fn oop_at_index(index: usize, space: &mut MemorySpace) -> OopSlice {
+    pub fn oop_at_index(index: usize, space: &mut MemorySpace) -> OopSlice<'_> {
         let oop_size = OopHeaders::new(index, space).oop_size();
         OopSlice::new(index, &mut space[index..index + oop_size])
     }
 
-    pub fn first_oop(space: &mut MemorySpace) -> OopSlice {
-        oop_at_index(0, space)
+    pub fn first_oop(space: &mut MemorySpace) -> OopSlice<'_> {
+        oop_at_index(space.get_start_index(), space)
     }
 }
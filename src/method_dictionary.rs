@@ -0,0 +1,237 @@
+use crate::class_table::class_constants;
+use crate::hashed_collection::{capacity_of, set_tally, tally_of};
+use crate::interpreter::Interpreter;
+use crate::special_object_index::SpecialObjectIndexes;
+
+// As in Squeak, the selectors are the indexable slots of the dictionary,
+// and the methods are at the same index in an Array.
+// Selectors are hashed by identity, collisions go to the next free slot.
+pub mod method_dictionary_constants {
+    // After the tally
+    pub const ARRAY_INDEX: usize = 2;
+    pub const NUMBER_OF_FIXED_SLOTS: usize = 2;
+
+    pub const INITIAL_CAPACITY: usize = 16;
+}
+
+pub fn new_method_dictionary(interpreter: &mut Interpreter, capacity: usize) -> usize {
+    let dictionary_class = interpreter.special_object(SpecialObjectIndexes::ClassMethodDictionary);
+    let array_class = interpreter.special_object(SpecialObjectIndexes::ClassArray);
+    let dictionary = interpreter
+        .instantiate_class(dictionary_class, capacity)
+        .expect("MethodDictionary should be indexable");
+    let array = interpreter
+        .instantiate_class(array_class, capacity)
+        .expect("Array should be indexable");
+
+    set_tally(interpreter, dictionary, 0);
+    interpreter
+        .space
+        .get_oop_at(dictionary)
+        .slot_at_index_put(method_dictionary_constants::ARRAY_INDEX, array);
+    dictionary
+}
+
+// Answers the 1 based index of the selector, or of the free slot where it would go
+fn scan_for(interpreter: &mut Interpreter, dictionary: usize, selector: usize) -> Option<usize> {
+    let nil = interpreter.nil_object();
+    let capacity = capacity_of(
+        interpreter,
+        dictionary,
+        method_dictionary_constants::NUMBER_OF_FIXED_SLOTS,
+    );
+    let start = interpreter.hash_of(selector) % capacity;
+    (0..capacity)
+        .map(|probe| (start + probe) % capacity + 1)
+        .find(|index| {
+            let key = interpreter
+                .space
+                .get_oop_at(dictionary)
+                .slot_at_index(method_dictionary_constants::NUMBER_OF_FIXED_SLOTS + index);
+            key == selector || key == nil
+        })
+}
+
+pub fn lookup(interpreter: &mut Interpreter, dictionary: usize, selector: usize) -> Option<usize> {
+    let index = scan_for(interpreter, dictionary, selector)?;
+    let dictionary_oop = interpreter.space.get_oop_at(dictionary);
+    let key =
+        dictionary_oop.slot_at_index(method_dictionary_constants::NUMBER_OF_FIXED_SLOTS + index);
+    if key != selector {
+        return None;
+    }
+    let array = dictionary_oop.slot_at_index(method_dictionary_constants::ARRAY_INDEX);
+    Some(interpreter.space.get_oop_at(array).slot_at_index(index))
+}
+
+// Answers the selectors and their methods
+pub fn associations(interpreter: &mut Interpreter, dictionary: usize) -> Vec<(usize, usize)> {
+    let nil = interpreter.nil_object();
+    let capacity = capacity_of(
+        interpreter,
+        dictionary,
+        method_dictionary_constants::NUMBER_OF_FIXED_SLOTS,
+    );
+    let dictionary_oop = interpreter.space.get_oop_at(dictionary);
+    let array = dictionary_oop.slot_at_index(method_dictionary_constants::ARRAY_INDEX);
+    let selectors: Vec<(usize, usize)> = (1..=capacity)
+        .map(|index| {
+            (
+                index,
+                dictionary_oop
+                    .slot_at_index(method_dictionary_constants::NUMBER_OF_FIXED_SLOTS + index),
+            )
+        })
+        .filter(|(_, selector)| *selector != nil)
+        .collect();
+    let array_oop = interpreter.space.get_oop_at(array);
+    selectors
+        .into_iter()
+        .map(|(index, selector)| (selector, array_oop.slot_at_index(index)))
+        .collect()
+}
+
+fn add_at(interpreter: &mut Interpreter, dictionary: usize, selector: usize, method: usize) {
+    let index = scan_for(interpreter, dictionary, selector).expect("MethodDictionary is full");
+    let key_index = method_dictionary_constants::NUMBER_OF_FIXED_SLOTS + index;
+    let mut dictionary_oop = interpreter.space.get_oop_at(dictionary);
+    if dictionary_oop.slot_at_index(key_index) != selector {
+        dictionary_oop.slot_at_index_put(key_index, selector);
+        let tally = tally_of(interpreter, dictionary);
+        set_tally(interpreter, dictionary, tally + 1);
+    }
+    let array = interpreter
+        .space
+        .get_oop_at(dictionary)
+        .slot_at_index(method_dictionary_constants::ARRAY_INDEX);
+    interpreter
+        .space
+        .get_oop_at(array)
+        .slot_at_index_put(index, method);
+}
+
+// Installs the method in the dictionary of the class, creating or growing it as needed.
+// The dictionary is kept at most three quarters full.
+pub fn install_method(interpreter: &mut Interpreter, class: usize, selector: usize, method: usize) {
+    let nil = interpreter.nil_object();
+    let mut dictionary = interpreter
+        .space
+        .get_oop_at(class)
+        .slot_at_index(class_constants::METHOD_DICTIONARY_INDEX);
+    if dictionary == nil {
+        dictionary =
+            new_method_dictionary(interpreter, method_dictionary_constants::INITIAL_CAPACITY);
+    }
+
+    let capacity = capacity_of(
+        interpreter,
+        dictionary,
+        method_dictionary_constants::NUMBER_OF_FIXED_SLOTS,
+    );
+    if (tally_of(interpreter, dictionary) + 1) * 4 > capacity * 3 {
+        let grown = new_method_dictionary(interpreter, capacity * 2);
+        for (old_selector, old_method) in associations(interpreter, dictionary) {
+            add_at(interpreter, grown, old_selector, old_method);
+        }
+        dictionary = grown;
+    }
+
+    add_at(interpreter, dictionary, selector, method);
+    interpreter
+        .space
+        .get_oop_at(class)
+        .slot_at_index_put(class_constants::METHOD_DICTIONARY_INDEX, dictionary);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::{bootstrap, define_class};
+    use crate::class_table::class_constants;
+    use crate::header_format_values::HeaderFormatValues;
+    use crate::interpreter::Interpreter;
+    use crate::method_dictionary::{associations, install_method, lookup};
+    use crate::slot_content::SlotContent;
+    use crate::symbol_table::intern;
+
+    fn new_class(interpreter: &mut Interpreter) -> usize {
+        let object = interpreter.class_named("Object").unwrap();
+        define_class(
+            interpreter,
+            "Foo",
+            object,
+            HeaderFormatValues::ZeroSizedFormat as usize,
+            &[],
+        )
+    }
+
+    fn method_dictionary_of(interpreter: &mut Interpreter, class: usize) -> usize {
+        interpreter
+            .space
+            .get_oop_at(class)
+            .slot_at_index(class_constants::METHOD_DICTIONARY_INDEX)
+    }
+
+    #[test]
+    fn test_installed_method_is_found() {
+        let mut interpreter = bootstrap(20000);
+        let class = new_class(&mut interpreter);
+        let selector = intern(&mut interpreter, "foo");
+        let method = SlotContent::small_integer(42);
+
+        install_method(&mut interpreter, class, selector, method);
+
+        let dictionary = method_dictionary_of(&mut interpreter, class);
+        assert_eq!(lookup(&mut interpreter, dictionary, selector), Some(method));
+    }
+
+    #[test]
+    fn test_missing_selector_is_not_found() {
+        let mut interpreter = bootstrap(20000);
+        let class = new_class(&mut interpreter);
+        let selector = intern(&mut interpreter, "foo");
+        let missing = intern(&mut interpreter, "bar");
+        install_method(&mut interpreter, class, selector, selector);
+
+        let dictionary = method_dictionary_of(&mut interpreter, class);
+        assert_eq!(lookup(&mut interpreter, dictionary, missing), None);
+    }
+
+    #[test]
+    fn test_reinstalling_replaces_the_method() {
+        let mut interpreter = bootstrap(20000);
+        let class = new_class(&mut interpreter);
+        let selector = intern(&mut interpreter, "foo");
+        let first = SlotContent::small_integer(1);
+        let second = SlotContent::small_integer(2);
+
+        install_method(&mut interpreter, class, selector, first);
+        install_method(&mut interpreter, class, selector, second);
+
+        let dictionary = method_dictionary_of(&mut interpreter, class);
+        assert_eq!(lookup(&mut interpreter, dictionary, selector), Some(second));
+        assert_eq!(associations(&mut interpreter, dictionary).len(), 1);
+    }
+
+    #[test]
+    fn test_dictionary_grows() {
+        let mut interpreter = bootstrap(20000);
+        let class = new_class(&mut interpreter);
+        let selectors: Vec<usize> = (0..40)
+            .map(|index| intern(&mut interpreter, &format!("selector{}", index)))
+            .collect();
+
+        for (index, selector) in selectors.iter().enumerate() {
+            let method = SlotContent::small_integer(index as isize);
+            install_method(&mut interpreter, class, *selector, method);
+        }
+
+        let dictionary = method_dictionary_of(&mut interpreter, class);
+        for (index, selector) in selectors.iter().enumerate() {
+            let method = SlotContent::small_integer(index as isize);
+            assert_eq!(
+                lookup(&mut interpreter, dictionary, *selector),
+                Some(method)
+            );
+        }
+    }
+}
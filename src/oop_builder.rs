@@ -1,12 +1,15 @@
-use crate::allocator::where_to_allocate;
-use crate::memory_space::MemorySpace;
+use crate::allocator::{allocate, split_free_chunk, where_to_allocate_in};
+use crate::memory_space::{MemorySpace, SegmentKind};
 use crate::oop_projections::oop_carcass::OopCarcass;
 use crate::oop_projections::oop_common::OopCommonState;
-use crate::oop_projections::oop_headers::OopHeaders;
 
 pub struct OopBuilder {
     number_of_slots: usize,
     class_index: usize,
+    format: usize,
+    slots_value: Option<usize>,
+    // Pinned objects are allocated where nothing moves
+    pinned: bool,
 }
 
 impl OopBuilder {
@@ -14,6 +17,9 @@ impl OopBuilder {
         Self {
             class_index: 2,
             number_of_slots: 0,
+            format: 0,
+            slots_value: None,
+            pinned: false,
         }
     }
 
@@ -22,6 +28,9 @@ impl OopBuilder {
         //Maybe it should always be required
         self.class_index = 2;
         self.number_of_slots = 0;
+        self.format = 0;
+        self.slots_value = None;
+        self.pinned = false;
     }
 
     // API, for code readability
@@ -37,26 +46,48 @@ impl OopBuilder {
         new_oop_carcass
             .get_header_mut()
             .set_class_index_bits(self.class_index);
+        new_oop_carcass
+            .get_header_mut()
+            .set_format_bits(self.format);
+        if self.pinned {
+            new_oop_carcass.get_header_mut().set_pinned_bit();
+        }
         new_oop_carcass.apply_at_index_on_space(index, space);
+
+        // Without a value, the slots keep whatever was in memory
+        if let Some(slots_value) = self.slots_value {
+            let first_slot_index = index + new_oop_carcass.get_header().header_size();
+            for slot_index in first_slot_index..index + new_oop_carcass.oop_size() {
+                space[slot_index] = slots_value;
+            }
+        }
     }
 
     pub fn build(&self, space: &mut MemorySpace) -> usize {
+        self.try_build(space)
+            .expect("Couldn't grow the memory space")
+    }
+
+    // None when the space cannot grow to make room for the object
+    pub fn try_build(&self, space: &mut MemorySpace) -> Option<usize> {
         let mut new_oop_carcass = OopCarcass::default();
         new_oop_carcass.set_number_of_slots(self.number_of_slots);
         let new_oop_size = new_oop_carcass.oop_size();
 
-        let allocated_index: usize = where_to_allocate(new_oop_carcass.oop_size(), space);
-        let free_header = OopHeaders::new(allocated_index, space);
-        let free_oop_size = free_header.oop_size();
-
-        if free_oop_size != new_oop_size {
-            let new_free_oop = free_header.carve_out(new_oop_size);
-            let new_free_oop_index: usize = allocated_index + new_oop_size;
-            new_free_oop.apply_at_index_on_space(new_free_oop_index, space);
-        }
+        let allocated_index: usize = if self.number_of_slots > space.large_object_threshold() {
+            let index = space.grow_for_large_object(new_oop_size)?;
+            split_free_chunk(index, new_oop_size, space);
+            index
+        } else if self.pinned {
+            let index = where_to_allocate_in(SegmentKind::Pinned, new_oop_size, space)?;
+            split_free_chunk(index, new_oop_size, space);
+            index
+        } else {
+            allocate(new_oop_size, space)?
+        };
 
         self.build_oop_at(allocated_index, space);
-        allocated_index
+        Some(allocated_index)
     }
 
     pub fn set_number_of_slots(&mut self, new_number_of_slots: usize) {
@@ -66,4 +97,22 @@ impl OopBuilder {
     pub fn set_class_index(&mut self, new_class_index: usize) {
         self.class_index = new_class_index;
     }
+
+    pub fn set_format(&mut self, new_format: usize) {
+        self.format = new_format;
+    }
+
+    pub fn set_slots_value(&mut self, new_slots_value: usize) {
+        self.slots_value = Some(new_slots_value);
+    }
+
+    pub fn set_pinned(&mut self, pinned: bool) {
+        self.pinned = pinned;
+    }
+}
+
+impl Default for OopBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
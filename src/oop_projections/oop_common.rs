@@ -11,8 +11,9 @@ pub mod oop_constants {
 pub mod oop_utilities {
     use crate::header::Header;
 
+    // The 255 words of an object with the most slots a header counts fit in one header
     pub fn how_many_headers_for(some_memory_size: usize) -> usize {
-        if some_memory_size <= Header::MAX_NUMBER_OF_SLOTS {
+        if some_memory_size <= Header::MAX_NUMBER_OF_SLOTS + 1 {
             1
         } else {
             2
@@ -56,6 +57,17 @@ pub trait OopCommonState {
                 .set_number_of_slots_bits(number_of_slots);
         }
     }
+
+    // The slots of an object of exactly that many words.
+    // From 256 words there is an extra header, even for the 254 slots of a 256 words object.
+    fn set_oop_size(&mut self, oop_size: usize) {
+        if oop_utilities::how_many_headers_for(oop_size) == 2 {
+            self.get_header_mut().set_number_of_slots_to_max();
+            self.set_extra_header(oop_size - 2);
+        } else {
+            self.set_number_of_slots(oop_size - 1);
+        }
+    }
 }
 
 pub trait OopNavigation: OopCommonState {
@@ -1,7 +1,8 @@
+use crate::free_chunk;
 use crate::header::Header;
 use crate::memory_space::MemorySpace;
 use crate::oop_projections::oop_carcass::OopCarcass;
-use crate::oop_projections::oop_common::{oop_constants, oop_utilities, OopCommonState, OopNavigation};
+use crate::oop_projections::oop_common::{oop_constants, OopCommonState, OopNavigation};
 
 #[derive(Debug)]
 pub struct OopHeaders {
@@ -48,31 +49,29 @@ impl OopHeaders {
         }
     }
 
+    // An object without slots is too short for a free chunk, it becomes a filler
     pub fn become_free_oop(&mut self, space: &mut MemorySpace) {
-        self.get_header_mut().become_free_oop();
+        if self.oop_size() < free_chunk::MINIMUM_FREE_CHUNK_SIZE {
+            self.get_header_mut().become_filler();
+        } else {
+            self.get_header_mut().become_free_oop();
+        }
         self.apply_header(space);
     }
 
     pub fn merge_with(&mut self, oop: OopHeaders, space: &mut MemorySpace) {
-        // Expects both oops to be free, merging doesn't make sense otherwise
+        // Expects both oops to be free or fillers, merging doesn't make sense otherwise
         let total_size = self.oop_size() + oop.oop_size();
-        let header_nb = oop_utilities::how_many_headers_for(total_size);
-        let new_nb_slots = total_size - header_nb;
-        self.set_number_of_slots(new_nb_slots);
+        self.get_header_mut().become_free_oop();
+        self.set_oop_size(total_size);
 
         self.apply_header(space);
     }
 
+    // The header of what is left once the first words are allocated,
+    // a filler when they are too few for a free chunk
     pub fn carve_out(&self, size: usize) -> OopCarcass {
-        // if the header grows, set_number_of_slots will add the extra header
-        // If it needs to shrink, it's implicitly done
-        let mut new_free_oop = OopCarcass::new_from(self);
-        let new_resulting_size = self.oop_size() - size;
-        let new_header_size = oop_utilities::how_many_headers_for(new_resulting_size);
-        let new_slot_numbers = new_resulting_size - new_header_size;
-        new_free_oop.set_number_of_slots(new_slot_numbers);
-
-        new_free_oop
+        free_chunk::free_carcass_of_size(self.oop_size() - size)
     }
 
     pub fn apply_header(&self, space: &mut MemorySpace) {
@@ -84,6 +83,7 @@ impl OopHeaders {
 }
 
 #[cfg(test)]
+#[allow(clippy::unnecessary_mut_passed)]
 mod tests {
     use super::*;
     use crate::oop_builder::OopBuilder;
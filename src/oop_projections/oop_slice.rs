@@ -1,7 +1,108 @@
+use std::fmt;
+
+use crate::free_chunk;
 use crate::header::Header;
 use crate::oop_projections::oop_common::{oop_constants, OopCommonState, OopNavigation};
 use crate::slot_content::SlotContent;
 
+// A slot access outside of 1..=number_of_slots
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotIndexOutOfBounds {
+    pub index: usize,
+    pub object_index: usize,
+    pub number_of_slots: usize,
+}
+
+impl fmt::Display for SlotIndexOutOfBounds {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "slot access was out of bound: index {} of the object at {}, which has {} slots",
+            self.index, self.object_index, self.number_of_slots
+        )
+    }
+}
+
+impl std::error::Error for SlotIndexOutOfBounds {}
+
+// A byte access outside of 1..=number_of_bytes
+#[derive(Debug, Clone, PartialEq)]
+pub struct ByteIndexOutOfBounds {
+    pub index: usize,
+    pub object_index: usize,
+    pub number_of_bytes: usize,
+}
+
+impl fmt::Display for ByteIndexOutOfBounds {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "byte access was out of bound: index {} of the object at {}, which has {} bytes",
+            self.index, self.object_index, self.number_of_bytes
+        )
+    }
+}
+
+impl std::error::Error for ByteIndexOutOfBounds {}
+
+// A store into an immutable object. It carries what the store was about,
+// so that it can be done again once the object is writable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModificationForbidden {
+    pub object_index: usize,
+    pub index: usize,
+    pub value: usize,
+}
+
+impl fmt::Display for ModificationForbidden {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "modification forbidden: the object at {} is immutable, {} was not stored at {}",
+            self.object_index, self.value, self.index
+        )
+    }
+}
+
+impl std::error::Error for ModificationForbidden {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoreError {
+    OutOfBounds(SlotIndexOutOfBounds),
+    ByteOutOfBounds(ByteIndexOutOfBounds),
+    ModificationForbidden(ModificationForbidden),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::OutOfBounds(error) => write!(formatter, "{}", error),
+            StoreError::ByteOutOfBounds(error) => write!(formatter, "{}", error),
+            StoreError::ModificationForbidden(error) => write!(formatter, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<SlotIndexOutOfBounds> for StoreError {
+    fn from(error: SlotIndexOutOfBounds) -> Self {
+        StoreError::OutOfBounds(error)
+    }
+}
+
+impl From<ByteIndexOutOfBounds> for StoreError {
+    fn from(error: ByteIndexOutOfBounds) -> Self {
+        StoreError::ByteOutOfBounds(error)
+    }
+}
+
+impl From<ModificationForbidden> for StoreError {
+    fn from(error: ModificationForbidden) -> Self {
+        StoreError::ModificationForbidden(error)
+    }
+}
+
 #[derive(Debug)]
 pub struct OopSlice<'a> {
     index: usize,
@@ -50,8 +151,13 @@ impl<'a> OopSlice<'a> {
         }
     }
 
+    // An object without slots is too short for a free chunk, it becomes a filler
     pub fn become_free_oop(&mut self) {
-        self.get_header_mut().become_free_oop();
+        if self.oop_size() < free_chunk::MINIMUM_FREE_CHUNK_SIZE {
+            self.get_header_mut().become_filler();
+        } else {
+            self.get_header_mut().become_free_oop();
+        }
         self.apply_header();
     }
 
@@ -64,30 +170,153 @@ impl<'a> OopSlice<'a> {
 
     // We define the slots as 1 base.
     // This simplifies the small object case
-    fn slot_bound_check(&self, an_index: usize) {
+    fn slot_bound_check(&self, an_index: usize) -> Result<(), SlotIndexOutOfBounds> {
         if an_index < 1 || an_index > self.number_of_slots() {
-            panic!("slot access was out of bound")
+            Err(SlotIndexOutOfBounds {
+                index: an_index,
+                object_index: self.index,
+                number_of_slots: self.number_of_slots(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn mutability_check(
+        &self,
+        an_index: usize,
+        a_value: usize,
+    ) -> Result<(), ModificationForbidden> {
+        if self.header.is_immutable() {
+            Err(ModificationForbidden {
+                object_index: self.index,
+                index: an_index,
+                value: a_value,
+            })
+        } else {
+            Ok(())
         }
     }
 
     fn compute_slot_index(&self, an_index: usize) -> usize {
-        return if self.header.has_extra_slot_header() {
+        if self.header.has_extra_slot_header() {
             oop_constants::EXTRA_HEADER_INDEX + an_index
         } else {
             an_index
-        };
+        }
     }
 
+    // Panics out of bounds, for the accesses the VM knows to be valid
     pub fn slot_at_index(&self, an_index: usize) -> usize {
-        self.slot_bound_check(an_index);
-        self.contents[self.compute_slot_index(an_index)]
+        match self.try_slot_at_index(an_index) {
+            Ok(value) => value,
+            Err(error) => panic!("{}", error),
+        }
     }
 
     pub fn slot_at_index_put(&mut self, an_index: usize, an_oop_address: usize) {
-        self.slot_bound_check(an_index);
+        if let Err(error) = self.try_slot_at_index_put(an_index, an_oop_address) {
+            panic!("{}", error)
+        }
+    }
+
+    // For the accesses the image asks for, such as the primitives ones
+    pub fn try_slot_at_index(&self, an_index: usize) -> Result<usize, SlotIndexOutOfBounds> {
+        self.slot_bound_check(an_index)?;
+        Ok(self.contents[self.compute_slot_index(an_index)])
+    }
+
+    // Immutable objects refuse the store
+    pub fn try_slot_at_index_put(
+        &mut self,
+        an_index: usize,
+        an_oop_address: usize,
+    ) -> Result<(), StoreError> {
+        self.slot_bound_check(an_index)?;
+        self.mutability_check(an_index, an_oop_address)?;
+        self.contents[self.compute_slot_index(an_index)] = an_oop_address;
+        Ok(())
+    }
+
+    // For the VM rewriting a reference to the object it already designates,
+    // such as a forwarder replaced by its target: immutable objects are not spared
+    pub fn slot_at_index_put_ignoring_immutability(
+        &mut self,
+        an_index: usize,
+        an_oop_address: usize,
+    ) {
+        if let Err(error) = self.slot_bound_check(an_index) {
+            panic!("{}", error)
+        }
         self.contents[self.compute_slot_index(an_index)] = an_oop_address;
     }
 
+    // The interpreter fast path, without bound check.
+    // # Safety
+    // an_index must be in 1..=number_of_slots.
+    pub unsafe fn slot_at_index_unchecked(&self, an_index: usize) -> usize {
+        debug_assert!(self.slot_bound_check(an_index).is_ok());
+        *self
+            .contents
+            .get_unchecked(self.compute_slot_index(an_index))
+    }
+
+    // Immutability is not checked either, the interpreter does it before.
+    // # Safety
+    // an_index must be in 1..=number_of_slots.
+    pub unsafe fn slot_at_index_put_unchecked(&mut self, an_index: usize, an_oop_address: usize) {
+        debug_assert!(self.slot_bound_check(an_index).is_ok());
+        let slot_index = self.compute_slot_index(an_index);
+        *self.contents.get_unchecked_mut(slot_index) = an_oop_address;
+    }
+
+    // Bytes objects store 8 bytes per slot, the low bits of the format tell how many are unused
+    pub fn number_of_bytes(&self) -> usize {
+        self.number_of_slots() * 8 - (self.header.format_bits() & 7)
+    }
+
+    fn byte_bound_check(&self, an_index: usize) -> Result<(), ByteIndexOutOfBounds> {
+        if an_index < 1 || an_index > self.number_of_bytes() {
+            Err(ByteIndexOutOfBounds {
+                index: an_index,
+                object_index: self.index,
+                number_of_bytes: self.number_of_bytes(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    // 1 based, like the slots
+    pub fn byte_at_index(&self, an_index: usize) -> u8 {
+        match self.try_byte_at_index(an_index) {
+            Ok(value) => value,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    pub fn try_byte_at_index(&self, an_index: usize) -> Result<u8, ByteIndexOutOfBounds> {
+        self.byte_bound_check(an_index)?;
+        let slot_value = self.contents[self.compute_slot_index((an_index - 1) / 8 + 1)];
+        Ok((slot_value >> (((an_index - 1) % 8) * 8)) as u8)
+    }
+
+    pub fn byte_at_index_put(&mut self, an_index: usize, a_byte: u8) {
+        if let Err(error) = self.try_byte_at_index_put(an_index, a_byte) {
+            panic!("{}", error)
+        }
+    }
+
+    pub fn try_byte_at_index_put(&mut self, an_index: usize, a_byte: u8) -> Result<(), StoreError> {
+        self.byte_bound_check(an_index)?;
+        self.mutability_check(an_index, a_byte as usize)?;
+        let slot_index = self.compute_slot_index((an_index - 1) / 8 + 1);
+        let shift = ((an_index - 1) % 8) * 8;
+        self.contents[slot_index] =
+            (self.contents[slot_index] & !(0xFF << shift)) | ((a_byte as usize) << shift);
+        Ok(())
+    }
+
     pub fn slots_select_into(
         &self,
         select_function: fn(&SlotContent) -> bool,
@@ -104,15 +333,19 @@ impl<'a> OopSlice<'a> {
 
 #[cfg(test)]
 mod tests {
+    use crate::header_format_values::HeaderFormatValues;
     use crate::memory_space::MemorySpace;
     use crate::oop_builder::OopBuilder;
     use crate::oop_projections::oop_common::OopCommonState;
-    use crate::oop_projections::oop_slice::OopSlice;
+    use crate::oop_projections::oop_slice::{
+        ByteIndexOutOfBounds, ModificationForbidden, OopSlice, SlotIndexOutOfBounds, StoreError,
+    };
 
     #[test]
     fn become_free_oop_is_free_oop() {
         let mut space = MemorySpace::for_bit_size(240);
-        let builder = OopBuilder::new();
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(1);
         let oop_index = builder.build(&mut space);
         let mut new_object = space.get_oop_at(oop_index);
 
@@ -120,6 +353,19 @@ mod tests {
         assert!(new_object.is_free_oop());
     }
 
+    #[test]
+    fn test_freed_object_without_slots_is_a_filler() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let builder = OopBuilder::new();
+        let oop_index = builder.build(&mut space);
+        let mut new_object = space.get_oop_at(oop_index);
+
+        new_object.become_free_oop();
+        assert!(new_object.get_header().is_filler());
+        assert!(!new_object.is_free_oop());
+        assert_eq!(new_object.oop_size(), 1);
+    }
+
     #[test]
     fn test_slot_at_index_returns_value() {
         let mut space = MemorySpace::for_bit_size(240);
@@ -147,6 +393,157 @@ mod tests {
         assert_eq!(oop.slot_at_index(slot_index), slot_value);
     }
 
+    #[test]
+    fn test_byte_at_index_put_sets_value() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(2);
+        builder.set_format(HeaderFormatValues::I8BitIndexable as usize);
+        builder.build(&mut space);
+        let mut oop: OopSlice = space.first_oop();
+        oop.byte_at_index_put(9, 42);
+        oop.byte_at_index_put(10, 43);
+
+        assert_eq!(oop.byte_at_index(9), 42);
+        assert_eq!(oop.byte_at_index(10), 43);
+        assert_eq!(oop.slot_at_index(2) & 0xFFFF, 43 << 8 | 42);
+    }
+
+    #[test]
+    fn test_number_of_bytes_excludes_unused_bytes() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(2);
+        builder.set_format(HeaderFormatValues::I8BitIndexable as usize + 3);
+        builder.build(&mut space);
+
+        assert_eq!(space.first_oop().number_of_bytes(), 13);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_byte_at_index_out_of_bound() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(1);
+        builder.set_format(HeaderFormatValues::I8BitIndexable as usize + 1);
+        builder.build(&mut space);
+
+        space.first_oop().byte_at_index(8);
+    }
+
+    #[test]
+    fn test_try_slot_at_index_out_of_bound_is_an_error() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(2);
+        let oop_index = builder.build(&mut space);
+        let mut oop: OopSlice = space.get_oop_at(oop_index);
+        let error = SlotIndexOutOfBounds {
+            index: 3,
+            object_index: oop_index,
+            number_of_slots: 2,
+        };
+
+        assert_eq!(oop.try_slot_at_index(3), Err(error.clone()));
+        assert_eq!(oop.try_slot_at_index_put(3, 42), Err(error.into()));
+        assert!(oop.try_slot_at_index(0).is_err());
+        assert_eq!(oop.try_slot_at_index_put(2, 42), Ok(()));
+        assert_eq!(oop.try_slot_at_index(2), Ok(42));
+    }
+
+    #[test]
+    fn test_try_byte_at_index_out_of_bound_is_an_error() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(1);
+        builder.set_format(HeaderFormatValues::I8BitIndexable as usize + 1);
+        let oop_index = builder.build(&mut space);
+        let mut oop: OopSlice = space.get_oop_at(oop_index);
+        let error = ByteIndexOutOfBounds {
+            index: 8,
+            object_index: oop_index,
+            number_of_bytes: 7,
+        };
+
+        assert_eq!(oop.try_byte_at_index(8), Err(error.clone()));
+        assert_eq!(oop.try_byte_at_index_put(8, 42), Err(error.into()));
+        assert!(oop.try_byte_at_index(0).is_err());
+        assert_eq!(oop.try_byte_at_index_put(7, 42), Ok(()));
+        assert_eq!(oop.try_byte_at_index(7), Ok(42));
+    }
+
+    #[test]
+    fn test_immutable_objects_refuse_the_stores() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(2);
+        builder.set_slots_value(7);
+        let oop_index = builder.build(&mut space);
+        let mut oop: OopSlice = space.get_oop_at(oop_index);
+        oop.get_header_mut().set_immutable_bit();
+        oop.apply_header();
+
+        assert_eq!(
+            oop.try_slot_at_index_put(2, 42),
+            Err(StoreError::ModificationForbidden(ModificationForbidden {
+                object_index: oop_index,
+                index: 2,
+                value: 42
+            }))
+        );
+        assert_eq!(
+            oop.try_byte_at_index_put(3, 42),
+            Err(StoreError::ModificationForbidden(ModificationForbidden {
+                object_index: oop_index,
+                index: 3,
+                value: 42
+            }))
+        );
+        assert_eq!(oop.slot_at_index(2), 7);
+        oop.slot_at_index_put_ignoring_immutability(2, 42);
+        assert_eq!(oop.slot_at_index(2), 42);
+    }
+
+    #[test]
+    #[should_panic(expected = "modification forbidden")]
+    fn test_slot_at_index_put_into_an_immutable_object_panics() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(1);
+        builder.build(&mut space);
+        let mut oop: OopSlice = space.first_oop();
+        oop.get_header_mut().set_immutable_bit();
+        oop.apply_header();
+
+        oop.slot_at_index_put(1, 42);
+    }
+
+    #[test]
+    #[should_panic(expected = "slot access was out of bound")]
+    fn test_slot_at_index_out_of_bound() {
+        let mut space = MemorySpace::for_bit_size(240);
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(1);
+        builder.build(&mut space);
+
+        space.first_oop().slot_at_index(2);
+    }
+
+    #[test]
+    fn test_slot_at_index_unchecked_reads_the_big_oop_slots() {
+        let mut space = MemorySpace::for_bit_size(1000);
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(300);
+        builder.build(&mut space);
+        let mut oop: OopSlice = space.first_oop();
+        unsafe {
+            oop.slot_at_index_put_unchecked(300, 42);
+            assert_eq!(oop.slot_at_index_unchecked(300), 42);
+        }
+        assert_eq!(oop.slot_at_index(300), 42);
+    }
+
     #[test]
     fn test_big_oop_slot_at_index_returns_value() {
         let mut space = MemorySpace::for_bit_size(1000);
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use crate::forwarding::{self, BecomeError};
+use crate::immutability;
+use crate::interpreter::Interpreter;
+use crate::memory_space::MemorySpace;
+use crate::oop_builder::OopBuilder;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::slot_content::SlotContent;
+
+// Pinned objects keep their address, the native code may hold on to it.
+// They live in the segments no collector moves: the pinned segments and the large object ones.
+// Pinning an object of the moving space copies it to a pinned segment, the original forwards
+// to the copy as with a become. A pinned object never takes another identity by moving.
+
+pub fn is_pinned(space: &mut MemorySpace, oop: usize) -> bool {
+    SlotContent::new(oop).is_slot_oop() && space.get_oop_at(oop).get_header().is_pinned()
+}
+
+pub fn set_pinned_bit_of(space: &mut MemorySpace, oop: usize, pinned: bool) {
+    let mut an_oop = space.get_oop_at(oop);
+    if pinned {
+        an_oop.get_header_mut().set_pinned_bit();
+    } else {
+        an_oop.get_header_mut().unset_pinned_bit();
+    }
+    an_oop.apply_header();
+}
+
+// A pinned object with the headers and the slots of the original,
+// None when the space cannot grow for it
+fn pinned_copy_of(space: &mut MemorySpace, oop: usize) -> Option<usize> {
+    let size = space.get_oop_at(oop).oop_size();
+    let mut builder = OopBuilder::new();
+    builder.set_number_of_slots(space.get_oop_at(oop).number_of_slots());
+    builder.set_pinned(true);
+    let copy = builder.try_build(space)?;
+    for offset in 0..size {
+        space[copy + offset] = space[oop + offset];
+    }
+    set_pinned_bit_of(space, copy, true);
+    Some(copy)
+}
+
+// Answers the pinned object: the object itself when it already is where nothing moves,
+// otherwise a copy in a pinned segment that took its identity
+pub fn pin(interpreter: &mut Interpreter, oop: usize) -> Result<usize, BecomeError> {
+    if SlotContent::new(oop).is_slot_immediate() {
+        return Err(BecomeError::Immediate(oop));
+    }
+    let oop = forwarding::follow_forwarded(&mut interpreter.space, oop);
+    if !interpreter.space.is_in_moving_space(oop) {
+        set_pinned_bit_of(&mut interpreter.space, oop, true);
+        return Ok(oop);
+    }
+
+    // The copy keeps the immutability, the original has to lose it to become a forwarder
+    let copy = pinned_copy_of(&mut interpreter.space, oop).ok_or(BecomeError::OutOfMemory(oop))?;
+    let immutable = immutability::is_immutable(&mut interpreter.space, oop);
+    immutability::set_immutable_bit_of(&mut interpreter.space, oop, false);
+    if interpreter.space.get_oop_at(oop).number_of_slots() == 0 {
+        forwarding::replace_references(interpreter, &HashMap::from([(oop, copy)]));
+    } else if let Err(error) = forwarding::forward_identities(interpreter, &[oop], &[copy]) {
+        immutability::set_immutable_bit_of(&mut interpreter.space, oop, immutable);
+        return Err(error);
+    }
+    Ok(copy)
+}
+
+// The object stays where it is, it may move again
+pub fn unpin(interpreter: &mut Interpreter, oop: usize) {
+    if SlotContent::new(oop).is_slot_oop() {
+        let oop = forwarding::follow_forwarded(&mut interpreter.space, oop);
+        set_pinned_bit_of(&mut interpreter.space, oop, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::bootstrap;
+    use crate::compiler::evaluate;
+    use crate::forwarding::{exchange_identities, forward_identities, BecomeError};
+    use crate::heap_verifier::verify_heap;
+    use crate::interpreter::interpreter_test_support::new_array;
+    use crate::interpreter::Interpreter;
+    use crate::memory_space::SegmentKind;
+    use crate::pinning::{is_pinned, pin, unpin};
+    use crate::slot_content::SlotContent;
+
+    fn segment_kind_of(interpreter: &Interpreter, oop: usize) -> SegmentKind {
+        interpreter.space.segment_containing(oop).unwrap().kind()
+    }
+
+    #[test]
+    fn test_pinning_moves_the_object_where_nothing_moves() {
+        let mut interpreter = bootstrap(40000);
+        let array = new_array(
+            &mut interpreter,
+            &[
+                SlotContent::small_integer(1),
+                SlotContent::small_integer(2),
+                SlotContent::small_integer(3),
+            ],
+        );
+        let holder = new_array(&mut interpreter, &[SlotContent::small_integer(1)]);
+        interpreter
+            .space
+            .get_oop_at(holder)
+            .slot_at_index_put(1, array);
+        let hash = interpreter.hash_of(array);
+
+        let pinned = pin(&mut interpreter, array).unwrap();
+
+        assert_ne!(pinned, array);
+        assert_eq!(segment_kind_of(&interpreter, pinned), SegmentKind::Pinned);
+        assert!(is_pinned(&mut interpreter.space, pinned));
+        assert_eq!(interpreter.fetch_pointer(holder, 1), pinned);
+        assert_eq!(interpreter.hash_of(pinned), hash);
+        assert_eq!(
+            interpreter.space.get_oop_at(pinned).slot_at_index(3),
+            SlotContent::small_integer(3)
+        );
+        assert_eq!(pin(&mut interpreter, pinned), Ok(pinned));
+        interpreter.collect_garbage();
+        assert_eq!(verify_heap(&mut interpreter), vec![]);
+    }
+
+    #[test]
+    fn test_pinned_objects_share_their_segment() {
+        let mut interpreter = bootstrap(40000);
+        let first = new_array(
+            &mut interpreter,
+            &[SlotContent::small_integer(1), SlotContent::small_integer(2)],
+        );
+        let second = new_array(&mut interpreter, &[]);
+        let first = pin(&mut interpreter, first).unwrap();
+        let second = pin(&mut interpreter, second).unwrap();
+
+        assert_eq!(
+            interpreter.space.segment_containing(first),
+            interpreter.space.segment_containing(second)
+        );
+        assert_eq!(verify_heap(&mut interpreter), vec![]);
+    }
+
+    #[test]
+    fn test_large_objects_are_pinned_in_place() {
+        let mut interpreter = bootstrap(40000);
+        let large = new_array(&mut interpreter, &[SlotContent::small_integer(0); 1000]);
+
+        assert_eq!(pin(&mut interpreter, large), Ok(large));
+        assert!(is_pinned(&mut interpreter.space, large));
+        unpin(&mut interpreter, large);
+        assert!(!is_pinned(&mut interpreter.space, large));
+    }
+
+    #[test]
+    fn test_pinned_objects_do_not_move_to_become_another() {
+        let mut interpreter = bootstrap(40000);
+        let array = new_array(
+            &mut interpreter,
+            &[SlotContent::small_integer(1), SlotContent::small_integer(2)],
+        );
+        let pinned = pin(&mut interpreter, array).unwrap();
+        let other = new_array(
+            &mut interpreter,
+            &[
+                SlotContent::small_integer(1),
+                SlotContent::small_integer(2),
+                SlotContent::small_integer(3),
+            ],
+        );
+        let same_size = new_array(
+            &mut interpreter,
+            &[SlotContent::small_integer(1), SlotContent::small_integer(2)],
+        );
+
+        assert_eq!(
+            forward_identities(&mut interpreter, &[pinned], &[other]),
+            Err(BecomeError::Pinned(pinned))
+        );
+        assert_eq!(
+            exchange_identities(&mut interpreter, &[other], &[pinned]),
+            Err(BecomeError::Pinned(pinned))
+        );
+        assert_eq!(
+            pin(&mut interpreter, SlotContent::small_integer(3)),
+            Err(BecomeError::Immediate(SlotContent::small_integer(3)))
+        );
+
+        // Swapping the contents leaves the pinned address pinned
+        exchange_identities(&mut interpreter, &[pinned], &[same_size]).unwrap();
+        assert!(is_pinned(&mut interpreter.space, pinned));
+        assert!(!is_pinned(&mut interpreter.space, same_size));
+        assert_eq!(verify_heap(&mut interpreter), vec![]);
+    }
+
+    #[test]
+    fn test_pin_from_the_kernel() {
+        let mut interpreter = bootstrap(40000);
+        let result = evaluate(
+            &mut interpreter,
+            "| array result | array := Array new: 2. array at: 1 put: 7. \
+             result := Array new: 3. result at: 1 put: array isPinned. array pin. \
+             result at: 2 put: array isPinned. result at: 3 put: (array at: 1). ^result",
+        )
+        .unwrap();
+
+        let true_object = interpreter.true_object();
+        let false_object = interpreter.false_object();
+        assert_eq!(interpreter.fetch_pointer(result, 1), false_object);
+        assert_eq!(interpreter.fetch_pointer(result, 2), true_object);
+        assert_eq!(
+            interpreter.fetch_pointer(result, 3),
+            SlotContent::small_integer(7)
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use crate::immutability;
+use crate::interpreter::Interpreter;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::oop_projections::oop_slice::{ByteIndexOutOfBounds, SlotIndexOutOfBounds, StoreError};
+use crate::primitives::PrimitiveResult;
+use crate::slot_content::SlotContent;
+
+// A named primitive gets the receiver and its arguments through the proxy.
+// Same contract as the numbered primitives: replace them with the result, or fail untouched.
+pub type PluginPrimitive = fn(&mut InterpreterProxy, usize) -> PrimitiveResult;
+
+// A module of named primitives, registered in the interpreter at startup.
+pub trait PrimitivePlugin {
+    fn module_name(&self) -> &str;
+    fn primitive_named(&self, function_name: &str) -> Option<PluginPrimitive>;
+}
+
+// The only view plugins have on the VM.
+// Keep it stable: plugins should not have to change when the interpreter does.
+pub struct InterpreterProxy<'a> {
+    interpreter: &'a mut Interpreter,
+}
+
+impl<'a> InterpreterProxy<'a> {
+    pub fn new(interpreter: &'a mut Interpreter) -> Self {
+        Self { interpreter }
+    }
+
+    // Stack. The offsets past the stack of the primitive answer None
+    pub fn stack_value(&self, offset: usize) -> Option<usize> {
+        if offset >= self.interpreter.stack_zone.stack_pointer() {
+            return None;
+        }
+        Some(self.interpreter.stack_value(offset))
+    }
+
+    pub fn pop_then_push(&mut self, count: usize, value: usize) {
+        self.interpreter.pop_then_push(count, value);
+    }
+
+    // Immediates
+    pub fn integer_value_of(&self, oop: usize) -> Option<isize> {
+        let content = SlotContent::new(oop);
+        if content.is_small_integer() {
+            Some(content.as_small_integer())
+        } else {
+            None
+        }
+    }
+
+    pub fn integer_object_of(&self, value: isize) -> Option<usize> {
+        if SlotContent::is_small_integer_value(value) {
+            Some(SlotContent::small_integer(value))
+        } else {
+            None
+        }
+    }
+
+    // Special objects
+    pub fn nil_object(&mut self) -> usize {
+        self.interpreter.nil_object()
+    }
+
+    pub fn true_object(&mut self) -> usize {
+        self.interpreter.true_object()
+    }
+
+    pub fn false_object(&mut self) -> usize {
+        self.interpreter.false_object()
+    }
+
+    // Objects. The accesses to an immediate, or to an oop outside of the space, answer None.
+    // The SmallIntegers and the Characters have a class all the same.
+    pub fn class_of(&mut self, oop: usize) -> Option<usize> {
+        let content = SlotContent::new(oop);
+        if !content.is_small_integer() && !content.is_character() && !self.is_object(oop) {
+            return None;
+        }
+        Some(self.interpreter.class_of(oop))
+    }
+
+    pub fn instantiate_class(&mut self, class: usize, indexable_size: usize) -> Option<usize> {
+        self.interpreter.instantiate_class(class, indexable_size)
+    }
+
+    pub fn is_immediate(&self, oop: usize) -> bool {
+        SlotContent::new(oop).is_slot_immediate()
+    }
+
+    // Neither an immediate nor an oop outside of the space
+    fn is_object(&self, oop: usize) -> bool {
+        !self.is_immediate(oop) && self.interpreter.space.segment_containing(oop).is_some()
+    }
+
+    // The oops are word indexes, native code gets the machine address of the object
+    pub fn address_of(&self, oop: usize) -> Option<*const usize> {
+        self.is_object(oop)
+            .then(|| self.interpreter.space.address_of(oop))
+    }
+
+    pub fn oop_at_address(&self, address: *const usize) -> Option<usize> {
+        self.interpreter.space.index_at_address(address)
+    }
+
+    pub fn is_bytes(&mut self, oop: usize) -> bool {
+        self.is_object(oop)
+            && !self
+                .interpreter
+                .space
+                .get_oop_at(oop)
+                .get_header()
+                .contains_pointers()
+    }
+
+    pub fn slot_size_of(&mut self, oop: usize) -> Option<usize> {
+        if !self.is_object(oop) {
+            return None;
+        }
+        Some(self.interpreter.space.get_oop_at(oop).number_of_slots())
+    }
+
+    // Slots are 1 based. The accesses outside of the object answer None
+    pub fn fetch_slot(&mut self, oop: usize, index: usize) -> Option<usize> {
+        if !self.is_object(oop) {
+            return None;
+        }
+        self.interpreter
+            .space
+            .get_oop_at(oop)
+            .try_slot_at_index(index)
+            .ok()
+    }
+
+    pub fn is_immutable(&mut self, oop: usize) -> bool {
+        self.is_object(oop) && immutability::is_immutable(&mut self.interpreter.space, oop)
+    }
+
+    // The stores into immutable objects are refused, the non objects have no slots to store into
+    pub fn store_slot(&mut self, oop: usize, index: usize, value: usize) -> Result<(), StoreError> {
+        if !self.is_object(oop) {
+            return Err(StoreError::OutOfBounds(SlotIndexOutOfBounds {
+                index,
+                object_index: oop,
+                number_of_slots: 0,
+            }));
+        }
+        self.interpreter
+            .space
+            .get_oop_at(oop)
+            .try_slot_at_index_put(index, value)
+    }
+
+    pub fn byte_size_of(&mut self, oop: usize) -> Option<usize> {
+        if !self.is_object(oop) {
+            return None;
+        }
+        Some(self.interpreter.space.get_oop_at(oop).number_of_bytes())
+    }
+
+    pub fn byte_at(&mut self, oop: usize, index: usize) -> Option<u8> {
+        if !self.is_object(oop) {
+            return None;
+        }
+        self.interpreter
+            .space
+            .get_oop_at(oop)
+            .try_byte_at_index(index)
+            .ok()
+    }
+
+    pub fn byte_at_put(&mut self, oop: usize, index: usize, value: u8) -> Result<(), StoreError> {
+        if !self.is_object(oop) {
+            return Err(StoreError::ByteOutOfBounds(ByteIndexOutOfBounds {
+                index,
+                object_index: oop,
+                number_of_bytes: 0,
+            }));
+        }
+        self.interpreter
+            .space
+            .get_oop_at(oop)
+            .try_byte_at_index_put(index, value)
+    }
+}
+
+// A named primitive resolved so far, with the names it was resolved from
+struct ExternalPrimitive {
+    module_name: String,
+    function_name: String,
+    primitive: PluginPrimitive,
+}
+
+// The modules, and the named primitives resolved so far.
+// Methods cache the index of their primitive in this table.
+#[derive(Default)]
+pub struct PluginRegistry {
+    plugins: Vec<Box<dyn PrimitivePlugin>>,
+    external_primitives: Vec<ExternalPrimitive>,
+}
+
+impl PluginRegistry {
+    pub fn new() -> Self {
+        Self {
+            plugins: Vec::new(),
+            external_primitives: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, plugin: Box<dyn PrimitivePlugin>) {
+        if self.plugin_named(plugin.module_name()).is_some() {
+            panic!(
+                "A plugin named {} is already registered",
+                plugin.module_name()
+            )
+        }
+        self.plugins.push(plugin);
+    }
+
+    pub fn plugin_named(&self, module_name: &str) -> Option<&dyn PrimitivePlugin> {
+        self.plugins
+            .iter()
+            .find(|plugin| plugin.module_name() == module_name)
+            .map(|plugin| plugin.as_ref())
+    }
+
+    // Answers the index of the primitive in the external table, None if it does not exist.
+    // A primitive resolved again keeps its index.
+    pub fn resolve(&mut self, module_name: &str, function_name: &str) -> Option<usize> {
+        let known = self.external_primitives.iter().position(|external| {
+            external.module_name == module_name && external.function_name == function_name
+        });
+        if known.is_some() {
+            return known;
+        }
+        let primitive = self
+            .plugin_named(module_name)?
+            .primitive_named(function_name)?;
+        self.external_primitives.push(ExternalPrimitive {
+            module_name: module_name.to_string(),
+            function_name: function_name.to_string(),
+            primitive,
+        });
+        Some(self.external_primitives.len() - 1)
+    }
+
+    pub fn external_primitive_at(&self, index: usize) -> Option<PluginPrimitive> {
+        self.external_primitives
+            .get(index)
+            .map(|external| external.primitive)
+    }
+}
+
+#[cfg(test)]
+pub mod plugin_test_support {
+    use crate::primitive_plugin::{InterpreterProxy, PluginPrimitive, PrimitivePlugin};
+    use crate::primitives::PrimitiveResult;
+
+    // Doubles a SmallInteger receiver
+    pub struct TestPlugin;
+
+    fn primitive_double(proxy: &mut InterpreterProxy, argument_count: usize) -> PrimitiveResult {
+        let doubled = proxy
+            .stack_value(0)
+            .and_then(|receiver| proxy.integer_value_of(receiver))
+            .and_then(|value| value.checked_mul(2))
+            .and_then(|value| proxy.integer_object_of(value));
+        match doubled {
+            Some(doubled) if argument_count == 0 => {
+                proxy.pop_then_push(1, doubled);
+                PrimitiveResult::Success
+            }
+            _ => PrimitiveResult::Failure,
+        }
+    }
+
+    impl PrimitivePlugin for TestPlugin {
+        fn module_name(&self) -> &str {
+            "TestPlugin"
+        }
+
+        fn primitive_named(&self, function_name: &str) -> Option<PluginPrimitive> {
+            match function_name {
+                "primitiveDouble" => Some(primitive_double),
+                _ => None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::header_format_values::HeaderFormatValues;
+    use crate::immutability;
+    use crate::interpreter::interpreter_test_support::{new_class, new_interpreter};
+    use crate::oop_projections::oop_slice::StoreError;
+    use crate::primitive_plugin::plugin_test_support::TestPlugin;
+    use crate::primitive_plugin::{InterpreterProxy, PluginRegistry};
+    use crate::slot_content::SlotContent;
+
+    #[test]
+    fn test_proxy_accesses_fail_instead_of_panicking() {
+        let mut interpreter = new_interpreter();
+        let array_class = new_class(
+            &mut interpreter,
+            HeaderFormatValues::IndexableWithoutSlotsFormat as usize,
+            0,
+        );
+        let array = interpreter.instantiate_class(array_class, 2).unwrap();
+        let bytes_class = new_class(
+            &mut interpreter,
+            HeaderFormatValues::I8BitIndexable as usize,
+            0,
+        );
+        let bytes = interpreter.instantiate_class(bytes_class, 3).unwrap();
+        let three = SlotContent::small_integer(3);
+        let mut proxy = InterpreterProxy::new(&mut interpreter);
+
+        assert_eq!(proxy.store_slot(array, 2, three), Ok(()));
+        assert_eq!(proxy.fetch_slot(array, 2), Some(three));
+        assert_eq!(proxy.fetch_slot(array, 3), None);
+        assert!(matches!(
+            proxy.store_slot(array, 3, three),
+            Err(StoreError::OutOfBounds(_))
+        ));
+        assert!(proxy.store_slot(three, 1, three).is_err());
+        assert_eq!(proxy.fetch_slot(three, 1), None);
+        assert_eq!(proxy.byte_at_put(bytes, 3, 42), Ok(()));
+        assert_eq!(proxy.byte_at(bytes, 3), Some(42));
+        assert_eq!(proxy.byte_at(bytes, 4), None);
+        assert!(matches!(
+            proxy.byte_at_put(bytes, 4, 42),
+            Err(StoreError::ByteOutOfBounds(_))
+        ));
+
+        immutability::set_immutable_bit_of(&mut interpreter.space, array, true);
+        immutability::set_immutable_bit_of(&mut interpreter.space, bytes, true);
+        let mut proxy = InterpreterProxy::new(&mut interpreter);
+        assert!(matches!(
+            proxy.store_slot(array, 1, three),
+            Err(StoreError::ModificationForbidden(_))
+        ));
+        assert!(matches!(
+            proxy.byte_at_put(bytes, 1, 42),
+            Err(StoreError::ModificationForbidden(_))
+        ));
+        let nil = proxy.nil_object();
+        assert_eq!(proxy.fetch_slot(array, 1), Some(nil));
+
+        let outside = interpreter.space.get_end_index() + 1000;
+        interpreter.push(array);
+        let mut proxy = InterpreterProxy::new(&mut interpreter);
+        assert_eq!(proxy.stack_value(0), Some(array));
+        assert_eq!(proxy.stack_value(1), None);
+        assert_eq!(proxy.slot_size_of(array), Some(2));
+        assert_eq!(proxy.slot_size_of(three), None);
+        assert_eq!(proxy.slot_size_of(outside), None);
+        assert_eq!(proxy.byte_size_of(bytes), Some(3));
+        assert_eq!(proxy.byte_size_of(three), None);
+        assert_eq!(proxy.byte_size_of(outside), None);
+        assert_eq!(proxy.class_of(array), Some(array_class));
+        assert!(proxy.class_of(three).is_some());
+        assert_eq!(proxy.class_of(outside), None);
+        assert_eq!(proxy.fetch_slot(outside, 1), None);
+        assert!(proxy.store_slot(outside, 1, three).is_err());
+        assert_eq!(proxy.byte_at(outside, 1), None);
+        assert!(!proxy.is_bytes(outside));
+        assert!(!proxy.is_immutable(outside));
+        let address = proxy.address_of(array).unwrap();
+        assert_eq!(proxy.oop_at_address(address), Some(array));
+        assert_eq!(proxy.address_of(three), None);
+        assert_eq!(proxy.address_of(outside), None);
+    }
+
+    #[test]
+    fn test_resolve_known_primitive() {
+        let mut registry = PluginRegistry::new();
+        registry.register(Box::new(TestPlugin));
+
+        let index = registry.resolve("TestPlugin", "primitiveDouble").unwrap();
+        assert!(registry.external_primitive_at(index).is_some());
+    }
+
+    #[test]
+    fn test_resolving_again_reuses_the_index() {
+        let mut registry = PluginRegistry::new();
+        registry.register(Box::new(TestPlugin));
+
+        let index = registry.resolve("TestPlugin", "primitiveDouble").unwrap();
+        assert_eq!(
+            registry.resolve("TestPlugin", "primitiveDouble"),
+            Some(index)
+        );
+        assert_eq!(registry.external_primitives.len(), 1);
+    }
+
+    #[test]
+    fn test_resolve_unknown_function() {
+        let mut registry = PluginRegistry::new();
+        registry.register(Box::new(TestPlugin));
+
+        assert_eq!(registry.resolve("TestPlugin", "primitiveTriple"), None);
+    }
+
+    #[test]
+    fn test_resolve_unknown_module() {
+        let mut registry = PluginRegistry::new();
+        assert_eq!(registry.resolve("MissingPlugin", "primitiveDouble"), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_register_twice_panics() {
+        let mut registry = PluginRegistry::new();
+        registry.register(Box::new(TestPlugin));
+        registry.register(Box::new(TestPlugin));
+    }
+}
@@ -0,0 +1,164 @@
+pub mod arithmetic_primitives;
+pub mod become_primitives;
+pub mod block_closure_primitives;
+pub mod context_primitives;
+pub mod external_primitives;
+pub mod object_primitives;
+
+use crate::interpreter::Interpreter;
+use crate::primitives::arithmetic_primitives::*;
+use crate::primitives::become_primitives::*;
+use crate::primitives::block_closure_primitives::*;
+use crate::primitives::context_primitives::*;
+use crate::primitives::external_primitives::*;
+use crate::primitives::object_primitives::*;
+
+#[derive(Debug, PartialEq)]
+pub enum PrimitiveResult {
+    Success,
+    Failure,
+}
+
+// A primitive finds the receiver and its arguments on the stack.
+// On success, it replaces them with its result. On failure, it leaves the stack untouched.
+pub type PrimitiveFunction = fn(&mut Interpreter, usize) -> PrimitiveResult;
+
+// Primitive indexes follow the Squeak numbering
+pub struct PrimitiveTable {
+    primitives: Vec<Option<PrimitiveFunction>>,
+}
+
+impl PrimitiveTable {
+    pub const MAX_PRIMITIVE_INDEX: usize = 1023;
+
+    pub fn new() -> Self {
+        let mut table = Self {
+            primitives: vec![None; PrimitiveTable::MAX_PRIMITIVE_INDEX + 1],
+        };
+        table.register_arithmetic_primitives();
+        table.register_object_primitives();
+        table.register_become_primitives();
+        table.register_block_closure_primitives();
+        table.register_context_primitives();
+        table.register_external_primitives();
+        table
+    }
+
+    pub fn register(&mut self, index: usize, primitive: PrimitiveFunction) {
+        if index == 0 || index > PrimitiveTable::MAX_PRIMITIVE_INDEX {
+            panic!("Primitive index {} is out of the table", index)
+        }
+        self.primitives[index] = Some(primitive);
+    }
+
+    pub fn primitive_at(&self, index: usize) -> Option<PrimitiveFunction> {
+        self.primitives.get(index).copied().flatten()
+    }
+
+    fn register_arithmetic_primitives(&mut self) {
+        self.register(1, primitive_add);
+        self.register(2, primitive_subtract);
+        self.register(3, primitive_less_than);
+        self.register(4, primitive_greater_than);
+        self.register(5, primitive_less_or_equal);
+        self.register(6, primitive_greater_or_equal);
+        self.register(7, primitive_equal);
+        self.register(8, primitive_not_equal);
+        self.register(9, primitive_multiply);
+        self.register(10, primitive_divide);
+        self.register(11, primitive_modulo);
+        self.register(12, primitive_div);
+        self.register(13, primitive_quo);
+        self.register(14, primitive_bit_and);
+        self.register(15, primitive_bit_or);
+        self.register(16, primitive_bit_xor);
+        self.register(17, primitive_bit_shift);
+    }
+
+    fn register_object_primitives(&mut self) {
+        self.register(60, primitive_at);
+        self.register(61, primitive_at_put);
+        self.register(62, primitive_size);
+        self.register(70, primitive_basic_new);
+        self.register(71, primitive_basic_new_with_size);
+        self.register(75, primitive_identity_hash);
+        self.register(110, primitive_identical);
+        self.register(111, primitive_class);
+        self.register(163, primitive_is_read_only);
+        self.register(164, primitive_set_read_only);
+        self.register(171, primitive_immediate_as_integer);
+        self.register(173, primitive_inst_var_at);
+        self.register(174, primitive_inst_var_at_put);
+        self.register(177, primitive_all_instances);
+        self.register(183, primitive_is_pinned);
+        self.register(184, primitive_set_pinned);
+    }
+
+    fn register_become_primitives(&mut self) {
+        self.register(72, primitive_array_become_one_way);
+        self.register(128, primitive_array_become);
+    }
+
+    fn register_block_closure_primitives(&mut self) {
+        for index in 201..=205 {
+            self.register(index, primitive_closure_value);
+        }
+        self.register(206, primitive_closure_value_with_arguments);
+    }
+
+    // 198 and 199 are markers, they are left out to always fail.
+    // 213 to 215 are not in Squeak, which restarts, jumps and reports errors from the image.
+    fn register_context_primitives(&mut self) {
+        self.register(195, primitive_find_next_unwind_context);
+        self.register(196, primitive_terminate_to);
+        self.register(197, primitive_find_next_handler_context);
+        self.register(210, primitive_context_at);
+        self.register(211, primitive_context_at_put);
+        self.register(212, primitive_context_size);
+        self.register(213, primitive_restart);
+        self.register(214, primitive_unhandled_exception);
+        self.register(215, primitive_jump);
+    }
+
+    // Named primitives all go through the same index
+    fn register_external_primitives(&mut self) {
+        self.register(117, primitive_external_call);
+    }
+}
+
+impl Default for PrimitiveTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+    use crate::primitives::{PrimitiveResult, PrimitiveTable};
+
+    fn primitive_fail(_interpreter: &mut Interpreter, _argument_count: usize) -> PrimitiveResult {
+        PrimitiveResult::Failure
+    }
+
+    #[test]
+    fn test_no_primitive_at_zero() {
+        let table = PrimitiveTable::new();
+        assert!(table.primitive_at(0).is_none());
+    }
+
+    #[test]
+    fn test_unknown_primitive_index() {
+        let table = PrimitiveTable::new();
+        assert!(table
+            .primitive_at(PrimitiveTable::MAX_PRIMITIVE_INDEX + 1)
+            .is_none());
+    }
+
+    #[test]
+    fn test_register_primitive() {
+        let mut table = PrimitiveTable::new();
+        table.register(500, primitive_fail);
+        assert!(table.primitive_at(500).is_some());
+    }
+}
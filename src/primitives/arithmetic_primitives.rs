@@ -0,0 +1,369 @@
+use crate::interpreter::Interpreter;
+use crate::primitives::PrimitiveResult;
+use crate::slot_content::SlotContent;
+
+// The receiver and the argument, when both are SmallIntegers
+fn small_integer_operands(
+    interpreter: &Interpreter,
+    argument_count: usize,
+) -> Option<(isize, isize)> {
+    if argument_count != 1 {
+        return None;
+    }
+    let receiver = SlotContent::new(interpreter.stack_value(1));
+    let argument = SlotContent::new(interpreter.stack_value(0));
+    if receiver.is_small_integer() && argument.is_small_integer() {
+        Some((receiver.as_small_integer(), argument.as_small_integer()))
+    } else {
+        None
+    }
+}
+
+// Overflowing results fail, the fallback code handles large integers
+fn arithmetic_primitive(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+    operation: fn(isize, isize) -> Option<isize>,
+) -> PrimitiveResult {
+    let result = small_integer_operands(interpreter, argument_count)
+        .and_then(|(receiver, argument)| operation(receiver, argument))
+        .filter(|result| SlotContent::is_small_integer_value(*result));
+    match result {
+        Some(result) => {
+            interpreter.pop_then_push(2, SlotContent::small_integer(result));
+            PrimitiveResult::Success
+        }
+        None => PrimitiveResult::Failure,
+    }
+}
+
+fn comparison_primitive(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+    comparison: fn(isize, isize) -> bool,
+) -> PrimitiveResult {
+    match small_integer_operands(interpreter, argument_count) {
+        Some((receiver, argument)) => {
+            let result = interpreter.boolean_object(comparison(receiver, argument));
+            interpreter.pop_then_push(2, result);
+            PrimitiveResult::Success
+        }
+        None => PrimitiveResult::Failure,
+    }
+}
+
+pub fn primitive_add(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    arithmetic_primitive(interpreter, argument_count, isize::checked_add)
+}
+
+pub fn primitive_subtract(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    arithmetic_primitive(interpreter, argument_count, isize::checked_sub)
+}
+
+pub fn primitive_multiply(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    arithmetic_primitive(interpreter, argument_count, isize::checked_mul)
+}
+
+// Only exact divisions, fractions are left to the fallback code
+pub fn primitive_divide(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    arithmetic_primitive(interpreter, argument_count, |receiver, argument| {
+        if argument != 0 && receiver % argument == 0 {
+            receiver.checked_div(argument)
+        } else {
+            None
+        }
+    })
+}
+
+// \\ rounds toward negative infinity
+pub fn primitive_modulo(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    arithmetic_primitive(interpreter, argument_count, |receiver, argument| {
+        if argument == 0 {
+            return None;
+        }
+        let remainder = receiver % argument;
+        if remainder != 0 && (remainder < 0) != (argument < 0) {
+            Some(remainder + argument)
+        } else {
+            Some(remainder)
+        }
+    })
+}
+
+// // rounds toward negative infinity
+pub fn primitive_div(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    arithmetic_primitive(interpreter, argument_count, |receiver, argument| {
+        if argument == 0 {
+            return None;
+        }
+        let quotient = receiver.checked_div(argument)?;
+        if receiver % argument != 0 && (receiver < 0) != (argument < 0) {
+            Some(quotient - 1)
+        } else {
+            Some(quotient)
+        }
+    })
+}
+
+// quo: truncates toward zero
+pub fn primitive_quo(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    arithmetic_primitive(interpreter, argument_count, isize::checked_div)
+}
+
+pub fn primitive_bit_and(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    arithmetic_primitive(interpreter, argument_count, |receiver, argument| {
+        Some(receiver & argument)
+    })
+}
+
+pub fn primitive_bit_or(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    arithmetic_primitive(interpreter, argument_count, |receiver, argument| {
+        Some(receiver | argument)
+    })
+}
+
+pub fn primitive_bit_xor(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    arithmetic_primitive(interpreter, argument_count, |receiver, argument| {
+        Some(receiver ^ argument)
+    })
+}
+
+// Positive shifts go left, and fail when bits would be lost
+pub fn primitive_bit_shift(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    arithmetic_primitive(interpreter, argument_count, |receiver, argument| {
+        if argument >= 0 {
+            let shift = u32::try_from(argument)
+                .ok()
+                .filter(|shift| *shift < isize::BITS)?;
+            let result = receiver << shift;
+            if result >> shift == receiver {
+                Some(result)
+            } else {
+                None
+            }
+        } else {
+            let shift = argument.unsigned_abs().min(isize::BITS as usize - 1) as u32;
+            Some(receiver >> shift)
+        }
+    })
+}
+
+pub fn primitive_less_than(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    comparison_primitive(interpreter, argument_count, |receiver, argument| {
+        receiver < argument
+    })
+}
+
+pub fn primitive_greater_than(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    comparison_primitive(interpreter, argument_count, |receiver, argument| {
+        receiver > argument
+    })
+}
+
+pub fn primitive_less_or_equal(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    comparison_primitive(interpreter, argument_count, |receiver, argument| {
+        receiver <= argument
+    })
+}
+
+pub fn primitive_greater_or_equal(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    comparison_primitive(interpreter, argument_count, |receiver, argument| {
+        receiver >= argument
+    })
+}
+
+pub fn primitive_equal(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    comparison_primitive(interpreter, argument_count, |receiver, argument| {
+        receiver == argument
+    })
+}
+
+pub fn primitive_not_equal(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    comparison_primitive(interpreter, argument_count, |receiver, argument| {
+        receiver != argument
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::interpreter_test_support::new_interpreter;
+    use crate::primitives::PrimitiveFunction;
+    use crate::slot_content::immediate_constants;
+
+    fn run_primitive(
+        primitive: PrimitiveFunction,
+        receiver: usize,
+        argument: usize,
+    ) -> (PrimitiveResult, Interpreter) {
+        let mut interpreter = new_interpreter();
+        interpreter.push(receiver);
+        interpreter.push(argument);
+        let result = primitive(&mut interpreter, 1);
+        (result, interpreter)
+    }
+
+    fn run_arithmetic(
+        primitive: PrimitiveFunction,
+        receiver: isize,
+        argument: isize,
+    ) -> Option<isize> {
+        let (result, interpreter) = run_primitive(
+            primitive,
+            SlotContent::small_integer(receiver),
+            SlotContent::small_integer(argument),
+        );
+        match result {
+            PrimitiveResult::Success => {
+                Some(SlotContent::new(interpreter.stack_value(0)).as_small_integer())
+            }
+            PrimitiveResult::Failure => None,
+        }
+    }
+
+    #[parameterized(receiver={ 3, -3, 0 }, argument={ 4, 1, -7 }, expected={ 7, -2, -7 })]
+    fn test_add(receiver: isize, argument: isize, expected: isize) {
+        assert_eq!(
+            run_arithmetic(primitive_add, receiver, argument),
+            Some(expected)
+        );
+    }
+
+    #[test]
+    fn test_add_overflow_fails() {
+        assert_eq!(
+            run_arithmetic(primitive_add, immediate_constants::MAX_SMALL_INTEGER, 1),
+            None
+        );
+    }
+
+    #[test]
+    fn test_subtract_overflow_fails() {
+        assert_eq!(
+            run_arithmetic(
+                primitive_subtract,
+                immediate_constants::MIN_SMALL_INTEGER,
+                1
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_multiply_overflow_fails() {
+        assert_eq!(
+            run_arithmetic(
+                primitive_multiply,
+                immediate_constants::MAX_SMALL_INTEGER,
+                2
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_failure_leaves_the_stack_untouched() {
+        let mut interpreter = new_interpreter();
+        let nil = interpreter.nil_object();
+        interpreter.push(SlotContent::small_integer(3));
+        interpreter.push(nil);
+
+        assert_eq!(primitive_add(&mut interpreter, 1), PrimitiveResult::Failure);
+        assert_eq!(interpreter.stack_value(0), nil);
+        assert_eq!(interpreter.stack_value(1), SlotContent::small_integer(3));
+    }
+
+    #[parameterized(receiver={ 12, 7, 3 }, argument={ 4, 2, 0 }, expected={ Some(3), None, None })]
+    fn test_divide(receiver: isize, argument: isize, expected: Option<isize>) {
+        assert_eq!(
+            run_arithmetic(primitive_divide, receiver, argument),
+            expected
+        );
+    }
+
+    #[parameterized(receiver={ 7, -7, 7, -7 }, argument={ 2, 2, -2, -2 }, expected={ 3, -4, -4, 3 })]
+    fn test_div(receiver: isize, argument: isize, expected: isize) {
+        assert_eq!(
+            run_arithmetic(primitive_div, receiver, argument),
+            Some(expected)
+        );
+    }
+
+    #[parameterized(receiver={ 7, -7, 7, -7 }, argument={ 2, 2, -2, -2 }, expected={ 1, 1, -1, -1 })]
+    fn test_modulo(receiver: isize, argument: isize, expected: isize) {
+        assert_eq!(
+            run_arithmetic(primitive_modulo, receiver, argument),
+            Some(expected)
+        );
+    }
+
+    #[parameterized(receiver={ 7, -7 }, argument={ 2, 2 }, expected={ 3, -3 })]
+    fn test_quo(receiver: isize, argument: isize, expected: isize) {
+        assert_eq!(
+            run_arithmetic(primitive_quo, receiver, argument),
+            Some(expected)
+        );
+    }
+
+    #[parameterized(receiver={ 1, 8, 1 }, argument={ 4, -2, 62 }, expected={ Some(16), Some(2), None })]
+    fn test_bit_shift(receiver: isize, argument: isize, expected: Option<isize>) {
+        assert_eq!(
+            run_arithmetic(primitive_bit_shift, receiver, argument),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_bit_operations() {
+        assert_eq!(run_arithmetic(primitive_bit_and, 6, 3), Some(2));
+        assert_eq!(run_arithmetic(primitive_bit_or, 6, 3), Some(7));
+        assert_eq!(run_arithmetic(primitive_bit_xor, 6, 3), Some(5));
+    }
+
+    #[test]
+    fn test_less_than_answers_true() {
+        let (result, mut interpreter) = run_primitive(
+            primitive_less_than,
+            SlotContent::small_integer(3),
+            SlotContent::small_integer(4),
+        );
+        assert_eq!(result, PrimitiveResult::Success);
+        assert_eq!(interpreter.stack_value(0), interpreter.true_object());
+    }
+
+    #[test]
+    fn test_equal_answers_false() {
+        let (result, mut interpreter) = run_primitive(
+            primitive_equal,
+            SlotContent::small_integer(3),
+            SlotContent::small_integer(4),
+        );
+        assert_eq!(result, PrimitiveResult::Success);
+        assert_eq!(interpreter.stack_value(0), interpreter.false_object());
+    }
+
+    #[test]
+    fn test_comparison_with_non_small_integer_fails() {
+        let (result, _) =
+            run_primitive(primitive_greater_or_equal, SlotContent::small_integer(3), 0);
+        assert_eq!(result, PrimitiveResult::Failure);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::forwarding::{self, BecomeError};
+use crate::interpreter::Interpreter;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::primitives::PrimitiveResult;
+use crate::slot_content::SlotContent;
+use crate::special_object_index::SpecialObjectIndexes;
+
+type BecomeFunction = fn(&mut Interpreter, &[usize], &[usize]) -> Result<(), BecomeError>;
+
+// Answers the elements of an Array, None for anything else
+fn elements_of(interpreter: &mut Interpreter, array: usize) -> Option<Vec<usize>> {
+    let array_class = interpreter.special_object(SpecialObjectIndexes::ClassArray);
+    if SlotContent::new(array).is_slot_immediate() || interpreter.class_of(array) != array_class {
+        return None;
+    }
+    let number_of_slots = interpreter.space.get_oop_at(array).number_of_slots();
+    Some(
+        (1..=number_of_slots)
+            .map(|index| interpreter.fetch_pointer(array, index))
+            .collect(),
+    )
+}
+
+fn primitive_become(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+    become_function: BecomeFunction,
+) -> PrimitiveResult {
+    if argument_count != 1 {
+        return PrimitiveResult::Failure;
+    }
+    let receiver = interpreter.stack_value(1);
+    let argument = interpreter.stack_value(0);
+    let (Some(from), Some(to)) = (
+        elements_of(interpreter, receiver),
+        elements_of(interpreter, argument),
+    ) else {
+        return PrimitiveResult::Failure;
+    };
+    if become_function(interpreter, &from, &to).is_err() {
+        return PrimitiveResult::Failure;
+    }
+    // The receiver array itself may have been forwarded
+    let receiver = interpreter.stack_value(1);
+    interpreter.pop_then_push(2, receiver);
+    PrimitiveResult::Success
+}
+
+pub fn primitive_array_become_one_way(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    primitive_become(interpreter, argument_count, forwarding::forward_identities)
+}
+
+pub fn primitive_array_become(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    primitive_become(interpreter, argument_count, forwarding::exchange_identities)
+}
@@ -0,0 +1,67 @@
+use crate::block_closure::block_closure_constants;
+use crate::interpreter::Interpreter;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::primitives::PrimitiveResult;
+use crate::slot_content::SlotContent;
+use crate::special_object_index::SpecialObjectIndexes;
+
+// Answers the number of arguments the closure takes, None when it is not a closure
+fn number_of_arguments_of(interpreter: &mut Interpreter, closure: usize) -> Option<usize> {
+    if SlotContent::new(closure).is_slot_immediate() {
+        return None;
+    }
+    let block_closure_class = interpreter.special_object(SpecialObjectIndexes::ClassBlockClosure);
+    if interpreter.class_of(closure) != block_closure_class {
+        return None;
+    }
+    let number_of_arguments = interpreter
+        .space
+        .get_oop_at(closure)
+        .try_slot_at_index(block_closure_constants::NUMBER_OF_ARGUMENTS_INDEX)
+        .ok()?;
+    Some(SlotContent::new(number_of_arguments).as_small_integer() as usize)
+}
+
+// value, value:, value:value: ... the arguments are already where the frame wants them
+pub fn primitive_closure_value(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    let closure = interpreter.stack_value(argument_count);
+    if number_of_arguments_of(interpreter, closure) != Some(argument_count) {
+        return PrimitiveResult::Failure;
+    }
+    interpreter.activate_closure(closure, argument_count);
+    PrimitiveResult::Success
+}
+
+pub fn primitive_closure_value_with_arguments(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 1 {
+        return PrimitiveResult::Failure;
+    }
+    let closure = interpreter.stack_value(1);
+    let arguments = interpreter.stack_value(0);
+    let array_class = interpreter.special_object(SpecialObjectIndexes::ClassArray);
+    if SlotContent::new(arguments).is_slot_immediate()
+        || interpreter.class_of(arguments) != array_class
+    {
+        return PrimitiveResult::Failure;
+    }
+    let arguments_oop = interpreter.space.get_oop_at(arguments);
+    let values: Vec<usize> = (1..=arguments_oop.number_of_slots())
+        .map(|index| arguments_oop.slot_at_index(index))
+        .collect();
+    if number_of_arguments_of(interpreter, closure) != Some(values.len()) {
+        return PrimitiveResult::Failure;
+    }
+
+    interpreter.pop();
+    for value in &values {
+        interpreter.push(*value);
+    }
+    interpreter.activate_closure(closure, values.len());
+    PrimitiveResult::Success
+}
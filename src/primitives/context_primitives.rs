@@ -0,0 +1,347 @@
+use crate::block_closure::block_closure_constants;
+use crate::interpreter::Interpreter;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::primitives::PrimitiveResult;
+use crate::slot_content::SlotContent;
+use crate::special_class_index::SpecialClassIndexes;
+use crate::stack_zone::context_constants;
+
+// Methods whose activations are found by the exception machinery.
+// The primitives always fail, they only mark the methods.
+pub mod context_primitive_constants {
+    pub const UNWIND_MARKER: usize = 198;
+    pub const HANDLER_MARKER: usize = 199;
+}
+
+fn is_context(interpreter: &mut Interpreter, oop: usize) -> bool {
+    interpreter.class_index_of(oop) == SpecialClassIndexes::Context as usize
+}
+
+fn context_slot_at(interpreter: &mut Interpreter, context: usize, index: usize) -> usize {
+    interpreter
+        .stack_zone
+        .context_slot_at(context, index, &mut interpreter.space)
+}
+
+fn small_integer_value_of(value: usize) -> usize {
+    SlotContent::new(value).as_small_integer() as usize
+}
+
+// Contexts without method stand for the callers outside the interpreter,
+// the searches do not go past them.
+fn is_bottom(interpreter: &mut Interpreter, context: usize) -> bool {
+    let nil = interpreter.nil_object();
+    context == nil || context_slot_at(interpreter, context, context_constants::METHOD_INDEX) == nil
+}
+
+fn is_marked_with(interpreter: &mut Interpreter, context: usize, marker: usize) -> bool {
+    let method = context_slot_at(interpreter, context, context_constants::METHOD_INDEX);
+    interpreter.method_header_of(method).primitive_index() == marker
+}
+
+// findNextUnwindContextUpTo: searches the senders of the receiver, up to the argument excluded
+pub fn primitive_find_next_unwind_context(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 1 || !is_context(interpreter, interpreter.stack_value(1)) {
+        return PrimitiveResult::Failure;
+    }
+    let limit = interpreter.stack_value(0);
+    let receiver = interpreter.stack_value(1);
+    let mut context = context_slot_at(interpreter, receiver, context_constants::SENDER_INDEX);
+    let mut found = interpreter.nil_object();
+    while context != limit && !is_bottom(interpreter, context) {
+        if is_marked_with(
+            interpreter,
+            context,
+            context_primitive_constants::UNWIND_MARKER,
+        ) {
+            found = context;
+            break;
+        }
+        context = context_slot_at(interpreter, context, context_constants::SENDER_INDEX);
+    }
+    interpreter.pop_then_push(2, found);
+    PrimitiveResult::Success
+}
+
+// findNextHandlerContextStarting searches from the receiver included
+pub fn primitive_find_next_handler_context(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 0 || !is_context(interpreter, interpreter.stack_value(0)) {
+        return PrimitiveResult::Failure;
+    }
+    let mut context = interpreter.stack_value(0);
+    let mut found = interpreter.nil_object();
+    while !is_bottom(interpreter, context) {
+        if is_marked_with(
+            interpreter,
+            context,
+            context_primitive_constants::HANDLER_MARKER,
+        ) {
+            found = context;
+            break;
+        }
+        context = context_slot_at(interpreter, context, context_constants::SENDER_INDEX);
+    }
+    interpreter.pop_then_push(1, found);
+    PrimitiveResult::Success
+}
+
+// terminateTo: kills the contexts between the receiver and the argument,
+// which becomes the sender of the receiver. It fails when the argument is not a sender.
+pub fn primitive_terminate_to(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 1 {
+        return PrimitiveResult::Failure;
+    }
+    let previous_context = interpreter.stack_value(0);
+    let receiver = interpreter.stack_value(1);
+    if !is_context(interpreter, receiver) || !is_context(interpreter, previous_context) {
+        return PrimitiveResult::Failure;
+    }
+    let mut killed_contexts = Vec::new();
+    let mut context = context_slot_at(interpreter, receiver, context_constants::SENDER_INDEX);
+    while context != previous_context {
+        if is_bottom(interpreter, context) {
+            return PrimitiveResult::Failure;
+        }
+        killed_contexts.push(context);
+        context = context_slot_at(interpreter, context, context_constants::SENDER_INDEX);
+    }
+
+    // The sender chain is rewired in the heap, then the active context comes back
+    let nil = interpreter.nil_object();
+    let active_context = interpreter
+        .stack_zone
+        .divorce_all_frames(&mut interpreter.space)
+        .unwrap();
+    for context in killed_contexts {
+        let mut context_oop = interpreter.space.get_oop_at(context);
+        context_oop.slot_at_index_put(context_constants::SENDER_INDEX, nil);
+        context_oop.slot_at_index_put(context_constants::PC_INDEX, nil);
+    }
+    interpreter
+        .space
+        .get_oop_at(receiver)
+        .slot_at_index_put(context_constants::SENDER_INDEX, previous_context);
+    interpreter
+        .stack_zone
+        .resume_context(active_context, &mut interpreter.space);
+    interpreter.pop_then_push(2, receiver);
+    PrimitiveResult::Success
+}
+
+// The receiver has to be the sender of the active context, which is abandoned.
+// Answers whether it was, the frames are then divorced.
+fn abandon_active_context_for(interpreter: &mut Interpreter, argument_count: usize) -> bool {
+    if argument_count != 0 || !is_context(interpreter, interpreter.stack_value(0)) {
+        return false;
+    }
+    let receiver = interpreter.stack_value(0);
+    let active_context = interpreter.stack_zone.this_context(&mut interpreter.space);
+    if context_slot_at(interpreter, active_context, context_constants::SENDER_INDEX) != receiver {
+        return false;
+    }
+
+    let nil = interpreter.nil_object();
+    interpreter
+        .stack_zone
+        .divorce_all_frames(&mut interpreter.space);
+    let mut active_context_oop = interpreter.space.get_oop_at(active_context);
+    active_context_oop.slot_at_index_put(context_constants::SENDER_INDEX, nil);
+    active_context_oop.slot_at_index_put(context_constants::PC_INDEX, nil);
+    true
+}
+
+// Runs the receiver again from its start. It has to be the sender of the active context,
+// which is abandoned: nothing is answered.
+pub fn primitive_restart(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    let receiver = interpreter.stack_value(0);
+    if !abandon_active_context_for(interpreter, argument_count) {
+        return PrimitiveResult::Failure;
+    }
+
+    let nil = interpreter.nil_object();
+
+    // Arguments and copied values are kept, temporaries are back to nil
+    let receiver_oop = interpreter.space.get_oop_at(receiver);
+    let method = receiver_oop.slot_at_index(context_constants::METHOD_INDEX);
+    let closure = receiver_oop.slot_at_index(context_constants::CLOSURE_OR_NIL_INDEX);
+    let header = interpreter.method_header_of(method);
+    let number_of_copied_values = if closure == nil {
+        0
+    } else {
+        interpreter.space.get_oop_at(closure).number_of_slots()
+            - block_closure_constants::NUMBER_OF_FIXED_SLOTS
+    };
+    let first_temporary = header.number_of_arguments() + number_of_copied_values + 1;
+    let mut receiver_oop = interpreter.space.get_oop_at(receiver);
+    for index in first_temporary..=header.number_of_temporaries() {
+        receiver_oop.slot_at_index_put(context_constants::NUMBER_OF_FIXED_SLOTS + index, nil);
+    }
+    receiver_oop.slot_at_index_put(
+        context_constants::PC_INDEX,
+        SlotContent::small_integer(header.initial_pc() as isize),
+    );
+    receiver_oop.slot_at_index_put(
+        context_constants::STACKP_INDEX,
+        SlotContent::small_integer(header.number_of_temporaries() as isize),
+    );
+    interpreter
+        .stack_zone
+        .resume_context(receiver, &mut interpreter.space);
+    PrimitiveResult::Success
+}
+
+// Goes on with the receiver where it stopped. It has to be the sender of the active context,
+// which is abandoned: unlike a return, nothing is answered.
+// For the methods the VM sends in the middle of an instruction, such as attemptToAssign:withIndex:
+pub fn primitive_jump(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    let receiver = interpreter.stack_value(0);
+    if !abandon_active_context_for(interpreter, argument_count) {
+        return PrimitiveResult::Failure;
+    }
+    interpreter
+        .stack_zone
+        .resume_context(receiver, &mut interpreter.space);
+    PrimitiveResult::Success
+}
+
+// Answers the 1 based index in the stack of the context, None out of its stack pointer
+fn checked_stack_index(
+    interpreter: &mut Interpreter,
+    context: usize,
+    index: usize,
+) -> Option<usize> {
+    let index = SlotContent::new(index);
+    if !index.is_small_integer() || index.as_small_integer() < 1 {
+        return None;
+    }
+    let stack_pointer = small_integer_value_of(context_slot_at(
+        interpreter,
+        context,
+        context_constants::STACKP_INDEX,
+    ));
+    let index = index.as_small_integer() as usize;
+    if index <= stack_pointer {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+pub fn primitive_context_at(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 1 || !is_context(interpreter, interpreter.stack_value(1)) {
+        return PrimitiveResult::Failure;
+    }
+    let context = interpreter.stack_value(1);
+    let index = match checked_stack_index(interpreter, context, interpreter.stack_value(0)) {
+        Some(index) => index,
+        None => return PrimitiveResult::Failure,
+    };
+    let value = context_slot_at(
+        interpreter,
+        context,
+        context_constants::NUMBER_OF_FIXED_SLOTS + index,
+    );
+    interpreter.pop_then_push(2, value);
+    PrimitiveResult::Success
+}
+
+pub fn primitive_context_at_put(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 2 || !is_context(interpreter, interpreter.stack_value(2)) {
+        return PrimitiveResult::Failure;
+    }
+    let context = interpreter.stack_value(2);
+    let value = interpreter.stack_value(0);
+    let index = match checked_stack_index(interpreter, context, interpreter.stack_value(1)) {
+        Some(index) => index,
+        None => return PrimitiveResult::Failure,
+    };
+    interpreter.stack_zone.context_slot_at_put(
+        context,
+        context_constants::NUMBER_OF_FIXED_SLOTS + index,
+        value,
+        &mut interpreter.space,
+    );
+    interpreter.pop_then_push(3, value);
+    PrimitiveResult::Success
+}
+
+// The size of a context is its stack pointer
+pub fn primitive_context_size(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 0 || !is_context(interpreter, interpreter.stack_value(0)) {
+        return PrimitiveResult::Failure;
+    }
+    let context = interpreter.stack_value(0);
+    let stack_pointer = context_slot_at(interpreter, context, context_constants::STACKP_INDEX);
+    interpreter.pop_then_push(1, stack_pointer);
+    PrimitiveResult::Success
+}
+
+// Keeps the exception for the caller of the interpreter, before the evaluation is abandoned
+pub fn primitive_unhandled_exception(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 0 {
+        return PrimitiveResult::Failure;
+    }
+    let exception = interpreter.stack_value(0);
+    interpreter.set_unhandled_exception(exception);
+    PrimitiveResult::Success
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::bootstrap;
+    use crate::compiler::evaluate;
+    use crate::slot_content::SlotContent;
+
+    #[parameterized(source={
+        "| a b | a := 3. b := 4. (thisContext at: 1) + (thisContext at: 2)",
+        "| a | thisContext at: 1 put: 7. a",
+        "[:x | thisContext size + 5] value: 3",
+        "[:x | thisContext at: 1] value: 7",
+        "(thisContext findNextHandlerContextStarting) isNil ifTrue: [7]",
+        "[thisContext sender findNextHandlerContextStarting == thisContext sender ifTrue: [7]] on: Error do: [:e | 0]",
+        "| a | a := 0. [(thisContext findNextUnwindContextUpTo: nil) tempAt: 1] ensure: [a := 7]. a"
+    })]
+    fn test_context_primitives_answer_seven(source: &str) {
+        let mut interpreter = bootstrap(40000);
+        assert_eq!(
+            evaluate(&mut interpreter, source).unwrap(),
+            SlotContent::small_integer(7)
+        );
+    }
+
+    #[parameterized(source={
+        "thisContext at: 0",
+        "thisContext at: 100",
+        "thisContext at: 100 put: 3",
+        "3 findNextHandlerContextStarting"
+    })]
+    fn test_context_primitive_failures_are_errors(source: &str) {
+        let mut interpreter = bootstrap(40000);
+        let guarded = format!("[{}. 0] on: Error do: [:e | 7]", source);
+        assert_eq!(
+            evaluate(&mut interpreter, &guarded).unwrap(),
+            SlotContent::small_integer(7)
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use crate::compiled_method::compiled_method_constants;
+use crate::compiler::code_generator::code_generator_constants;
+use crate::heap_queries;
+use crate::interpreter::Interpreter;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::primitive_plugin::InterpreterProxy;
+use crate::primitives::PrimitiveResult;
+use crate::slot_content::SlotContent;
+
+// The first literal of a method calling a named primitive describes it:
+// an array of the module name, the function name, and the cached index in the external table.
+pub mod external_call_constants {
+    pub const MODULE_NAME_INDEX: usize = 1;
+    pub const FUNCTION_NAME_INDEX: usize = 2;
+    pub const CACHED_INDEX_INDEX: usize = 3;
+    pub const NUMBER_OF_SLOTS: usize = 3;
+
+    // Values of the cache, external table indexes are stored plus one
+    pub const NOT_RESOLVED: isize = 0;
+    pub const RESOLUTION_FAILED: isize = -1;
+}
+
+// The cached indexes are only valid in the external table of the registry that resolved them,
+// and a failure may resolve once another plugin is registered.
+// They are all reset when an image is loaded and when a plugin is registered.
+pub fn flush_external_primitive_caches(interpreter: &mut Interpreter) {
+    let not_resolved = SlotContent::small_integer(external_call_constants::NOT_RESOLVED);
+    for object in heap_queries::all_objects(&mut interpreter.space) {
+        if !interpreter
+            .space
+            .get_oop_at(object)
+            .get_header()
+            .is_compiled_method()
+        {
+            continue;
+        }
+        let header = interpreter.method_header_of(object);
+        if header.primitive_index() != code_generator_constants::EXTERNAL_CALL_PRIMITIVE
+            || header.number_of_literals() < 1
+        {
+            continue;
+        }
+        let description = interpreter
+            .space
+            .get_oop_at(object)
+            .slot_at_index(compiled_method_constants::FIRST_LITERAL_INDEX);
+        if SlotContent::new(description).is_slot_oop()
+            && interpreter.space.get_oop_at(description).number_of_slots()
+                >= external_call_constants::NUMBER_OF_SLOTS
+        {
+            interpreter
+                .space
+                .get_oop_at(description)
+                .slot_at_index_put_ignoring_immutability(
+                    external_call_constants::CACHED_INDEX_INDEX,
+                    not_resolved,
+                );
+        }
+    }
+}
+
+fn resolve_external_primitive(interpreter: &mut Interpreter, description: usize) -> Option<usize> {
+    let mut description_oop = interpreter.space.get_oop_at(description);
+    let cached_index = SlotContent::new(
+        description_oop.slot_at_index(external_call_constants::CACHED_INDEX_INDEX),
+    )
+    .as_small_integer();
+    match cached_index {
+        external_call_constants::RESOLUTION_FAILED => return None,
+        external_call_constants::NOT_RESOLVED => {}
+        _ => return Some(cached_index as usize - 1),
+    }
+
+    let module_name = description_oop.slot_at_index(external_call_constants::MODULE_NAME_INDEX);
+    let function_name = description_oop.slot_at_index(external_call_constants::FUNCTION_NAME_INDEX);
+    let resolved = match (
+        interpreter.string_value_of(module_name),
+        interpreter.string_value_of(function_name),
+    ) {
+        (Some(module_name), Some(function_name)) => {
+            interpreter.plugins.resolve(&module_name, &function_name)
+        }
+        _ => None,
+    };
+
+    let new_cached_index = match resolved {
+        Some(index) => index as isize + 1,
+        None => external_call_constants::RESOLUTION_FAILED,
+    };
+    description_oop = interpreter.space.get_oop_at(description);
+    description_oop.slot_at_index_put(
+        external_call_constants::CACHED_INDEX_INDEX,
+        SlotContent::small_integer(new_cached_index),
+    );
+    resolved
+}
+
+pub fn primitive_external_call(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    let method = interpreter.new_method();
+    if interpreter.method_header_of(method).number_of_literals() < 1 {
+        return PrimitiveResult::Failure;
+    }
+    let description = interpreter
+        .space
+        .get_oop_at(method)
+        .slot_at_index(compiled_method_constants::FIRST_LITERAL_INDEX);
+
+    let primitive = resolve_external_primitive(interpreter, description)
+        .and_then(|index| interpreter.plugins.external_primitive_at(index));
+    match primitive {
+        Some(primitive) => primitive(&mut InterpreterProxy::new(interpreter), argument_count),
+        None => PrimitiveResult::Failure,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::bootstrap;
+    use crate::compiler::{evaluate, install_method};
+    use crate::image::{read_image, write_image};
+    use crate::interpreter::interpreter_test_support::{
+        new_byte_object, new_interpreter, new_method_with_literals,
+    };
+    use crate::interpreter::Interpreter;
+    use crate::oop_builder::OopBuilder;
+    use crate::primitive_plugin::plugin_test_support::TestPlugin;
+    use crate::primitives::external_primitives::external_call_constants;
+    use crate::slot_content::SlotContent;
+
+    fn new_external_call_method(
+        interpreter: &mut Interpreter,
+        module_name: &str,
+        function_name: &str,
+    ) -> (usize, usize) {
+        let module_name = new_byte_object(interpreter, module_name);
+        let function_name = new_byte_object(interpreter, function_name);
+        let mut builder = OopBuilder::new();
+        builder.set_number_of_slots(external_call_constants::NUMBER_OF_SLOTS);
+        builder.set_slots_value(SlotContent::small_integer(
+            external_call_constants::NOT_RESOLVED,
+        ));
+        let description = builder.build(&mut interpreter.space);
+        let mut description_oop = interpreter.space.get_oop_at(description);
+        description_oop.slot_at_index_put(external_call_constants::MODULE_NAME_INDEX, module_name);
+        description_oop
+            .slot_at_index_put(external_call_constants::FUNCTION_NAME_INDEX, function_name);
+
+        let method = new_method_with_literals(interpreter, 0, 117, &[description]);
+        (method, description)
+    }
+
+    fn cached_index(interpreter: &mut Interpreter, description: usize) -> isize {
+        SlotContent::new(
+            interpreter
+                .space
+                .get_oop_at(description)
+                .slot_at_index(external_call_constants::CACHED_INDEX_INDEX),
+        )
+        .as_small_integer()
+    }
+
+    #[test]
+    fn test_external_call_runs_the_plugin_primitive() {
+        let mut interpreter = new_interpreter();
+        interpreter.register_plugin(Box::new(TestPlugin));
+        let (method, _) =
+            new_external_call_method(&mut interpreter, "TestPlugin", "primitiveDouble");
+        interpreter.push(SlotContent::small_integer(21));
+
+        interpreter.execute_method(method, 0);
+
+        assert_eq!(interpreter.stack_zone.depth(), 1);
+        assert_eq!(interpreter.stack_value(0), SlotContent::small_integer(42));
+    }
+
+    #[test]
+    fn test_external_call_caches_the_resolution() {
+        let mut interpreter = new_interpreter();
+        interpreter.register_plugin(Box::new(TestPlugin));
+        let (method, description) =
+            new_external_call_method(&mut interpreter, "TestPlugin", "primitiveDouble");
+        interpreter.push(SlotContent::small_integer(1));
+        interpreter.execute_method(method, 0);
+        let first_index = cached_index(&mut interpreter, description);
+
+        interpreter.execute_method(method, 0);
+
+        assert!(first_index > 0);
+        assert_eq!(cached_index(&mut interpreter, description), first_index);
+        assert_eq!(interpreter.stack_value(0), SlotContent::small_integer(4));
+    }
+
+    #[test]
+    fn test_external_call_resolves_again_after_an_image_load() {
+        let mut interpreter = bootstrap(40000);
+        let small_integer_class = interpreter.class_named("SmallInteger").unwrap();
+        install_method(
+            &mut interpreter,
+            small_integer_class,
+            "double <primitive: 'primitiveDouble' module: 'TestPlugin'> ^nil",
+        )
+        .unwrap();
+        let nil = interpreter.nil_object();
+        assert_eq!(evaluate(&mut interpreter, "^21 double").unwrap(), nil);
+
+        let mut image = Vec::new();
+        write_image(&mut interpreter, &mut image).unwrap();
+        let mut interpreter = read_image(&mut image.as_slice()).unwrap();
+        interpreter.register_plugin(Box::new(TestPlugin));
+
+        assert_eq!(
+            evaluate(&mut interpreter, "^21 double").unwrap(),
+            SlotContent::small_integer(42)
+        );
+    }
+
+    #[test]
+    fn test_registering_a_plugin_retries_the_failed_resolutions() {
+        let mut interpreter = bootstrap(40000);
+        let small_integer_class = interpreter.class_named("SmallInteger").unwrap();
+        install_method(
+            &mut interpreter,
+            small_integer_class,
+            "double <primitive: 'primitiveDouble' module: 'TestPlugin'> ^nil",
+        )
+        .unwrap();
+        let nil = interpreter.nil_object();
+        assert_eq!(evaluate(&mut interpreter, "^21 double").unwrap(), nil);
+
+        interpreter.register_plugin(Box::new(TestPlugin));
+
+        assert_eq!(
+            evaluate(&mut interpreter, "^21 double").unwrap(),
+            SlotContent::small_integer(42)
+        );
+    }
+
+    #[test]
+    fn test_unknown_module_falls_through_to_the_method() {
+        let mut interpreter = new_interpreter();
+        let (method, description) =
+            new_external_call_method(&mut interpreter, "MissingPlugin", "primitiveDouble");
+        interpreter.push(SlotContent::small_integer(21));
+
+        interpreter.execute_method(method, 0);
+
+        assert_eq!(interpreter.stack_zone.depth(), 2);
+        assert_eq!(
+            cached_index(&mut interpreter, description),
+            external_call_constants::RESOLUTION_FAILED
+        );
+    }
+
+    #[test]
+    fn test_failing_plugin_primitive_falls_through_to_the_method() {
+        let mut interpreter = new_interpreter();
+        interpreter.register_plugin(Box::new(TestPlugin));
+        let (method, _) =
+            new_external_call_method(&mut interpreter, "TestPlugin", "primitiveDouble");
+        let nil = interpreter.nil_object();
+        interpreter.push(nil);
+
+        interpreter.execute_method(method, 0);
+
+        assert_eq!(interpreter.stack_zone.depth(), 2);
+    }
+}
@@ -0,0 +1,680 @@
+use crate::forwarding;
+use crate::header_format_values::HeaderFormatValues;
+use crate::heap_queries;
+use crate::immutability;
+use crate::interpreter::Interpreter;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::pinning;
+use crate::primitives::PrimitiveResult;
+use crate::slot_content::SlotContent;
+use crate::special_object_index::SpecialObjectIndexes;
+
+fn positive_small_integer_value(value: usize) -> Option<usize> {
+    let content = SlotContent::new(value);
+    if content.is_small_integer() && content.as_small_integer() >= 0 {
+        Some(content.as_small_integer() as usize)
+    } else {
+        None
+    }
+}
+
+fn is_pointers_indexable(format: usize) -> bool {
+    format >= HeaderFormatValues::IndexableWithoutSlotsFormat as usize
+        && format <= HeaderFormatValues::WeakIndexableWithSlotsFormat as usize
+}
+
+fn is_bytes_indexable(format: usize) -> bool {
+    (HeaderFormatValues::I8BitIndexable as usize..HeaderFormatValues::CompiledMethodFormat as usize)
+        .contains(&format)
+}
+
+fn number_of_fixed_slots_of(interpreter: &mut Interpreter, oop: usize) -> usize {
+    let class_index = interpreter.class_index_of(oop);
+    match interpreter.class_table.class_at_index(class_index) {
+        Some(class) => interpreter.class_format_of(class).number_of_fixed_slots(),
+        None => 0,
+    }
+}
+
+// Answers the number of indexable fields, or None when the receiver is not indexable
+fn indexable_size_of(interpreter: &mut Interpreter, receiver: usize) -> Option<usize> {
+    if SlotContent::new(receiver).is_slot_immediate() {
+        return None;
+    }
+    let number_of_fixed_slots = number_of_fixed_slots_of(interpreter, receiver);
+    let receiver_oop = interpreter.space.get_oop_at(receiver);
+    let format = receiver_oop.get_header().format_bits();
+    if is_pointers_indexable(format) {
+        Some(receiver_oop.number_of_slots() - number_of_fixed_slots)
+    } else if is_bytes_indexable(format) {
+        Some(receiver_oop.number_of_bytes())
+    } else {
+        None
+    }
+}
+
+fn checked_index(interpreter: &mut Interpreter, receiver: usize, index: usize) -> Option<usize> {
+    let index = positive_small_integer_value(index)?;
+    let size = indexable_size_of(interpreter, receiver)?;
+    if index >= 1 && index <= size {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+pub fn primitive_at(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    if argument_count != 1 {
+        return PrimitiveResult::Failure;
+    }
+    let receiver = interpreter.stack_value(1);
+    let index = match checked_index(interpreter, receiver, interpreter.stack_value(0)) {
+        Some(index) => index,
+        None => return PrimitiveResult::Failure,
+    };
+
+    let number_of_fixed_slots = number_of_fixed_slots_of(interpreter, receiver);
+    let receiver_oop = interpreter.space.get_oop_at(receiver);
+    let value = if is_bytes_indexable(receiver_oop.get_header().format_bits()) {
+        SlotContent::small_integer(receiver_oop.byte_at_index(index) as isize)
+    } else {
+        match receiver_oop.try_slot_at_index(number_of_fixed_slots + index) {
+            Ok(value) => value,
+            Err(_) => return PrimitiveResult::Failure,
+        }
+    };
+    let value = forwarding::follow_forwarded(&mut interpreter.space, value);
+    interpreter.pop_then_push(2, value);
+    PrimitiveResult::Success
+}
+
+pub fn primitive_at_put(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    if argument_count != 2 {
+        return PrimitiveResult::Failure;
+    }
+    let receiver = interpreter.stack_value(2);
+    let value = interpreter.stack_value(0);
+    let index = match checked_index(interpreter, receiver, interpreter.stack_value(1)) {
+        Some(index) => index,
+        None => return PrimitiveResult::Failure,
+    };
+
+    let number_of_fixed_slots = number_of_fixed_slots_of(interpreter, receiver);
+    let mut receiver_oop = interpreter.space.get_oop_at(receiver);
+    if is_bytes_indexable(receiver_oop.get_header().format_bits()) {
+        match positive_small_integer_value(value).filter(|byte| *byte <= 255) {
+            Some(byte) => {
+                if receiver_oop
+                    .try_byte_at_index_put(index, byte as u8)
+                    .is_err()
+                {
+                    return PrimitiveResult::Failure;
+                }
+            }
+            None => return PrimitiveResult::Failure,
+        }
+    } else if receiver_oop
+        .try_slot_at_index_put(number_of_fixed_slots + index, value)
+        .is_err()
+    {
+        return PrimitiveResult::Failure;
+    }
+    interpreter.pop_then_push(3, value);
+    PrimitiveResult::Success
+}
+
+pub fn primitive_size(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    if argument_count != 0 {
+        return PrimitiveResult::Failure;
+    }
+    match indexable_size_of(interpreter, interpreter.stack_value(0)) {
+        Some(size) => {
+            interpreter.pop_then_push(1, SlotContent::small_integer(size as isize));
+            PrimitiveResult::Success
+        }
+        None => PrimitiveResult::Failure,
+    }
+}
+
+// Answers the 1 based slot index, None when the receiver has no such pointer slot
+fn checked_slot_index(
+    interpreter: &mut Interpreter,
+    receiver: usize,
+    index: usize,
+) -> Option<usize> {
+    let index = positive_small_integer_value(index)?;
+    if SlotContent::new(receiver).is_slot_immediate() {
+        return None;
+    }
+    let receiver_oop = interpreter.space.get_oop_at(receiver);
+    if !receiver_oop.get_header().contains_pointers() {
+        return None;
+    }
+    if index >= 1 && index <= receiver_oop.number_of_slots() {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+// Contexts are accessed through their frame when they have one
+pub fn primitive_inst_var_at(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 1 {
+        return PrimitiveResult::Failure;
+    }
+    let receiver = interpreter.stack_value(1);
+    let index = match checked_slot_index(interpreter, receiver, interpreter.stack_value(0)) {
+        Some(index) => index,
+        None => return PrimitiveResult::Failure,
+    };
+    let value = interpreter
+        .stack_zone
+        .context_slot_at(receiver, index, &mut interpreter.space);
+    let value = forwarding::follow_forwarded(&mut interpreter.space, value);
+    interpreter.pop_then_push(2, value);
+    PrimitiveResult::Success
+}
+
+pub fn primitive_inst_var_at_put(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 2 {
+        return PrimitiveResult::Failure;
+    }
+    let receiver = interpreter.stack_value(2);
+    let value = interpreter.stack_value(0);
+    let index = match checked_slot_index(interpreter, receiver, interpreter.stack_value(1)) {
+        Some(index) => index,
+        None => return PrimitiveResult::Failure,
+    };
+    if immutability::is_immutable(&mut interpreter.space, receiver) {
+        return PrimitiveResult::Failure;
+    }
+    interpreter
+        .stack_zone
+        .context_slot_at_put(receiver, index, value, &mut interpreter.space);
+    interpreter.pop_then_push(3, value);
+    PrimitiveResult::Success
+}
+
+pub fn primitive_basic_new(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 0 {
+        return PrimitiveResult::Failure;
+    }
+    let class = interpreter.stack_value(0);
+    match interpreter.instantiate_class(class, 0) {
+        Some(instance) => {
+            interpreter.pop_then_push(1, instance);
+            PrimitiveResult::Success
+        }
+        None => PrimitiveResult::Failure,
+    }
+}
+
+// Behavior>>allInstances, the answer is allocated after the instances are found
+pub fn primitive_all_instances(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 0 {
+        return PrimitiveResult::Failure;
+    }
+    let class = interpreter.stack_value(0);
+    if SlotContent::new(class).is_slot_immediate() {
+        return PrimitiveResult::Failure;
+    }
+    let class_index = interpreter.space.get_oop_at(class).get_header().hash_bits();
+    if interpreter.class_table.class_at_index(class_index) != Some(class) {
+        return PrimitiveResult::Failure;
+    }
+    let instances = heap_queries::all_instances_of(&mut interpreter.space, class_index);
+    let array_class = interpreter.special_object(SpecialObjectIndexes::ClassArray);
+    let Some(array) = interpreter.instantiate_class(array_class, instances.len()) else {
+        return PrimitiveResult::Failure;
+    };
+    let mut array_oop = interpreter.space.get_oop_at(array);
+    for (index, instance) in instances.into_iter().enumerate() {
+        array_oop.slot_at_index_put(index + 1, instance);
+    }
+    interpreter.pop_then_push(1, array);
+    PrimitiveResult::Success
+}
+
+pub fn primitive_is_read_only(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 0 {
+        return PrimitiveResult::Failure;
+    }
+    let receiver = interpreter.stack_value(0);
+    let read_only = immutability::is_immutable(&mut interpreter.space, receiver);
+    let answer = interpreter.boolean_object(read_only);
+    interpreter.pop_then_push(1, answer);
+    PrimitiveResult::Success
+}
+
+// Answers whether the receiver was read-only
+pub fn primitive_set_read_only(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 1 {
+        return PrimitiveResult::Failure;
+    }
+    let receiver = interpreter.stack_value(1);
+    let argument = interpreter.stack_value(0);
+    if SlotContent::new(receiver).is_slot_immediate() {
+        return PrimitiveResult::Failure;
+    }
+    let read_only = if argument == interpreter.true_object() {
+        true
+    } else if argument == interpreter.false_object() {
+        false
+    } else {
+        return PrimitiveResult::Failure;
+    };
+    let was_read_only = immutability::is_immutable(&mut interpreter.space, receiver);
+    immutability::set_immutable_bit_of(&mut interpreter.space, receiver, read_only);
+    let answer = interpreter.boolean_object(was_read_only);
+    interpreter.pop_then_push(2, answer);
+    PrimitiveResult::Success
+}
+
+pub fn primitive_is_pinned(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    let receiver = interpreter.stack_value(0);
+    if argument_count != 0 || SlotContent::new(receiver).is_slot_immediate() {
+        return PrimitiveResult::Failure;
+    }
+    let pinned = pinning::is_pinned(&mut interpreter.space, receiver);
+    let answer = interpreter.boolean_object(pinned);
+    interpreter.pop_then_push(1, answer);
+    PrimitiveResult::Success
+}
+
+// Answers whether the receiver was pinned.
+// Pinning may move the receiver, the stack then references where it went.
+pub fn primitive_set_pinned(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 1 {
+        return PrimitiveResult::Failure;
+    }
+    let receiver = interpreter.stack_value(1);
+    let argument = interpreter.stack_value(0);
+    if SlotContent::new(receiver).is_slot_immediate() {
+        return PrimitiveResult::Failure;
+    }
+    let was_pinned = pinning::is_pinned(&mut interpreter.space, receiver);
+    if argument == interpreter.true_object() {
+        if pinning::pin(interpreter, receiver).is_err() {
+            return PrimitiveResult::Failure;
+        }
+    } else if argument == interpreter.false_object() {
+        pinning::unpin(interpreter, receiver);
+    } else {
+        return PrimitiveResult::Failure;
+    }
+    let answer = interpreter.boolean_object(was_pinned);
+    interpreter.pop_then_push(2, answer);
+    PrimitiveResult::Success
+}
+
+pub fn primitive_basic_new_with_size(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 1 {
+        return PrimitiveResult::Failure;
+    }
+    let class = interpreter.stack_value(1);
+    let instance = positive_small_integer_value(interpreter.stack_value(0))
+        .and_then(|size| interpreter.instantiate_class(class, size));
+    match instance {
+        Some(instance) => {
+            interpreter.pop_then_push(2, instance);
+            PrimitiveResult::Success
+        }
+        None => PrimitiveResult::Failure,
+    }
+}
+
+pub fn primitive_identity_hash(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    let receiver = interpreter.stack_value(0);
+    if argument_count != 0 || SlotContent::new(receiver).is_slot_immediate() {
+        return PrimitiveResult::Failure;
+    }
+    let hash = interpreter.hash_of(receiver);
+    interpreter.pop_then_push(1, SlotContent::small_integer(hash as isize));
+    PrimitiveResult::Success
+}
+
+// Characters answer their code point
+pub fn primitive_immediate_as_integer(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    let receiver = SlotContent::new(interpreter.stack_value(0));
+    if argument_count != 0 || !receiver.is_character() {
+        return PrimitiveResult::Failure;
+    }
+    interpreter.pop_then_push(
+        1,
+        SlotContent::small_integer(receiver.as_character() as isize),
+    );
+    PrimitiveResult::Success
+}
+
+pub fn primitive_identical(
+    interpreter: &mut Interpreter,
+    argument_count: usize,
+) -> PrimitiveResult {
+    if argument_count != 1 {
+        return PrimitiveResult::Failure;
+    }
+    let result = interpreter.stack_value(1) == interpreter.stack_value(0);
+    let result = interpreter.boolean_object(result);
+    interpreter.pop_then_push(2, result);
+    PrimitiveResult::Success
+}
+
+pub fn primitive_class(interpreter: &mut Interpreter, argument_count: usize) -> PrimitiveResult {
+    if argument_count != 0 {
+        return PrimitiveResult::Failure;
+    }
+    let class = interpreter.class_of(interpreter.stack_value(0));
+    interpreter.pop_then_push(1, class);
+    PrimitiveResult::Success
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::interpreter_test_support::{new_array, new_class, new_interpreter};
+    use crate::special_class_index::SpecialClassIndexes;
+
+    #[test]
+    fn test_basic_new() {
+        let mut interpreter = new_interpreter();
+        let class = new_class(
+            &mut interpreter,
+            HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+            2,
+        );
+        interpreter.push(class);
+
+        assert_eq!(
+            primitive_basic_new(&mut interpreter, 0),
+            PrimitiveResult::Success
+        );
+        let instance = interpreter.stack_value(0);
+        assert_eq!(interpreter.class_of(instance), class);
+        assert_eq!(interpreter.space.get_oop_at(instance).number_of_slots(), 2);
+    }
+
+    #[test]
+    fn test_set_read_only_with_the_wrong_argument_count_fails() {
+        let mut interpreter = new_interpreter();
+        let array = new_array(&mut interpreter, &[SlotContent::small_integer(0); 1]);
+        interpreter.push(array);
+
+        assert_eq!(
+            primitive_set_read_only(&mut interpreter, 0),
+            PrimitiveResult::Failure
+        );
+        assert_eq!(interpreter.stack_value(0), array);
+        assert!(!immutability::is_immutable(&mut interpreter.space, array));
+    }
+
+    #[test]
+    fn test_set_pinned_with_the_wrong_argument_count_fails() {
+        let mut interpreter = new_interpreter();
+        let array = new_array(&mut interpreter, &[SlotContent::small_integer(0); 1]);
+        interpreter.push(array);
+
+        assert_eq!(
+            primitive_set_pinned(&mut interpreter, 0),
+            PrimitiveResult::Failure
+        );
+        assert_eq!(interpreter.stack_value(0), array);
+        assert!(!pinning::is_pinned(&mut interpreter.space, array));
+    }
+
+    #[test]
+    fn test_basic_new_with_size() {
+        let mut interpreter = new_interpreter();
+        let class = new_class(
+            &mut interpreter,
+            HeaderFormatValues::IndexableWithSlotsFormat as usize,
+            1,
+        );
+        interpreter.push(class);
+        interpreter.push(SlotContent::small_integer(3));
+
+        assert_eq!(
+            primitive_basic_new_with_size(&mut interpreter, 1),
+            PrimitiveResult::Success
+        );
+        let instance = interpreter.stack_value(0);
+        assert_eq!(interpreter.space.get_oop_at(instance).number_of_slots(), 4);
+    }
+
+    #[test]
+    fn test_basic_new_with_negative_size_fails() {
+        let mut interpreter = new_interpreter();
+        let class = new_class(
+            &mut interpreter,
+            HeaderFormatValues::IndexableWithoutSlotsFormat as usize,
+            0,
+        );
+        interpreter.push(class);
+        interpreter.push(SlotContent::small_integer(-1));
+
+        assert_eq!(
+            primitive_basic_new_with_size(&mut interpreter, 1),
+            PrimitiveResult::Failure
+        );
+    }
+
+    #[parameterized(instance_specification={
+        HeaderFormatValues::IndexableWithoutSlotsFormat as usize,
+        HeaderFormatValues::I8BitIndexable as usize
+    })]
+    fn test_basic_new_with_a_huge_size_fails(instance_specification: usize) {
+        let mut interpreter = new_interpreter();
+        let class = new_class(&mut interpreter, instance_specification, 0);
+        interpreter.push(class);
+        interpreter.push(SlotContent::small_integer(100_000_000_000));
+
+        assert_eq!(
+            primitive_basic_new_with_size(&mut interpreter, 1),
+            PrimitiveResult::Failure
+        );
+        assert_eq!(
+            interpreter.stack_value(0),
+            SlotContent::small_integer(100_000_000_000)
+        );
+    }
+
+    #[test]
+    fn test_at_put_then_at() {
+        let mut interpreter = new_interpreter();
+        let array = new_array(&mut interpreter, &[SlotContent::small_integer(0); 3]);
+        interpreter.push(array);
+        interpreter.push(SlotContent::small_integer(2));
+        interpreter.push(SlotContent::small_integer(42));
+        assert_eq!(
+            primitive_at_put(&mut interpreter, 2),
+            PrimitiveResult::Success
+        );
+        interpreter.pop();
+
+        interpreter.push(array);
+        interpreter.push(SlotContent::small_integer(2));
+        assert_eq!(primitive_at(&mut interpreter, 1), PrimitiveResult::Success);
+        assert_eq!(interpreter.stack_value(0), SlotContent::small_integer(42));
+    }
+
+    #[test]
+    fn test_at_skips_fixed_slots() {
+        let mut interpreter = new_interpreter();
+        let class = new_class(
+            &mut interpreter,
+            HeaderFormatValues::IndexableWithSlotsFormat as usize,
+            2,
+        );
+        let instance = interpreter.instantiate_class(class, 1).unwrap();
+        interpreter
+            .space
+            .get_oop_at(instance)
+            .slot_at_index_put(3, SlotContent::small_integer(7));
+        interpreter.push(instance);
+        interpreter.push(SlotContent::small_integer(1));
+
+        assert_eq!(primitive_at(&mut interpreter, 1), PrimitiveResult::Success);
+        assert_eq!(interpreter.stack_value(0), SlotContent::small_integer(7));
+    }
+
+    #[parameterized(index={ 0, 4 })]
+    fn test_at_out_of_bounds_fails(index: usize) {
+        let mut interpreter = new_interpreter();
+        let array = new_array(&mut interpreter, &[SlotContent::small_integer(0); 3]);
+        interpreter.push(array);
+        interpreter.push(SlotContent::small_integer(index as isize));
+
+        assert_eq!(primitive_at(&mut interpreter, 1), PrimitiveResult::Failure);
+    }
+
+    #[test]
+    fn test_at_on_non_indexable_fails() {
+        let mut interpreter = new_interpreter();
+        let class = new_class(
+            &mut interpreter,
+            HeaderFormatValues::NonIndexableWithSlotsFormat as usize,
+            2,
+        );
+        let instance = interpreter.instantiate_class(class, 0).unwrap();
+        interpreter.push(instance);
+        interpreter.push(SlotContent::small_integer(1));
+
+        assert_eq!(primitive_at(&mut interpreter, 1), PrimitiveResult::Failure);
+    }
+
+    #[test]
+    fn test_byte_at_put_rejects_non_bytes() {
+        let mut interpreter = new_interpreter();
+        let class = new_class(
+            &mut interpreter,
+            HeaderFormatValues::I8BitIndexable as usize,
+            0,
+        );
+        let string = interpreter.instantiate_class(class, 5).unwrap();
+        interpreter.push(string);
+        interpreter.push(SlotContent::small_integer(1));
+        interpreter.push(SlotContent::small_integer(256));
+
+        assert_eq!(
+            primitive_at_put(&mut interpreter, 2),
+            PrimitiveResult::Failure
+        );
+    }
+
+    #[test]
+    fn test_size_of_bytes() {
+        let mut interpreter = new_interpreter();
+        let class = new_class(
+            &mut interpreter,
+            HeaderFormatValues::I8BitIndexable as usize,
+            0,
+        );
+        let string = interpreter.instantiate_class(class, 5).unwrap();
+        interpreter.push(string);
+
+        assert_eq!(
+            primitive_size(&mut interpreter, 0),
+            PrimitiveResult::Success
+        );
+        assert_eq!(interpreter.stack_value(0), SlotContent::small_integer(5));
+    }
+
+    #[test]
+    fn test_size_of_small_integer_fails() {
+        let mut interpreter = new_interpreter();
+        interpreter.push(SlotContent::small_integer(5));
+
+        assert_eq!(
+            primitive_size(&mut interpreter, 0),
+            PrimitiveResult::Failure
+        );
+    }
+
+    #[test]
+    fn test_identity_hash_is_stable() {
+        let mut interpreter = new_interpreter();
+        let array = new_array(&mut interpreter, &[SlotContent::small_integer(0); 1]);
+        interpreter.push(array);
+        primitive_identity_hash(&mut interpreter, 0);
+        let hash = interpreter.pop();
+        interpreter.push(array);
+        primitive_identity_hash(&mut interpreter, 0);
+
+        assert_eq!(interpreter.stack_value(0), hash);
+    }
+
+    #[test]
+    fn test_character_as_integer() {
+        let mut interpreter = new_interpreter();
+        interpreter.push(SlotContent::from_character('a' as u32).get_content());
+
+        assert_eq!(
+            primitive_immediate_as_integer(&mut interpreter, 0),
+            PrimitiveResult::Success
+        );
+        assert_eq!(interpreter.stack_value(0), SlotContent::small_integer(97));
+    }
+
+    #[test]
+    fn test_class_of_small_integer() {
+        let mut interpreter = new_interpreter();
+        interpreter.push(SlotContent::small_integer(5));
+
+        assert_eq!(
+            primitive_class(&mut interpreter, 0),
+            PrimitiveResult::Success
+        );
+        assert_eq!(
+            Some(interpreter.stack_value(0)),
+            interpreter
+                .class_table
+                .class_at_index(SpecialClassIndexes::SmallInteger as usize)
+        );
+    }
+
+    #[test]
+    fn test_identical() {
+        let mut interpreter = new_interpreter();
+        let array = new_array(&mut interpreter, &[SlotContent::small_integer(0); 1]);
+        interpreter.push(array);
+        interpreter.push(array);
+
+        assert_eq!(
+            primitive_identical(&mut interpreter, 1),
+            PrimitiveResult::Success
+        );
+        assert_eq!(interpreter.stack_value(0), interpreter.true_object());
+    }
+}
@@ -0,0 +1,295 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::cli;
+use crate::compiler;
+use crate::heap_export;
+use crate::image;
+use crate::interpreter::Interpreter;
+use crate::slot_content::SlotContent;
+use crate::symbol_table::intern;
+
+// Evaluates the lines typed at the prompt as doIts and prints their value with printString.
+// The lines starting with a colon are meta-commands.
+pub const PROMPT: &str = "funvm> ";
+
+const HELP: &str = "<expression>       evaluates the expression and prints its value
+:inspect <expr>    evaluates the expression and decodes the object it answers
+:gc                collects the garbage
+:census            counts the objects and their words per class
+:save <image>      writes the image
+:history           lists the lines entered so far
+:history <n>       evaluates the line n of the history again
+:help              shows this help
+:quit              leaves the repl";
+
+pub struct Repl<'a> {
+    interpreter: &'a mut Interpreter,
+    history: Vec<String>,
+}
+
+impl<'a> Repl<'a> {
+    pub fn new(interpreter: &'a mut Interpreter) -> Self {
+        Self {
+            interpreter,
+            history: Vec::new(),
+        }
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    // Reads until the end of the input or :quit
+    pub fn run<R: BufRead, W: Write>(&mut self, input: &mut R, output: &mut W) -> io::Result<()> {
+        loop {
+            write!(output, "{}", PROMPT)?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(output)?;
+                return Ok(());
+            }
+            if !self.evaluate_line(line.trim(), output)? {
+                return Ok(());
+            }
+        }
+    }
+
+    // Answers false when the repl should stop
+    pub fn evaluate_line<W: Write>(&mut self, line: &str, output: &mut W) -> io::Result<bool> {
+        if line.is_empty() {
+            return Ok(true);
+        }
+        self.history.push(line.to_string());
+        let (command, argument) = match line.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line, ""),
+        };
+        match command {
+            ":quit" | ":q" => return Ok(false),
+            ":help" => writeln!(output, "{}", HELP)?,
+            ":history" if !argument.is_empty() => {
+                // The recalled line takes the place of the command in the history
+                self.history.pop();
+                let entry = argument
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|position| position.checked_sub(1))
+                    .and_then(|position| self.history.get(position))
+                    .cloned();
+                match entry {
+                    Some(entry) => {
+                        writeln!(output, "{}", entry)?;
+                        return self.evaluate_line(&entry, output);
+                    }
+                    None => writeln!(output, "no line {} in the history", argument)?,
+                }
+            }
+            ":history" => {
+                for (position, entry) in self.history.iter().enumerate() {
+                    writeln!(output, "{:>4}  {}", position + 1, entry)?;
+                }
+            }
+            ":gc" => {
+                let free_words_before = cli::free_words_of(self.interpreter);
+                self.interpreter.collect_garbage();
+                let free_words_after = cli::free_words_of(self.interpreter);
+                writeln!(
+                    output,
+                    "reclaimed {} words, {} words free",
+                    free_words_after.saturating_sub(free_words_before),
+                    free_words_after
+                )?;
+            }
+            ":census" => report(cli::census(self.interpreter, output), output)?,
+            ":inspect" => {
+                if let Some(value) = self.evaluate(argument, output)? {
+                    if SlotContent::new(value).is_slot_oop() {
+                        report(cli::show(self.interpreter, value, output), output)?;
+                    } else {
+                        let description = cli::describe_slot(self.interpreter, value);
+                        writeln!(output, "{}", description)?;
+                    }
+                }
+            }
+            ":save" => {
+                if argument.is_empty() {
+                    writeln!(output, "missing image")?;
+                } else if let Err(error) = image::save_image(self.interpreter, Path::new(argument))
+                {
+                    writeln!(output, "{}", error)?;
+                }
+            }
+            _ if command.starts_with(':') => {
+                writeln!(output, "unknown command {}, :help lists them", command)?
+            }
+            _ => {
+                if let Some(value) = self.evaluate(line, output)? {
+                    let printed = print_string_of(self.interpreter, value);
+                    writeln!(output, "{}", printed)?;
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    // None when the expression does not compile or signals an unhandled exception, reported
+    fn evaluate<W: Write>(&mut self, source: &str, output: &mut W) -> io::Result<Option<usize>> {
+        let value = match compiler::evaluate(self.interpreter, source) {
+            Ok(value) => value,
+            Err(error) => {
+                writeln!(output, "syntax error: {}", error)?;
+                return Ok(None);
+            }
+        };
+        if let Some(exception) = self.interpreter.take_unhandled_exception() {
+            let description = describe_exception(self.interpreter, exception);
+            writeln!(output, "unhandled {}", description)?;
+            return Ok(None);
+        }
+        Ok(Some(value))
+    }
+}
+
+fn report<W: Write>(result: Result<bool, cli::CliError>, output: &mut W) -> io::Result<()> {
+    match result {
+        Err(cli::CliError::Io(error)) => Err(error),
+        Err(error) => writeln!(output, "{}", error),
+        Ok(_) => Ok(()),
+    }
+}
+
+// Falls back on the heap inspector description when printString fails
+fn print_string_of(interpreter: &mut Interpreter, value: usize) -> String {
+    let selector = intern(interpreter, "printString");
+    let printed = interpreter.send_message(value, selector, &[]);
+    if interpreter.take_unhandled_exception().is_none() {
+        if let Some(string) = interpreter.string_value_of(printed) {
+            return string;
+        }
+    }
+    cli::describe_slot(interpreter, value)
+}
+
+fn describe_exception(interpreter: &mut Interpreter, exception: usize) -> String {
+    let class_name = heap_export::exported_object(interpreter, exception)
+        .class_name
+        .unwrap_or_else(|| String::from("exception"));
+    let selector = intern(interpreter, "messageText");
+    let message_text = interpreter.send_message(exception, selector, &[]);
+    match interpreter.string_value_of(message_text) {
+        Some(message_text) => format!("{}: {}", class_name, message_text),
+        None => class_name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::bootstrap::bootstrap;
+    use crate::repl::{Repl, PROMPT};
+
+    fn run_lines(lines: &str) -> String {
+        let mut interpreter = bootstrap(40000);
+        let mut output: Vec<u8> = Vec::new();
+        Repl::new(&mut interpreter)
+            .run(&mut Cursor::new(lines.as_bytes()), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn printed(expression: &str) -> String {
+        let mut interpreter = bootstrap(40000);
+        let mut output: Vec<u8> = Vec::new();
+        Repl::new(&mut interpreter)
+            .evaluate_line(expression, &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[parameterized(expression_and_expected = {
+        ("3 + 4", "7"),
+        ("0", "0"),
+        ("3 - 1250", "-1247"),
+        ("nil", "nil"),
+        ("3 < 4", "true"),
+        ("4 < 3", "false"),
+        ("$a", "$a"),
+        ("'it''s'", "'it''s'"),
+        ("'ab' , 'cd'", "'abcd'"),
+        ("#foo", "#foo"),
+        ("#(1 $a foo (2 3))", "#(1 $a #foo #(2 3))"),
+        ("Object new", "an Object"),
+        ("Message new", "a Message"),
+        ("Array", "Array"),
+        ("Array class", "Array class"),
+    })]
+    fn test_values_are_printed(expression_and_expected: (&str, &str)) {
+        let (expression, expected) = expression_and_expected;
+        assert_eq!(printed(expression), format!("{}\n", expected));
+    }
+
+    #[test]
+    fn test_unhandled_exceptions_are_reported() {
+        assert_eq!(printed("1 / 0"), "unhandled ZeroDivide: Division by zero\n");
+        assert_eq!(printed("3 foo"), "unhandled MessageNotUnderstood: foo\n");
+    }
+
+    #[test]
+    fn test_syntax_errors_are_reported() {
+        assert!(printed("3 +").starts_with("syntax error: "));
+    }
+
+    #[test]
+    fn test_run_reads_until_quit() {
+        let output = run_lines("3 + 4\n\n:quit\n5\n");
+
+        assert_eq!(output, format!("{}7\n{}{}", PROMPT, PROMPT, PROMPT));
+    }
+
+    #[test]
+    fn test_history_keeps_the_lines() {
+        let mut interpreter = bootstrap(40000);
+        let mut output: Vec<u8> = Vec::new();
+        let mut repl = Repl::new(&mut interpreter);
+        repl.run(&mut Cursor::new("1\n:gc\n2\n:history\n"), &mut output)
+            .unwrap();
+
+        assert_eq!(repl.history(), ["1", ":gc", "2", ":history"]);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("   3  2\n"));
+        assert!(output.contains("reclaimed "));
+    }
+
+    #[test]
+    fn test_history_recalls_a_line() {
+        let mut interpreter = bootstrap(40000);
+        let mut output: Vec<u8> = Vec::new();
+        let mut repl = Repl::new(&mut interpreter);
+        repl.run(
+            &mut Cursor::new("3 + 4\n:history 1\n:history 3\n:history x\n"),
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(repl.history(), ["3 + 4", "3 + 4"]);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            format!(
+                "{0}7\n{0}3 + 4\n7\n{0}no line 3 in the history\n{0}no line x in the history\n{0}\n",
+                PROMPT
+            )
+        );
+    }
+
+    #[test]
+    fn test_meta_commands() {
+        assert!(printed(":census").contains("  Symbol\n"));
+        assert!(printed(":inspect #(1 2)").contains("  2: SmallInteger 2\n"));
+        assert_eq!(printed(":inspect 42"), "SmallInteger 42\n");
+        assert!(printed(":frobnicate").starts_with("unknown command :frobnicate"));
+    }
+}
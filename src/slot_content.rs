@@ -1,5 +1,19 @@
 //use crate::oop::Oop;
 
+// Immediates are tagged in the two top bits of the slot.
+// Oops are indexes in the memory space, so they never reach those bits.
+pub mod immediate_constants {
+    pub const TAG_MASK: usize = 0xC000000000000000;
+    pub const VALUE_MASK: usize = 0x3FFFFFFFFFFFFFFF;
+    pub const IMMEDIATE_BIT: usize = 0x8000000000000000;
+    pub const SMALL_INTEGER_TAG: usize = 0x8000000000000000;
+    pub const CHARACTER_TAG: usize = 0xC000000000000000;
+
+    // SmallIntegers are 62 bits, signed
+    pub const MAX_SMALL_INTEGER: isize = (1 << 61) - 1;
+    pub const MIN_SMALL_INTEGER: isize = -(1 << 61);
+}
+
 #[derive(Debug)]
 pub struct SlotContent {
     content: usize,
@@ -13,26 +27,99 @@ impl SlotContent {
         }
     }
 
+    pub fn from_small_integer(value: isize) -> Self {
+        if !SlotContent::is_small_integer_value(value) {
+            panic!("{} does not fit in a SmallInteger", value)
+        }
+        Self::new(
+            ((value as usize) & immediate_constants::VALUE_MASK)
+                | immediate_constants::SMALL_INTEGER_TAG,
+        )
+    }
+
+    // The slot value of the SmallInteger
+    pub fn small_integer(value: isize) -> usize {
+        Self::from_small_integer(value).get_content()
+    }
+
+    pub fn from_character(value: u32) -> Self {
+        Self::new(value as usize | immediate_constants::CHARACTER_TAG)
+    }
+
     // Accessing
     pub fn get_content(&self) -> usize {
         self.content
     }
 
+    pub fn as_small_integer(&self) -> isize {
+        // shifting left then right extends the sign of the 62 bits value
+        ((self.content << 2) as isize) >> 2
+    }
+
+    pub fn as_character(&self) -> u32 {
+        (self.content & immediate_constants::VALUE_MASK) as u32
+    }
+
     // Testing
-    // todo(immediate)
+    pub fn is_small_integer_value(value: isize) -> bool {
+        (immediate_constants::MIN_SMALL_INTEGER..=immediate_constants::MAX_SMALL_INTEGER)
+            .contains(&value)
+    }
+
     pub fn is_slot_immediate(&self) -> bool {
-        false
+        self.content & immediate_constants::IMMEDIATE_BIT != 0
     }
 
-    // todo(immediate)
     pub fn is_slot_oop(&self) -> bool {
-        true
+        !self.is_slot_immediate()
+    }
+
+    pub fn is_small_integer(&self) -> bool {
+        self.content & immediate_constants::TAG_MASK == immediate_constants::SMALL_INTEGER_TAG
+    }
+
+    pub fn is_character(&self) -> bool {
+        self.content & immediate_constants::TAG_MASK == immediate_constants::CHARACTER_TAG
     }
+}
 
-    // todo conversions;
-    // pub fn as_oop(&self) -> Oop {
-    // }
+#[cfg(test)]
+mod tests {
+    use crate::slot_content::immediate_constants;
+    use crate::slot_content::SlotContent;
+
+    #[parameterized(value={ 0, 1, -1, 42, -42, immediate_constants::MAX_SMALL_INTEGER, immediate_constants::MIN_SMALL_INTEGER })]
+    fn test_small_integer_round_trip(value: isize) {
+        let content = SlotContent::from_small_integer(value);
+        assert!(content.is_small_integer());
+        assert_eq!(content.as_small_integer(), value);
+    }
 
-    // pub fn as_immediate(&self) -> Immediate {
-    // }
+    #[test]
+    fn test_character_round_trip() {
+        let content = SlotContent::from_character('a' as u32);
+        assert!(content.is_character());
+        assert_eq!(content.as_character(), 'a' as u32);
+    }
+
+    #[test]
+    fn test_small_integer_is_immediate() {
+        let content = SlotContent::from_small_integer(3);
+        assert!(content.is_slot_immediate());
+        assert!(!content.is_slot_oop());
+        assert!(!content.is_character());
+    }
+
+    #[test]
+    fn test_oop_index_is_not_immediate() {
+        let content = SlotContent::new(3);
+        assert!(content.is_slot_oop());
+        assert!(!content.is_small_integer());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_small_integer_overflow_panics() {
+        SlotContent::from_small_integer(immediate_constants::MAX_SMALL_INTEGER + 1);
+    }
 }
@@ -1,4 +1,21 @@
 #[repr(usize)]
 pub enum SpecialClassIndexes {
     FreeObject = 1,
+    Context = 3,
+    SmallInteger = 4,
+    Character = 5,
+    CompiledMethod = 6,
+    Array = 7,
+    // As in Spur, the objects whose identity went to another object
+    Forwarded = 8,
+    ByteString = 9,
+    Symbol = 10,
+    MethodDictionary = 11,
+    UndefinedObject = 12,
+    True = 13,
+    False = 14,
+    // Ends the segments of the memory space, not an object either
+    SegmentBridge = 15,
+    // The words too few for a free chunk, see free_chunk
+    Filler = 16,
 }
@@ -0,0 +1,35 @@
+// Slots of the special objects array, 1 based like the oop slots.
+#[repr(usize)]
+pub enum SpecialObjectIndexes {
+    Nil = 1,
+    False = 2,
+    True = 3,
+    SymbolTable = 4,
+    ClassSmallInteger = 5,
+    ClassCharacter = 6,
+    ClassArray = 7,
+    ClassByteString = 8,
+    ClassSymbol = 9,
+    ClassCompiledMethod = 10,
+    ClassMethodDictionary = 11,
+    ClassContext = 12,
+    // Pairs of selector and number of arguments, for the special send bytecodes
+    SpecialSelectors = 13,
+    ClassBlockClosure = 14,
+    ClassCompiledBlock = 15,
+    // Sent to a closure context whose home context cannot be returned to
+    SelectorCannotReturn = 16,
+    ClassMessage = 17,
+    // Sent with a Message when the lookup fails
+    SelectorDoesNotUnderstand = 18,
+    // Sent to the non boolean found by a conditional jump
+    SelectorMustBeBoolean = 19,
+    // The class of the symbol table
+    ClassWeakSet = 20,
+    // Sent to the immutable receiver of a store bytecode
+    SelectorAttemptToAssign = 21,
+}
+
+impl SpecialObjectIndexes {
+    pub const NUMBER_OF_SPECIAL_OBJECTS: usize = 21;
+}
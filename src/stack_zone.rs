@@ -0,0 +1,921 @@
+use crate::memory_space::MemorySpace;
+use crate::oop_builder::OopBuilder;
+use crate::oop_projections::oop_common::OopCommonState;
+use crate::slot_content::SlotContent;
+use crate::special_class_index::SpecialClassIndexes;
+
+// Context slots, 1 based like the oop slots.
+// The stack contents (arguments, temporaries, then the operand stack) follow the fixed slots.
+pub mod context_constants {
+    pub const SENDER_INDEX: usize = 1;
+    pub const PC_INDEX: usize = 2;
+    pub const STACKP_INDEX: usize = 3;
+    pub const METHOD_INDEX: usize = 4;
+    pub const CLOSURE_OR_NIL_INDEX: usize = 5;
+    pub const RECEIVER_INDEX: usize = 6;
+    pub const NUMBER_OF_FIXED_SLOTS: usize = 6;
+}
+
+// An activation living on a stack page.
+// A frame only gets a context object when someone asks for it (it is then "married" to it).
+#[derive(Debug)]
+struct StackFrame {
+    frame_id: usize,
+    method: usize,
+    receiver: usize,
+    closure_or_nil: usize,
+    pc: usize,
+    base: usize,
+    frame_size: usize,
+    stack_pointer: usize,
+    context: Option<usize>,
+    // None when the sender is the frame right below this one.
+    // Otherwise, the sender lives in the heap (or is nil for the bottom of the stack).
+    sender_context: Option<usize>,
+}
+
+#[derive(Debug)]
+struct StackPage {
+    slots: Vec<usize>,
+    frames: Vec<StackFrame>,
+}
+
+impl StackPage {
+    fn new(page_size: usize) -> Self {
+        Self {
+            slots: vec![0; page_size],
+            frames: Vec::new(),
+        }
+    }
+
+    fn first_free_slot(&self) -> usize {
+        match self.frames.last() {
+            Some(frame) => frame.base + frame.frame_size,
+            None => 0,
+        }
+    }
+}
+
+// A frame is located by its position in pages_in_use, then its position in the page.
+type FrameLocation = (usize, usize);
+
+#[derive(Debug)]
+pub struct StackZone {
+    pages: Vec<StackPage>,
+    page_size: usize,
+    // Oldest page first
+    pages_in_use: Vec<usize>,
+    next_frame_id: usize,
+    nil: usize,
+}
+
+impl StackZone {
+    pub fn new(number_of_pages: usize, page_size: usize, nil: usize) -> Self {
+        Self {
+            pages: (0..number_of_pages)
+                .map(|_| StackPage::new(page_size))
+                .collect(),
+            page_size,
+            pages_in_use: Vec::new(),
+            next_frame_id: 1,
+            nil,
+        }
+    }
+
+    // Testing
+    pub fn is_empty(&self) -> bool {
+        self.pages_in_use.is_empty()
+    }
+
+    pub fn depth(&self) -> usize {
+        self.pages_in_use
+            .iter()
+            .map(|page_index| self.pages[*page_index].frames.len())
+            .sum()
+    }
+
+    // Frames
+    pub fn push_frame(
+        &mut self,
+        method: usize,
+        receiver: usize,
+        frame_size: usize,
+        space: &mut MemorySpace,
+    ) -> usize {
+        let sender_context = if self.is_empty() {
+            Some(self.nil)
+        } else {
+            None
+        };
+        self.push_frame_with_sender(method, receiver, frame_size, sender_context, space)
+    }
+
+    fn push_frame_with_sender(
+        &mut self,
+        method: usize,
+        receiver: usize,
+        frame_size: usize,
+        sender_context: Option<usize>,
+        space: &mut MemorySpace,
+    ) -> usize {
+        if frame_size > self.page_size {
+            panic!(
+                "Frame of {} slots does not fit in stack pages of {} slots",
+                frame_size, self.page_size
+            )
+        }
+
+        let fits_in_current_page = match self.pages_in_use.last() {
+            Some(page_index) => {
+                self.pages[*page_index].first_free_slot() + frame_size <= self.page_size
+            }
+            None => false,
+        };
+        let mut sender_context = sender_context;
+        if !fits_in_current_page {
+            if let Some(divorced_sender) = self.use_new_page(space) {
+                sender_context = sender_context.or(Some(divorced_sender));
+            }
+        }
+
+        let frame_id = self.next_frame_id;
+        self.next_frame_id += 1;
+        let nil = self.nil;
+        let page = self.current_page_mut();
+        let base = page.first_free_slot();
+        page.frames.push(StackFrame {
+            frame_id,
+            method,
+            receiver,
+            closure_or_nil: nil,
+            pc: 0,
+            base,
+            frame_size,
+            stack_pointer: 0,
+            context: None,
+            sender_context,
+        });
+        frame_id
+    }
+
+    // Returns to the sender.
+    // If the sender was divorced, it is brought back on the stack.
+    pub fn pop_frame(&mut self, space: &mut MemorySpace) {
+        let current_page_index = *self.pages_in_use.last().expect("Stack zone is empty");
+        let frame = self.pages[current_page_index].frames.pop().unwrap();
+        if self.pages[current_page_index].frames.is_empty() {
+            self.pages_in_use.pop();
+        }
+
+        if let Some(context) = frame.context {
+            // The context outlives its frame, it is now dead.
+            self.write_back_frame(&frame, current_page_index, self.nil, space);
+            let mut context_oop = space.get_oop_at(context);
+            context_oop.slot_at_index_put(context_constants::PC_INDEX, self.nil);
+        }
+
+        if let Some(sender) = frame.sender_context {
+            if sender != self.nil && self.is_empty() {
+                self.resume_context(sender, space);
+            }
+        }
+    }
+
+    // Stack manipulation of the current frame
+    pub fn push(&mut self, value: usize) {
+        let page_index = *self.pages_in_use.last().expect("Stack zone is empty");
+        let page = &mut self.pages[page_index];
+        let frame = page.frames.last_mut().unwrap();
+        if frame.stack_pointer >= frame.frame_size {
+            panic!(
+                "Frame {} overflowed its {} slots",
+                frame.frame_id, frame.frame_size
+            )
+        }
+        page.slots[frame.base + frame.stack_pointer] = value;
+        frame.stack_pointer += 1;
+    }
+
+    pub fn pop(&mut self) -> usize {
+        let value = self.top();
+        self.current_frame_mut().stack_pointer -= 1;
+        value
+    }
+
+    pub fn pop_n(&mut self, count: usize) {
+        self.current_frame_mut().stack_pointer -= count;
+    }
+
+    pub fn top(&self) -> usize {
+        self.stack_value(0)
+    }
+
+    // 0 is the top of the stack
+    pub fn stack_value(&self, offset: usize) -> usize {
+        let frame = self.current_frame();
+        if offset >= frame.stack_pointer {
+            panic!("stack access was out of bound")
+        }
+        self.current_page().slots[frame.base + frame.stack_pointer - 1 - offset]
+    }
+
+    pub fn stack_value_put(&mut self, offset: usize, value: usize) {
+        let frame = self.current_frame();
+        if offset >= frame.stack_pointer {
+            panic!("stack access was out of bound")
+        }
+        let slot_index = frame.base + frame.stack_pointer - 1 - offset;
+        self.current_page_mut().slots[slot_index] = value;
+    }
+
+    // Temporaries are 0 based, arguments come first
+    pub fn temp_at(&self, index: usize) -> usize {
+        let frame = self.current_frame();
+        if index >= frame.stack_pointer {
+            panic!("temporary access was out of bound")
+        }
+        self.current_page().slots[frame.base + index]
+    }
+
+    pub fn temp_at_put(&mut self, index: usize, value: usize) {
+        let frame = self.current_frame();
+        if index >= frame.stack_pointer {
+            panic!("temporary access was out of bound")
+        }
+        let slot_index = frame.base + index;
+        self.current_page_mut().slots[slot_index] = value;
+    }
+
+    pub fn stack_pointer(&self) -> usize {
+        self.current_frame().stack_pointer
+    }
+
+    // Current frame accessing
+    pub fn method(&self) -> usize {
+        self.current_frame().method
+    }
+
+    pub fn receiver(&self) -> usize {
+        self.current_frame().receiver
+    }
+
+    pub fn pc(&self) -> usize {
+        self.current_frame().pc
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.current_frame_mut().pc = pc;
+    }
+
+    pub fn closure_or_nil(&self) -> usize {
+        self.current_frame().closure_or_nil
+    }
+
+    pub fn set_closure_or_nil(&mut self, closure_or_nil: usize) {
+        self.current_frame_mut().closure_or_nil = closure_or_nil;
+    }
+
+    pub fn current_frame_id(&self) -> usize {
+        self.current_frame().frame_id
+    }
+
+    // Contexts
+    pub fn this_context(&mut self, space: &mut MemorySpace) -> usize {
+        let location = self.current_location();
+        self.ensure_frame_is_married(location, space)
+    }
+
+    pub fn is_current_context(&self, context: usize) -> bool {
+        self.current_frame().context == Some(context)
+    }
+
+    // Looks for the context from the current frame down to the bottom of the stack,
+    // then along the senders in the heap. The search stops at frames without method,
+    // which stand for the callers outside the interpreter.
+    pub fn is_on_sender_chain(&self, context: usize, space: &mut MemorySpace) -> bool {
+        for page_index in self.pages_in_use.iter().rev() {
+            for frame in self.pages[*page_index].frames.iter().rev() {
+                if frame.context == Some(context) {
+                    return true;
+                }
+                if frame.method == self.nil {
+                    return false;
+                }
+                if let Some(sender) = frame.sender_context {
+                    return self.is_in_heap_chain(context, sender, space);
+                }
+            }
+        }
+        false
+    }
+
+    fn is_in_heap_chain(&self, context: usize, first: usize, space: &mut MemorySpace) -> bool {
+        let mut current = first;
+        while current != self.nil && SlotContent::new(current).is_slot_oop() {
+            if current == context {
+                return true;
+            }
+            let current_oop = space.get_oop_at(current);
+            if current_oop.slot_at_index(context_constants::METHOD_INDEX) == self.nil {
+                return false;
+            }
+            current = current_oop.slot_at_index(context_constants::SENDER_INDEX);
+        }
+        false
+    }
+
+    pub fn is_married_context(&self, context: usize, space: &mut MemorySpace) -> bool {
+        self.married_frame_location(context, space).is_some()
+    }
+
+    pub fn context_slot_at(
+        &mut self,
+        context: usize,
+        index: usize,
+        space: &mut MemorySpace,
+    ) -> usize {
+        let location = match self.married_frame_location(context, space) {
+            Some(location) => location,
+            None => return space.get_oop_at(context).slot_at_index(index),
+        };
+
+        let frame = self.frame_at(location);
+        match index {
+            context_constants::SENDER_INDEX => self.sender_of(location, space),
+            context_constants::PC_INDEX => SlotContent::small_integer(frame.pc as isize),
+            context_constants::STACKP_INDEX => {
+                SlotContent::small_integer(frame.stack_pointer as isize)
+            }
+            context_constants::METHOD_INDEX => frame.method,
+            context_constants::CLOSURE_OR_NIL_INDEX => frame.closure_or_nil,
+            context_constants::RECEIVER_INDEX => frame.receiver,
+            _ => {
+                let stack_index = index - context_constants::NUMBER_OF_FIXED_SLOTS - 1;
+                if stack_index < frame.stack_pointer {
+                    self.pages[self.pages_in_use[location.0]].slots[frame.base + stack_index]
+                } else {
+                    self.nil
+                }
+            }
+        }
+    }
+
+    pub fn context_slot_at_put(
+        &mut self,
+        context: usize,
+        index: usize,
+        value: usize,
+        space: &mut MemorySpace,
+    ) {
+        let location = match self.married_frame_location(context, space) {
+            Some(location) => location,
+            None => {
+                space.get_oop_at(context).slot_at_index_put(index, value);
+                return;
+            }
+        };
+
+        // Stores into the live part of the stack go straight to the frame
+        let page_index = self.pages_in_use[location.0];
+        let frame = &mut self.pages[page_index].frames[location.1];
+        if index == context_constants::RECEIVER_INDEX {
+            frame.receiver = value;
+            return;
+        }
+        if index > context_constants::NUMBER_OF_FIXED_SLOTS {
+            let stack_index = index - context_constants::NUMBER_OF_FIXED_SLOTS - 1;
+            if stack_index < frame.stack_pointer {
+                let slot_index = frame.base + stack_index;
+                self.pages[page_index].slots[slot_index] = value;
+                return;
+            }
+        }
+
+        // Anything else changes the shape of the stack: flush it in the heap, then resume.
+        let active_context = self.divorce_all_frames(space).unwrap();
+        space.get_oop_at(context).slot_at_index_put(index, value);
+        self.resume_context(active_context, space);
+    }
+
+    // Flushes every frame into its context, leaving the stack zone empty.
+    // Answers the context of the frame that was executing.
+    pub fn divorce_all_frames(&mut self, space: &mut MemorySpace) -> Option<usize> {
+        if self.is_empty() {
+            return None;
+        }
+        let active_context = self.this_context(space);
+        while !self.is_empty() {
+            self.divorce_oldest_page(space);
+        }
+        Some(active_context)
+    }
+
+    // Brings a context from the heap back on the stack.
+    // The new frame is married to it.
+    pub fn resume_context(&mut self, context: usize, space: &mut MemorySpace) {
+        let context_oop = space.get_oop_at(context);
+        let pc = SlotContent::new(context_oop.slot_at_index(context_constants::PC_INDEX));
+        if !pc.is_small_integer() {
+            panic!("Cannot resume dead context {}", context)
+        }
+        let stack_pointer =
+            SlotContent::new(context_oop.slot_at_index(context_constants::STACKP_INDEX))
+                .as_small_integer() as usize;
+        let sender = context_oop.slot_at_index(context_constants::SENDER_INDEX);
+        let method = context_oop.slot_at_index(context_constants::METHOD_INDEX);
+        let closure_or_nil = context_oop.slot_at_index(context_constants::CLOSURE_OR_NIL_INDEX);
+        let receiver = context_oop.slot_at_index(context_constants::RECEIVER_INDEX);
+        let frame_size = context_oop.number_of_slots() - context_constants::NUMBER_OF_FIXED_SLOTS;
+        let stack_contents: Vec<usize> = (1..=stack_pointer)
+            .map(|index| {
+                context_oop.slot_at_index(context_constants::NUMBER_OF_FIXED_SLOTS + index)
+            })
+            .collect();
+
+        let frame_id =
+            self.push_frame_with_sender(method, receiver, frame_size, Some(sender), space);
+        for value in stack_contents {
+            self.push(value);
+        }
+        let frame = self.current_frame_mut();
+        frame.pc = pc.as_small_integer() as usize;
+        frame.closure_or_nil = closure_or_nil;
+        frame.context = Some(context);
+
+        let mut context_oop = space.get_oop_at(context);
+        context_oop.slot_at_index_put(
+            context_constants::SENDER_INDEX,
+            SlotContent::small_integer(frame_id as isize),
+        );
+    }
+
+    // GC support
+    pub fn roots(&self) -> Vec<usize> {
+        let mut roots: Vec<usize> = vec![self.nil];
+        for page_index in &self.pages_in_use {
+            let page = &self.pages[*page_index];
+            for frame in &page.frames {
+                roots.push(frame.method);
+                roots.push(frame.receiver);
+                roots.push(frame.closure_or_nil);
+                roots.extend(frame.context);
+                roots.extend(frame.sender_context);
+                roots.extend(&page.slots[frame.base..frame.base + frame.stack_pointer]);
+            }
+        }
+        roots.retain(|value| SlotContent::new(*value).is_slot_oop());
+        roots
+    }
+
+    // Maps the oops the frames reference, such as the forwarders to their targets
+    pub fn map_oops(
+        &mut self,
+        space: &mut MemorySpace,
+        map: &mut dyn FnMut(&mut MemorySpace, usize) -> usize,
+    ) {
+        for page_index in &self.pages_in_use {
+            let page = &mut self.pages[*page_index];
+            for frame in &mut page.frames {
+                frame.method = map(space, frame.method);
+                frame.receiver = map(space, frame.receiver);
+                frame.closure_or_nil = map(space, frame.closure_or_nil);
+                frame.context = frame.context.map(|context| map(space, context));
+                frame.sender_context = frame.sender_context.map(|context| map(space, context));
+                for slot in &mut page.slots[frame.base..frame.base + frame.stack_pointer] {
+                    *slot = map(space, *slot);
+                }
+            }
+        }
+    }
+
+    // Private
+    fn current_page(&self) -> &StackPage {
+        &self.pages[*self.pages_in_use.last().expect("Stack zone is empty")]
+    }
+
+    fn current_page_mut(&mut self) -> &mut StackPage {
+        let page_index = *self.pages_in_use.last().expect("Stack zone is empty");
+        &mut self.pages[page_index]
+    }
+
+    fn current_frame(&self) -> &StackFrame {
+        self.current_page().frames.last().unwrap()
+    }
+
+    fn current_frame_mut(&mut self) -> &mut StackFrame {
+        self.current_page_mut().frames.last_mut().unwrap()
+    }
+
+    fn current_location(&self) -> FrameLocation {
+        let page_position = self.pages_in_use.len() - 1;
+        (page_position, self.current_page().frames.len() - 1)
+    }
+
+    fn frame_at(&self, location: FrameLocation) -> &StackFrame {
+        &self.pages[self.pages_in_use[location.0]].frames[location.1]
+    }
+
+    fn frame_location(&self, frame_id: usize) -> Option<FrameLocation> {
+        for (page_position, page_index) in self.pages_in_use.iter().enumerate() {
+            for (frame_position, frame) in self.pages[*page_index].frames.iter().enumerate() {
+                if frame.frame_id == frame_id {
+                    return Some((page_position, frame_position));
+                }
+            }
+        }
+        None
+    }
+
+    // Married contexts keep the id of their frame in their sender slot
+    fn married_frame_location(
+        &self,
+        context: usize,
+        space: &mut MemorySpace,
+    ) -> Option<FrameLocation> {
+        let sender = SlotContent::new(
+            space
+                .get_oop_at(context)
+                .slot_at_index(context_constants::SENDER_INDEX),
+        );
+        if !sender.is_small_integer() {
+            return None;
+        }
+        let location = self.frame_location(sender.as_small_integer() as usize)?;
+        if self.frame_at(location).context == Some(context) {
+            Some(location)
+        } else {
+            None
+        }
+    }
+
+    fn caller_location(&self, location: FrameLocation) -> Option<FrameLocation> {
+        let (page_position, frame_position) = location;
+        if frame_position > 0 {
+            return Some((page_position, frame_position - 1));
+        }
+        if page_position > 0 {
+            let previous_page = &self.pages[self.pages_in_use[page_position - 1]];
+            return Some((page_position - 1, previous_page.frames.len() - 1));
+        }
+        None
+    }
+
+    fn sender_of(&mut self, location: FrameLocation, space: &mut MemorySpace) -> usize {
+        if let Some(sender_context) = self.frame_at(location).sender_context {
+            return sender_context;
+        }
+        match self.caller_location(location) {
+            Some(caller) => self.ensure_frame_is_married(caller, space),
+            None => self.nil,
+        }
+    }
+
+    fn ensure_frame_is_married(
+        &mut self,
+        location: FrameLocation,
+        space: &mut MemorySpace,
+    ) -> usize {
+        if let Some(context) = self.frame_at(location).context {
+            return context;
+        }
+
+        let frame = self.frame_at(location);
+        let mut builder = OopBuilder::new();
+        builder.set_class_index(SpecialClassIndexes::Context as usize);
+        builder.set_number_of_slots(context_constants::NUMBER_OF_FIXED_SLOTS + frame.frame_size);
+        let context = builder.build(space);
+
+        let mut context_oop = space.get_oop_at(context);
+        for index in 1..=context_oop.number_of_slots() {
+            context_oop.slot_at_index_put(index, self.nil);
+        }
+        context_oop.slot_at_index_put(
+            context_constants::SENDER_INDEX,
+            SlotContent::small_integer(frame.frame_id as isize),
+        );
+        context_oop.slot_at_index_put(context_constants::METHOD_INDEX, frame.method);
+
+        let page_index = self.pages_in_use[location.0];
+        self.pages[page_index].frames[location.1].context = Some(context);
+        context
+    }
+
+    // Copies the frame state in its context, which then stands on its own.
+    fn write_back_frame(
+        &self,
+        frame: &StackFrame,
+        page_index: usize,
+        sender: usize,
+        space: &mut MemorySpace,
+    ) {
+        let page = &self.pages[page_index];
+        let mut context_oop = space.get_oop_at(frame.context.unwrap());
+        context_oop.slot_at_index_put(context_constants::SENDER_INDEX, sender);
+        context_oop.slot_at_index_put(
+            context_constants::PC_INDEX,
+            SlotContent::small_integer(frame.pc as isize),
+        );
+        context_oop.slot_at_index_put(
+            context_constants::STACKP_INDEX,
+            SlotContent::small_integer(frame.stack_pointer as isize),
+        );
+        context_oop.slot_at_index_put(context_constants::METHOD_INDEX, frame.method);
+        context_oop.slot_at_index_put(
+            context_constants::CLOSURE_OR_NIL_INDEX,
+            frame.closure_or_nil,
+        );
+        context_oop.slot_at_index_put(context_constants::RECEIVER_INDEX, frame.receiver);
+        for stack_index in 0..frame.frame_size {
+            let value = if stack_index < frame.stack_pointer {
+                page.slots[frame.base + stack_index]
+            } else {
+                self.nil
+            };
+            context_oop.slot_at_index_put(
+                context_constants::NUMBER_OF_FIXED_SLOTS + 1 + stack_index,
+                value,
+            );
+        }
+    }
+
+    // Answers the divorced sender when no frame is left on the stack to link with
+    fn use_new_page(&mut self, space: &mut MemorySpace) -> Option<usize> {
+        let mut divorced_sender = None;
+        if self.pages_in_use.len() == self.pages.len() {
+            let last_context = self.divorce_oldest_page(space);
+            if self.pages_in_use.is_empty() {
+                divorced_sender = Some(last_context);
+            }
+        }
+        let free_page = (0..self.pages.len())
+            .find(|page_index| !self.pages_in_use.contains(page_index))
+            .unwrap();
+        self.pages_in_use.push(free_page);
+        divorced_sender
+    }
+
+    // Turns every frame of the oldest page into a context, freeing the page.
+    // Answers the context of the newest frame of the page.
+    fn divorce_oldest_page(&mut self, space: &mut MemorySpace) -> usize {
+        let number_of_frames = self.pages[self.pages_in_use[0]].frames.len();
+        for frame_position in 0..number_of_frames {
+            self.ensure_frame_is_married((0, frame_position), space);
+        }
+
+        let page_index = self.pages_in_use.remove(0);
+        let frames = std::mem::take(&mut self.pages[page_index].frames);
+        let mut sender = frames[0].sender_context.unwrap_or(self.nil);
+        for frame in &frames {
+            self.write_back_frame(frame, page_index, sender, space);
+            sender = frame.context.unwrap();
+        }
+
+        if let Some(next_page_index) = self.pages_in_use.first() {
+            self.pages[*next_page_index].frames[0].sender_context = Some(sender);
+        }
+        sender
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::garbage_collector::simple_garbage_collector;
+    use crate::memory_space::MemorySpace;
+    use crate::oop_builder::OopBuilder;
+    use crate::oop_projections::oop_common::OopCommonState;
+    use crate::slot_content::SlotContent;
+    use crate::special_class_index::SpecialClassIndexes;
+    use crate::stack_zone::{context_constants, StackZone};
+
+    fn space_with_nil() -> (MemorySpace, usize) {
+        let mut space = MemorySpace::for_bit_size(2000);
+        let nil = OopBuilder::new().build(&mut space);
+        (space, nil)
+    }
+
+    #[test]
+    fn test_push_frame_does_not_allocate_a_context() {
+        let (mut space, nil) = space_with_nil();
+        let mut zone = StackZone::new(2, 32, nil);
+        zone.push_frame(nil, nil, 8, &mut space);
+
+        let mut iter = space.iter();
+        iter.next(&mut space);
+        assert!(iter.next(&mut space).unwrap().is_free_oop());
+    }
+
+    #[test]
+    fn test_push_and_pop_values() {
+        let (mut space, nil) = space_with_nil();
+        let mut zone = StackZone::new(2, 32, nil);
+        zone.push_frame(nil, nil, 8, &mut space);
+        zone.push(SlotContent::small_integer(1));
+        zone.push(SlotContent::small_integer(2));
+
+        assert_eq!(zone.pop(), SlotContent::small_integer(2));
+        assert_eq!(zone.top(), SlotContent::small_integer(1));
+    }
+
+    #[test]
+    fn test_temps_are_relative_to_the_frame() {
+        let (mut space, nil) = space_with_nil();
+        let mut zone = StackZone::new(2, 32, nil);
+        zone.push_frame(nil, nil, 8, &mut space);
+        zone.push(SlotContent::small_integer(1));
+        zone.push_frame(nil, nil, 8, &mut space);
+        zone.push(SlotContent::small_integer(2));
+        zone.temp_at_put(0, SlotContent::small_integer(3));
+
+        assert_eq!(zone.temp_at(0), SlotContent::small_integer(3));
+        zone.pop_frame(&mut space);
+        assert_eq!(zone.temp_at(0), SlotContent::small_integer(1));
+    }
+
+    #[test]
+    fn test_this_context_materializes_a_context() {
+        let (mut space, nil) = space_with_nil();
+        let mut zone = StackZone::new(2, 32, nil);
+        let receiver = OopBuilder::new().build(&mut space);
+        zone.push_frame(nil, receiver, 8, &mut space);
+
+        let context = zone.this_context(&mut space);
+
+        assert_eq!(
+            space.get_oop_at(context).get_header().class_index_bits(),
+            SpecialClassIndexes::Context as usize
+        );
+        assert_eq!(
+            zone.context_slot_at(context, context_constants::RECEIVER_INDEX, &mut space),
+            receiver
+        );
+    }
+
+    #[test]
+    fn test_this_context_twice_answers_the_same_context() {
+        let (mut space, nil) = space_with_nil();
+        let mut zone = StackZone::new(2, 32, nil);
+        zone.push_frame(nil, nil, 8, &mut space);
+
+        let context = zone.this_context(&mut space);
+        assert_eq!(zone.this_context(&mut space), context);
+        assert!(zone.is_married_context(context, &mut space));
+    }
+
+    #[test]
+    fn test_married_context_reads_the_frame() {
+        let (mut space, nil) = space_with_nil();
+        let mut zone = StackZone::new(2, 32, nil);
+        zone.push_frame(nil, nil, 8, &mut space);
+        let context = zone.this_context(&mut space);
+        zone.push(SlotContent::small_integer(7));
+        zone.set_pc(3);
+
+        assert_eq!(
+            zone.context_slot_at(
+                context,
+                context_constants::NUMBER_OF_FIXED_SLOTS + 1,
+                &mut space
+            ),
+            SlotContent::small_integer(7)
+        );
+        assert_eq!(
+            zone.context_slot_at(context, context_constants::PC_INDEX, &mut space),
+            SlotContent::small_integer(3)
+        );
+    }
+
+    #[test]
+    fn test_married_context_writes_back_to_the_frame() {
+        let (mut space, nil) = space_with_nil();
+        let mut zone = StackZone::new(2, 32, nil);
+        zone.push_frame(nil, nil, 8, &mut space);
+        zone.push(SlotContent::small_integer(7));
+        let context = zone.this_context(&mut space);
+
+        zone.context_slot_at_put(
+            context,
+            context_constants::NUMBER_OF_FIXED_SLOTS + 1,
+            SlotContent::small_integer(9),
+            &mut space,
+        );
+
+        assert_eq!(zone.temp_at(0), SlotContent::small_integer(9));
+    }
+
+    #[test]
+    fn test_sender_of_married_context_is_the_caller_context() {
+        let (mut space, nil) = space_with_nil();
+        let mut zone = StackZone::new(2, 32, nil);
+        let caller_receiver = OopBuilder::new().build(&mut space);
+        zone.push_frame(nil, caller_receiver, 8, &mut space);
+        zone.push_frame(nil, nil, 8, &mut space);
+        let context = zone.this_context(&mut space);
+
+        let sender = zone.context_slot_at(context, context_constants::SENDER_INDEX, &mut space);
+
+        assert_eq!(
+            zone.context_slot_at(sender, context_constants::RECEIVER_INDEX, &mut space),
+            caller_receiver
+        );
+        assert_eq!(
+            zone.context_slot_at(sender, context_constants::SENDER_INDEX, &mut space),
+            nil
+        );
+    }
+
+    #[test]
+    fn test_returning_from_a_married_frame_kills_its_context() {
+        let (mut space, nil) = space_with_nil();
+        let mut zone = StackZone::new(2, 32, nil);
+        zone.push_frame(nil, nil, 8, &mut space);
+        zone.push_frame(nil, nil, 8, &mut space);
+        zone.push(SlotContent::small_integer(5));
+        let context = zone.this_context(&mut space);
+
+        zone.pop_frame(&mut space);
+
+        assert!(!zone.is_married_context(context, &mut space));
+        let context_oop = space.get_oop_at(context);
+        assert_eq!(context_oop.slot_at_index(context_constants::PC_INDEX), nil);
+        assert_eq!(
+            context_oop.slot_at_index(context_constants::NUMBER_OF_FIXED_SLOTS + 1),
+            SlotContent::small_integer(5)
+        );
+    }
+
+    #[test]
+    fn test_page_overflow_divorces_the_oldest_page() {
+        let (mut space, nil) = space_with_nil();
+        let mut zone = StackZone::new(2, 16, nil);
+        for value in 0..6 {
+            zone.push_frame(nil, nil, 8, &mut space);
+            zone.push(SlotContent::small_integer(value));
+        }
+
+        // Only the two last pages (4 frames) are on the stack
+        assert_eq!(zone.depth(), 4);
+
+        for value in (0..6).rev() {
+            assert_eq!(zone.temp_at(0), SlotContent::small_integer(value));
+            zone.pop_frame(&mut space);
+        }
+        assert!(zone.is_empty());
+    }
+
+    #[test]
+    fn test_divorced_frames_keep_their_sender_chain() {
+        let (mut space, nil) = space_with_nil();
+        let mut zone = StackZone::new(1, 8, nil);
+        zone.push_frame(nil, nil, 8, &mut space);
+        zone.push(SlotContent::small_integer(1));
+        zone.push_frame(nil, nil, 8, &mut space);
+        zone.push(SlotContent::small_integer(2));
+
+        let context = zone.this_context(&mut space);
+        let sender = zone.context_slot_at(context, context_constants::SENDER_INDEX, &mut space);
+
+        assert!(!zone.is_married_context(sender, &mut space));
+        assert_eq!(
+            space
+                .get_oop_at(sender)
+                .slot_at_index(context_constants::NUMBER_OF_FIXED_SLOTS + 1),
+            SlotContent::small_integer(1)
+        );
+    }
+
+    #[test]
+    fn test_divorce_all_frames_then_resume() {
+        let (mut space, nil) = space_with_nil();
+        let mut zone = StackZone::new(2, 32, nil);
+        zone.push_frame(nil, nil, 8, &mut space);
+        zone.push(SlotContent::small_integer(1));
+        zone.push_frame(nil, nil, 8, &mut space);
+        zone.push(SlotContent::small_integer(2));
+        zone.set_pc(4);
+
+        let active_context = zone.divorce_all_frames(&mut space).unwrap();
+        assert!(zone.is_empty());
+
+        zone.resume_context(active_context, &mut space);
+        assert_eq!(zone.pc(), 4);
+        assert_eq!(zone.temp_at(0), SlotContent::small_integer(2));
+        zone.pop_frame(&mut space);
+        assert_eq!(zone.temp_at(0), SlotContent::small_integer(1));
+    }
+
+    #[test]
+    fn test_stack_frames_are_gc_roots() {
+        let (mut space, nil) = space_with_nil();
+        let mut zone = StackZone::new(2, 32, nil);
+        let receiver = OopBuilder::new().build(&mut space);
+        let temp = OopBuilder::new().build(&mut space);
+        zone.push_frame(nil, receiver, 8, &mut space);
+        zone.push(temp);
+        zone.push(SlotContent::small_integer(1));
+
+        simple_garbage_collector::collect_from_roots(zone.roots(), &mut space);
+
+        assert!(!space.get_oop_at(receiver).is_free_oop());
+        assert!(!space.get_oop_at(temp).is_free_oop());
+    }
+}
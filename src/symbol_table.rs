@@ -0,0 +1,275 @@
+use crate::hashed_collection::{capacity_of, set_tally, tally_of};
+use crate::interpreter::Interpreter;
+use crate::special_object_index::SpecialObjectIndexes;
+
+// The symbol table is a WeakSet referenced from the special objects array:
+// a tally, then the symbols in weak slots, so that the symbols nothing else references get
+// reclaimed. Symbols are hashed by their characters, collisions go to the next free slot.
+// The hash does not depend on where the symbols are, the table survives moves and image
+// save and load. The garbage collector leaves nil holes in the probe sequences, the table
+// is rehashed after each collection.
+pub mod symbol_table_constants {
+    // The tally
+    pub const NUMBER_OF_FIXED_SLOTS: usize = 1;
+}
+
+pub const INITIAL_SIZE: usize = 64;
+
+pub fn new_symbol_table(interpreter: &mut Interpreter, size: usize) -> usize {
+    let weak_set_class = interpreter.special_object(SpecialObjectIndexes::ClassWeakSet);
+    let table = interpreter
+        .instantiate_class(weak_set_class, size)
+        .expect("WeakSet should be indexable");
+    set_tally(interpreter, table, 0);
+    table
+}
+
+fn symbol_table(interpreter: &mut Interpreter) -> usize {
+    interpreter.special_object(SpecialObjectIndexes::SymbolTable)
+}
+
+// FNV-1a, on the bytes of the symbol
+fn hash_of(name: &str) -> usize {
+    name.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as usize).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn symbol_at(interpreter: &mut Interpreter, table: usize, index: usize) -> usize {
+    interpreter
+        .space
+        .get_oop_at(table)
+        .slot_at_index(symbol_table_constants::NUMBER_OF_FIXED_SLOTS + index)
+}
+
+fn symbol_at_put(interpreter: &mut Interpreter, table: usize, index: usize, symbol: usize) {
+    interpreter.space.get_oop_at(table).slot_at_index_put(
+        symbol_table_constants::NUMBER_OF_FIXED_SLOTS + index,
+        symbol,
+    );
+}
+
+// Answers the 1 based index of the symbol, or of the free slot where it would go
+fn scan_for(interpreter: &mut Interpreter, table: usize, name: &str) -> Option<usize> {
+    let nil = interpreter.nil_object();
+    let capacity = capacity_of(
+        interpreter,
+        table,
+        symbol_table_constants::NUMBER_OF_FIXED_SLOTS,
+    );
+    let start = hash_of(name) % capacity;
+    (0..capacity)
+        .map(|probe| (start + probe) % capacity + 1)
+        .find(|index| {
+            let symbol = symbol_at(interpreter, table, *index);
+            symbol == nil || interpreter.string_value_of(symbol).as_deref() == Some(name)
+        })
+}
+
+pub fn lookup(interpreter: &mut Interpreter, name: &str) -> Option<usize> {
+    let table = symbol_table(interpreter);
+    let index = scan_for(interpreter, table, name)?;
+    let symbol = symbol_at(interpreter, table, index);
+    if symbol == interpreter.nil_object() {
+        None
+    } else {
+        Some(symbol)
+    }
+}
+
+// Answers the symbols of the table
+pub fn symbols(interpreter: &mut Interpreter) -> Vec<usize> {
+    let table = symbol_table(interpreter);
+    let nil = interpreter.nil_object();
+    let capacity = capacity_of(
+        interpreter,
+        table,
+        symbol_table_constants::NUMBER_OF_FIXED_SLOTS,
+    );
+    (1..=capacity)
+        .map(|index| symbol_at(interpreter, table, index))
+        .filter(|symbol| *symbol != nil)
+        .collect()
+}
+
+fn add(interpreter: &mut Interpreter, table: usize, symbol: usize) {
+    let name = interpreter
+        .string_value_of(symbol)
+        .expect("Symbols should be bytes");
+    let index = scan_for(interpreter, table, &name).expect("The symbol table is full");
+    symbol_at_put(interpreter, table, index, symbol);
+    let tally = tally_of(interpreter, table);
+    set_tally(interpreter, table, tally + 1);
+}
+
+// The table is kept at most three quarters full
+pub fn intern(interpreter: &mut Interpreter, name: &str) -> usize {
+    if let Some(symbol) = lookup(interpreter, name) {
+        return symbol;
+    }
+
+    let symbol_class = interpreter.special_object(SpecialObjectIndexes::ClassSymbol);
+    let symbol = interpreter
+        .instantiate_class_with_bytes(symbol_class, name.as_bytes())
+        .expect("Symbol should be bytes");
+    let mut table = symbol_table(interpreter);
+    let capacity = capacity_of(
+        interpreter,
+        table,
+        symbol_table_constants::NUMBER_OF_FIXED_SLOTS,
+    );
+    if (tally_of(interpreter, table) + 1) * 4 > capacity * 3 {
+        table = grow(interpreter, table);
+    }
+    add(interpreter, table, symbol);
+    symbol
+}
+
+// Rebuilds the table without the holes the garbage collector left.
+// Nothing to do for the spaces built without a symbol table.
+pub fn rehash(interpreter: &mut Interpreter) {
+    let nil = interpreter.nil_object();
+    let special_objects = interpreter.special_objects();
+    let table = interpreter
+        .space
+        .get_oop_at(special_objects)
+        .try_slot_at_index(SpecialObjectIndexes::SymbolTable as usize)
+        .unwrap_or(nil);
+    if table == nil {
+        return;
+    }
+    let old_symbols = symbols(interpreter);
+    for index in 1..=capacity_of(
+        interpreter,
+        table,
+        symbol_table_constants::NUMBER_OF_FIXED_SLOTS,
+    ) {
+        symbol_at_put(interpreter, table, index, nil);
+    }
+    set_tally(interpreter, table, 0);
+    for symbol in old_symbols {
+        add(interpreter, table, symbol);
+    }
+}
+
+fn grow(interpreter: &mut Interpreter, table: usize) -> usize {
+    let old_symbols = symbols(interpreter);
+    let capacity = capacity_of(
+        interpreter,
+        table,
+        symbol_table_constants::NUMBER_OF_FIXED_SLOTS,
+    );
+    let new_table = new_symbol_table(interpreter, capacity * 2);
+    for symbol in old_symbols {
+        add(interpreter, new_table, symbol);
+    }
+    let special_objects = interpreter.special_objects();
+    interpreter
+        .space
+        .get_oop_at(special_objects)
+        .slot_at_index_put(SpecialObjectIndexes::SymbolTable as usize, new_table);
+    new_table
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bootstrap::bootstrap;
+    use crate::compiler::install_method;
+    use crate::hashed_collection::tally_of;
+    use crate::interpreter::Interpreter;
+    use crate::oop_projections::oop_common::OopCommonState;
+    use crate::special_object_index::SpecialObjectIndexes;
+    use crate::symbol_table::{intern, lookup, symbols, INITIAL_SIZE};
+
+    // Keeps the symbols alive, as literals of a method of Object
+    fn install_literal_symbols(interpreter: &mut Interpreter, names: &[String]) {
+        let object = interpreter.class_named("Object").unwrap();
+        let source = format!("keptSymbols ^#({})", names.join(" "));
+        install_method(interpreter, object, &source).unwrap();
+    }
+
+    #[test]
+    fn test_intern_twice_answers_the_same_symbol() {
+        let mut interpreter = bootstrap(20000);
+        let symbol = intern(&mut interpreter, "foo:bar:");
+        assert_eq!(intern(&mut interpreter, "foo:bar:"), symbol);
+    }
+
+    #[test]
+    fn test_interned_symbol_is_a_symbol() {
+        let mut interpreter = bootstrap(20000);
+        let symbol = intern(&mut interpreter, "foo");
+        assert_eq!(
+            interpreter.class_of(symbol),
+            interpreter.special_object(SpecialObjectIndexes::ClassSymbol)
+        );
+        assert_eq!(
+            interpreter.string_value_of(symbol),
+            Some(String::from("foo"))
+        );
+    }
+
+    #[test]
+    fn test_lookup_missing_symbol() {
+        let mut interpreter = bootstrap(20000);
+        assert_eq!(lookup(&mut interpreter, "notInterned"), None);
+    }
+
+    #[test]
+    fn test_table_grows() {
+        let mut interpreter = bootstrap(20000);
+        let first = intern(&mut interpreter, "symbol0");
+        for index in 1..INITIAL_SIZE * 2 {
+            intern(&mut interpreter, &format!("symbol{}", index));
+        }
+
+        assert_eq!(lookup(&mut interpreter, "symbol0"), Some(first));
+        assert!(lookup(&mut interpreter, &format!("symbol{}", INITIAL_SIZE * 2 - 1)).is_some());
+    }
+
+    #[test]
+    fn test_garbage_collection_reclaims_unreferenced_symbols() {
+        let mut interpreter = bootstrap(20000);
+        let unreferenced = intern(&mut interpreter, "unreferenced");
+        install_literal_symbols(&mut interpreter, &[String::from("kept")]);
+        let kept = lookup(&mut interpreter, "kept").unwrap();
+
+        interpreter.collect_garbage();
+
+        assert_eq!(lookup(&mut interpreter, "unreferenced"), None);
+        assert!(interpreter.space.get_oop_at(unreferenced).is_free_oop());
+        assert_eq!(lookup(&mut interpreter, "kept"), Some(kept));
+        assert_eq!(intern(&mut interpreter, "kept"), kept);
+    }
+
+    #[test]
+    fn test_kept_symbols_are_found_after_the_holes_are_rehashed() {
+        let mut interpreter = bootstrap(40000);
+        let names: Vec<String> = (0..INITIAL_SIZE * 2)
+            .map(|index| format!("symbol{}", index))
+            .collect();
+        let kept_names: Vec<String> = names.iter().step_by(2).cloned().collect();
+        for name in &names {
+            intern(&mut interpreter, name);
+        }
+        install_literal_symbols(&mut interpreter, &kept_names);
+        let kept: Vec<usize> = kept_names
+            .iter()
+            .map(|name| lookup(&mut interpreter, name).unwrap())
+            .collect();
+
+        interpreter.collect_garbage();
+
+        for (name, symbol) in kept_names.iter().zip(kept) {
+            assert_eq!(lookup(&mut interpreter, name), Some(symbol));
+        }
+        for name in names.iter().skip(1).step_by(2) {
+            assert_eq!(lookup(&mut interpreter, name), None);
+        }
+        let table = interpreter.special_object(SpecialObjectIndexes::SymbolTable);
+        assert_eq!(
+            tally_of(&mut interpreter, table),
+            symbols(&mut interpreter).len()
+        );
+    }
+}